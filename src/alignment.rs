@@ -0,0 +1,252 @@
+use std::fmt;
+
+/// A CIGAR string, stored as run-length encoded `(length, operation)` pairs
+/// using the SAM operation characters (`M`, `I`, `D`, `S`, ...).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cigar(Vec<(u32, u8)>);
+
+impl Cigar {
+    pub fn new() -> Self {
+        Cigar(Vec::new())
+    }
+
+    /// A CIGAR of `length` aligned bases.
+    pub fn matched(length: usize) -> Self {
+        Cigar(vec![(length as u32, b'M')])
+    }
+
+    /// Appends one operation, extending the last run when it has the same type.
+    pub fn push(&mut self, op: u8) {
+        self.push_run(1, op);
+    }
+
+    /// Appends `length` copies of `op`.
+    pub fn push_run(&mut self, length: usize, op: u8) {
+        if length == 0 {
+            return;
+        }
+        match self.0.last_mut() {
+            Some((run, last)) if *last == op => *run += length as u32,
+            _ => self.0.push((length as u32, op)),
+        }
+    }
+
+    /// Removes every operation, keeping the allocation.
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    pub fn ops(&self) -> &[(u32, u8)] {
+        &self.0
+    }
+
+    /// Number of reference bases the alignment spans.
+    pub fn reference_length(&self) -> usize {
+        self.0.iter()
+            .filter(|(_, op)| matches!(op, b'M' | b'D' | b'N' | b'=' | b'X'))
+            .map(|(length, _)| *length as usize)
+            .sum()
+    }
+
+    /// Edit distance (SAM `NM`) of `read`, in reference orientation, aligned to
+    /// `reference` starting at 0-based `position`.
+    pub fn edit_distance(&self, reference: &[u8], position: usize, read: &[u8]) -> usize {
+        let (mut ref_pos, mut read_pos, mut distance) = (position, 0, 0);
+        for &(length, op) in &self.0 {
+            let length = length as usize;
+            match op {
+                b'M' | b'=' | b'X' => {
+                    distance += (0..length).filter(|i| reference[ref_pos + i] != read[read_pos + i]).count();
+                    ref_pos += length;
+                    read_pos += length;
+                }
+                b'I' => {
+                    distance += length;
+                    read_pos += length;
+                }
+                b'D' => {
+                    distance += length;
+                    ref_pos += length;
+                }
+                b'N' => ref_pos += length,
+                b'S' => read_pos += length,
+                _ => {}
+            }
+        }
+        distance
+    }
+
+    /// Keeps only the first `reference_length` aligned reference bases and
+    /// soft-clips every read base after them, as for a read that runs off the end
+    /// of its fragment into adapter sequence.
+    pub fn clipped(&self, reference_length: usize) -> Self {
+        let mut clipped = Cigar::new();
+        let mut remaining = reference_length;
+        for &(length, op) in &self.0 {
+            let length = length as usize;
+            match op {
+                b'M' | b'=' | b'X' => {
+                    let aligned = length.min(remaining);
+                    clipped.push_run(aligned, op);
+                    clipped.push_run(length - aligned, b'S');
+                    remaining -= aligned;
+                }
+                // A deletion running up to the clip point would border the clip.
+                b'D' | b'N' if length < remaining => {
+                    clipped.push_run(length, op);
+                    remaining -= length;
+                }
+                b'D' | b'N' => remaining = 0,
+                b'I' | b'S' if remaining == 0 => clipped.push_run(length, b'S'),
+                _ => clipped.push_run(length, op),
+            }
+        }
+        clipped
+    }
+
+    /// The same alignment read in the opposite direction, as needed when a read
+    /// sequenced from the reverse strand is reported in forward-strand orientation.
+    pub fn reversed(&self) -> Self {
+        Cigar(self.0.iter().rev().copied().collect())
+    }
+}
+
+impl fmt::Display for Cigar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "*");
+        }
+        for (length, op) in &self.0 {
+            write!(f, "{}{}", length, *op as char)?;
+        }
+        Ok(())
+    }
+}
+
+/// The true origin of a simulated read on the reference.
+#[derive(Debug, Clone)]
+pub struct Alignment {
+    /// Index of the contig in the [`Reference`](crate::Reference).
+    pub contig: usize,
+    /// 0-based leftmost reference position covered by the read.
+    pub position: usize,
+    /// Whether the read was sequenced from the reverse strand.
+    pub reverse: bool,
+    /// CIGAR in forward-strand orientation, including injected indels.
+    pub cigar: Cigar,
+    /// Number of sequencing errors: substituted, inserted and deleted bases.
+    /// Differs from the edit distance to the reference when variants were injected.
+    pub edit_distance: usize,
+    /// Haplotype the read was drawn from (always 0 without variants).
+    pub haplotype: usize,
+}
+
+impl Alignment {
+    /// One past the rightmost reference position covered by the read.
+    pub fn end(&self) -> usize {
+        self.position + self.cigar.reference_length()
+    }
+
+    /// The 0-based, half-open reference intervals the read covers, split at every
+    /// `N` (intron) of the CIGAR.
+    pub fn blocks(&self) -> Vec<(usize, usize)> {
+        let mut blocks = Vec::new();
+        let (mut start, mut end) = (self.position, self.position);
+        for &(length, op) in self.cigar.ops() {
+            match op {
+                b'N' => {
+                    blocks.push((start, end));
+                    start = end + length as usize;
+                    end = start;
+                }
+                b'M' | b'D' | b'=' | b'X' => end += length as usize,
+                _ => {}
+            }
+        }
+        blocks.push((start, end));
+        blocks
+    }
+
+    /// Soft-clips `length` bases added before the 5' end of the read, such as an
+    /// inline UMI. They lead the CIGAR of forward reads and trail reverse ones.
+    pub fn clip_read_start(&mut self, length: usize) {
+        let mut cigar = Cigar::new();
+        if self.reverse {
+            cigar = self.cigar.clone();
+            cigar.push_run(length, b'S');
+        } else {
+            cigar.push_run(length, b'S');
+            for &(run, op) in self.cigar.ops() {
+                cigar.push_run(run as usize, op);
+            }
+        }
+        self.cigar = cigar;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cigar(ops: &[(u32, u8)]) -> Cigar {
+        Cigar(ops.to_vec())
+    }
+
+    #[test]
+    fn reference_length_skips_inserted_and_clipped_bases() {
+        let cigar = cigar(&[(2, b'S'), (5, b'M'), (2, b'I'), (3, b'D'), (4, b'N'), (1, b'M')]);
+        assert_eq!(cigar.reference_length(), 13);
+        assert_eq!(cigar.to_string(), "2S5M2I3D4N1M");
+        assert_eq!(Cigar::new().to_string(), "*");
+    }
+
+    #[test]
+    fn edit_distance_counts_mismatches_and_indels() {
+        let reference = b"AACGTACGTT";
+        // A mismatch at the third base, then one deleted and one inserted base.
+        let cigar = cigar(&[(4, b'M'), (1, b'D'), (2, b'M'), (1, b'I'), (2, b'M')]);
+        assert_eq!(cigar.edit_distance(reference, 1, b"ACTTCGATT"), 3);
+        assert_eq!(Cigar::matched(4).edit_distance(reference, 1, b"ACGT"), 0);
+    }
+
+    #[test]
+    fn clipped_soft_clips_past_the_reference_length() {
+        let cigar = cigar(&[(3, b'M'), (1, b'I'), (3, b'M')]);
+        assert_eq!(cigar.clipped(4).to_string(), "3M1I1M2S");
+        assert_eq!(cigar.clipped(3).to_string(), "3M4S");
+        // A deletion reaching the clip point is dropped rather than left bordering the clip.
+        let cigar = self::cigar(&[(2, b'M'), (2, b'D'), (3, b'M')]);
+        assert_eq!(cigar.clipped(4).to_string(), "2M3S");
+        assert_eq!(cigar.clipped(5).to_string(), "2M2D1M2S");
+    }
+
+    #[test]
+    fn reversed_reverses_the_operations() {
+        assert_eq!(cigar(&[(2, b'S'), (5, b'M'), (1, b'I')]).reversed().to_string(), "1I5M2S");
+    }
+
+    #[test]
+    fn blocks_split_at_introns() {
+        let alignment = Alignment {
+            contig: 0,
+            position: 10,
+            reverse: false,
+            cigar: cigar(&[(1, b'S'), (5, b'M'), (100, b'N'), (2, b'M'), (1, b'D'), (3, b'M')]),
+            edit_distance: 0,
+            haplotype: 0,
+        };
+        assert_eq!(alignment.blocks(), vec![(10, 15), (115, 121)]);
+        assert_eq!(alignment.end(), 121);
+    }
+
+    #[test]
+    fn clip_read_start_clips_the_five_prime_end() {
+        let mut alignment = Alignment { contig: 0, position: 0, reverse: false, cigar: Cigar::matched(5), edit_distance: 0, haplotype: 0 };
+        alignment.clip_read_start(3);
+        assert_eq!(alignment.cigar.to_string(), "3S5M");
+        alignment.reverse = true;
+        alignment.cigar = Cigar::matched(5);
+        alignment.clip_read_start(3);
+        assert_eq!(alignment.cigar.to_string(), "5M3S");
+    }
+}
@@ -0,0 +1,177 @@
+use rand::Rng;
+use std::io::BufRead;
+use std::path::Path;
+
+use crate::error::FastqGenError;
+use crate::bed::read_bed;
+use crate::reference::{Reference, open_text};
+use crate::seq::reverse_complement;
+
+/// Length of the primers assumed at either end of an amplicon read from a BED file.
+pub const BED_PRIMER_LENGTH: usize = 20;
+/// Longest amplicon searched for between the primers of a primer pair.
+pub const MAX_AMPLICON_LENGTH: usize = 2000;
+
+/// One amplicon of a targeted panel: the reference region between (and including)
+/// its primers.
+#[derive(Debug, Clone)]
+pub struct Amplicon {
+    pub name: String,
+    pub contig: usize,
+    /// 0-based, half-open extent on the forward strand of the reference.
+    pub start: usize,
+    pub end: usize,
+    /// Forward primer, 5' to 3', as found at `start`.
+    pub forward_primer: Vec<u8>,
+    /// Reverse primer, 5' to 3', whose reverse complement ends at `end`.
+    pub reverse_primer: Vec<u8>,
+}
+
+/// What an amplicon library molecule is drawn from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PanelTarget {
+    /// The amplicon at this index of the panel.
+    Amplicon(usize),
+    /// A random stretch of the reference, as from mispriming.
+    OffTarget,
+    /// The primers of the amplicon at this index ligated to each other.
+    PrimerDimer(usize),
+}
+
+/// The amplicons of a targeted panel and how often a molecule misses them. Every
+/// amplicon is equally likely.
+#[derive(Debug, Clone)]
+pub struct AmpliconPanel {
+    amplicons: Vec<Amplicon>,
+    off_target_rate: f64,
+    primer_dimer_rate: f64,
+}
+
+impl AmpliconPanel {
+    pub fn new(amplicons: Vec<Amplicon>, off_target_rate: f64, primer_dimer_rate: f64) -> Result<Self, FastqGenError> {
+        if amplicons.is_empty() {
+            return Err(FastqGenError::invalid("An amplicon panel needs at least one amplicon."));
+        }
+        if off_target_rate < 0.0 || primer_dimer_rate < 0.0 || off_target_rate + primer_dimer_rate > 1.0 {
+            return Err(FastqGenError::invalid("Off-target and primer-dimer rates must be non-negative and sum to at most 1."));
+        }
+        Ok(AmpliconPanel { amplicons, off_target_rate, primer_dimer_rate })
+    }
+
+    pub fn amplicons(&self) -> &[Amplicon] {
+        &self.amplicons
+    }
+
+    /// Draws the target of one molecule.
+    pub fn sample(&self, rng: &mut impl Rng) -> PanelTarget {
+        let draw: f64 = rng.random();
+        let amplicon = rng.random_range(0..self.amplicons.len());
+        if draw < self.off_target_rate {
+            PanelTarget::OffTarget
+        } else if draw < self.off_target_rate + self.primer_dimer_rate {
+            PanelTarget::PrimerDimer(amplicon)
+        } else {
+            PanelTarget::Amplicon(amplicon)
+        }
+    }
+
+    /// A primer dimer of the amplicon at `index`: its forward primer followed by
+    /// the reverse complement of its reverse primer, so it reads like a minimal
+    /// amplicon.
+    pub fn primer_dimer(&self, index: usize) -> Vec<u8> {
+        let amplicon = &self.amplicons[index];
+        let mut dimer = amplicon.forward_primer.clone();
+        dimer.extend(reverse_complement(&amplicon.reverse_primer));
+        dimer
+    }
+}
+
+/// Reads amplicons from a BED file (see [`crate::read_bed`]). The primers are
+/// taken to be the first and last [`BED_PRIMER_LENGTH`] bases of each amplicon,
+/// which is named after the BED name column or else its region.
+pub fn read_amplicon_bed(path: &Path, reference: &Reference) -> Result<Vec<Amplicon>, FastqGenError> {
+    let amplicons = read_bed(path, reference, "amplicon BED")?
+        .into_iter()
+        .map(|interval| {
+            let seq = reference.contig_seq(interval.contig);
+            let (start, end) = (interval.start, interval.end);
+            let primer_length = BED_PRIMER_LENGTH.min((end - start) / 2);
+            Amplicon {
+                name: interval.name.unwrap_or_else(|| format!("{}:{}-{}", reference.contig_name(interval.contig), start + 1, end)),
+                contig: interval.contig,
+                start,
+                end,
+                forward_primer: seq[start..start + primer_length].to_vec(),
+                reverse_primer: reverse_complement(&seq[end - primer_length..end]),
+            }
+        })
+        .collect();
+    Ok(amplicons)
+}
+
+/// Reads primer pairs from `name<TAB>forward<TAB>reverse` lines (tabs, commas or
+/// spaces separate the columns), with both primers 5' to 3', and places each
+/// amplicon on the reference: from the first exact match of the forward primer on
+/// either strand to the nearest downstream match of the reverse primer on the
+/// other, at most [`MAX_AMPLICON_LENGTH`] bases in all. A header line whose primer
+/// columns are not bases is skipped.
+pub fn read_primer_pairs(path: &Path, reference: &Reference) -> Result<Vec<Amplicon>, FastqGenError> {
+    let reader = open_text(path)
+        .map_err(|e| FastqGenError::io(format!("Failed to open primer table {}", path.display()), e))?;
+    let mut amplicons = Vec::new();
+
+    for (line_number, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        let location = format!("{}:{}", path.display(), line_number + 1);
+        let fields: Vec<&str> = line.split(['\t', ',', ' ']).filter(|field| !field.is_empty()).collect();
+        if fields.len() < 3 {
+            return Err(FastqGenError::parse(format!("{}: expected a name, a forward primer and a reverse primer.", location)));
+        }
+        let is_primer = |field: &str| field.bytes().all(|base| b"ACGTacgt".contains(&base));
+        if !is_primer(fields[1]) || !is_primer(fields[2]) {
+            if line_number == 0 {
+                continue;
+            }
+            return Err(FastqGenError::parse(format!("{}: primers may only contain A, C, G and T.", location)));
+        }
+        let forward = fields[1].to_ascii_uppercase().into_bytes();
+        let reverse = fields[2].to_ascii_uppercase().into_bytes();
+        let (contig, start, end) = locate_amplicon(reference, &forward, &reverse)
+            .ok_or_else(|| FastqGenError::parse(format!("{}: primers of {} do not flank a reference region of at most {} bases.", location, fields[0], MAX_AMPLICON_LENGTH)))?;
+        // Keep the primers in the orientation of the reference forward strand.
+        let (forward_primer, reverse_primer) = if reference.contig_seq(contig)[start..].starts_with(&forward) {
+            (forward, reverse)
+        } else {
+            (reverse, forward)
+        };
+        amplicons.push(Amplicon { name: fields[0].to_string(), contig, start, end, forward_primer, reverse_primer });
+    }
+    Ok(amplicons)
+}
+
+/// The first region of the reference that starts with one primer and ends with
+/// the reverse complement of the other.
+fn locate_amplicon(reference: &Reference, forward: &[u8], reverse: &[u8]) -> Option<(usize, usize, usize)> {
+    let reverse_site = reverse_complement(reverse);
+    let forward_site = reverse_complement(forward);
+    for (contig, _) in reference.contigs().enumerate() {
+        let seq = reference.contig_seq(contig);
+        for (left, right) in [(forward, &reverse_site), (reverse, &forward_site)] {
+            for start in find_all(seq, left) {
+                let window = &seq[start..seq.len().min(start + MAX_AMPLICON_LENGTH)];
+                if let Some(offset) = find_all(window, right).find(|&offset| offset >= left.len()) {
+                    return Some((contig, start, start + offset + right.len()));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Start positions of every exact occurrence of `needle` in `haystack`.
+fn find_all<'a>(haystack: &'a [u8], needle: &'a [u8]) -> impl Iterator<Item = usize> + 'a {
+    haystack.windows(needle.len()).enumerate().filter(move |(_, window)| *window == needle).map(|(position, _)| position)
+}
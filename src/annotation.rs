@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::path::Path;
+
+use crate::error::FastqGenError;
+use crate::alignment::{Alignment, Cigar};
+use crate::reference::{Reference, open_text};
+use crate::seq::reverse_complement;
+
+/// A transcript of a gene annotation: its exons on one genome contig.
+#[derive(Debug, Clone)]
+pub struct Transcript {
+    pub id: String,
+    pub gene_id: String,
+    /// Index of the genome contig the transcript lies on.
+    pub contig: usize,
+    /// Whether the transcript is on the reverse strand.
+    pub reverse: bool,
+    /// 0-based, half-open exon coordinates, sorted by position.
+    pub exons: Vec<(usize, usize)>,
+}
+
+impl Transcript {
+    /// Length of the spliced transcript.
+    pub fn len(&self) -> usize {
+        self.exons.iter().map(|(start, end)| end - start).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The spliced sequence of the transcript, 5' to 3'.
+    pub fn spliced_seq(&self, genome: &Reference) -> Vec<u8> {
+        let contig = genome.contig_seq(self.contig);
+        let seq: Vec<u8> = self.exons.iter().flat_map(|&(start, end)| contig[start..end].iter().copied()).collect();
+        if self.reverse { reverse_complement(&seq) } else { seq }
+    }
+
+    /// Converts an alignment to the spliced transcript into the equivalent alignment
+    /// to the genome, with an `N` for every intron the read spans.
+    pub fn to_genome(&self, alignment: &Alignment) -> Alignment {
+        // Work in genome orientation, where the transcript's exons read left to right.
+        let (position, cigar) = if self.reverse {
+            (self.len() - alignment.end(), alignment.cigar.reversed())
+        } else {
+            (alignment.position, alignment.cigar.clone())
+        };
+
+        // Spliced offset of the start of each exon.
+        let mut offsets = Vec::with_capacity(self.exons.len() + 1);
+        offsets.push(0);
+        for (start, end) in &self.exons {
+            offsets.push(offsets.last().unwrap() + end - start);
+        }
+        let mut exon = offsets.partition_point(|&offset| offset <= position) - 1;
+        let genome_position = self.exons[exon].0 + position - offsets[exon];
+
+        let mut spliced = Cigar::new();
+        let mut offset = position;
+        for &(length, op) in cigar.ops() {
+            let mut remaining = length as usize;
+            if !matches!(op, b'M' | b'D' | b'N' | b'=' | b'X') {
+                spliced.push_run(remaining, op);
+                continue;
+            }
+            while remaining > 0 {
+                if offset == offsets[exon + 1] {
+                    spliced.push_run(self.exons[exon + 1].0 - self.exons[exon].1, b'N');
+                    exon += 1;
+                }
+                let run = remaining.min(offsets[exon + 1] - offset);
+                spliced.push_run(run, op);
+                offset += run;
+                remaining -= run;
+            }
+        }
+
+        Alignment {
+            contig: self.contig,
+            position: genome_position,
+            reverse: alignment.reverse != self.reverse,
+            cigar: spliced,
+            edit_distance: alignment.edit_distance,
+            haplotype: alignment.haplotype,
+        }
+    }
+}
+
+/// Reads the transcripts of a GTF (or GFF2) annotation, optionally gzipped, from
+/// its `exon` features and their `transcript_id` and `gene_id` attributes.
+/// Transcripts are returned in the order they first appear.
+pub fn read_gtf(path: &Path, genome: &Reference) -> Result<Vec<Transcript>, FastqGenError> {
+    let reader = open_text(path)
+        .map_err(|e| FastqGenError::io(format!("Failed to open GTF {}", path.display()), e))?;
+    let mut transcripts: Vec<Transcript> = Vec::new();
+    let mut by_id: HashMap<String, usize> = HashMap::new();
+
+    for (line_number, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        let location = format!("{}:{}", path.display(), line_number + 1);
+        let fields: Vec<&str> = line.trim_end().split('\t').collect();
+        if fields.len() < 9 {
+            return Err(FastqGenError::parse(format!("{}: expected 9 tab-separated columns.", location)));
+        }
+        if fields[2] != "exon" {
+            continue;
+        }
+
+        let contig = genome.contig_index(fields[0])
+            .ok_or_else(|| FastqGenError::parse(format!("{}: contig {} is not in the genome.", location, fields[0])))?;
+        let start = fields[3].parse::<usize>().ok().filter(|&start| start > 0)
+            .ok_or_else(|| FastqGenError::parse(format!("{}: invalid start {}.", location, fields[3])))? - 1;
+        let end = fields[4].parse::<usize>().ok().filter(|&end| end > start && end <= genome.contig_seq(contig).len())
+            .ok_or_else(|| FastqGenError::parse(format!("{}: invalid end {} for {} of length {}.", location, fields[4], fields[0], genome.contig_seq(contig).len())))?;
+        let reverse = match fields[6] {
+            "+" => false,
+            "-" => true,
+            strand => return Err(FastqGenError::parse(format!("{}: exon strand must be + or -, found {}.", location, strand))),
+        };
+        let transcript_id = gtf_attribute(fields[8], "transcript_id")
+            .ok_or_else(|| FastqGenError::parse(format!("{}: exon has no transcript_id.", location)))?;
+        let gene_id = gtf_attribute(fields[8], "gene_id").unwrap_or(transcript_id);
+
+        let index = *by_id.entry(transcript_id.to_string()).or_insert_with(|| {
+            transcripts.push(Transcript { id: transcript_id.to_string(), gene_id: gene_id.to_string(), contig, reverse, exons: Vec::new() });
+            transcripts.len() - 1
+        });
+        let transcript = &mut transcripts[index];
+        if transcript.contig != contig || transcript.reverse != reverse {
+            return Err(FastqGenError::parse(format!("{}: exons of {} lie on different contigs or strands.", location, transcript_id)));
+        }
+        transcript.exons.push((start, end));
+    }
+
+    for transcript in &mut transcripts {
+        transcript.exons.sort_unstable();
+        if transcript.exons.windows(2).any(|pair| pair[0].1 > pair[1].0) {
+            return Err(FastqGenError::parse(format!("{}: exons of {} overlap.", path.display(), transcript.id)));
+        }
+    }
+    if transcripts.is_empty() {
+        return Err(FastqGenError::parse(format!("GTF {} has no exon features.", path.display())));
+    }
+    Ok(transcripts)
+}
+
+/// The value of `key` in a GTF attribute column such as
+/// `gene_id "G1"; transcript_id "T1";`.
+fn gtf_attribute<'a>(attributes: &'a str, key: &str) -> Option<&'a str> {
+    attributes.split(';').find_map(|attribute| {
+        let (name, value) = attribute.trim().split_once(char::is_whitespace)?;
+        (name == key).then(|| value.trim().trim_matches('"'))
+    })
+}
@@ -0,0 +1,94 @@
+use rand::Rng;
+use rand::distr::Distribution;
+use rand::distr::weighted::WeightedIndex;
+
+use crate::error::FastqGenError;
+use crate::bed::BedInterval;
+use crate::reference::Reference;
+
+/// Shortest fragment Tn5 leaves between two insertions.
+const MIN_FRAGMENT_LENGTH: usize = 20;
+
+/// Bases from the start of a forward-strand read to the Tn5 insertion site it
+/// came from. With [`TN5_REVERSE_SHIFT`] it spans the 9 bases Tn5 duplicates at
+/// every insertion.
+pub const TN5_FORWARD_SHIFT: usize = 4;
+/// Bases from a Tn5 insertion site to the end of the reverse-strand read it
+/// left.
+pub const TN5_REVERSE_SHIFT: usize = 5;
+
+/// An ATAC-seq library: Tn5 transposase inserts adapters into open chromatin,
+/// so most fragments have an end in a peak and the rest come from anywhere on
+/// the reference.
+///
+/// Fragment lengths mix a nucleosome-free class of about 50 bases with classes
+/// spanning one, two and three nucleosomes, at multiples of the nucleosome
+/// spacing, in decreasing proportions. In-peak fragments start or end at a Tn5
+/// insertion site drawn uniformly from the peaks, shifted as Tn5's staggered
+/// cut leaves reads 4 bases before it on the forward strand and 5 bases after
+/// it on the reverse strand.
+#[derive(Debug, Clone)]
+pub struct AtacLibrary {
+    peaks: Vec<BedInterval>,
+    weights: WeightedIndex<usize>,
+    in_peaks: f64,
+    lengths: WeightedIndex<f64>,
+}
+
+impl AtacLibrary {
+    /// Draws a fraction `in_peaks` of fragments from `peaks` (the FRiP), with
+    /// nucleosomes `nucleosome_spacing` bases apart.
+    pub fn new(peaks: Vec<BedInterval>, in_peaks: f64, nucleosome_spacing: usize) -> Result<Self, FastqGenError> {
+        if !(0.0..=1.0).contains(&in_peaks) {
+            return Err(FastqGenError::invalid("The fraction of fragments in peaks must be between 0 and 1."));
+        }
+        if nucleosome_spacing < MIN_FRAGMENT_LENGTH {
+            return Err(FastqGenError::invalid(format!("The nucleosome spacing must be at least {} bases.", MIN_FRAGMENT_LENGTH)));
+        }
+        let weights = WeightedIndex::new(peaks.iter().map(|peak| peak.end - peak.start))
+            .map_err(|_| FastqGenError::invalid("An ATAC-seq library needs at least one peak."))?;
+        // (mean, standard deviation, share) of the nucleosome-free class and of
+        // the mono-, di- and tri-nucleosomal ones.
+        let spacing = nucleosome_spacing as f64;
+        let classes = [(50.0, 20.0, 0.5), (spacing, 30.0, 0.3), (2.0 * spacing, 40.0, 0.15), (3.0 * spacing, 50.0, 0.05)];
+        let density = |length: f64| -> f64 {
+            classes.iter()
+                .map(|&(mean, sd, share)| share / sd * (-0.5 * ((length - mean) / sd).powi(2)).exp())
+                .sum()
+        };
+        let lengths = WeightedIndex::new((MIN_FRAGMENT_LENGTH..=4 * nucleosome_spacing).map(|length| density(length as f64)))
+            .map_err(|e| FastqGenError::invalid(format!("Invalid fragment length distribution: {}", e)))?;
+        Ok(AtacLibrary { peaks, weights, in_peaks, lengths })
+    }
+
+    /// Total length of the peaks, counting overlapping bases once per peak.
+    pub fn total_length(&self) -> usize {
+        self.peaks.iter().map(|peak| peak.end - peak.start).sum()
+    }
+
+    /// Draws the length of a fragment between two Tn5 insertions, including the
+    /// bases each insertion duplicates.
+    pub fn fragment_length(&self, rng: &mut impl Rng) -> usize {
+        MIN_FRAGMENT_LENGTH + self.lengths.sample(rng)
+    }
+
+    /// Draws the reference region (contig, start and end) of an in-peak fragment
+    /// of `length` bases, one of whose insertion sites lies in a peak, or `None`
+    /// for a background fragment.
+    pub fn sample(&self, rng: &mut impl Rng, reference: &Reference, length: usize) -> Option<(usize, usize, usize)> {
+        if !rng.random_bool(self.in_peaks) {
+            return None;
+        }
+        let peak = &self.peaks[self.weights.sample(rng)];
+        let contig_length = reference.contig_seq(peak.contig).len();
+        let length = length.clamp(1, contig_length);
+        let insertion = rng.random_range(peak.start..peak.end);
+        let start = if rng.random_bool(0.5) {
+            insertion.saturating_sub(TN5_FORWARD_SHIFT)
+        } else {
+            (insertion + TN5_REVERSE_SHIFT).saturating_sub(length)
+        };
+        let start = start.min(contig_length - length);
+        Some((peak.contig, start, start + length))
+    }
+}
@@ -0,0 +1,275 @@
+use std::io::{self, Write};
+
+use flate2::Compression;
+use flate2::Crc;
+use flate2::write::DeflateEncoder;
+
+use crate::error::FastqGenError;
+
+/// Largest amount of uncompressed data in one BGZF block, leaving room for
+/// incompressible input to fit in the 64 KiB block limit.
+pub(crate) const BGZF_BLOCK_SIZE: usize = 0xff00;
+
+/// The empty block that marks the end of a BGZF file.
+pub(crate) const BGZF_EOF: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0, 0, 0, 0, 0, 0xff, 0x06, 0, b'B', b'C', 0x02, 0, 0x1b, 0, 0x03, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+];
+
+/// Longest read name a BAM record can hold: its length, with the terminating NUL,
+/// is stored in one byte.
+pub const MAX_READ_NAME_LENGTH: usize = 254;
+
+/// Unmapped-read flags for uBAM records.
+const PAIRED: u16 = 0x1;
+const UNMAPPED: u16 = 0x4;
+const MATE_UNMAPPED: u16 = 0x8;
+const FIRST_IN_PAIR: u16 = 0x40;
+const SECOND_IN_PAIR: u16 = 0x80;
+
+/// Writes BGZF, the blocked gzip format BAM files are compressed with. Every block
+/// is a complete gzip member, so standard gzip tools can also read the output.
+pub struct BgzfWriter<W: Write> {
+    inner: W,
+    buffer: Vec<u8>,
+    level: Compression,
+}
+
+impl<W: Write> BgzfWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self::with_level(inner, Compression::default())
+    }
+
+    pub fn with_level(inner: W, level: Compression) -> Self {
+        BgzfWriter { inner, buffer: Vec::with_capacity(BGZF_BLOCK_SIZE), level }
+    }
+
+    fn write_block(&mut self) -> io::Result<()> {
+        self.inner.write_all(&bgzf_block(&self.buffer, self.level)?)?;
+        self.buffer.clear();
+        Ok(())
+    }
+
+    /// Writes any buffered data and the end-of-file marker, returning the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.buffer.is_empty() {
+            self.write_block()?;
+        }
+        self.inner.write_all(&BGZF_EOF)?;
+        Ok(self.inner)
+    }
+}
+
+/// Compresses `data`, at most [`BGZF_BLOCK_SIZE`] bytes, into one BGZF block.
+pub(crate) fn bgzf_block(data: &[u8], level: Compression) -> io::Result<Vec<u8>> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), level);
+    encoder.write_all(data)?;
+    let compressed = encoder.finish()?;
+    let mut crc = Crc::new();
+    crc.update(data);
+
+    let block_size = (compressed.len() + 25) as u16;
+    let mut block = Vec::with_capacity(compressed.len() + 26);
+    block.extend_from_slice(&[0x1f, 0x8b, 0x08, 0x04, 0, 0, 0, 0, 0, 0xff, 0x06, 0, b'B', b'C', 0x02, 0]);
+    block.extend_from_slice(&block_size.to_le_bytes());
+    block.extend_from_slice(&compressed);
+    block.extend_from_slice(&crc.sum().to_le_bytes());
+    block.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    Ok(block)
+}
+
+impl<W: Write> Write for BgzfWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let length = buf.len().min(BGZF_BLOCK_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..length]);
+        if self.buffer.len() == BGZF_BLOCK_SIZE {
+            self.write_block()?;
+        }
+        Ok(length)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Writes the header of an unaligned BAM whose reads all belong to read group
+/// `read_group` of sample `sample`.
+pub fn write_ubam_header(out: &mut impl Write, read_group: &str, sample: &str) -> io::Result<()> {
+    let text = format!(
+        "@HD\tVN:1.6\tSO:unsorted\n@RG\tID:{}\tSM:{}\tPL:ILLUMINA\n@PG\tID:fastqgen\tPN:fastqgen\tVN:{}\n",
+        read_group,
+        sample,
+        env!("CARGO_PKG_VERSION")
+    );
+    out.write_all(b"BAM\x01")?;
+    out.write_all(&(text.len() as u32).to_le_bytes())?;
+    out.write_all(text.as_bytes())?;
+    // No reference sequences.
+    out.write_all(&0u32.to_le_bytes())
+}
+
+/// Which read of a template an unaligned BAM record holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UbamMate {
+    Single,
+    First,
+    Second,
+}
+
+/// One record of an unaligned BAM.
+#[derive(Debug, Clone)]
+pub struct UbamRecord<'a> {
+    pub name: &'a str,
+    pub mate: UbamMate,
+    pub seq: &'a [u8],
+    /// ASCII-encoded qualities, with `quality_offset` added to each Phred score.
+    pub quality: &'a [u8],
+    pub quality_offset: u8,
+    /// String (`Z`) tags such as `RG`, `RX` or `BC`.
+    pub tags: &'a [(&'a str, &'a str)],
+}
+
+impl UbamRecord<'_> {
+    /// Appends the encoded record to `out`. Fails if the name is longer than
+    /// BAM allows.
+    pub fn write(&self, out: &mut Vec<u8>) -> Result<(), FastqGenError> {
+        let (name, seq) = (self.name, self.seq);
+        if name.len() > MAX_READ_NAME_LENGTH {
+            return Err(FastqGenError::invalid(format!("Read name {} is {} bytes long; BAM allows at most {}.", name, name.len(), MAX_READ_NAME_LENGTH)));
+        }
+        let flag = match self.mate {
+            UbamMate::Single => UNMAPPED,
+            UbamMate::First => PAIRED | UNMAPPED | MATE_UNMAPPED | FIRST_IN_PAIR,
+            UbamMate::Second => PAIRED | UNMAPPED | MATE_UNMAPPED | SECOND_IN_PAIR,
+        };
+
+        let start = out.len();
+        out.extend_from_slice(&[0; 4]);
+        out.extend_from_slice(&(-1i32).to_le_bytes());
+        out.extend_from_slice(&(-1i32).to_le_bytes());
+        out.push(name.len() as u8 + 1);
+        out.push(0);
+        // bin for an unplaced read: reg2bin(-1, 0).
+        out.extend_from_slice(&4680u16.to_le_bytes());
+        out.extend_from_slice(&0u16.to_le_bytes());
+        out.extend_from_slice(&flag.to_le_bytes());
+        out.extend_from_slice(&(seq.len() as u32).to_le_bytes());
+        out.extend_from_slice(&(-1i32).to_le_bytes());
+        out.extend_from_slice(&(-1i32).to_le_bytes());
+        out.extend_from_slice(&0i32.to_le_bytes());
+        out.extend_from_slice(name.as_bytes());
+        out.push(0);
+        for pair in seq.chunks(2) {
+            let high = encode_base(pair[0]) << 4;
+            let low = pair.get(1).map_or(0, |&base| encode_base(base));
+            out.push(high | low);
+        }
+        out.extend(self.quality.iter().map(|&score| score.saturating_sub(self.quality_offset)));
+
+        for (tag, value) in self.tags {
+            out.extend_from_slice(tag.as_bytes());
+            out.push(b'Z');
+            out.extend_from_slice(value.as_bytes());
+            out.push(0);
+        }
+
+        let block_size = (out.len() - start - 4) as u32;
+        out[start..start + 4].copy_from_slice(&block_size.to_le_bytes());
+        Ok(())
+    }
+}
+
+/// The 4-bit BAM code of a base in `=ACMGRSVTWYHKDBN`.
+fn encode_base(base: u8) -> u8 {
+    match base.to_ascii_uppercase() {
+        b'=' => 0,
+        b'A' => 1,
+        b'C' => 2,
+        b'M' => 3,
+        b'G' => 4,
+        b'R' => 5,
+        b'S' => 6,
+        b'V' => 7,
+        b'T' => 8,
+        b'W' => 9,
+        b'Y' => 10,
+        b'H' => 11,
+        b'K' => 12,
+        b'D' => 13,
+        b'B' => 14,
+        _ => 15,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::MultiGzDecoder;
+    use std::io::Read;
+
+    fn record(name: &str) -> UbamRecord<'_> {
+        UbamRecord { name, mate: UbamMate::Single, seq: b"ACGTN", quality: b"IIIII", quality_offset: 33, tags: &[] }
+    }
+
+    #[test]
+    fn rejects_names_too_long_for_bam() {
+        let mut out = Vec::new();
+        assert!(record(&"x".repeat(MAX_READ_NAME_LENGTH)).write(&mut out).is_ok());
+        assert!(record(&"x".repeat(MAX_READ_NAME_LENGTH + 1)).write(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn bgzf_blocks_decompress_to_the_input() {
+        let data: Vec<u8> = (0..3 * BGZF_BLOCK_SIZE / 2).map(|i| (i * 7 % 251) as u8).collect();
+        let mut writer = BgzfWriter::new(Vec::new());
+        writer.write_all(&data).unwrap();
+        let compressed = writer.finish().unwrap();
+        assert!(compressed.ends_with(&BGZF_EOF));
+
+        // Every block records its own size, less one, in the BC extra field.
+        let mut offset = 0;
+        let mut blocks = 0;
+        while offset < compressed.len() {
+            assert_eq!(&compressed[offset + 12..offset + 14], b"BC");
+            offset += u16::from_le_bytes([compressed[offset + 16], compressed[offset + 17]]) as usize + 1;
+            blocks += 1;
+        }
+        assert_eq!((offset, blocks), (compressed.len(), 3));
+
+        let mut decompressed = Vec::new();
+        MultiGzDecoder::new(&compressed[..]).read_to_end(&mut decompressed).unwrap();
+        assert_eq!(decompressed, data);
+    }
+
+    #[test]
+    fn writes_the_ubam_header() {
+        let mut out = Vec::new();
+        write_ubam_header(&mut out, "rg1", "sample").unwrap();
+        assert_eq!(&out[..4], b"BAM\x01");
+        let length = u32::from_le_bytes(out[4..8].try_into().unwrap()) as usize;
+        let text = std::str::from_utf8(&out[8..8 + length]).unwrap();
+        assert!(text.contains("@RG\tID:rg1\tSM:sample\t"));
+        assert_eq!(&out[8 + length..], &[0; 4]);
+    }
+
+    #[test]
+    fn encodes_unmapped_records() {
+        let tags = [("RG", "rg1")];
+        let record = UbamRecord { mate: UbamMate::Second, tags: &tags, ..record("read") };
+        let mut out = Vec::new();
+        record.write(&mut out).unwrap();
+
+        let int = |at: usize| i32::from_le_bytes(out[at..at + 4].try_into().unwrap());
+        let short = |at: usize| u16::from_le_bytes(out[at..at + 2].try_into().unwrap());
+        assert_eq!(int(0) as usize, out.len() - 4);
+        assert_eq!((int(4), int(8)), (-1, -1));
+        assert_eq!((out[12], short(14)), (5, 4680));
+        assert_eq!(short(18), PAIRED | UNMAPPED | MATE_UNMAPPED | SECOND_IN_PAIR);
+        assert_eq!(int(20), 5);
+        assert_eq!(&out[36..41], b"read\0");
+        // ACGTN packed two bases to a byte, then qualities without the offset.
+        assert_eq!(&out[41..44], &[0x12, 0x48, 0xf0]);
+        assert_eq!(&out[44..49], &[40; 5]);
+        assert_eq!(&out[49..], b"RGZrg1\0");
+    }
+}
@@ -0,0 +1,296 @@
+use rand::Rng;
+use rand::prelude::IndexedRandom;
+use std::collections::HashSet;
+use std::io::BufRead;
+use std::path::Path;
+
+use crate::error::FastqGenError;
+use crate::reference::open_text;
+
+/// One sample of a multiplexed run and its index sequences.
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub name: String,
+    /// The i7 index, read as index read 1.
+    pub i7: Vec<u8>,
+    /// The i5 index of dual-indexed libraries, read as index read 2.
+    pub i5: Option<Vec<u8>>,
+}
+
+impl Sample {
+    /// The observed barcode as written in Casava read comments: `i7` or `i7+i5`.
+    pub fn barcode_string(i7: &[u8], i5: Option<&[u8]>) -> String {
+        let mut barcode = String::from_utf8_lossy(i7).into_owned();
+        if let Some(i5) = i5 {
+            barcode.push('+');
+            barcode.push_str(&String::from_utf8_lossy(i5));
+        }
+        barcode
+    }
+}
+
+/// Reads a sample sheet: a CSV of `sample,i7[,i5]` lines, optionally with a
+/// header line whose first column is `sample` (or `Sample_ID`). Every sample
+/// needs an i7 index of the same length; the i5 index is given for all samples or
+/// none. Sample names become file names, so they must be unique and must not
+/// contain path separators.
+pub fn read_sample_sheet(path: &Path) -> Result<Vec<Sample>, FastqGenError> {
+    let reader = open_text(path)
+        .map_err(|e| FastqGenError::io(format!("Failed to open sample sheet {}", path.display()), e))?;
+    let mut samples: Vec<Sample> = Vec::new();
+    let mut names = HashSet::new();
+
+    for (line_number, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let location = format!("{}:{}", path.display(), line_number + 1);
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        if samples.is_empty() && ["sample", "sample_id"].contains(&fields[0].to_ascii_lowercase().as_str()) {
+            continue;
+        }
+        if !(2..=3).contains(&fields.len()) {
+            return Err(FastqGenError::parse(format!("{}: expected sample,i7[,i5] but found {} columns.", location, fields.len())));
+        }
+
+        let name = fields[0];
+        if name.is_empty() || name.contains(['/', '\\']) {
+            return Err(FastqGenError::parse(format!("{}: invalid sample name {:?}.", location, name)));
+        }
+        if !names.insert(name.to_string()) {
+            return Err(FastqGenError::parse(format!("{}: duplicate sample name {}.", location, name)));
+        }
+        let index = |column: usize| -> Result<Option<Vec<u8>>, FastqGenError> {
+            match fields.get(column).filter(|field| !field.is_empty()) {
+                None => Ok(None),
+                Some(field) if field.bytes().all(|base| b"ACGTacgt".contains(&base)) => Ok(Some(field.to_ascii_uppercase().into_bytes())),
+                Some(field) => Err(FastqGenError::parse(format!("{}: index {} may only contain A, C, G and T.", location, field))),
+            }
+        };
+        let i7 = index(1)?.ok_or_else(|| FastqGenError::parse(format!("{}: sample {} has no i7 index.", location, name)))?;
+        let i5 = index(2)?;
+
+        if let Some(first) = samples.first()
+            && (first.i7.len() != i7.len() || first.i5.as_ref().map(Vec::len) != i5.as_ref().map(Vec::len))
+        {
+            return Err(FastqGenError::parse(format!("{}: every sample needs indices of the same lengths as {}.", location, first.name)));
+        }
+        samples.push(Sample { name: name.to_string(), i7, i5 });
+    }
+
+    if samples.is_empty() {
+        return Err(FastqGenError::parse(format!("Sample sheet {} lists no samples.", path.display())));
+    }
+    Ok(samples)
+}
+
+/// Reads a cell barcode whitelist, optionally gzipped, of one barcode per line,
+/// such as the `3M-february-2018.txt` list of 10x Genomics 3' v3 chemistry.
+/// Barcodes must be unique and all of the same length.
+pub fn read_whitelist(path: &Path) -> Result<Vec<Vec<u8>>, FastqGenError> {
+    let reader = open_text(path)
+        .map_err(|e| FastqGenError::io(format!("Failed to open whitelist {}", path.display()), e))?;
+    let mut barcodes: Vec<Vec<u8>> = Vec::new();
+    let mut seen = HashSet::new();
+
+    for (line_number, line) in reader.lines().enumerate() {
+        let line = line?;
+        let barcode = line.trim();
+        if barcode.is_empty() {
+            continue;
+        }
+        let location = format!("{}:{}", path.display(), line_number + 1);
+        if !barcode.bytes().all(|base| b"ACGTacgt".contains(&base)) {
+            return Err(FastqGenError::parse(format!("{}: barcode {} may only contain A, C, G and T.", location, barcode)));
+        }
+        let barcode = barcode.to_ascii_uppercase().into_bytes();
+        if let Some(first) = barcodes.first()
+            && first.len() != barcode.len()
+        {
+            return Err(FastqGenError::parse(format!("{}: every barcode needs the length of the first, {}.", location, first.len())));
+        }
+        if seen.insert(barcode.clone()) {
+            barcodes.push(barcode);
+        }
+    }
+
+    if barcodes.is_empty() {
+        return Err(FastqGenError::parse(format!("Whitelist {} lists no barcodes.", path.display())));
+    }
+    Ok(barcodes)
+}
+
+/// The barcodes a downstream tool corrects sequenced barcodes against.
+#[derive(Debug, Clone)]
+pub struct BarcodeWhitelist {
+    barcodes: HashSet<Vec<u8>>,
+}
+
+/// Sequenced barcodes by how far sequencing errors took them from the whitelist.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BarcodeErrorCounts {
+    pub barcodes: u64,
+    /// Barcodes with at least one substitution.
+    pub mutated: u64,
+    /// Mutated barcodes within Hamming distance 1 of a whitelist barcode.
+    pub within_one: u64,
+    /// Mutated barcodes within Hamming distance 1 of their true barcode only, which
+    /// correction therefore restores.
+    pub correctable: u64,
+}
+
+impl BarcodeWhitelist {
+    pub fn new(barcodes: impl IntoIterator<Item = Vec<u8>>) -> Self {
+        BarcodeWhitelist { barcodes: barcodes.into_iter().collect() }
+    }
+
+    /// Counts a barcode sequenced as `observed` from `truth`.
+    pub fn record(&self, truth: &[u8], observed: &[u8], counts: &mut BarcodeErrorCounts) {
+        counts.barcodes += 1;
+        if observed == truth {
+            return;
+        }
+        counts.mutated += 1;
+        let (matches, first) = self.neighbors(observed);
+        if matches > 0 {
+            counts.within_one += 1;
+        }
+        if matches == 1 && first.as_deref() == Some(truth) {
+            counts.correctable += 1;
+        }
+    }
+
+    /// Number of whitelist barcodes within Hamming distance 1 of `barcode`, up to
+    /// two, and the first of them.
+    fn neighbors(&self, barcode: &[u8]) -> (usize, Option<Vec<u8>>) {
+        let mut candidate = barcode.to_vec();
+        let (mut matches, mut first) = (0, None);
+        if self.barcodes.contains(&candidate) {
+            matches += 1;
+            first = Some(candidate.clone());
+        }
+        for position in 0..candidate.len() {
+            let base = candidate[position];
+            for &alt in b"ACGT".iter().filter(|&&alt| alt != base) {
+                candidate[position] = alt;
+                if self.barcodes.contains(&candidate) {
+                    matches += 1;
+                    if matches > 1 {
+                        return (matches, first);
+                    }
+                    first = Some(candidate.clone());
+                }
+            }
+            candidate[position] = base;
+        }
+        (matches, first)
+    }
+}
+
+impl std::ops::AddAssign for BarcodeErrorCounts {
+    fn add_assign(&mut self, other: Self) {
+        self.barcodes += other.barcodes;
+        self.mutated += other.mutated;
+        self.within_one += other.within_one;
+        self.correctable += other.correctable;
+    }
+}
+
+/// Consecutive rejected candidates after which [`BarcodeDesign::generate`] gives
+/// up on finding another barcode.
+const MAX_BARCODE_ATTEMPTS: usize = 100_000;
+
+/// Constraints on a set of random barcodes: their length, the least distance
+/// between any two of them and the range of their GC content.
+#[derive(Debug, Clone)]
+pub struct BarcodeDesign {
+    length: usize,
+    min_distance: usize,
+    gc_min: f64,
+    gc_max: f64,
+    edit_distance: bool,
+}
+
+impl BarcodeDesign {
+    /// Barcodes of `length` bases, every two differing in at least
+    /// `min_distance` positions.
+    pub fn new(length: usize, min_distance: usize) -> Result<Self, FastqGenError> {
+        if length == 0 {
+            return Err(FastqGenError::invalid("Barcode length must be positive."));
+        }
+        if min_distance > length {
+            return Err(FastqGenError::invalid(format!("Barcodes of {} bases cannot be {} apart.", length, min_distance)));
+        }
+        Ok(BarcodeDesign { length, min_distance, gc_min: 0.0, gc_max: 1.0, edit_distance: false })
+    }
+
+    /// Keeps only barcodes whose fraction of G and C lies in `min..=max`.
+    pub fn with_gc(mut self, min: f64, max: f64) -> Result<Self, FastqGenError> {
+        if !(0.0..=1.0).contains(&min) || !(0.0..=1.0).contains(&max) || min > max {
+            return Err(FastqGenError::invalid("GC bounds must be between 0 and 1, the minimum no more than the maximum."));
+        }
+        if !(0..=self.length).any(|gc| (min..=max).contains(&(gc as f64 / self.length as f64))) {
+            return Err(FastqGenError::invalid(format!("No barcode of {} bases has a GC content between {} and {}.", self.length, min, max)));
+        }
+        self.gc_min = min;
+        self.gc_max = max;
+        Ok(self)
+    }
+
+    /// Measures the distance between barcodes as edit (Levenshtein) distance
+    /// rather than Hamming distance, so that barcodes stay apart when reads
+    /// carry insertions and deletions.
+    pub fn with_edit_distance(mut self) -> Self {
+        self.edit_distance = true;
+        self
+    }
+
+    /// Draws `count` barcodes, accepting each random candidate that meets the GC
+    /// bounds and is far enough from every barcode accepted before it. Fails if
+    /// too many candidates in a row are rejected.
+    pub fn generate(&self, rng: &mut impl Rng, count: usize) -> Result<Vec<Vec<u8>>, FastqGenError> {
+        let mut barcodes: Vec<Vec<u8>> = Vec::with_capacity(count);
+        let mut rejected = 0;
+        while barcodes.len() < count {
+            let candidate: Vec<u8> = (0..self.length).map(|_| *b"ACGT".choose(rng).unwrap()).collect();
+            let gc = candidate.iter().filter(|&&base| base == b'G' || base == b'C').count() as f64 / self.length as f64;
+            if (self.gc_min..=self.gc_max).contains(&gc) && barcodes.iter().all(|barcode| self.is_distant(barcode, &candidate)) {
+                barcodes.push(candidate);
+                rejected = 0;
+                continue;
+            }
+            rejected += 1;
+            if rejected == MAX_BARCODE_ATTEMPTS {
+                return Err(FastqGenError::invalid(format!(
+                    "Found only {} of {} barcodes of {} bases at distance {} or more; lower the count or distance, or lengthen the barcodes.",
+                    barcodes.len(), count, self.length, self.min_distance
+                )));
+            }
+        }
+        Ok(barcodes)
+    }
+
+    fn is_distant(&self, a: &[u8], b: &[u8]) -> bool {
+        if self.edit_distance {
+            edit_distance(a, b) >= self.min_distance
+        } else {
+            a.iter().zip(b).filter(|(x, y)| x != y).count() >= self.min_distance
+        }
+    }
+}
+
+/// Levenshtein distance between `a` and `b`.
+fn edit_distance(a: &[u8], b: &[u8]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, &x) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, &y) in b.iter().enumerate() {
+            current[j + 1] = (previous[j] + usize::from(x != y)).min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
@@ -0,0 +1,49 @@
+use std::io::BufRead;
+use std::path::Path;
+
+use crate::error::FastqGenError;
+use crate::reference::{Reference, open_text};
+
+/// One interval of a BED file, on a contig of the reference.
+#[derive(Debug, Clone)]
+pub struct BedInterval {
+    pub contig: usize,
+    /// 0-based, half-open extent on the forward strand.
+    pub start: usize,
+    pub end: usize,
+    /// The fourth column, when present.
+    pub name: Option<String>,
+    /// The fifth column, when present and a number.
+    pub score: Option<f64>,
+}
+
+/// Reads a BED file of `contig<TAB>start<TAB>end[<TAB>name<TAB>score...]` lines
+/// (0-based, half-open), optionally gzipped. `track` and `browser` lines are
+/// skipped. `kind` names the file in error messages, e.g. `amplicon BED`.
+pub fn read_bed(path: &Path, reference: &Reference, kind: &str) -> Result<Vec<BedInterval>, FastqGenError> {
+    let reader = open_text(path)
+        .map_err(|e| FastqGenError::io(format!("Failed to open {} {}", kind, path.display()), e))?;
+    let mut intervals = Vec::new();
+
+    for (line_number, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.starts_with('#') || line.starts_with("track") || line.starts_with("browser") || line.trim().is_empty() {
+            continue;
+        }
+        let location = format!("{}:{}", path.display(), line_number + 1);
+        let fields: Vec<&str> = line.trim_end().split('\t').collect();
+        if fields.len() < 3 {
+            return Err(FastqGenError::parse(format!("{}: expected contig, start and end.", location)));
+        }
+        let contig = reference.contig_index(fields[0])
+            .ok_or_else(|| FastqGenError::parse(format!("{}: contig {} is not in the reference.", location, fields[0])))?;
+        let length = reference.contig_seq(contig).len();
+        let start = fields[1].parse::<usize>()
+            .map_err(|_| FastqGenError::parse(format!("{}: invalid start {}.", location, fields[1])))?;
+        let end = fields[2].parse::<usize>().ok().filter(|&end| end > start && end <= length)
+            .ok_or_else(|| FastqGenError::parse(format!("{}: invalid end {} for {} of length {}.", location, fields[2], fields[0], length)))?;
+        let score = fields.get(4).and_then(|score| score.parse::<f64>().ok());
+        intervals.push(BedInterval { contig, start, end, name: fields.get(3).map(|name| name.to_string()), score });
+    }
+    Ok(intervals)
+}
@@ -0,0 +1,96 @@
+use rand::Rng;
+use rand::distr::Distribution;
+use rand::distr::weighted::WeightedIndex;
+
+use crate::error::FastqGenError;
+use crate::bed::BedInterval;
+use crate::reference::Reference;
+
+/// The target intervals of a hybrid-capture (e.g. exome) library.
+///
+/// On-target fragments overlap a base drawn uniformly from the targets, so
+/// coverage is flat within a target and falls off over about one fragment length
+/// past its edges, as when fragments are pulled down by probes they only partly
+/// overlap. The remaining fragments come from anywhere on the reference.
+#[derive(Debug, Clone)]
+pub struct CaptureTargets {
+    targets: Vec<BedInterval>,
+    weights: WeightedIndex<usize>,
+    on_target: f64,
+}
+
+impl CaptureTargets {
+    /// Captures a fraction `on_target` of fragments from `targets`.
+    pub fn new(targets: Vec<BedInterval>, on_target: f64) -> Result<Self, FastqGenError> {
+        if !(0.0..=1.0).contains(&on_target) {
+            return Err(FastqGenError::invalid("The on-target fraction must be between 0 and 1."));
+        }
+        let weights = WeightedIndex::new(targets.iter().map(|target| target.end - target.start))
+            .map_err(|_| FastqGenError::invalid("A capture needs at least one target."))?;
+        Ok(CaptureTargets { targets, weights, on_target })
+    }
+
+    /// Enriches `targets` as ChIP-seq enriches the sites a protein binds: the
+    /// on-target fraction is chosen so that coverage in the middle of a target of
+    /// average width is about `fold` times that of the rest of a reference of
+    /// `genome_length` bases, given fragments of `fragment_length` bases.
+    pub fn enriched(targets: Vec<BedInterval>, fold: f64, fragment_length: f64, genome_length: usize) -> Result<Self, FastqGenError> {
+        if fold.is_nan() || fold < 1.0 {
+            return Err(FastqGenError::invalid("Fold enrichment must be at least 1."));
+        }
+        // A fragment of length L overlapping a base drawn uniformly from a target
+        // of width w covers its middle with probability 1 - w / 4L, or L / w once
+        // w exceeds 2L.
+        let width = targets.iter().map(|target| target.end - target.start).sum::<usize>() as f64 / targets.len().max(1) as f64;
+        let length = fragment_length.max(1.0);
+        let covered = if width <= 2.0 * length { 1.0 - width / (4.0 * length) } else { length / width };
+        let ratio = (fold - 1.0) * length * targets.len() as f64 / (genome_length.max(1) as f64 * covered);
+        CaptureTargets::new(targets, ratio / (1.0 + ratio))
+    }
+
+    /// Total length of the targets, counting overlapping bases once per target.
+    pub fn total_length(&self) -> usize {
+        self.targets.iter().map(|target| target.end - target.start).sum()
+    }
+
+    pub fn on_target(&self) -> f64 {
+        self.on_target
+    }
+
+    /// Draws the reference region (contig, start and end) of a captured fragment of
+    /// `length` bases, or `None` for an off-target fragment.
+    pub fn sample(&self, rng: &mut impl Rng, reference: &Reference, length: usize) -> Option<(usize, usize, usize)> {
+        if !rng.random_bool(self.on_target) {
+            return None;
+        }
+        let target = &self.targets[self.weights.sample(rng)];
+        let contig_length = reference.contig_seq(target.contig).len();
+        let length = length.clamp(1, contig_length);
+        let base = rng.random_range(target.start..target.end);
+        let start = rng.random_range(base.saturating_sub(length - 1)..=base).min(contig_length - length);
+        Some((target.contig, start, start + length))
+    }
+}
+
+/// Draws `count` targets of `width` bases at uniformly random positions of the
+/// reference, such as the binding sites of a simulated ChIP-seq run, named
+/// `site_1`, `site_2` and so on and sorted by position.
+pub fn random_targets(rng: &mut impl Rng, reference: &Reference, count: usize, width: usize) -> Result<Vec<BedInterval>, FastqGenError> {
+    if width == 0 {
+        return Err(FastqGenError::invalid("The target width must be positive."));
+    }
+    let lengths: Vec<usize> = reference.contigs().map(|(_, length)| length).collect();
+    let weights = WeightedIndex::new(lengths.iter().map(|&length| length.saturating_sub(width)))
+        .map_err(|_| FastqGenError::invalid(format!("No contig is longer than the target width of {} bases.", width)))?;
+    let mut positions: Vec<(usize, usize)> = (0..count)
+        .map(|_| {
+            let contig = weights.sample(rng);
+            (contig, rng.random_range(0..=lengths[contig] - width))
+        })
+        .collect();
+    positions.sort_unstable();
+    Ok(positions.into_iter()
+        .enumerate()
+        .map(|(index, (contig, start))| BedInterval { contig, start, end: start + width, name: Some(format!("site_{}", index + 1)), score: None })
+        .collect())
+}
@@ -0,0 +1,181 @@
+use rand::Rng;
+use rand::distr::Distribution;
+use rand::distr::weighted::WeightedIndex;
+use rand_distr::Beta;
+use std::io::{self, Write};
+
+use crate::error::FastqGenError;
+use crate::reference::Reference;
+use crate::variants::Variant;
+
+/// Diploid samples drawn from one or more populations, sharing their variant
+/// sites. Each site gets an ancestral frequency from the neutral site-frequency
+/// spectrum of the cohort's haplotypes, where a variant carried by `i` of them is
+/// `1/i` as likely as a singleton, so most variants are rare and private to one
+/// sample and a few are common and shared. With several populations, each drifts
+/// from the ancestral frequency as in the Balding-Nichols model, by `fst`.
+#[derive(Debug, Clone)]
+pub struct Cohort {
+    /// The population of each sample.
+    populations: Vec<usize>,
+    population_count: usize,
+    fst: f64,
+    /// Number of haplotypes carrying a site, less one, in the neutral spectrum.
+    spectrum: WeightedIndex<f64>,
+}
+
+impl Cohort {
+    /// `samples` diploid samples split into `populations` populations of equal
+    /// size, in order, which differ by `fst`.
+    pub fn new(samples: usize, populations: usize, fst: f64) -> Result<Self, FastqGenError> {
+        if samples == 0 {
+            return Err(FastqGenError::invalid("A cohort needs at least one sample."));
+        }
+        if populations == 0 || populations > samples {
+            return Err(FastqGenError::invalid("A cohort needs between one population and one per sample."));
+        }
+        if !(0.0..1.0).contains(&fst) {
+            return Err(FastqGenError::invalid("FST must be at least 0 and less than 1."));
+        }
+        let haplotypes = 2 * samples;
+        let spectrum = WeightedIndex::new((1..haplotypes.max(2)).map(|carriers| 1.0 / carriers as f64))
+            .map_err(|e| FastqGenError::invalid(format!("Invalid cohort: {}", e)))?;
+        let assignment = (0..samples).map(|sample| sample * populations / samples).collect();
+        Ok(Cohort { populations: assignment, population_count: populations, fst, spectrum })
+    }
+
+    pub fn samples(&self) -> usize {
+        self.populations.len()
+    }
+
+    /// Name of a sample in file names and the cohort VCF.
+    pub fn sample_name(&self, sample: usize) -> String {
+        format!("sample{}", sample + 1)
+    }
+
+    /// Draws which of the cohort's haplotypes, two per sample, carry a variant.
+    /// Sites no haplotype would carry are drawn again, so that every variant is
+    /// seen in the cohort.
+    pub fn sample_genotype(&self, rng: &mut impl Rng) -> Result<Vec<bool>, FastqGenError> {
+        let haplotypes = 2 * self.samples();
+        loop {
+            let ancestral = (self.spectrum.sample(rng) + 1) as f64 / haplotypes as f64;
+            let frequencies = if self.fst > 0.0 {
+                let scale = (1.0 - self.fst) / self.fst;
+                let drift = Beta::new(ancestral * scale, (1.0 - ancestral) * scale)
+                    .map_err(|e| FastqGenError::invalid(format!("Invalid FST: {}", e)))?;
+                (0..self.population_count).map(|_| drift.sample(rng)).collect()
+            } else {
+                vec![ancestral; self.population_count]
+            };
+            let genotype: Vec<bool> = (0..haplotypes)
+                .map(|haplotype| rng.random_bool(frequencies[self.populations[haplotype / 2]].clamp(0.0, 1.0)))
+                .collect();
+            if genotype.contains(&true) {
+                return Ok(genotype);
+            }
+        }
+    }
+
+    /// The diploid genotype of `sample` from the genotype of the cohort's
+    /// haplotypes.
+    pub fn genotype(&self, sample: usize, cohort: &[bool]) -> Vec<bool> {
+        cohort[2 * sample..2 * sample + 2].to_vec()
+    }
+
+    /// Writes `variants` as a VCF with the phased genotype of every sample, their
+    /// count and frequency in the cohort as `AC`, `AN` and `AF`, and the
+    /// population of every sample in `##SAMPLE` header lines.
+    pub fn write_vcf(&self, out: &mut impl Write, reference: &Reference, variants: &[Variant]) -> io::Result<()> {
+        writeln!(out, "##fileformat=VCFv4.2")?;
+        writeln!(out, "##source=fastqgen-{}", env!("CARGO_PKG_VERSION"))?;
+        for (name, length) in reference.contigs() {
+            writeln!(out, "##contig=<ID={},length={}>", name, length)?;
+        }
+        for (sample, population) in self.populations.iter().enumerate() {
+            writeln!(out, "##SAMPLE=<ID={},Population=pop{}>", self.sample_name(sample), population + 1)?;
+        }
+        writeln!(out, "##INFO=<ID=AC,Number=A,Type=Integer,Description=\"Allele count in the cohort\">")?;
+        writeln!(out, "##INFO=<ID=AN,Number=1,Type=Integer,Description=\"Number of alleles in the cohort\">")?;
+        writeln!(out, "##INFO=<ID=AF,Number=A,Type=Float,Description=\"Allele frequency in the cohort\">")?;
+        writeln!(out, "##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">")?;
+        let names: Vec<String> = (0..self.samples()).map(|sample| self.sample_name(sample)).collect();
+        writeln!(out, "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\t{}", names.join("\t"))?;
+
+        for variant in variants {
+            let count = variant.genotype.iter().filter(|&&alt| alt).count();
+            let genotypes: Vec<String> = variant.genotype.chunks(2)
+                .map(|pair| pair.iter().map(|&alt| if alt { "1" } else { "0" }).collect::<Vec<_>>().join("|"))
+                .collect();
+            writeln!(
+                out,
+                "{}\t{}\t.\t{}\t{}\t.\tPASS\tAC={};AN={};AF={:.4}\tGT\t{}",
+                reference.contig_name(variant.contig),
+                variant.position + 1,
+                String::from_utf8_lossy(&variant.reference),
+                String::from_utf8_lossy(&variant.alternate),
+                count,
+                variant.genotype.len(),
+                count as f64 / variant.genotype.len() as f64,
+                genotypes.join("\t")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn splits_samples_into_populations_in_order() {
+        assert_eq!(Cohort::new(5, 2, 0.1).unwrap().populations, vec![0, 0, 0, 1, 1]);
+        assert!(Cohort::new(0, 1, 0.0).is_err());
+        assert!(Cohort::new(3, 4, 0.0).is_err());
+        assert!(Cohort::new(3, 1, 1.0).is_err());
+    }
+
+    #[test]
+    fn genotypes_follow_the_neutral_spectrum() {
+        let cohort = Cohort::new(10, 1, 0.0).unwrap();
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut carriers = [0; 21];
+        for _ in 0..2000 {
+            let genotype = cohort.sample_genotype(&mut rng).unwrap();
+            assert_eq!(genotype.len(), 20);
+            carriers[genotype.iter().filter(|&&alt| alt).count()] += 1;
+        }
+        assert_eq!(carriers[0], 0);
+        // Singletons are the most common sites, and variants carried by at most a quarter
+        // of the haplotypes outnumber the rest.
+        assert_eq!((1..=20).max_by_key(|&count| carriers[count]), Some(1));
+        assert!(carriers[1..=5].iter().sum::<usize>() > carriers[6..].iter().sum::<usize>());
+    }
+
+    #[test]
+    fn drifted_genotypes_are_carried_by_the_cohort() {
+        let cohort = Cohort::new(6, 3, 0.3).unwrap();
+        let mut rng = StdRng::seed_from_u64(2);
+        for _ in 0..200 {
+            let genotype = cohort.sample_genotype(&mut rng).unwrap();
+            assert_eq!(genotype.len(), 12);
+            assert!(genotype.contains(&true));
+        }
+        assert_eq!(cohort.genotype(2, &[false, false, false, false, true, false, false, false, false, false, false, true]), vec![true, false]);
+    }
+
+    #[test]
+    fn writes_allele_counts() {
+        let reference = Reference::from_sequences(vec![("chr".to_string(), b"ACGTACGT".to_vec())], 1).unwrap();
+        let cohort = Cohort::new(2, 2, 0.1).unwrap();
+        let variant = Variant { contig: 0, position: 2, reference: b"G".to_vec(), alternate: b"T".to_vec(), genotype: vec![true, false, true, true] };
+        let mut vcf = Vec::new();
+        cohort.write_vcf(&mut vcf, &reference, &[variant]).unwrap();
+        let vcf = String::from_utf8(vcf).unwrap();
+        assert!(vcf.contains("##SAMPLE=<ID=sample2,Population=pop2>\n"));
+        assert!(vcf.ends_with("chr\t3\t.\tG\tT\t.\tPASS\tAC=3;AN=4;AF=0.7500\tGT\t1|0\t1|1\n"));
+    }
+}
@@ -0,0 +1,145 @@
+use rand::Rng;
+use rand::distr::Distribution;
+use rand::distr::weighted::WeightedIndex;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use crate::error::FastqGenError;
+use crate::expression::{read_abundance_table, sample_abundances};
+use crate::reference::open_text;
+
+/// End of the U6 promoter of lentiGuide-Puro and lentiCRISPRv2, read before the
+/// guide.
+pub const GUIDE_UPSTREAM: &[u8] = b"TCTTGTGGAAAGGACGAAACACCG";
+/// Start of the sgRNA scaffold, read after the guide.
+pub const GUIDE_DOWNSTREAM: &[u8] = b"GTTTTAGAGCTAGAAATAGCAAGTTAAAATAAGGCTAGTCCGTTATCAACTTGAAAAAGTGGCACCGAGTCGGTGC";
+
+/// One guide RNA of a CRISPR screening library.
+#[derive(Debug, Clone)]
+pub struct Guide {
+    pub id: String,
+    /// Spacer sequence, 5' to 3'.
+    pub sequence: Vec<u8>,
+    /// Gene the guide targets.
+    pub gene: String,
+}
+
+/// Reads a guide library in the format MAGeCK takes: `id,sequence,gene` lines
+/// (commas or tabs separate the columns), optionally gzipped. A header line whose
+/// sequence column is not bases is skipped, as are lines starting with `#`.
+pub fn read_guide_library(path: &Path) -> Result<Vec<Guide>, FastqGenError> {
+    let reader = open_text(path)
+        .map_err(|e| FastqGenError::io(format!("Failed to open guide library {}", path.display()), e))?;
+    let mut guides: Vec<Guide> = Vec::new();
+
+    for (line_number, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        let location = format!("{}:{}", path.display(), line_number + 1);
+        let fields: Vec<&str> = line.trim_end().split([',', '\t']).map(str::trim).collect();
+        if fields.len() < 3 {
+            return Err(FastqGenError::parse(format!("{}: expected a guide ID, a sequence and a gene.", location)));
+        }
+        let sequence = fields[1].to_ascii_uppercase().into_bytes();
+        if sequence.is_empty() || !sequence.iter().all(|base| b"ACGT".contains(base)) {
+            if line_number == 0 {
+                continue;
+            }
+            return Err(FastqGenError::parse(format!("{}: invalid guide sequence {}.", location, fields[1])));
+        }
+        if guides.iter().any(|guide| guide.id == fields[0]) {
+            return Err(FastqGenError::parse(format!("{}: guide {} is listed twice.", location, fields[0])));
+        }
+        guides.push(Guide { id: fields[0].to_string(), sequence, gene: fields[2].to_string() });
+    }
+    Ok(guides)
+}
+
+/// Reads the abundance of every guide from `guide<TAB>abundance` lines, in the
+/// format of [`crate::read_expression`]. Guides missing from the table are absent
+/// from the screen; the number of names that match no guide is returned
+/// alongside.
+pub fn read_guide_abundances(path: &Path, guides: &[Guide]) -> Result<(Vec<f64>, usize), FastqGenError> {
+    let reader = open_text(path)
+        .map_err(|e| FastqGenError::io(format!("Failed to open abundance table {}", path.display()), e))?;
+    read_abundance_table(reader, path, guides.len(), |name| guides.iter().position(|guide| guide.id == name))
+}
+
+/// Draws the abundance of every guide from a log-normal with log-scale standard
+/// deviation `sd`, as the uneven representation of a plasmid library or a screen.
+pub fn sample_guide_abundances(guides: &[Guide], rng: &mut impl Rng, sd: f64) -> Result<Vec<f64>, FastqGenError> {
+    sample_abundances(guides.len(), rng, sd)
+}
+
+/// The guides of a CRISPR screen in their vector: every read is of a guide drawn
+/// by abundance, between the vector sequences that flank it, after a random
+/// stagger of up to `max_stagger` bases as added by staggered PCR primers.
+#[derive(Debug, Clone)]
+pub struct GuideLibrary {
+    guides: Vec<Guide>,
+    weights: WeightedIndex<f64>,
+    upstream: Vec<u8>,
+    downstream: Vec<u8>,
+    max_stagger: usize,
+}
+
+impl GuideLibrary {
+    /// Draws guides in proportion to `abundances`, one per guide, in the
+    /// lentiGuide vector without a stagger.
+    pub fn new(guides: Vec<Guide>, abundances: &[f64]) -> Result<Self, FastqGenError> {
+        if guides.is_empty() || abundances.len() != guides.len() {
+            return Err(FastqGenError::invalid("A guide library needs one abundance per guide, and at least one guide."));
+        }
+        let weights = WeightedIndex::new(abundances)
+            .map_err(|_| FastqGenError::invalid("At least one guide must be abundant."))?;
+        Ok(GuideLibrary { guides, weights, upstream: GUIDE_UPSTREAM.to_vec(), downstream: GUIDE_DOWNSTREAM.to_vec(), max_stagger: 0 })
+    }
+
+    /// Reads guides between `upstream` and `downstream` instead of the lentiGuide
+    /// vector.
+    pub fn with_vector(mut self, upstream: Vec<u8>, downstream: Vec<u8>) -> Self {
+        self.upstream = upstream;
+        self.downstream = downstream;
+        self
+    }
+
+    /// Starts every read with between 0 and `max_stagger` random bases.
+    pub fn with_stagger(mut self, max_stagger: usize) -> Self {
+        self.max_stagger = max_stagger;
+        self
+    }
+
+    pub fn guides(&self) -> &[Guide] {
+        &self.guides
+    }
+
+    /// Draws the index of a guide.
+    pub fn sample(&self, rng: &mut impl Rng) -> usize {
+        self.weights.sample(rng)
+    }
+
+    /// Writes the construct read of the guide at `index`: the stagger, the
+    /// upstream vector, the guide and the downstream vector, into `template`.
+    pub fn construct(&self, rng: &mut impl Rng, index: usize, template: &mut Vec<u8>) {
+        template.clear();
+        let stagger = rng.random_range(0..=self.max_stagger);
+        template.extend((0..stagger).map(|_| b"ACGT"[rng.random_range(0..4)]));
+        template.extend_from_slice(&self.upstream);
+        template.extend_from_slice(&self.guides[index].sequence);
+        template.extend_from_slice(&self.downstream);
+    }
+
+    /// Writes the true number of reads of every guide as a MAGeCK count table:
+    /// guide ID, gene, then one count column per name in `columns`. `counts` holds
+    /// the counts of each guide in turn, one per column.
+    pub fn write_counts(&self, out: &mut impl Write, columns: &[&str], counts: &[u64]) -> io::Result<()> {
+        writeln!(out, "sgRNA\tGene\t{}", columns.join("\t"))?;
+        for (guide, counts) in self.guides.iter().zip(counts.chunks(columns.len())) {
+            let counts: Vec<String> = counts.iter().map(u64::to_string).collect();
+            writeln!(out, "{}\t{}\t{}", guide.id, guide.gene, counts.join("\t"))?;
+        }
+        Ok(())
+    }
+}
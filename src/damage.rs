@@ -0,0 +1,73 @@
+use rand::Rng;
+
+use crate::error::FastqGenError;
+
+/// Mean length of the fragments of an ancient DNA library, whose molecules have
+/// broken down over time.
+pub const ANCIENT_FRAGMENT_MEAN: f64 = 50.0;
+/// Standard deviation of the fragment lengths of an ancient DNA library.
+pub const ANCIENT_FRAGMENT_SD: f64 = 20.0;
+
+/// Deamination of ancient DNA, as mapDamage models it: cytosines in the
+/// single-stranded overhangs at the ends of a molecule deaminate to uracil and
+/// read as thymine. The rate is highest at the terminal base and decays
+/// exponentially into the molecule, down to a background rate from nicks.
+///
+/// In double-stranded libraries, the overhang at the 3' end is filled in from the
+/// damaged 5' overhang of the other strand, so the 3' end shows G→A rather than
+/// C→T. Single-stranded libraries read every strand as it is, with C→T at both
+/// ends.
+#[derive(Debug, Clone)]
+pub struct DamageModel {
+    /// Rate of deamination at each distance from the end of the molecule, down to
+    /// where it reaches the background rate.
+    profile: Vec<f64>,
+    interior: f64,
+    single_stranded: bool,
+}
+
+impl DamageModel {
+    /// Deaminates cytosines at rate `interior` plus `terminal` at the end of the
+    /// molecule, the excess falling by a fraction `decay` with every base inwards.
+    pub fn new(terminal: f64, decay: f64, interior: f64) -> Result<Self, FastqGenError> {
+        if [terminal, interior].iter().any(|rate| !(0.0..=1.0).contains(rate)) || terminal + interior > 1.0 {
+            return Err(FastqGenError::invalid("Damage rates must be between 0 and 1, and add up to at most 1."));
+        }
+        if !(decay > 0.0 && decay <= 1.0) {
+            return Err(FastqGenError::invalid("The decay of damage must be greater than 0 and at most 1."));
+        }
+        let profile = std::iter::successors(Some(terminal), |excess| Some(excess * (1.0 - decay)))
+            .take_while(|excess| *excess > 1e-6)
+            .map(|excess| interior + excess)
+            .collect();
+        Ok(DamageModel { profile, interior, single_stranded: false })
+    }
+
+    /// Damages molecules as a single-stranded library reads them.
+    pub fn with_single_stranded(mut self) -> Self {
+        self.single_stranded = true;
+        self
+    }
+
+    /// Rate of deamination of a base `distance` bases from the end of its strand.
+    pub fn rate(&self, distance: usize) -> f64 {
+        self.profile.get(distance).copied().unwrap_or(self.interior)
+    }
+
+    /// Damages `template`, the strand of a molecule read 1 sequences, 5' to 3'.
+    pub fn deaminate(&self, rng: &mut impl Rng, template: &mut [u8]) {
+        let last = template.len().saturating_sub(1);
+        for (position, base) in template.iter_mut().enumerate() {
+            let (from_5, from_3) = (position, last - position);
+            let (distance, damaged) = match *base {
+                b'C' if self.single_stranded => (from_5.min(from_3), b'T'),
+                b'C' => (from_5, b'T'),
+                b'G' if !self.single_stranded => (from_3, b'A'),
+                _ => continue,
+            };
+            if rng.random_bool(self.rate(distance)) {
+                *base = damaged;
+            }
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use std::io;
+
+use thiserror::Error;
+
+/// What went wrong in a fastqgen call, by kind, so that callers can tell bad
+/// parameters from unreadable or malformed input.
+#[derive(Debug, Error)]
+pub enum FastqGenError {
+    /// Reading or writing a file failed.
+    #[error("{context}: {source}")]
+    Io {
+        context: String,
+        #[source]
+        source: io::Error,
+    },
+    /// A parameter is out of range or inconsistent with the others.
+    #[error("{0}")]
+    InvalidParameter(String),
+    /// An input file (reference FASTA, FASTQ, VCF, BED, GTF, sample sheet, table
+    /// or model JSON) is malformed.
+    #[error("{0}")]
+    Parse(String),
+    /// A configuration file is malformed or sets an unknown option.
+    #[error("{0}")]
+    Config(String),
+}
+
+impl FastqGenError {
+    /// An I/O error, described by what was being done when it happened.
+    pub fn io(context: impl Into<String>, source: io::Error) -> Self {
+        FastqGenError::Io { context: context.into(), source }
+    }
+
+    pub fn invalid(message: impl Into<String>) -> Self {
+        FastqGenError::InvalidParameter(message.into())
+    }
+
+    pub fn parse(message: impl Into<String>) -> Self {
+        FastqGenError::Parse(message.into())
+    }
+}
+
+impl From<io::Error> for FastqGenError {
+    fn from(source: io::Error) -> Self {
+        FastqGenError::io("I/O error", source)
+    }
+}
@@ -0,0 +1,219 @@
+use rand::Rng;
+use rand::prelude::IndexedRandom;
+use std::ops::Range;
+
+use crate::error::FastqGenError;
+use crate::alignment::Cigar;
+
+/// Per-base sequencing error rates applied to reads after they are sampled.
+#[derive(Debug, Clone, Default)]
+pub struct ErrorModel {
+    sub_rate: f64,
+    ins_rate: f64,
+    del_rate: f64,
+    /// Substitution rate of a base by its Phred score, replacing `sub_rate`.
+    phred_sub_rates: Option<Vec<f64>>,
+    /// Extra indel rate per base of homopolymer run length beyond the first, as a
+    /// multiple of the base rates.
+    homopolymer_factor: Option<f64>,
+}
+
+/// Phred scores assigned to bases that carry a sequencing error.
+pub(crate) const ERROR_PHRED_RANGE: Range<u8> = 2..13;
+
+/// Highest Phred score that printable quality strings can hold.
+const MAX_PHRED: u8 = 93;
+
+/// Cap on indel rates raised in long homopolymers, so that reads still advance
+/// through them.
+const MAX_HOMOPOLYMER_INDEL_RATE: f64 = 0.5;
+
+impl ErrorModel {
+    pub fn new(sub_rate: f64, ins_rate: f64, del_rate: f64) -> Result<Self, FastqGenError> {
+        for (name, rate) in [("Substitution", sub_rate), ("Insertion", ins_rate), ("Deletion", del_rate)] {
+            if !(0.0..1.0).contains(&rate) {
+                return Err(FastqGenError::invalid(format!("{} rate must be in [0, 1), got {}.", name, rate)));
+            }
+        }
+        Ok(ErrorModel { sub_rate, ins_rate, del_rate, phred_sub_rates: None, homopolymer_factor: None })
+    }
+
+    /// Substitutes each base with the rate of its Phred score instead of a single
+    /// rate, so errors fall where qualities are low. `rates[q]` is the rate at Q`q`;
+    /// scores beyond the last rate use it.
+    pub fn with_phred_substitution_rates(mut self, rates: Vec<f64>) -> Result<Self, FastqGenError> {
+        if rates.is_empty() || rates.iter().any(|rate| !(0.0..=1.0).contains(rate)) {
+            return Err(FastqGenError::invalid("Substitution rates by quality must be between 0 and 1."));
+        }
+        self.phred_sub_rates = Some(rates);
+        Ok(self)
+    }
+
+    /// Substitutes each base with the error probability its Phred score stands
+    /// for, 10^(-Q/10), instead of a single rate.
+    pub fn with_phred_errors(self) -> Self {
+        let rates = (0..=MAX_PHRED).map(|q| 10f64.powf(-f64::from(q) / 10.0)).collect();
+        self.with_phred_substitution_rates(rates).expect("Phred error probabilities are between 0 and 1")
+    }
+
+    /// Raises the insertion and deletion rates inside homopolymers, as on Ion Torrent
+    /// and Nanopore instruments, which miscount long runs of one base: in a run of
+    /// `n` bases they are multiplied by `1 + factor * (n - 1)`, up to 0.5.
+    /// Insertions there repeat the run's base.
+    pub fn with_homopolymer_indels(mut self, factor: f64) -> Result<Self, FastqGenError> {
+        if factor.is_nan() || factor < 0.0 {
+            return Err(FastqGenError::invalid("Homopolymer indel factor must not be negative."));
+        }
+        self.homopolymer_factor = Some(factor);
+        Ok(self)
+    }
+
+    /// This model with every error rate multiplied by `factor`. Rates by quality
+    /// are capped at 1.
+    pub fn scaled(&self, factor: f64) -> Result<Self, FastqGenError> {
+        if factor.is_nan() || factor < 0.0 {
+            return Err(FastqGenError::invalid("Error rate multiplier must not be negative."));
+        }
+        let mut scaled = ErrorModel::new(self.sub_rate * factor, self.ins_rate * factor, self.del_rate * factor)?;
+        scaled.phred_sub_rates = self.phred_sub_rates.as_ref().map(|rates| rates.iter().map(|rate| (rate * factor).min(1.0)).collect());
+        scaled.homopolymer_factor = self.homopolymer_factor;
+        Ok(scaled)
+    }
+
+    pub fn is_error_free(&self) -> bool {
+        self.sub_rate == 0.0 && self.ins_rate == 0.0 && self.del_rate == 0.0 && self.phred_sub_rates.is_none()
+    }
+
+    /// Whether substitutions are drawn from the quality of each base, which then
+    /// already reflects them.
+    pub(crate) fn is_quality_driven(&self) -> bool {
+        self.phred_sub_rates.is_some()
+    }
+
+    /// Extra template bases to sample beyond the read length so that deletions
+    /// rarely run a read off the end of its template.
+    pub(crate) fn template_padding(&self, read_length: usize) -> usize {
+        if self.del_rate == 0.0 {
+            0
+        } else {
+            (self.del_rate * read_length as f64 * 3.0).ceil() as usize + 5
+        }
+    }
+
+    /// Reads one base per score of `quality` (encoded with `quality_offset`) from
+    /// `template`, injecting substitutions, insertions and deletions. If deletions
+    /// exhaust the template, the remaining positions are no-calls (`N`),
+    /// soft-clipped in the CIGAR. Overwrites `read`, reusing its buffers.
+    pub(crate) fn sequence_into(&self, rng: &mut impl Rng, template: &[u8], quality: &[u8], quality_offset: u8, bases: &[u8], read: &mut SequencedRead) {
+        let length = quality.len();
+        read.clear();
+        let SequencedRead { seq, errors, cigar, edit_distance, template_offset } = read;
+        let mut next = 0;
+
+        while seq.len() < length {
+            let (ins_rate, del_rate) = self.indel_rates(template, next);
+            if rng.random_bool(ins_rate) {
+                errors.push(seq.len());
+                let inserted = match (self.homopolymer_factor, template.get(next)) {
+                    (Some(_), Some(&base)) => base,
+                    _ => *bases.choose(rng).unwrap(),
+                };
+                seq.push(inserted);
+                cigar.push(b'I');
+                *edit_distance += 1;
+                continue;
+            }
+            let Some(&base) = template.get(next) else {
+                errors.push(seq.len());
+                seq.push(b'N');
+                cigar.push(b'S');
+                continue;
+            };
+            next += 1;
+            if rng.random_bool(del_rate) {
+                // A deletion before the first aligned base just moves the read's start.
+                if seq.is_empty() {
+                    *template_offset += 1;
+                } else {
+                    cigar.push(b'D');
+                    *edit_distance += 1;
+                }
+                continue;
+            }
+            let sub_rate = match &self.phred_sub_rates {
+                Some(rates) => rates[(quality[seq.len()].saturating_sub(quality_offset) as usize).min(rates.len() - 1)],
+                None => self.sub_rate,
+            };
+            if rng.random_bool(sub_rate) {
+                errors.push(seq.len());
+                seq.push(substitute(rng, base, bases));
+                *edit_distance += 1;
+            } else {
+                seq.push(base);
+            }
+            cigar.push(b'M');
+        }
+    }
+
+    /// Insertion and deletion rates before template base `position`.
+    fn indel_rates(&self, template: &[u8], position: usize) -> (f64, f64) {
+        let Some(factor) = self.homopolymer_factor else {
+            return (self.ins_rate, self.del_rate);
+        };
+        let scale = 1.0 + factor * (homopolymer_length(template, position) - 1) as f64;
+        let raise = |rate: f64| (rate * scale).min(MAX_HOMOPOLYMER_INDEL_RATE.max(rate));
+        (raise(self.ins_rate), raise(self.del_rate))
+    }
+}
+
+/// Length of the run of identical bases `template[position]` belongs to; 1 past
+/// the end of the template.
+fn homopolymer_length(template: &[u8], position: usize) -> usize {
+    let Some(&base) = template.get(position) else {
+        return 1;
+    };
+    let before = template[..position].iter().rev().take_while(|&&b| b == base).count();
+    let after = template[position + 1..].iter().take_while(|&&b| b == base).count();
+    before + 1 + after
+}
+
+/// A read produced by [`ErrorModel::sequence_into`].
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SequencedRead {
+    pub seq: Vec<u8>,
+    /// Positions in `seq` that carry an error.
+    pub errors: Vec<usize>,
+    /// Alignment of `seq` to the template, in sequencing orientation.
+    pub cigar: Cigar,
+    pub edit_distance: usize,
+    /// Template bases skipped before the first aligned base.
+    pub template_offset: usize,
+}
+
+impl SequencedRead {
+    /// Overwrites this read with an error-free read of the first `length`
+    /// template bases.
+    pub fn set_exact(&mut self, template: &[u8], length: usize) {
+        self.clear();
+        self.seq.extend_from_slice(&template[..length]);
+        self.cigar.push_run(length, b'M');
+    }
+
+    fn clear(&mut self) {
+        self.seq.clear();
+        self.errors.clear();
+        self.cigar.clear();
+        self.edit_distance = 0;
+        self.template_offset = 0;
+    }
+}
+
+/// Replaces `base` with a different base drawn uniformly from `bases`.
+pub(crate) fn substitute(rng: &mut impl Rng, base: u8, bases: &[u8]) -> u8 {
+    loop {
+        let alt = *bases.choose(rng).unwrap();
+        if alt != base {
+            return alt;
+        }
+    }
+}
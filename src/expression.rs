@@ -0,0 +1,91 @@
+use rand::Rng;
+use rand::distr::Distribution;
+use rand_distr::LogNormal;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use crate::error::FastqGenError;
+use crate::reference::{Reference, open_text};
+
+/// Scales abundances to transcripts per million.
+fn to_tpm(mut abundances: Vec<f64>) -> Result<Vec<f64>, FastqGenError> {
+    let total: f64 = abundances.iter().sum();
+    if total <= 0.0 {
+        return Err(FastqGenError::invalid("At least one transcript must be expressed."));
+    }
+    abundances.iter_mut().for_each(|abundance| *abundance *= 1e6 / total);
+    Ok(abundances)
+}
+
+/// Reads an abundance table of `transcript<TAB>abundance` lines (tabs, commas or
+/// spaces separate the columns) and returns the TPM of every transcript of
+/// `reference`, in contig order. Transcripts missing from the table are not
+/// expressed. A header line whose second column is not a number is skipped, as are
+/// transcripts that are not in the reference, whose number is returned alongside.
+pub fn read_expression(path: &Path, reference: &Reference) -> Result<(Vec<f64>, usize), FastqGenError> {
+    let reader = open_text(path)
+        .map_err(|e| FastqGenError::io(format!("Failed to open expression table {}", path.display()), e))?;
+    read_abundance_table(reader, path, reference.contigs().count(), |name| reference.contig_index(name))
+}
+
+/// Parses `name<TAB>abundance` lines as [`read_expression`] does, placing each
+/// abundance at the index `lookup` gives its name, and scales them to TPM.
+pub(crate) fn read_abundance_table(
+    reader: impl BufRead,
+    path: &Path,
+    count: usize,
+    lookup: impl Fn(&str) -> Option<usize>,
+) -> Result<(Vec<f64>, usize), FastqGenError> {
+    let mut abundances = vec![0.0; count];
+    let mut unknown = 0;
+
+    for (line_number, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        let location = format!("{}:{}", path.display(), line_number + 1);
+        let fields: Vec<&str> = line.split(['\t', ',', ' ']).filter(|field| !field.is_empty()).collect();
+        if fields.len() < 2 {
+            return Err(FastqGenError::parse(format!("{}: expected a name and an abundance.", location)));
+        }
+        let abundance = match fields[1].parse::<f64>() {
+            Ok(abundance) if abundance >= 0.0 && abundance.is_finite() => abundance,
+            Err(_) if line_number == 0 => continue,
+            _ => return Err(FastqGenError::parse(format!("{}: invalid abundance {}.", location, fields[1]))),
+        };
+        match lookup(fields[0]) {
+            Some(index) => abundances[index] = abundance,
+            None => unknown += 1,
+        }
+    }
+
+    Ok((to_tpm(abundances)?, unknown))
+}
+
+/// Draws the TPM of every transcript of `reference` from a log-normal with
+/// log-scale standard deviation `sd`, giving the long-tailed expression profile
+/// of a typical sample.
+pub fn sample_expression(reference: &Reference, rng: &mut impl Rng, sd: f64) -> Result<Vec<f64>, FastqGenError> {
+    sample_abundances(reference.contigs().count(), rng, sd)
+}
+
+/// Draws `count` abundances from a log-normal with log-scale standard deviation
+/// `sd`, scaled to TPM.
+pub(crate) fn sample_abundances(count: usize, rng: &mut impl Rng, sd: f64) -> Result<Vec<f64>, FastqGenError> {
+    let distribution = LogNormal::new(0.0, sd).map_err(|e| FastqGenError::invalid(format!("Invalid abundance standard deviation: {}", e)))?;
+    let abundances = (0..count).map(|_| distribution.sample(rng)).collect();
+    to_tpm(abundances)
+}
+
+/// Writes the true number of reads drawn from every transcript as a TSV matrix:
+/// transcript ID, length, TPM, then one count column per name in `columns`.
+/// `counts` holds the counts of each transcript in turn, one per column.
+pub fn write_counts(out: &mut impl Write, reference: &Reference, tpm: &[f64], columns: &[&str], counts: &[u64]) -> io::Result<()> {
+    writeln!(out, "transcript\tlength\ttpm\t{}", columns.join("\t"))?;
+    for (((name, length), tpm), counts) in reference.contigs().zip(tpm).zip(counts.chunks(columns.len())) {
+        let counts: Vec<String> = counts.iter().map(u64::to_string).collect();
+        writeln!(out, "{}\t{}\t{:.4}\t{}", name, length, tpm, counts.join("\t"))?;
+    }
+    Ok(())
+}
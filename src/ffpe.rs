@@ -0,0 +1,104 @@
+use rand::Rng;
+use rand::distr::Distribution;
+use rand_distr::Geometric;
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use crate::error::FastqGenError;
+use crate::reference::{Fragment, Reference};
+
+/// Mean length of the fragments of an FFPE library, whose DNA formalin fixation
+/// has broken up.
+pub const FFPE_FRAGMENT_MEAN: f64 = 150.0;
+/// Standard deviation of the fragment lengths of an FFPE library.
+pub const FFPE_FRAGMENT_SD: f64 = 60.0;
+
+/// Cytosines of a reference that formalin fixation deaminated in part of the
+/// sample's molecules, so that they read as thymine. Damage strikes one strand
+/// of a molecule: a site at a reference C reads as T only in molecules read 1
+/// sequences from the forward strand (F1R2 pairs), and a site at a reference G,
+/// a C of the reverse strand, reads as A only in F2R1 pairs. Orientation-bias
+/// filters tell these artifacts apart from real variants, seen in both.
+#[derive(Debug, Clone)]
+pub struct FfpeArtifacts {
+    /// Allele fraction of the artifact at each damaged position, per contig.
+    sites: Vec<HashMap<usize, f64>>,
+}
+
+impl FfpeArtifacts {
+    /// Damages C and G bases of `reference` at a per-base rate of `site_rate`, each
+    /// site at an allele fraction drawn uniformly up to `max_fraction`, the
+    /// fraction of all reads over the site that show it.
+    pub fn simulate(reference: &Reference, rng: &mut impl Rng, site_rate: f64, max_fraction: f64) -> Result<Self, FastqGenError> {
+        if !(0.0..=1.0).contains(&site_rate) {
+            return Err(FastqGenError::invalid("The rate of FFPE artifact sites must be between 0 and 1."));
+        }
+        // Only reads from the damaged strand, half of them, can show an artifact.
+        if !(max_fraction > 0.0 && max_fraction <= 0.5) {
+            return Err(FastqGenError::invalid("The allele fraction of FFPE artifacts must be greater than 0 and at most 0.5."));
+        }
+        let mut sites = vec![HashMap::new(); reference.contigs().count()];
+        if site_rate == 0.0 {
+            return Ok(FfpeArtifacts { sites });
+        }
+        let gap = Geometric::new(site_rate).map_err(|e| FastqGenError::invalid(format!("Invalid FFPE site rate: {}", e)))?;
+        for (contig, sites) in sites.iter_mut().enumerate() {
+            let seq = reference.contig_seq(contig);
+            let mut position = gap.sample(rng) as usize;
+            while position < seq.len() {
+                if matches!(seq[position], b'C' | b'G') {
+                    sites.insert(position, rng.random_range(0.0..max_fraction));
+                }
+                position += 1 + gap.sample(rng) as usize;
+            }
+        }
+        Ok(FfpeArtifacts { sites })
+    }
+
+    /// Damages `fragment`, whose forward-strand sequence it changes, in a molecule
+    /// read 1 sequences from the forward strand, or from the reverse strand when
+    /// `reverse`.
+    pub fn apply(&self, rng: &mut impl Rng, reference: &Reference, fragment: &mut Fragment, reverse: bool) {
+        // A C of the reverse strand is a G of the forward strand.
+        let (damaged, converted) = if reverse { (b'G', b'A') } else { (b'C', b'T') };
+        for (offset, base) in fragment.seq.iter_mut().enumerate() {
+            if *base != damaged {
+                continue;
+            }
+            let Some((contig, site)) = reference.reference_position(fragment.haplotype, fragment.contig, fragment.start + offset) else {
+                continue;
+            };
+            let fraction = self.sites.get(contig).and_then(|sites| sites.get(&site));
+            if let Some(&fraction) = fraction
+                && reference.contig_seq(contig)[site] == damaged
+                && rng.random_bool(2.0 * fraction)
+            {
+                *base = converted;
+            }
+        }
+    }
+
+    /// Writes every artifact site as a sites-only VCF: C>T or G>A, with its allele
+    /// fraction as `AF` and the pair orientation it shows in as `ORIENTATION`.
+    pub fn write_vcf(&self, out: &mut impl Write, reference: &Reference) -> io::Result<()> {
+        writeln!(out, "##fileformat=VCFv4.2")?;
+        writeln!(out, "##source=fastqgen-{}", env!("CARGO_PKG_VERSION"))?;
+        for (name, length) in reference.contigs() {
+            writeln!(out, "##contig=<ID={},length={}>", name, length)?;
+        }
+        writeln!(out, "##INFO=<ID=AF,Number=A,Type=Float,Description=\"Fraction of reads over the site that show the artifact\">")?;
+        writeln!(out, "##INFO=<ID=ORIENTATION,Number=1,Type=String,Description=\"Pair orientation the artifact shows in: F1R2 or F2R1\">")?;
+        writeln!(out, "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO")?;
+
+        for (contig, sites) in self.sites.iter().enumerate() {
+            let mut positions: Vec<(&usize, &f64)> = sites.iter().collect();
+            positions.sort_unstable_by_key(|&(position, _)| *position);
+            let seq = reference.contig_seq(contig);
+            for (&position, fraction) in positions {
+                let (alleles, orientation) = if seq[position] == b'C' { ("C\tT", "F1R2") } else { ("G\tA", "F2R1") };
+                writeln!(out, "{}\t{}\t.\t{}\t.\tPASS\tAF={:.4};ORIENTATION={}", reference.contig_name(contig), position + 1, alleles, fraction, orientation)?;
+            }
+        }
+        Ok(())
+    }
+}
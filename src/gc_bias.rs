@@ -0,0 +1,117 @@
+use std::io::{self, Write};
+
+use crate::error::FastqGenError;
+
+/// How PCR and cluster amplification favour fragments by their GC content: the
+/// relative chance a fragment is sequenced, a curve that peaks at one GC
+/// fraction and falls off on either side, where GC-poor fragments melt and
+/// GC-rich ones fail to denature. The curve is a two-piece normal: one half of
+/// a normal below the peak and another, of a different width, above it, so that
+/// it can lean toward AT-rich or GC-rich fragments.
+#[derive(Debug, Clone)]
+pub struct GcBias {
+    optimum: f64,
+    /// Standard deviations of the curve below and above the optimum.
+    widths: (f64, f64),
+}
+
+impl GcBias {
+    /// A curve peaking at GC fraction `optimum`, `width` wide on average. A
+    /// `skew` between -1 and 1 widens the side above the optimum (toward GC-rich
+    /// fragments) by that fraction of `width` and narrows the side below it by as
+    /// much, or the other way around when negative.
+    pub fn new(optimum: f64, width: f64, skew: f64) -> Result<Self, FastqGenError> {
+        if !(0.0..=1.0).contains(&optimum) {
+            return Err(FastqGenError::invalid("The optimal GC content must be between 0 and 1."));
+        }
+        if !(width > 0.0 && width.is_finite()) {
+            return Err(FastqGenError::invalid("The width of the GC bias curve must be positive."));
+        }
+        if !(skew > -1.0 && skew < 1.0) {
+            return Err(FastqGenError::invalid("The skew of the GC bias curve must be greater than -1 and less than 1."));
+        }
+        Ok(GcBias { optimum, widths: (width * (1.0 - skew), width * (1.0 + skew)) })
+    }
+
+    /// Relative chance, up to 1 at the optimum, that a fragment of GC fraction
+    /// `gc` is sequenced.
+    pub fn weight_at(&self, gc: f64) -> f64 {
+        let width = if gc < self.optimum { self.widths.0 } else { self.widths.1 };
+        let z = (gc - self.optimum) / width;
+        (-0.5 * z * z).exp()
+    }
+
+    /// Relative chance that the fragment `seq` is sequenced, from the GC fraction
+    /// of its A, C, G and T bases. Fragments of none are not biased.
+    pub fn weight(&self, seq: &[u8]) -> f64 {
+        let (gc, called) = seq.iter().fold((0usize, 0usize), |(gc, called), base| match base {
+            b'C' | b'G' => (gc + 1, called + 1),
+            b'A' | b'T' => (gc, called + 1),
+            _ => (gc, called),
+        });
+        match called {
+            0 => 1.0,
+            _ => self.weight_at(gc as f64 / called as f64),
+        }
+    }
+
+    /// Writes the curve as `gc<TAB>weight` lines, one per percent of GC content.
+    pub fn write_curve(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "gc\tweight")?;
+        for percent in 0..=100 {
+            let gc = percent as f64 / 100.0;
+            writeln!(out, "{:.2}\t{:.6}", gc, self.weight_at(gc))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_curve_peaks_at_the_optimum() {
+        let bias = GcBias::new(0.45, 0.1, 0.0).unwrap();
+        assert_eq!(bias.weight_at(0.45), 1.0);
+        assert!((bias.weight_at(0.35) - (-0.5f64).exp()).abs() < 1e-12);
+        assert!((bias.weight_at(0.35) - bias.weight_at(0.55)).abs() < 1e-12);
+        assert!(bias.weight_at(0.2) < bias.weight_at(0.3));
+    }
+
+    #[test]
+    fn skew_widens_the_gc_rich_side() {
+        let bias = GcBias::new(0.5, 0.1, 0.5).unwrap();
+        // One width above the optimum is 0.15 and below it 0.05.
+        assert!((bias.weight_at(0.65) - (-0.5f64).exp()).abs() < 1e-12);
+        assert!((bias.weight_at(0.45) - (-0.5f64).exp()).abs() < 1e-12);
+        assert!(bias.weight_at(0.6) > bias.weight_at(0.4));
+    }
+
+    #[test]
+    fn weight_counts_called_bases_only() {
+        let bias = GcBias::new(0.5, 0.1, 0.0).unwrap();
+        assert_eq!(bias.weight(b"ACGTNN"), 1.0);
+        assert_eq!(bias.weight(b"NNN"), 1.0);
+        assert_eq!(bias.weight(b""), 1.0);
+        assert_eq!(bias.weight(b"GGCC"), bias.weight_at(1.0));
+    }
+
+    #[test]
+    fn rejects_invalid_curves() {
+        assert!(GcBias::new(1.5, 0.1, 0.0).is_err());
+        assert!(GcBias::new(0.5, 0.0, 0.0).is_err());
+        assert!(GcBias::new(0.5, f64::INFINITY, 0.0).is_err());
+        assert!(GcBias::new(0.5, 0.1, 1.0).is_err());
+        assert!(GcBias::new(0.5, 0.1, -1.0).is_err());
+    }
+
+    #[test]
+    fn writes_one_line_per_percent() {
+        let mut out = Vec::new();
+        GcBias::new(0.5, 0.1, 0.0).unwrap().write_curve(&mut out).unwrap();
+        let curve = String::from_utf8(out).unwrap();
+        assert_eq!(curve.lines().count(), 102);
+        assert!(curve.contains("\n0.50\t1.000000\n"));
+    }
+}
@@ -0,0 +1,1153 @@
+use rand::Rng;
+use rand::distr::{Distribution, Uniform};
+use rand::distr::weighted::WeightedIndex;
+use rand::prelude::IndexedRandom;
+use rand_distr::Normal;
+use std::mem;
+use std::ops::Range;
+
+use crate::error::FastqGenError;
+use crate::alignment::Alignment;
+use crate::amplicon::{AmpliconPanel, PanelTarget};
+use crate::atac::AtacLibrary;
+use crate::capture::CaptureTargets;
+use crate::damage::DamageModel;
+use crate::hic::ContactModel;
+use crate::ffpe::FfpeArtifacts;
+use crate::gc_bias::GcBias;
+use crate::error_model::{ErrorModel, SequencedRead, ERROR_PHRED_RANGE, substitute};
+use crate::length::ReadLengths;
+use crate::markov::MarkovModel;
+use crate::methylation::Methylation;
+use crate::naming::NameFormat;
+use crate::quality::{QualityBins, QualityModel};
+use crate::reference::{Fragment, Reference};
+use crate::regions::Regions;
+use crate::seq::{reverse_complement, reverse_complement_into};
+use crate::umi::{Umi, UmiPlacement};
+
+/// Illumina TruSeq adapter read into by read 1 when the fragment is shorter than the read.
+pub const TRUSEQ_ADAPTER_1: &[u8] = b"AGATCGGAAGAGCACACGTCTGAACTCCAGTCA";
+/// Illumina TruSeq adapter read into by read 2.
+pub const TRUSEQ_ADAPTER_2: &[u8] = b"AGATCGGAAGAGCGTCGTGTAGGGAAAGAGTGT";
+/// Nextera (Tn5) adapter read into by both reads of ATAC-seq and Nextera libraries.
+pub const NEXTERA_ADAPTER: &[u8] = b"CTGTCTCTTATACACATCT";
+
+/// Most reference fragments drawn for one read under a GC bias; the last is kept
+/// even if the bias rejects it.
+const MAX_GC_BIAS_DRAWS: usize = 1000;
+
+/// A single-end read.
+#[derive(Debug, Clone, Default)]
+pub struct FastqRecord {
+    pub id: String,
+    pub seq: Vec<u8>,
+    pub quality: Vec<u8>,
+    /// True origin of the read, when sampled from a reference.
+    pub alignment: Option<Alignment>,
+    /// UMI of the read's molecule, when UMIs are simulated.
+    pub umi: Option<Umi>,
+}
+
+/// A read pair sharing one name: `seq` is read 1 and `mate` is read 2.
+#[derive(Debug, Clone, Default)]
+pub struct PairedFastqRecord {
+    pub id: String,
+    pub seq: Vec<u8>,
+    pub mate: Vec<u8>,
+    pub quality_1: Vec<u8>,
+    pub quality_2: Vec<u8>,
+    /// True origins of read 1 and read 2, when sampled from a reference.
+    pub alignment_1: Option<Alignment>,
+    pub alignment_2: Option<Alignment>,
+    /// UMI of the pair's molecule, when UMIs are simulated.
+    pub umi: Option<Umi>,
+}
+
+/// A sampled fragment, ready to be sequenced one or more times.
+#[derive(Debug, Clone, Default)]
+pub struct Molecule {
+    /// The fragment in the orientation read 1 sequences it.
+    template: Vec<u8>,
+    origin: Option<FragmentOrigin>,
+    /// Lengths of read 1 and read 2.
+    lengths: (usize, usize),
+    umi: Option<Vec<u8>>,
+    /// The fragment read 2 sequences, in the orientation it reads it, when it is
+    /// not the other end of `template`, as in the ligation products of Hi-C.
+    mate_template: Option<Vec<u8>>,
+    mate_origin: Option<FragmentOrigin>,
+}
+
+impl Molecule {
+    /// The fragment in the orientation read 1 sequences it.
+    pub fn template(&self) -> &[u8] {
+        &self.template
+    }
+
+    /// The molecule's UMI, those of both ends in turn for duplex UMIs.
+    pub fn umi(&self) -> Option<&[u8]> {
+        self.umi.as_deref()
+    }
+}
+
+/// Scratch space reused from read to read by [`FastqGenerator::sequence_single_into`]
+/// and [`FastqGenerator::sequence_paired_into`].
+#[derive(Debug, Default)]
+pub struct ReadBuffers {
+    /// Reverse complement of the part of the template a mate reads.
+    antisense: Vec<u8>,
+    /// Template followed by adapter, for reads that run off its end.
+    extended: Vec<u8>,
+    read_1: SequencedRead,
+    read_2: SequencedRead,
+}
+
+/// Where a fragment was drawn from on the reference.
+#[derive(Debug, Clone, Copy)]
+struct FragmentOrigin {
+    haplotype: usize,
+    contig: usize,
+    start: usize,
+    length: usize,
+    /// Whether read 1 is sequenced from the reverse strand.
+    reverse: bool,
+}
+
+/// How the two mates of a pair face each other on the fragment.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MateOrientation {
+    /// Inward-facing, as in standard paired-end libraries: read 1 on the forward
+    /// strand at one end, read 2 on the reverse strand at the other.
+    #[default]
+    Fr,
+    /// Outward-facing, as in mate-pair libraries.
+    Rf,
+    /// Both mates read the same strand.
+    Ff,
+}
+
+impl FragmentOrigin {
+    /// True alignment of a read sequenced from the 5' end of the `span` of this
+    /// fragment, or of its reverse complement when `antisense`.
+    fn alignment(&self, reference: &Reference, read: &SequencedRead, span: &Range<usize>, antisense: bool) -> Alignment {
+        let reverse = self.reverse != antisense;
+        let (span_start, span_end) = if self.reverse {
+            (self.start + self.length - span.end, self.start + self.length - span.start)
+        } else {
+            (self.start + span.start, self.start + span.end)
+        };
+        let (position, cigar) = if reverse {
+            let end = span_end - read.template_offset;
+            (end - read.cigar.reference_length(), read.cigar.reversed())
+        } else {
+            (span_start + read.template_offset, read.cigar.clone())
+        };
+        let alignment = Alignment { contig: self.contig, position, reverse, cigar, edit_distance: read.edit_distance, haplotype: self.haplotype };
+        reference.lift_alignment(self.haplotype, alignment)
+    }
+}
+
+/// Generates synthetic reads from random sequence or a [`Reference`], configured
+/// builder-style with the `with_*` methods.
+#[derive(Debug)]
+pub struct FastqGenerator {
+    bases: &'static [u8],
+    read_lengths: ReadLengths,
+    /// Lengths of read 2, when they differ from read 1's.
+    mate_read_lengths: Option<ReadLengths>,
+    quality_range: Range<u8>,
+    /// Uniform distributions over `quality_range` and over the scores of
+    /// erroneous bases, built once rather than for every read.
+    uniform_quality: Uniform<u8>,
+    error_quality: Uniform<u8>,
+    quality_model: QualityModel,
+    /// Quality model of read 2, when it differs from read 1's.
+    mate_quality_model: Option<QualityModel>,
+    reference: Option<Reference>,
+    error_model: ErrorModel,
+    /// Error model of read 2, when it differs from read 1's.
+    mate_error_model: Option<ErrorModel>,
+    /// Phred points taken off every quality score of read 2.
+    mate_quality_penalty: u8,
+    insert_size: Option<Normal<f64>>,
+    name_format: NameFormat,
+    quality_bins: Option<QualityBins>,
+    poly_g_rate: f64,
+    /// Fraction of bases called `N`, and the higher fraction in the first
+    /// `early_n_cycles` cycles.
+    n_rate: f64,
+    early_n_rate: f64,
+    early_n_cycles: usize,
+    /// Weights of A, C, G and T in random sequence; uniform when unset.
+    composition: Option<WeightedIndex<f64>>,
+    /// Markov chain random sequence is drawn from instead of independent bases.
+    markov: Option<MarkovModel>,
+    /// Which way the mates of a pair face on their fragment.
+    orientation: MateOrientation,
+    /// Adapters following the fragment in read 1 and read 2.
+    adapters: Option<(Vec<u8>, Vec<u8>)>,
+    /// Length and placement of the UMI drawn for every molecule.
+    umi: Option<(usize, UmiPlacement)>,
+    /// Give every molecule a UMI at each end rather than one.
+    duplex_umis: bool,
+    /// Per-base substitution rate of index reads.
+    barcode_error_rate: f64,
+    /// Methylation of the reference when simulating bisulfite sequencing.
+    methylation: Option<Methylation>,
+    /// Deamination of reference fragments when simulating ancient DNA.
+    damage: Option<DamageModel>,
+    /// Formalin damage of reference fragments when simulating FFPE samples.
+    ffpe: Option<FfpeArtifacts>,
+    /// Targeted panel whose amplicons reference fragments are drawn from.
+    amplicons: Option<AmpliconPanel>,
+    /// Capture targets that most reference fragments are drawn around.
+    capture: Option<CaptureTargets>,
+    /// ATAC-seq library whose peaks and fragment lengths reference fragments follow.
+    atac: Option<AtacLibrary>,
+    /// Intervals every other reference fragment is drawn from.
+    regions: Option<Regions>,
+    /// Curve by which reference fragments are kept or drawn again by GC content.
+    gc_bias: Option<GcBias>,
+    /// Contact model whose ligated loci the mates of Hi-C pairs are read from.
+    contacts: Option<ContactModel>,
+    /// Draw uniform random bases 32 to a random number rather than one per draw.
+    packed_bases: bool,
+    /// Index of the first read of the next [`Self::generate_batch`].
+    next_batch_index: u64,
+}
+
+impl FastqGenerator {
+    pub fn new(read_length: usize) -> Self {
+        let phred_range: Range<u8> = 33u8..74u8;
+        FastqGenerator { 
+            bases: b"ATCG", 
+            read_lengths: ReadLengths::Fixed(read_length),
+            mate_read_lengths: None,
+            uniform_quality: uniform_quality(&phred_range),
+            error_quality: error_quality(&phred_range),
+            quality_range: phred_range,
+            quality_model: QualityModel::Uniform,
+            mate_quality_model: None,
+            reference: None,
+            error_model: ErrorModel::default(),
+            mate_error_model: None,
+            mate_quality_penalty: 0,
+            insert_size: None,
+            name_format: NameFormat::default(),
+            quality_bins: None,
+            poly_g_rate: 0.0,
+            n_rate: 0.0,
+            early_n_rate: 0.0,
+            early_n_cycles: 0,
+            composition: None,
+            markov: None,
+            orientation: MateOrientation::Fr,
+            adapters: None,
+            umi: None,
+            duplex_umis: false,
+            barcode_error_rate: 0.0,
+            methylation: None,
+            damage: None,
+            ffpe: None,
+            amplicons: None,
+            capture: None,
+            atac: None,
+            regions: None,
+            gc_bias: None,
+            contacts: None,
+            packed_bases: false,
+            next_batch_index: 0,
+        }
+    }
+
+    /// Encodes qualities with ASCII offset `offset` (33 or 64) and caps them at
+    /// Phred score `max_quality`, instead of Phred+33 up to Q40.
+    pub fn with_quality_encoding(mut self, offset: u8, max_quality: u8) -> Result<Self, FastqGenError> {
+        if offset != 33 && offset != 64 {
+            return Err(FastqGenError::invalid("Phred offset must be 33 or 64."));
+        }
+        // Scores must stay printable, up to '~'.
+        let highest = b'~' - offset;
+        if max_quality > highest {
+            return Err(FastqGenError::invalid(format!("Maximum quality must be at most {} with Phred offset {}.", highest, offset)));
+        }
+        self.quality_range = offset..offset + max_quality + 1;
+        self.uniform_quality = uniform_quality(&self.quality_range);
+        self.error_quality = error_quality(&self.quality_range);
+        Ok(self)
+    }
+
+    pub fn with_reference(mut self, reference: Reference) -> Self {
+        self.reference = Some(reference);
+        self
+    }
+
+    /// Draws every read's length from `read_lengths` instead of the fixed length
+    /// passed to [`Self::new`].
+    pub fn with_read_lengths(mut self, read_lengths: ReadLengths) -> Self {
+        self.read_lengths = read_lengths;
+        self
+    }
+
+    /// Draws the length of read 2 from `read_lengths`, so that the mates of a pair
+    /// can differ, such as a short technical read 1 and a long read 2.
+    pub fn with_mate_read_lengths(mut self, read_lengths: ReadLengths) -> Self {
+        self.mate_read_lengths = Some(read_lengths);
+        self
+    }
+
+    pub fn with_quality_model(mut self, quality_model: QualityModel) -> Self {
+        self.quality_model = quality_model;
+        self
+    }
+
+    /// Draws the qualities of read 2 from `quality_model` rather than read 1's model.
+    pub fn with_mate_quality_model(mut self, quality_model: QualityModel) -> Self {
+        self.mate_quality_model = Some(quality_model);
+        self
+    }
+
+    pub fn with_error_model(mut self, error_model: ErrorModel) -> Self {
+        self.error_model = error_model;
+        self
+    }
+
+    /// Sequences read 2 with `error_model` rather than read 1's, as real read 2s
+    /// carry more errors.
+    pub fn with_mate_error_model(mut self, error_model: ErrorModel) -> Self {
+        self.mate_error_model = Some(error_model);
+        self
+    }
+
+    /// Lowers every quality score of read 2 by `penalty` Phred points.
+    pub fn with_mate_quality_penalty(mut self, penalty: u8) -> Self {
+        self.mate_quality_penalty = penalty;
+        self
+    }
+
+    fn mate_error_model(&self) -> &ErrorModel {
+        self.mate_error_model.as_ref().unwrap_or(&self.error_model)
+    }
+
+    /// Template bases to sample beyond a read of `read_length` bases, enough for
+    /// the deletions of either mate.
+    fn template_padding(&self, read_length: usize) -> usize {
+        self.error_model.template_padding(read_length).max(self.mate_error_model().template_padding(read_length))
+    }
+
+    pub fn with_name_format(mut self, name_format: NameFormat) -> Self {
+        self.name_format = name_format;
+        self
+    }
+
+    /// Reports binned quality scores, applied after every other quality adjustment.
+    pub fn with_quality_bins(mut self, quality_bins: QualityBins) -> Self {
+        self.quality_bins = Some(quality_bins);
+        self
+    }
+
+    /// Makes a `rate` fraction of reads lose signal at a uniformly chosen cycle, after
+    /// which a two-colour instrument calls G at high quality.
+    pub fn with_poly_g_rate(mut self, rate: f64) -> Result<Self, FastqGenError> {
+        if !(0.0..=1.0).contains(&rate) {
+            return Err(FastqGenError::invalid("Poly-G rate must be between 0 and 1."));
+        }
+        self.poly_g_rate = rate;
+        Ok(self)
+    }
+
+    /// Replaces a `rate` fraction of bases with no-calls, `N` at Q2, and an
+    /// `early_rate` fraction of those in the first `early_cycles` cycles, where
+    /// instruments call `N` more often while clusters are being located.
+    pub fn with_n_rate(mut self, rate: f64, early_rate: f64, early_cycles: usize) -> Result<Self, FastqGenError> {
+        if !(0.0..=1.0).contains(&rate) || !(0.0..=1.0).contains(&early_rate) {
+            return Err(FastqGenError::invalid("N rates must be between 0 and 1."));
+        }
+        self.n_rate = rate;
+        self.early_n_rate = early_rate;
+        self.early_n_cycles = early_cycles;
+        Ok(self)
+    }
+
+    /// Draws random sequence with the given relative weights of A, C, G and T.
+    pub fn with_base_weights(mut self, weights: [f64; 4]) -> Result<Self, FastqGenError> {
+        let composition = WeightedIndex::new(weights).map_err(|e| FastqGenError::invalid(format!("Invalid base weights: {}", e)))?;
+        self.composition = Some(composition);
+        Ok(self)
+    }
+
+    /// Draws random sequence from an order-k Markov chain, so that it has the k-mer
+    /// composition the chain was trained on.
+    pub fn with_markov_model(mut self, model: MarkovModel) -> Self {
+        self.markov = Some(model);
+        self
+    }
+
+    /// Draws uniform random bases two bits at a time from 64-bit random numbers,
+    /// rather than with one draw each. Faster, but the same seed gives different
+    /// sequence.
+    pub fn with_packed_bases(mut self) -> Self {
+        self.packed_bases = true;
+        self
+    }
+
+    /// Draws random sequence with a `gc` fraction of G and C bases.
+    pub fn with_gc_content(self, gc: f64) -> Result<Self, FastqGenError> {
+        if !(0.0..=1.0).contains(&gc) {
+            return Err(FastqGenError::invalid("GC content must be between 0 and 1."));
+        }
+        let at = (1.0 - gc) / 2.0;
+        self.with_base_weights([at, gc / 2.0, gc / 2.0, at])
+    }
+
+    /// Sequences the mates of a pair in `orientation` rather than facing inward.
+    pub fn with_orientation(mut self, orientation: MateOrientation) -> Self {
+        self.orientation = orientation;
+        self
+    }
+
+    /// Lets fragments be shorter than the read length, so reads run through the
+    /// fragment into `adapter_1` (read 1) or `adapter_2` (read 2) and then random
+    /// sequence. Fragment lengths come from the insert-size distribution, so this
+    /// has no effect without one. Adapter bases are soft-clipped in truth alignments.
+    pub fn with_adapters(mut self, adapter_1: Vec<u8>, adapter_2: Vec<u8>) -> Self {
+        self.adapters = Some((adapter_1, adapter_2));
+        self
+    }
+
+    /// Gives every molecule a random UMI of `length` bases, written where
+    /// `placement` says. Duplicates of a molecule carry the same UMI.
+    pub fn with_umi(mut self, length: usize, placement: UmiPlacement) -> Result<Self, FastqGenError> {
+        if length == 0 {
+            return Err(FastqGenError::invalid("UMI length must be positive."));
+        }
+        self.umi = Some((length, placement));
+        Ok(self)
+    }
+
+    /// Gives every molecule a UMI of the [`Self::with_umi`] length at each end,
+    /// as in duplex sequencing. Placed in the reads, the first is read at the
+    /// start of read 1 and the second at the start of read 2; in the name, they
+    /// are joined with `-`.
+    pub fn with_duplex_umis(mut self) -> Self {
+        self.duplex_umis = true;
+        self
+    }
+
+    /// Sets the per-base substitution rate of index reads sequenced with
+    /// [`Self::sequence_barcode`].
+    pub fn with_barcode_error_rate(mut self, rate: f64) -> Result<Self, FastqGenError> {
+        if !(0.0..=1.0).contains(&rate) {
+            return Err(FastqGenError::invalid("Barcode error rate must be between 0 and 1."));
+        }
+        self.barcode_error_rate = rate;
+        Ok(self)
+    }
+
+    /// Simulates bisulfite sequencing: reference fragments are converted according
+    /// to `methylation` before they are sequenced, so errors come on top of the
+    /// conversions. Needs a reference.
+    pub fn with_bisulfite(mut self, methylation: Methylation) -> Self {
+        self.methylation = Some(methylation);
+        self
+    }
+
+    /// Simulates ancient DNA: reference fragments are deaminated by `damage` after
+    /// any bisulfite conversion, so their C→T and G→A changes show in the reads
+    /// (and as mismatches against the truth alignments) before sequencing errors.
+    /// Needs a reference.
+    pub fn with_damage(mut self, damage: DamageModel) -> Self {
+        self.damage = Some(damage);
+        self
+    }
+
+    /// Simulates an FFPE sample: reference fragments carry the C→T artifacts of
+    /// `artifacts` on the strand read 1 sequences, before any bisulfite conversion
+    /// or ancient DNA damage. Needs a reference.
+    pub fn with_ffpe(mut self, artifacts: FfpeArtifacts) -> Self {
+        self.ffpe = Some(artifacts);
+        self
+    }
+
+    /// Draws reference fragments from the amplicons of a targeted panel, so that
+    /// reads start exactly at a primer, apart from the panel's off-target
+    /// fragments and primer dimers. Needs a reference.
+    pub fn with_amplicons(mut self, panel: AmpliconPanel) -> Self {
+        self.amplicons = Some(panel);
+        self
+    }
+
+    /// Draws reference fragments from within `regions` rather than the whole
+    /// reference, apart from those of capture targets and ATAC-seq peaks. Needs a
+    /// reference.
+    pub fn with_regions(mut self, regions: Regions) -> Self {
+        self.regions = Some(regions);
+        self
+    }
+
+    /// Keeps reference fragments with the probability `bias` gives their GC
+    /// content, drawing others in their place, so that coverage follows GC
+    /// content as after PCR. Amplicons are not biased.
+    pub fn with_gc_bias(mut self, bias: GcBias) -> Self {
+        self.gc_bias = Some(bias);
+        self
+    }
+
+    /// Draws most reference fragments from around capture targets, as in exome
+    /// sequencing. Needs a reference.
+    pub fn with_capture(mut self, capture: CaptureTargets) -> Self {
+        self.capture = Some(capture);
+        self
+    }
+
+    /// Draws fragments as Tn5 cuts them in an ATAC-seq library: with its
+    /// nucleosomal fragment lengths in place of the insert-size distribution, and
+    /// most of them from its peaks. Needs a reference.
+    pub fn with_atac(mut self, atac: AtacLibrary) -> Self {
+        self.atac = Some(atac);
+        self
+    }
+
+    /// Reads the mates of each pair from two loci ligated in a Hi-C library,
+    /// placed by `contacts`, rather than from the two ends of one fragment. Each
+    /// mate reads a fragment of its read length, from either strand. Needs a
+    /// reference; single-end reads are unaffected.
+    pub fn with_hic(mut self, contacts: ContactModel) -> Self {
+        self.contacts = Some(contacts);
+        self
+    }
+
+    /// ASCII offset of the generated quality scores.
+    pub fn quality_offset(&self) -> u8 {
+        self.quality_range.start
+    }
+
+    pub fn name_format(&self) -> &NameFormat {
+        &self.name_format
+    }
+
+    /// Where UMIs are placed, when they are simulated.
+    pub fn umi_placement(&self) -> Option<UmiPlacement> {
+        self.umi.map(|(_, placement)| placement)
+    }
+
+    pub fn reference(&self) -> Option<&Reference> {
+        self.reference.as_ref()
+    }
+
+    /// Draws fragment lengths from a normal insert-size distribution, so that R1 and R2
+    /// are read from opposite ends of the fragment rather than covering the same bases.
+    pub fn with_insert_size(mut self, mean: f64, sd: f64) -> Result<Self, FastqGenError> {
+        if mean <= 0.0 {
+            return Err(FastqGenError::invalid("Insert size mean must be positive."));
+        }
+        self.insert_size = Some(Normal::new(mean, sd).map_err(|e| FastqGenError::invalid(format!("Invalid insert size distribution: {}", e)))?);
+        Ok(self)
+    }
+
+    /// Shortest fragment ever sampled: the shortest read length (of the longer mate)
+    /// plus any slack the error model needs to absorb deletions.
+    pub fn min_fragment_length(&self) -> usize {
+        let read_length = self.read_lengths.min().max(self.mate_read_lengths.as_ref().map_or(0, ReadLengths::min));
+        read_length + self.template_padding(read_length)
+    }
+
+    /// Samples a fragment length from the insert-size distribution (or the ATAC-seq
+    /// library's fragment lengths), never shorter
+    /// than `read_length` plus padding (or a single base plus padding when reads may
+    /// run into the adapter). Without an insert-size model every fragment is exactly
+    /// the read length plus padding and the mates fully overlap.
+    fn sample_fragment_length(&self, rng: &mut impl Rng, read_length: usize) -> usize {
+        let insert = match (&self.atac, &self.insert_size) {
+            (Some(atac), _) => atac.fragment_length(rng),
+            (None, Some(dist)) => dist.sample(rng).round().max(0.0) as usize,
+            (None, None) => 0,
+        };
+        let padding = self.template_padding(read_length);
+        let minimum = if self.adapters.is_some() && (self.insert_size.is_some() || self.atac.is_some()) { 1 } else { read_length };
+        insert.max(minimum) + padding
+    }
+
+    fn sample_quality(&self, rng: &mut impl Rng, length: usize) -> Vec<u8> {
+        let mut quality = Vec::with_capacity(length);
+        self.sample_quality_into(rng, &self.quality_model, length, &mut quality);
+        quality
+    }
+
+    /// Appends `length` scores drawn from `model` to `out`.
+    fn sample_quality_into(&self, rng: &mut impl Rng, model: &QualityModel, length: usize, out: &mut Vec<u8>) {
+        match model {
+            QualityModel::Uniform => out.extend((0..length).map(|_| self.uniform_quality.sample(rng))),
+            model => model.sample_into(rng, length, &self.quality_range, out),
+        }
+    }
+
+    /// Appends `length` random bases to `out`.
+    fn sample_seq_into(&self, rng: &mut impl Rng, length: usize, out: &mut Vec<u8>) {
+        if let Some(markov) = &self.markov {
+            return markov.sample_into(rng, length, out);
+        }
+        match &self.composition {
+            Some(composition) => out.extend((0..length).map(|_| b"ACGT"[composition.sample(rng)])),
+            None if self.packed_bases => {
+                out.reserve(length);
+                let mut bits = 0;
+                for position in 0..length {
+                    if position % 32 == 0 {
+                        bits = rng.next_u64();
+                    }
+                    out.push(self.bases[(bits & 3) as usize]);
+                    bits >>= 2;
+                }
+            }
+            None => out.extend((0..length).map(|_| *self.bases.choose(rng).unwrap())),
+        }
+    }
+
+    /// Truth alignment of `read`, of the `span` of its fragment (reverse-complemented
+    /// when `antisense`), when the fragment came from the reference.
+    fn alignment(&self, origin: Option<FragmentOrigin>, read: &SequencedRead, span: &Range<usize>, antisense: bool) -> Option<Alignment> {
+        let reference = self.reference.as_ref()?;
+        origin.map(|origin| origin.alignment(reference, read, span, antisense))
+    }
+
+    /// The part of a `length`-base template each mate reads, and whether it reads
+    /// its reverse complement. Mates that face outward or the same way read only
+    /// the bases (`needed_1` and `needed_2`) at their end of the fragment.
+    fn mate_spans(&self, length: usize, needed_1: usize, needed_2: usize) -> [(Range<usize>, bool); 2] {
+        let (end_1, start_2) = (needed_1.min(length), length.saturating_sub(needed_2));
+        match self.orientation {
+            MateOrientation::Fr => [(0..length, false), (0..length, true)],
+            MateOrientation::Rf => [(0..end_1, true), (start_2..length, false)],
+            MateOrientation::Ff => [(0..length, false), (start_2..length, false)],
+        }
+    }
+
+    /// Draws the fragment in the orientation read 1 sequences it. Reference fragments
+    /// are read from either strand with equal probability, so the mate comes from the
+    /// opposite strand. The mate is sequenced from the 5' end of the reverse complement,
+    /// so mates overlap whenever the fragment is shorter than twice the read length.
+    /// Random fragments are written into `template`, reusing its allocation.
+    fn sample_template(&self, rng: &mut impl Rng, read_length: usize, template: &mut Vec<u8>) -> Option<FragmentOrigin> {
+        let length = self.sample_fragment_length(rng, read_length);
+        match &self.reference {
+            Some(reference) => {
+                let fragment = match reference.sample_spike_in(rng, length) {
+                    Some(fragment) => fragment,
+                    None => match self.amplicons.as_ref().map(|panel| (panel, panel.sample(rng))) {
+                        None => self.sample_reference_fragment(rng, reference, length),
+                        Some((_, PanelTarget::OffTarget)) => reference.sample_fragment(rng, length),
+                        Some((panel, PanelTarget::Amplicon(index))) => {
+                            let amplicon = &panel.amplicons()[index];
+                            reference.fragment_at(rng, amplicon.contig, amplicon.start, amplicon.end)
+                        }
+                        // Primer dimers come from no reference position, so they have no
+                        // truth alignment.
+                        Some((panel, PanelTarget::PrimerDimer(index))) => {
+                            *template = panel.primer_dimer(index);
+                            return None;
+                        }
+                    },
+                };
+                Some(self.fragment_template(rng, reference, fragment, template))
+            }
+            None => {
+                template.clear();
+                self.sample_seq_into(rng, length, template);
+                None
+            }
+        }
+    }
+
+    /// Draws a reference fragment of `length` bases from the capture targets, ATAC-seq
+    /// peaks or regions, or from anywhere, drawing again while the GC bias rejects it.
+    fn sample_reference_fragment(&self, rng: &mut impl Rng, reference: &Reference, length: usize) -> Fragment {
+        let mut draws = 0;
+        loop {
+            let fragment = match self.capture.as_ref().and_then(|capture| capture.sample(rng, reference, length))
+                .or_else(|| self.atac.as_ref().and_then(|atac| atac.sample(rng, reference, length)))
+                .or_else(|| self.regions.as_ref().map(|regions| regions.sample(rng, length)))
+            {
+                Some((contig, start, end)) => reference.fragment_at(rng, contig, start, end),
+                None => reference.sample_fragment(rng, length),
+            };
+            draws += 1;
+            match &self.gc_bias {
+                Some(bias) if draws < MAX_GC_BIAS_DRAWS && !rng.random_bool(bias.weight(&fragment.seq)) => continue,
+                _ => return fragment,
+            }
+        }
+    }
+
+    /// Reads `fragment` from either strand with equal probability into `template`,
+    /// bisulfite-converting it when simulating bisulfite sequencing and damaging it
+    /// when simulating FFPE samples or ancient DNA.
+    fn fragment_template(&self, rng: &mut impl Rng, reference: &Reference, mut fragment: Fragment, template: &mut Vec<u8>) -> FragmentOrigin {
+        let reverse = !rng.random_bool(0.5);
+        if let Some(ffpe) = &self.ffpe {
+            ffpe.apply(rng, reference, &mut fragment, reverse);
+        }
+        let origin = FragmentOrigin { haplotype: fragment.haplotype, contig: fragment.contig, start: fragment.start, length: fragment.seq.len(), reverse };
+        *template = match &self.methylation {
+            Some(methylation) => methylation.convert(rng, reference, &fragment, reverse),
+            None if reverse => reverse_complement(&fragment.seq),
+            None => fragment.seq,
+        };
+        if let Some(damage) = &self.damage {
+            damage.deaminate(rng, template);
+        }
+        origin
+    }
+
+    /// Sequences one read of `quality.len()` bases from the 5' end of `template` into
+    /// `read`, applying `error_model` and lowering the quality of every erroneous base.
+    fn sequence_read(&self, rng: &mut impl Rng, error_model: &ErrorModel, template: &[u8], quality: &mut [u8], read: &mut SequencedRead) {
+        if error_model.is_error_free() {
+            return read.set_exact(template, quality.len());
+        }
+
+        let offset = self.quality_range.start;
+        error_model.sequence_into(rng, template, quality, offset, self.bases, read);
+        if error_model.is_quality_driven() {
+            return;
+        }
+        for &position in &read.errors {
+            quality[position] = quality[position].min(self.error_quality.sample(rng));
+        }
+    }
+
+    /// A read never runs past the end of a molecule shorter than itself, unless it
+    /// can read on into the adapter.
+    fn capped_length(&self, length: usize, template: &[u8]) -> usize {
+        if self.adapters.is_some() { length } else { length.min(template.len()) }
+    }
+
+    /// Sequences a read from `template` like [`Self::sequence_read`], first appending
+    /// the adapter and random sequence (in `extended`) when the read would otherwise
+    /// run off the end. Bases past the template are soft-clipped in the CIGAR.
+    #[allow(clippy::too_many_arguments)]
+    fn sequence_through(
+        &self,
+        rng: &mut impl Rng,
+        error_model: &ErrorModel,
+        template: &[u8],
+        adapter: Option<&[u8]>,
+        quality: &mut [u8],
+        extended: &mut Vec<u8>,
+        read: &mut SequencedRead,
+    ) {
+        let needed = quality.len() + error_model.template_padding(quality.len());
+        let Some(adapter) = adapter.filter(|_| template.len() < needed) else {
+            return self.sequence_read(rng, error_model, template, quality, read);
+        };
+
+        extended.clear();
+        extended.extend_from_slice(template);
+        extended.extend_from_slice(adapter);
+        if extended.len() < needed {
+            self.sample_seq_into(rng, needed - extended.len(), extended);
+        }
+        self.sequence_read(rng, error_model, extended, quality, read);
+        read.cigar = read.cigar.clipped(template.len().saturating_sub(read.template_offset));
+    }
+
+    /// Applies instrument artifacts to a sequenced read: no-calls, poly-G tails,
+    /// then quality binning.
+    fn apply_artifacts(&self, rng: &mut impl Rng, seq: &mut [u8], quality: &mut [u8]) {
+        if self.n_rate > 0.0 || self.early_n_rate > 0.0 {
+            let no_call = (self.quality_range.start + 2).min(self.quality_range.end - 1);
+            for (cycle, (base, score)) in seq.iter_mut().zip(quality.iter_mut()).enumerate() {
+                let rate = if cycle < self.early_n_cycles { self.early_n_rate } else { self.n_rate };
+                if rng.random_bool(rate) {
+                    *base = b'N';
+                    *score = no_call;
+                }
+            }
+        }
+        if self.poly_g_rate > 0.0 && !seq.is_empty() && rng.random_bool(self.poly_g_rate) {
+            let dropout = rng.random_range(0..seq.len());
+            seq[dropout..].fill(b'G');
+            quality[dropout..].fill(self.quality_range.end - 1);
+        }
+        if let Some(bins) = &self.quality_bins {
+            bins.apply(quality, &self.quality_range);
+        }
+    }
+
+    /// Degrades an existing read as if it were sequenced again from `seq`: shifts
+    /// its `quality` (encoded with this generator's offset) by `quality_shift` Phred
+    /// points within the quality range, injects errors from the error model (read
+    /// 2's when `mate`), lowering the quality of erroneous bases, then applies no-calls,
+    /// poly-G tails and quality binning. Deletions that run past the end of `seq` leave
+    /// trailing `N`s. Returns the new bases; `quality` is updated in place.
+    pub fn degrade(&self, rng: &mut impl Rng, seq: &[u8], quality: &mut [u8], quality_shift: i16, mate: bool) -> Vec<u8> {
+        let (lowest, highest) = (self.quality_range.start as i16, self.quality_range.end as i16 - 1);
+        for score in quality.iter_mut() {
+            *score = (*score as i16 + quality_shift).clamp(lowest, highest) as u8;
+        }
+        let error_model = if mate { self.mate_error_model() } else { &self.error_model };
+        let mut read = SequencedRead::default();
+        self.sequence_read(rng, error_model, seq, quality, &mut read);
+        self.apply_artifacts(rng, &mut read.seq, quality);
+        read.seq
+    }
+
+    /// Draws a molecule to be sequenced: its template and read lengths (one, or one
+    /// per mate when `paired`).
+    pub fn sample_molecule(&self, rng: &mut impl Rng, paired: bool) -> Molecule {
+        let mut molecule = Molecule::default();
+        self.sample_molecule_into(rng, paired, &mut molecule);
+        molecule
+    }
+
+    /// Like [`Self::sample_molecule`], but overwrites `molecule`, reusing its buffers.
+    pub fn sample_molecule_into(&self, rng: &mut impl Rng, paired: bool, molecule: &mut Molecule) {
+        let length_1 = self.read_lengths.sample(rng);
+        let length_2 = if paired { self.mate_read_lengths.as_ref().unwrap_or(&self.read_lengths).sample(rng) } else { length_1 };
+        molecule.lengths = (length_1, length_2);
+        molecule.mate_origin = None;
+        match (&self.contacts, &self.reference) {
+            (Some(contacts), Some(reference)) if paired => {
+                let fragment = reference.sample_fragment(rng, length_1 + self.template_padding(length_1));
+                let partner = contacts.partner(rng, reference, &fragment, length_2 + self.template_padding(length_2));
+                molecule.origin = Some(self.fragment_template(rng, reference, fragment, &mut molecule.template));
+                let mate_template = molecule.mate_template.get_or_insert_with(Vec::new);
+                molecule.mate_origin = Some(self.fragment_template(rng, reference, partner, mate_template));
+            }
+            _ => {
+                molecule.origin = self.sample_template(rng, length_1.max(length_2), &mut molecule.template);
+                molecule.mate_template = None;
+            }
+        }
+        self.sample_umi(rng, molecule);
+    }
+
+    /// Like [`Self::sample_molecule_into`], but draws the fragment from within
+    /// `parent`, a long stretch of the reference such as the molecule of a linked
+    /// read. Fragments are never longer than `parent`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the generator has no reference.
+    pub fn sample_molecule_within(&self, rng: &mut impl Rng, paired: bool, parent: &Fragment, molecule: &mut Molecule) {
+        let reference = self.reference.as_ref().expect("fragments within a parent molecule need a reference");
+        let length_1 = self.read_lengths.sample(rng);
+        let length_2 = if paired { self.mate_read_lengths.as_ref().unwrap_or(&self.read_lengths).sample(rng) } else { length_1 };
+        let length = self.sample_fragment_length(rng, length_1.max(length_2)).min(parent.seq.len());
+        let offset = rng.random_range(0..=parent.seq.len() - length);
+        let fragment = Fragment { haplotype: parent.haplotype, contig: parent.contig, start: parent.start + offset, seq: parent.seq[offset..offset + length].to_vec() };
+        molecule.origin = Some(self.fragment_template(rng, reference, fragment, &mut molecule.template));
+        molecule.lengths = (length_1, length_2);
+        (molecule.mate_template, molecule.mate_origin) = (None, None);
+        self.sample_umi(rng, molecule);
+    }
+
+    /// Like [`Self::sample_molecule_into`], but sequences `construct`, a synthetic
+    /// molecule such as a guide RNA in its vector, from its 5' end. Constructs come
+    /// from no reference position.
+    pub fn sample_construct_into(&self, rng: &mut impl Rng, paired: bool, construct: &[u8], molecule: &mut Molecule) {
+        molecule.template.clear();
+        molecule.template.extend_from_slice(construct);
+        let length_1 = self.read_lengths.sample(rng);
+        let length_2 = if paired { self.mate_read_lengths.as_ref().unwrap_or(&self.read_lengths).sample(rng) } else { length_1 };
+        molecule.lengths = (length_1, length_2);
+        (molecule.origin, molecule.mate_template, molecule.mate_origin) = (None, None, None);
+        self.sample_umi(rng, molecule);
+    }
+
+    /// Copies `original` into `molecule` as PCR does, substituting bases of its
+    /// template and UMI at `pcr_error_rate`. A copy of the `bottom` strand of a
+    /// duplex reads the original from its other end, with the UMIs of its ends
+    /// swapped.
+    pub fn copy_molecule_into(&self, rng: &mut impl Rng, original: &Molecule, bottom: bool, pcr_error_rate: f64, molecule: &mut Molecule) {
+        molecule.clone_from(original);
+        if bottom {
+            match molecule.mate_template.as_mut() {
+                // The mates of a ligation product read their own fragments.
+                Some(mate_template) => {
+                    mem::swap(&mut molecule.template, mate_template);
+                    mem::swap(&mut molecule.origin, &mut molecule.mate_origin);
+                }
+                None => {
+                    molecule.template.clear();
+                    reverse_complement_into(&original.template, &mut molecule.template);
+                    if let Some(origin) = molecule.origin.as_mut() {
+                        origin.reverse = !origin.reverse;
+                    }
+                }
+            }
+            if let Some(umi) = molecule.umi.as_mut().filter(|_| self.duplex_umis) {
+                let half = umi.len() / 2;
+                umi.rotate_left(half);
+            }
+        }
+        if pcr_error_rate > 0.0 {
+            let mate_template = molecule.mate_template.iter_mut().flatten();
+            let umi = molecule.umi.iter_mut().flatten();
+            for base in molecule.template.iter_mut().chain(mate_template).chain(umi) {
+                if rng.random_bool(pcr_error_rate) {
+                    *base = substitute(rng, *base, self.bases);
+                }
+            }
+        }
+    }
+
+    /// Draws a UMI for `molecule` when UMIs are simulated.
+    fn sample_umi(&self, rng: &mut impl Rng, molecule: &mut Molecule) {
+        match self.umi {
+            Some((length, _)) => {
+                let umi = molecule.umi.get_or_insert_with(Vec::new);
+                umi.clear();
+                self.sample_seq_into(rng, if self.duplex_umis { 2 * length } else { length }, umi);
+            }
+            None => molecule.umi = None,
+        }
+    }
+
+    /// Sequences an index read of `barcode`, substituting bases at the barcode error
+    /// rate and lowering their quality. Returns the bases and qualities.
+    pub fn sequence_barcode(&self, rng: &mut impl Rng, barcode: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let mut seq = barcode.to_vec();
+        let mut quality = self.sample_quality(rng, seq.len());
+        if self.barcode_error_rate > 0.0 {
+            for (base, quality) in seq.iter_mut().zip(quality.iter_mut()) {
+                if rng.random_bool(self.barcode_error_rate) {
+                    *base = substitute(rng, *base, self.bases);
+                    *quality = (*quality).min(self.error_quality.sample(rng));
+                }
+            }
+        }
+        if let Some(bins) = &self.quality_bins {
+            bins.apply(&mut quality, &self.quality_range);
+        }
+        (seq, quality)
+    }
+
+    /// Sequences the UMI of `molecule`, if it has one, and places it in the name
+    /// or in front of read 1 (`seq`, `quality` and `alignment`). The second UMI of
+    /// a duplex molecule goes in front of read 2 (`mate`), when there is one.
+    fn sequence_umi(
+        &self,
+        rng: &mut impl Rng,
+        molecule: &Molecule,
+        id: &mut String,
+        read: (&mut Vec<u8>, &mut Vec<u8>, Option<&mut Alignment>),
+        mate: Option<(&mut Vec<u8>, &mut Vec<u8>, Option<&mut Alignment>)>,
+    ) -> Option<Umi> {
+        let (umi_seq, (_, placement)) = molecule.umi.as_ref().zip(self.umi)?;
+        let mut umi_quality = self.sample_quality(rng, umi_seq.len());
+        if let Some(bins) = &self.quality_bins {
+            bins.apply(&mut umi_quality, &self.quality_range);
+        }
+        let split = if self.duplex_umis { umi_seq.len() / 2 } else { umi_seq.len() };
+        match placement {
+            UmiPlacement::Read => match mate.filter(|_| self.duplex_umis) {
+                Some(mate) => {
+                    prepend_umi(read, &umi_seq[..split], &umi_quality[..split]);
+                    prepend_umi(mate, &umi_seq[split..], &umi_quality[split..]);
+                }
+                None => prepend_umi(read, umi_seq, &umi_quality),
+            },
+            UmiPlacement::Name => {
+                id.push(':');
+                id.push_str(std::str::from_utf8(&umi_seq[..split]).expect("UMI bases are ASCII"));
+                if split < umi_seq.len() {
+                    id.push('-');
+                    id.push_str(std::str::from_utf8(&umi_seq[split..]).expect("UMI bases are ASCII"));
+                }
+            }
+            UmiPlacement::Index => {}
+        }
+        if split == umi_seq.len() {
+            return Some(Umi { seq: umi_seq.clone(), quality: umi_quality });
+        }
+        let seq = [&umi_seq[..split], b"-", &umi_seq[split..]].concat();
+        let quality = [&umi_quality[..split], b" ", &umi_quality[split..]].concat();
+        Some(Umi { seq, quality })
+    }
+
+    pub fn generate_single_record(&self, rng: &mut impl Rng, id_index: u64) -> FastqRecord {
+        let molecule = self.sample_molecule(rng, false);
+        self.sequence_single(rng, &molecule, id_index)
+    }
+
+    pub fn generate_paired_record(&self, rng: &mut impl Rng, id_index: u64) -> PairedFastqRecord {
+        let molecule = self.sample_molecule(rng, true);
+        self.sequence_paired(rng, &molecule, id_index)
+    }
+
+    /// Replaces the contents of `records` with `n` read pairs, reusing the buffers
+    /// of the records already there. Reads are numbered on from the previous
+    /// batch, so names stay unique across batches, and generating batch after batch
+    /// into the same vector allocates little once it has warmed up.
+    pub fn generate_batch(&mut self, rng: &mut impl Rng, n: usize, records: &mut Vec<PairedFastqRecord>) {
+        records.resize_with(n, PairedFastqRecord::default);
+        let (mut molecule, mut buffers) = (Molecule::default(), ReadBuffers::default());
+        for (index, record) in (self.next_batch_index..).zip(records.iter_mut()) {
+            self.sample_molecule_into(rng, true, &mut molecule);
+            self.sequence_paired_into(rng, &molecule, index, &mut buffers, record);
+        }
+        self.next_batch_index += n as u64;
+    }
+
+    /// Sequences a single-end read from `molecule`. Sequencing the same molecule
+    /// again gives a duplicate with independent qualities and errors.
+    pub fn sequence_single(&self, rng: &mut impl Rng, molecule: &Molecule, id_index: u64) -> FastqRecord {
+        let mut record = FastqRecord::default();
+        self.sequence_single_into(rng, molecule, id_index, &mut ReadBuffers::default(), &mut record);
+        record
+    }
+
+    /// Like [`Self::sequence_single`], but overwrites `record`, reusing its buffers
+    /// and those of `buffers`, so that sequencing allocates nothing once they have
+    /// grown to fit the longest read.
+    pub fn sequence_single_into(&self, rng: &mut impl Rng, molecule: &Molecule, id_index: u64, buffers: &mut ReadBuffers, record: &mut FastqRecord) {
+        let template = &molecule.template;
+        let read = &mut buffers.read_1;
+        record.quality.clear();
+        self.sample_quality_into(rng, &self.quality_model, self.capped_length(molecule.lengths.0, template), &mut record.quality);
+        let adapter = self.adapters.as_ref().map(|(adapter, _)| &adapter[..]);
+        self.sequence_through(rng, &self.error_model, template, adapter, &mut record.quality, &mut buffers.extended, read);
+        record.alignment = self.alignment(molecule.origin, read, &(0..template.len()), false);
+        self.apply_artifacts(rng, &mut read.seq, &mut record.quality);
+        mem::swap(&mut record.seq, &mut read.seq);
+        record.id.clear();
+        self.name_format.write_read_id(id_index, &mut record.id);
+        record.umi = self.sequence_umi(rng, molecule, &mut record.id, (&mut record.seq, &mut record.quality, record.alignment.as_mut()), None);
+    }
+
+    /// Sequences both mates of `molecule`, as [`Self::sequence_single`].
+    pub fn sequence_paired(&self, rng: &mut impl Rng, molecule: &Molecule, id_index: u64) -> PairedFastqRecord {
+        let mut record = PairedFastqRecord::default();
+        self.sequence_paired_into(rng, molecule, id_index, &mut ReadBuffers::default(), &mut record);
+        record
+    }
+
+    /// Sequences both mates of `molecule` into `record`, as [`Self::sequence_single_into`].
+    pub fn sequence_paired_into(&self, rng: &mut impl Rng, molecule: &Molecule, id_index: u64, buffers: &mut ReadBuffers, record: &mut PairedFastqRecord) {
+        let template = &molecule.template;
+        let mate_template = molecule.mate_template.as_deref().unwrap_or(template);
+        let (length_1, length_2) = molecule.lengths;
+        let ReadBuffers { antisense, extended, read_1, read_2 } = buffers;
+        let (qual_1, qual_2) = (&mut record.quality_1, &mut record.quality_2);
+        qual_1.clear();
+        qual_2.clear();
+        self.sample_quality_into(rng, &self.quality_model, self.capped_length(length_1, template), qual_1);
+        let mate_quality_model = self.mate_quality_model.as_ref().unwrap_or(&self.quality_model);
+        self.sample_quality_into(rng, mate_quality_model, self.capped_length(length_2, mate_template), qual_2);
+        if self.mate_quality_penalty > 0 {
+            let offset = self.quality_range.start;
+            qual_2.iter_mut().for_each(|score| *score = score.saturating_sub(self.mate_quality_penalty).max(offset));
+        }
+
+        let (adapter_1, adapter_2) = match &self.adapters {
+            Some((adapter_1, adapter_2)) => (Some(&adapter_1[..]), Some(&adapter_2[..])),
+            None => (None, None),
+        };
+        let mate_error_model = self.mate_error_model();
+        let needed_1 = qual_1.len() + self.error_model.template_padding(qual_1.len());
+        let needed_2 = qual_2.len() + mate_error_model.template_padding(qual_2.len());
+        // Ligated mates each read their own fragment from its 5' end.
+        let [(span_1, antisense_1), (span_2, antisense_2)] = match &molecule.mate_template {
+            Some(mate) => [(0..template.len(), false), (0..mate.len(), false)],
+            None => self.mate_spans(template.len(), needed_1, needed_2),
+        };
+        self.sequence_through(rng, &self.error_model, read_template(template, &span_1, antisense_1, antisense), adapter_1, qual_1, extended, read_1);
+        self.sequence_through(rng, mate_error_model, read_template(mate_template, &span_2, antisense_2, antisense), adapter_2, qual_2, extended, read_2);
+        record.alignment_1 = self.alignment(molecule.origin, read_1, &span_1, antisense_1);
+        record.alignment_2 = self.alignment(molecule.mate_origin.or(molecule.origin), read_2, &span_2, antisense_2);
+        self.apply_artifacts(rng, &mut read_1.seq, qual_1);
+        self.apply_artifacts(rng, &mut read_2.seq, qual_2);
+        mem::swap(&mut record.seq, &mut read_1.seq);
+        mem::swap(&mut record.mate, &mut read_2.seq);
+        record.id.clear();
+        self.name_format.write_read_id(id_index, &mut record.id);
+        let mate = (&mut record.mate, qual_2, record.alignment_2.as_mut());
+        record.umi = self.sequence_umi(rng, molecule, &mut record.id, (&mut record.seq, qual_1, record.alignment_1.as_mut()), Some(mate));
+    }
+}
+
+/// Places UMI bases and qualities in front of a read's `(seq, quality,
+/// alignment)`, soft-clipping them in its truth alignment.
+fn prepend_umi((seq, quality, alignment): (&mut Vec<u8>, &mut Vec<u8>, Option<&mut Alignment>), umi: &[u8], umi_quality: &[u8]) {
+    seq.splice(0..0, umi.iter().copied());
+    quality.splice(0..0, umi_quality.iter().copied());
+    if let Some(alignment) = alignment {
+        alignment.clip_read_start(umi.len());
+    }
+}
+
+/// Uniform distribution over the scores of `range`.
+fn uniform_quality(range: &Range<u8>) -> Uniform<u8> {
+    Uniform::new(range.start, range.end).unwrap()
+}
+
+/// Uniform distribution over the scores given to erroneous bases, encoded with
+/// the offset `range` starts at.
+fn error_quality(range: &Range<u8>) -> Uniform<u8> {
+    let offset = range.start;
+    Uniform::new(offset + ERROR_PHRED_RANGE.start, offset + ERROR_PHRED_RANGE.end).unwrap()
+}
+
+/// The `span` of `template` a mate reads, reverse-complemented into `scratch`
+/// when it reads the opposite strand.
+fn read_template<'a>(template: &'a [u8], span: &Range<usize>, antisense: bool, scratch: &'a mut Vec<u8>) -> &'a [u8] {
+    if !antisense {
+        return &template[span.clone()];
+    }
+    scratch.clear();
+    reverse_complement_into(&template[span.clone()], scratch);
+    scratch
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn degrade_keeps_an_empty_read_empty() {
+        let generator = FastqGenerator::new(100).with_poly_g_rate(1.0).unwrap();
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut quality = Vec::new();
+        let seq = generator.degrade(&mut rng, b"", &mut quality, 0, false);
+        assert!(seq.is_empty());
+        assert!(quality.is_empty());
+    }
+
+    #[test]
+    fn degrade_adds_poly_g_tails() {
+        let generator = FastqGenerator::new(100).with_poly_g_rate(1.0).unwrap();
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut quality = vec![b'I'; 20];
+        let seq = generator.degrade(&mut rng, &[b'A'; 20], &mut quality, 0, false);
+        assert_eq!(seq.len(), 20);
+        assert_eq!(seq.last(), Some(&b'G'));
+    }
+
+    #[test]
+    fn generate_batch_numbers_reads_across_batches() {
+        let mut generator = FastqGenerator::new(50);
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut batch = Vec::new();
+        generator.generate_batch(&mut rng, 3, &mut batch);
+        assert_eq!(batch.len(), 3);
+        let first: Vec<String> = batch.iter().map(|record| record.id.clone()).collect();
+        generator.generate_batch(&mut rng, 3, &mut batch);
+        assert_eq!(batch.len(), 3);
+        assert!(batch.iter().all(|record| !first.contains(&record.id)));
+    }
+
+    #[test]
+    fn mate_spans_follow_the_orientation() {
+        let spans = |orientation| FastqGenerator::new(50).with_orientation(orientation).mate_spans(300, 100, 80);
+        assert_eq!(spans(MateOrientation::Fr), [(0..300, false), (0..300, true)]);
+        assert_eq!(spans(MateOrientation::Rf), [(0..100, true), (220..300, false)]);
+        assert_eq!(spans(MateOrientation::Ff), [(0..300, false), (220..300, false)]);
+        // Mates needing more than the fragment read all of it.
+        let short = FastqGenerator::new(50).with_orientation(MateOrientation::Rf).mate_spans(60, 100, 80);
+        assert_eq!(short, [(0..60, true), (0..60, false)]);
+    }
+}
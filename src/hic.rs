@@ -0,0 +1,89 @@
+use rand::Rng;
+use rand::distr::Distribution;
+use rand::distr::weighted::WeightedIndex;
+
+use crate::error::FastqGenError;
+use crate::reference::{Fragment, Reference};
+
+/// Contact model of a Hi-C library: the two ends of a ligation product lie on
+/// the same contig (cis) at a distance drawn from a power law, P(s) ~ s^-exponent,
+/// or, with probability `trans_fraction`, on different contigs (trans).
+#[derive(Debug, Clone)]
+pub struct ContactModel {
+    trans_fraction: f64,
+    exponent: f64,
+    min_distance: usize,
+}
+
+impl ContactModel {
+    /// Cis contacts are at least `min_distance` bases apart; an `exponent` of
+    /// about 1 matches the contact decay of mammalian genomes.
+    pub fn new(trans_fraction: f64, exponent: f64, min_distance: usize) -> Result<Self, FastqGenError> {
+        if !(0.0..=1.0).contains(&trans_fraction) {
+            return Err(FastqGenError::invalid("The trans contact fraction must be between 0 and 1."));
+        }
+        if !exponent.is_finite() || exponent < 0.0 {
+            return Err(FastqGenError::invalid("The contact exponent must not be negative."));
+        }
+        if min_distance == 0 {
+            return Err(FastqGenError::invalid("The minimum contact distance must be positive."));
+        }
+        Ok(ContactModel { trans_fraction, exponent, min_distance })
+    }
+
+    /// Draws the `length`-base fragment ligated to `fragment`, from the same
+    /// haplotype. Trans partners come from another contig chosen by length and
+    /// lie anywhere on it; cis partners lie at a power-law distance on either
+    /// side, or anywhere on contigs too short for the minimum distance.
+    pub fn partner(&self, rng: &mut impl Rng, reference: &Reference, fragment: &Fragment, length: usize) -> Fragment {
+        let haplotype = fragment.haplotype;
+        // Spike-ins, such as PhiX, are not part of the nucleus.
+        let contigs: Vec<usize> = (0..reference.contigs().count())
+            .filter(|&contig| contig != fragment.contig && reference.spike_in_name(contig).is_none())
+            .collect();
+        if !contigs.is_empty() && rng.random_bool(self.trans_fraction) {
+            let weights = WeightedIndex::new(contigs.iter().map(|&contig| reference.haplotype_seq(haplotype, contig).len()))
+                .expect("contigs are not empty");
+            let contig = contigs[weights.sample(rng)];
+            let seq = reference.haplotype_seq(haplotype, contig);
+            let length = length.min(seq.len());
+            let start = rng.random_range(0..=seq.len() - length);
+            return Fragment { haplotype, contig, start, seq: seq[start..start + length].to_vec() };
+        }
+
+        let seq = reference.haplotype_seq(haplotype, fragment.contig);
+        let length = length.min(seq.len());
+        let last = seq.len() - length;
+        let start = match self.distance(rng, last) {
+            Some(distance) => {
+                let (before, after) = (fragment.start.checked_sub(distance), Some(fragment.start + distance).filter(|&start| start <= last));
+                match (before, after) {
+                    (Some(before), Some(after)) => if rng.random_bool(0.5) { before } else { after },
+                    (Some(start), None) | (None, Some(start)) => start,
+                    // Farther than either end of the contig: the other end of it.
+                    (None, None) => if fragment.start > last / 2 { 0 } else { last },
+                }
+            }
+            None => rng.random_range(0..=last),
+        };
+        Fragment { haplotype, contig: fragment.contig, start, seq: seq[start..start + length].to_vec() }
+    }
+
+    /// Draws a cis contact distance between the minimum and `max` by inverting
+    /// the power law's distribution function, or `None` when `max` is below the
+    /// minimum.
+    fn distance(&self, rng: &mut impl Rng, max: usize) -> Option<usize> {
+        if max < self.min_distance {
+            return None;
+        }
+        let (low, high) = (self.min_distance as f64, max as f64);
+        let u: f64 = rng.random();
+        let distance = if (self.exponent - 1.0).abs() < 1e-9 {
+            low * (high / low).powf(u)
+        } else {
+            let power = 1.0 - self.exponent;
+            (low.powf(power) + u * (high.powf(power) - low.powf(power))).powf(1.0 / power)
+        };
+        Some((distance.round() as usize).clamp(self.min_distance, max))
+    }
+}
@@ -0,0 +1,197 @@
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+use flate2::read::MultiGzDecoder;
+
+use crate::error::FastqGenError;
+use crate::reference::open_text;
+
+/// One record read back from a FASTQ file.
+#[derive(Debug, Clone, Default)]
+pub struct FastqRead {
+    /// The header line without its leading `@`: the read name, then an optional
+    /// comment after the first space.
+    pub header: String,
+    pub seq: Vec<u8>,
+    pub quality: Vec<u8>,
+}
+
+impl FastqRead {
+    /// The read name: the header up to the first whitespace.
+    pub fn name(&self) -> &str {
+        self.header.split_whitespace().next().unwrap_or_default()
+    }
+
+    /// The read name without a legacy `/1` or `/2` mate suffix, which is what
+    /// both mates of a pair have in common.
+    pub fn pair_name(&self) -> &str {
+        let name = self.name();
+        name.strip_suffix("/1").or_else(|| name.strip_suffix("/2")).unwrap_or(name)
+    }
+}
+
+/// Reads FASTQ records from a file (gzip-compressed if the name ends in `.gz`) or
+/// from standard input, checking the four-line record structure as it goes.
+pub struct FastqReader {
+    reader: Box<dyn BufRead>,
+    source: String,
+    line_number: u64,
+    /// The last line read, without its line ending. Kept as bytes, so that a stray
+    /// non-UTF-8 byte is reported with its line like any other illegal character.
+    line: Vec<u8>,
+}
+
+impl FastqReader {
+    /// Opens `path`, or standard input (auto-detecting gzip) when it is `-`.
+    pub fn open(path: &Path) -> Result<Self, FastqGenError> {
+        let reader: Box<dyn BufRead> = if path == Path::new("-") {
+            let mut stdin = BufReader::new(io::stdin());
+            if stdin.fill_buf()?.starts_with(&[0x1f, 0x8b]) {
+                Box::new(BufReader::new(MultiGzDecoder::new(stdin)))
+            } else {
+                Box::new(stdin)
+            }
+        } else {
+            open_text(path).map_err(|e| FastqGenError::io(format!("Failed to open {}", path.display()), e))?
+        };
+        Ok(Self::new(reader, &path.display().to_string()))
+    }
+
+    /// Wraps an open reader; `source` names it in error messages.
+    pub fn new(reader: Box<dyn BufRead>, source: &str) -> Self {
+        FastqReader { reader, source: source.to_string(), line_number: 0, line: Vec::new() }
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Number of lines read so far.
+    pub fn line_number(&self) -> u64 {
+        self.line_number
+    }
+
+    fn next_line(&mut self) -> Result<bool, FastqGenError> {
+        self.line.clear();
+        let read = self.reader.read_until(b'\n', &mut self.line)
+            .map_err(|e| FastqGenError::io(format!("Failed to read {}", self.source), e))?;
+        if read == 0 {
+            return Ok(false);
+        }
+        self.line_number += 1;
+        while let Some(b'\n' | b'\r') = self.line.last() {
+            self.line.pop();
+        }
+        Ok(true)
+    }
+
+    fn error(&self, message: &str) -> FastqGenError {
+        FastqGenError::parse(format!("{}:{}: {}", self.source, self.line_number, message))
+    }
+
+    /// Reads the next record into `record`, returning `false` at the end of the
+    /// input. Fails with the offending line number if the record is malformed.
+    pub fn read_into(&mut self, record: &mut FastqRead) -> Result<bool, FastqGenError> {
+        if !self.next_line()? {
+            return Ok(false);
+        }
+        let Some(header) = self.line.strip_prefix(b"@") else {
+            return Err(self.error("expected a header line starting with '@'"));
+        };
+        let header = std::str::from_utf8(header).map_err(|e| self.error(&format!("header has a byte that is not UTF-8 at position {}", e.valid_up_to() + 2)))?;
+        record.header.clear();
+        record.header.push_str(header);
+
+        if !self.next_line()? {
+            return Err(self.error("truncated record: missing sequence line"));
+        }
+        record.seq.clear();
+        record.seq.extend_from_slice(&self.line);
+
+        if !self.next_line()? {
+            return Err(self.error("truncated record: missing '+' separator line"));
+        }
+        if !self.line.starts_with(b"+") {
+            return Err(self.error("expected a '+' separator line"));
+        }
+
+        if !self.next_line()? {
+            return Err(self.error("truncated record: missing quality line"));
+        }
+        record.quality.clear();
+        record.quality.extend_from_slice(&self.line);
+        if record.quality.len() != record.seq.len() {
+            return Err(self.error(&format!(
+                "quality length {} does not match sequence length {}",
+                record.quality.len(),
+                record.seq.len()
+            )));
+        }
+        Ok(true)
+    }
+}
+
+impl Iterator for FastqReader {
+    type Item = Result<FastqRead, FastqGenError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut record = FastqRead::default();
+        match self.read_into(&mut record) {
+            Ok(true) => Some(Ok(record)),
+            Ok(false) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reader(text: &[u8]) -> FastqReader {
+        FastqReader::new(Box::new(io::Cursor::new(text.to_vec())), "test.fq")
+    }
+
+    fn error(text: &[u8]) -> String {
+        let mut record = FastqRead::default();
+        let mut reader = reader(text);
+        loop {
+            match reader.read_into(&mut record) {
+                Ok(true) => continue,
+                Ok(false) => panic!("no error"),
+                Err(e) => return e.to_string(),
+            }
+        }
+    }
+
+    #[test]
+    fn reads_records_and_strips_line_endings() {
+        let records: Vec<FastqRead> = reader(b"@r1/1 comment\r\nACGT\r\n+r1\r\nIIII\r\n@r2/1\nAC\n+\nII").map(Result::unwrap).collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!((records[0].header.as_str(), records[0].name(), records[0].pair_name()), ("r1/1 comment", "r1/1", "r1"));
+        assert_eq!((&records[0].seq[..], &records[0].quality[..]), (&b"ACGT"[..], &b"IIII"[..]));
+        assert_eq!((&records[1].seq[..], &records[1].quality[..]), (&b"AC"[..], &b"II"[..]));
+    }
+
+    #[test]
+    fn reports_malformed_records_with_their_line() {
+        let record = b"@r1\nACGT\n+\nIIII\n";
+        let after = |rest: &[u8]| error(&[&record[..], rest].concat());
+        assert_eq!(after(b"r2\nACGT\n"), "test.fq:5: expected a header line starting with '@'");
+        assert_eq!(after(b"@r2\n"), "test.fq:5: truncated record: missing sequence line");
+        assert_eq!(after(b"@r2\nACGT\n"), "test.fq:6: truncated record: missing '+' separator line");
+        assert_eq!(after(b"@r2\nACGT\nIIII\n"), "test.fq:7: expected a '+' separator line");
+        assert_eq!(after(b"@r2\nACGT\n+\n"), "test.fq:7: truncated record: missing quality line");
+        assert_eq!(after(b"@r2\nACGT\n+\nIII\n"), "test.fq:8: quality length 3 does not match sequence length 4");
+    }
+
+    #[test]
+    fn keeps_non_utf8_bytes_for_validation() {
+        let mut record = FastqRead::default();
+        assert!(reader(b"@r1\nACGT\n+\nII\xffI\n").read_into(&mut record).unwrap());
+        assert_eq!(record.quality, b"II\xffI");
+
+        let error = reader(b"@r1\nACGT\n+\nIIII\n@r\xff2\nACGT\n+\nIIII\n").nth(1).unwrap().unwrap_err();
+        assert_eq!(error.to_string(), "test.fq:5: header has a byte that is not UTF-8 at position 3");
+    }
+}
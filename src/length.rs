@@ -0,0 +1,97 @@
+use rand::{Rng, SeedableRng};
+use rand::distr::{Distribution, Uniform};
+use rand::rngs::StdRng;
+use rand_distr::{LogNormal, Normal};
+use std::fmt;
+
+use crate::error::FastqGenError;
+
+/// Draws used to estimate the mean of a clamped distribution.
+const MEAN_SAMPLES: usize = 100_000;
+
+/// Distribution of read lengths. Continuous distributions are rounded and clamped
+/// to `min..=max`.
+#[derive(Debug, Clone)]
+pub enum ReadLengths {
+    /// Every read has the same length.
+    Fixed(usize),
+    Normal { mean: f64, sd: f64, dist: Normal<f64>, min: usize, max: usize },
+    Uniform { min: usize, max: usize, dist: Uniform<usize> },
+    /// Heavy-tailed lengths, parameterised by the mean and standard deviation of
+    /// the lengths themselves rather than of their logarithm.
+    LogNormal { mean: f64, sd: f64, dist: LogNormal<f64>, min: usize, max: usize },
+}
+
+impl ReadLengths {
+    pub fn normal(mean: f64, sd: f64, min: usize, max: usize) -> Result<Self, FastqGenError> {
+        Self::check_bounds(mean, min, max)?;
+        let dist = Normal::new(mean, sd).map_err(|e| FastqGenError::invalid(format!("Invalid read length distribution: {}", e)))?;
+        Ok(ReadLengths::Normal { mean, sd, dist, min, max })
+    }
+
+    pub fn uniform(min: usize, max: usize) -> Result<Self, FastqGenError> {
+        Self::check_bounds(min as f64, min, max)?;
+        let dist = Uniform::new_inclusive(min, max).map_err(|e| FastqGenError::invalid(format!("Invalid read length distribution: {}", e)))?;
+        Ok(ReadLengths::Uniform { min, max, dist })
+    }
+
+    pub fn lognormal(mean: f64, sd: f64, min: usize, max: usize) -> Result<Self, FastqGenError> {
+        Self::check_bounds(mean, min, max)?;
+        let sigma_squared = (1.0 + (sd / mean).powi(2)).ln();
+        let dist = LogNormal::new(mean.ln() - sigma_squared / 2.0, sigma_squared.sqrt())
+            .map_err(|e| FastqGenError::invalid(format!("Invalid read length distribution: {}", e)))?;
+        Ok(ReadLengths::LogNormal { mean, sd, dist, min, max })
+    }
+
+    fn check_bounds(mean: f64, min: usize, max: usize) -> Result<(), FastqGenError> {
+        if min == 0 || min > max {
+            return Err(FastqGenError::invalid("Read length bounds must satisfy 1 <= min <= max."));
+        }
+        if mean <= 0.0 {
+            return Err(FastqGenError::invalid("Mean read length must be positive."));
+        }
+        Ok(())
+    }
+
+    pub fn sample(&self, rng: &mut impl Rng) -> usize {
+        match self {
+            ReadLengths::Fixed(length) => *length,
+            ReadLengths::Normal { dist, min, max, .. } => (dist.sample(rng).round().max(0.0) as usize).clamp(*min, *max),
+            ReadLengths::Uniform { dist, .. } => dist.sample(rng),
+            ReadLengths::LogNormal { dist, min, max, .. } => (dist.sample(rng).round() as usize).clamp(*min, *max),
+        }
+    }
+
+    /// Shortest length ever sampled.
+    pub fn min(&self) -> usize {
+        match self {
+            ReadLengths::Fixed(length) => *length,
+            ReadLengths::Normal { min, .. } | ReadLengths::Uniform { min, .. } | ReadLengths::LogNormal { min, .. } => *min,
+        }
+    }
+
+    /// Mean length of the reads sampled. Clamping to `min..=max` shifts the mean of
+    /// the normal and lognormal distributions, so theirs is estimated from a fixed
+    /// sample and is the same on every call.
+    pub fn mean(&self) -> f64 {
+        match self {
+            ReadLengths::Fixed(length) => *length as f64,
+            ReadLengths::Uniform { min, max, .. } => (min + max) as f64 / 2.0,
+            ReadLengths::Normal { .. } | ReadLengths::LogNormal { .. } => {
+                let mut rng = StdRng::seed_from_u64(0);
+                (0..MEAN_SAMPLES).map(|_| self.sample(&mut rng) as f64).sum::<f64>() / MEAN_SAMPLES as f64
+            }
+        }
+    }
+}
+
+impl fmt::Display for ReadLengths {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReadLengths::Fixed(length) => write!(f, "{}", length),
+            ReadLengths::Normal { mean, sd, .. } => write!(f, "normal, mean {} sd {}", mean, sd),
+            ReadLengths::Uniform { min, max, .. } => write!(f, "uniform {}-{}", min, max),
+            ReadLengths::LogNormal { mean, sd, .. } => write!(f, "lognormal, mean {} sd {}", mean, sd),
+        }
+    }
+}
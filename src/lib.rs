@@ -0,0 +1,107 @@
+//! Random paired FASTQ generation.
+//!
+//! The [`FastqGenerator`] behind the `fastqgen` command line tool, for generating
+//! synthetic reads directly from Rust code such as integration tests.
+//!
+//! ```
+//! use fastqgen::FastqGenerator;
+//! use rand::SeedableRng;
+//! use rand::rngs::StdRng;
+//!
+//! let generator = FastqGenerator::new(100);
+//! let mut rng = StdRng::seed_from_u64(42);
+//! let record = generator.generate_paired_record(&mut rng, 0);
+//!
+//! assert_eq!(record.seq.len(), 100);
+//! assert_eq!(record.mate, fastqgen::reverse_complement(&record.seq));
+//! ```
+
+mod alignment;
+mod amplicon;
+mod annotation;
+mod atac;
+mod bam;
+mod barcode;
+mod bed;
+mod capture;
+mod cohort;
+mod crispr;
+mod damage;
+mod error;
+mod error_model;
+mod expression;
+mod ffpe;
+mod gc_bias;
+mod generator;
+mod hic;
+mod input;
+mod length;
+mod linked;
+mod marker;
+mod markov;
+mod metagenome;
+mod methylation;
+mod naming;
+mod output;
+mod preset;
+mod profile;
+mod quality;
+mod quasispecies;
+mod random_genome;
+mod reference;
+mod regions;
+mod sam;
+mod seq;
+mod signature;
+mod somatic;
+mod structural;
+mod stats;
+mod trio;
+mod umi;
+mod variants;
+mod vdj;
+
+pub use alignment::{Alignment, Cigar};
+pub use amplicon::{Amplicon, AmpliconPanel, PanelTarget, read_amplicon_bed, read_primer_pairs};
+pub use annotation::{Transcript, read_gtf};
+pub use atac::{AtacLibrary, TN5_FORWARD_SHIFT, TN5_REVERSE_SHIFT};
+pub use bam::{BgzfWriter, MAX_READ_NAME_LENGTH, UbamMate, UbamRecord, write_ubam_header};
+pub use barcode::{BarcodeDesign, BarcodeErrorCounts, BarcodeWhitelist, Sample, read_sample_sheet, read_whitelist};
+pub use bed::{BedInterval, read_bed};
+pub use capture::{CaptureTargets, random_targets};
+pub use cohort::Cohort;
+pub use crispr::{GUIDE_DOWNSTREAM, GUIDE_UPSTREAM, Guide, GuideLibrary, read_guide_abundances, read_guide_library, sample_guide_abundances};
+pub use damage::{ANCIENT_FRAGMENT_MEAN, ANCIENT_FRAGMENT_SD, DamageModel};
+pub use error::FastqGenError;
+pub use error_model::ErrorModel;
+pub use expression::{read_expression, sample_expression, write_counts};
+pub use ffpe::{FFPE_FRAGMENT_MEAN, FFPE_FRAGMENT_SD, FfpeArtifacts};
+pub use gc_bias::GcBias;
+pub use generator::{FastqGenerator, FastqRecord, MateOrientation, Molecule, PairedFastqRecord, ReadBuffers, NEXTERA_ADAPTER, TRUSEQ_ADAPTER_1, TRUSEQ_ADAPTER_2};
+pub use hic::ContactModel;
+pub use input::{FastqRead, FastqReader};
+pub use length::ReadLengths;
+pub use linked::{LinkedMolecule, LinkedReads};
+pub use markov::{MAX_MARKOV_ORDER, MarkovModel};
+pub use marker::{MAX_PRIMER_MISMATCHES, MarkerCommunity, MarkerTaxon, V4_FORWARD_PRIMER, V4_REVERSE_PRIMER, amplify_markers, read_marker_abundances, read_marker_fasta, sample_marker_abundances};
+pub use metagenome::{Genome, genome_name, read_abundances, read_community, sample_community, write_community_counts};
+pub use methylation::{CytosineContext, Methylation};
+pub use naming::{DEFAULT_ID_WIDTH, IdTemplate, IlluminaNames, NameFormat, ReadNameFields};
+pub use output::{BatchedFastqWriter, FastqWriter, OutputCompression, ParallelWriter, write_fastq_record};
+pub use preset::Preset;
+pub use profile::{ErrorProfile, ErrorProfiler};
+pub use quality::{QualityBins, QualityModel, read_cycle_qualities};
+pub use quasispecies::{Quasispecies, sample_haplotype_frequencies};
+pub use random_genome::{RandomGenome, RepeatCopy, write_fasta, write_repeats_bed};
+pub use reference::{Fragment, Reference};
+pub use regions::Regions;
+pub use sam::{write_sam_header, write_sam_pair, write_sam_single};
+pub use seq::{complement, reverse_complement, reverse_complement_in_place, reverse_complement_into};
+pub use signature::{SBS_CHANNELS, Signature, SignatureMixture, read_signatures};
+pub use somatic::{TumorModel, write_somatic_vcf};
+pub use structural::{StructuralVariant, SvCounts, SvKind, remove_within_structural_variants, simulate_structural_variants, write_sv_bedpe, write_sv_vcf};
+pub use stats::{FastqStats, StatsReport};
+pub use trio::{PARENTAL_HAPLOTYPES, Pedigree, TrioMember};
+pub use umi::{Umi, UmiFamilies, UmiPlacement};
+pub use variants::{IndelLengths, Variant, read_vcf, remove_overlapping, resolve_overlaps, simulate_indels, simulate_snps, write_vcf};
+pub use vdj::{Clonotype, GeneSegment, Repertoire, VdjSegments, read_gene_segments, sample_clone_abundances};
@@ -0,0 +1,84 @@
+use rand::Rng;
+use rand::distr::Distribution;
+use rand_distr::{Exp, Poisson};
+use std::io::{self, Write};
+
+use crate::error::FastqGenError;
+use crate::reference::{Fragment, Reference};
+
+/// A linked-read library (10x Genomics Chromium or stLFR): long molecules from the
+/// reference are partitioned into droplets or beads, each with its own barcode,
+/// and short read pairs are drawn from within every molecule, so that reads
+/// sharing a barcode come from a few long stretches of one haplotype.
+#[derive(Debug, Clone)]
+pub struct LinkedReads {
+    barcodes: Vec<Vec<u8>>,
+    molecule_length: Exp<f64>,
+    reads_per_molecule: Poisson<f64>,
+}
+
+/// A long molecule of a [`LinkedReads`] library and the reads drawn from it.
+#[derive(Debug, Clone)]
+pub struct LinkedMolecule {
+    /// The molecule's sequence and where on its haplotype it lies.
+    pub fragment: Fragment,
+    /// Index of the partition barcode the molecule was tagged with.
+    pub barcode: usize,
+    /// Number of reads to draw from the molecule.
+    pub reads: u64,
+}
+
+impl LinkedReads {
+    /// Molecules whose lengths are exponentially distributed with mean
+    /// `molecule_length`, each yielding a Poisson number of reads with mean
+    /// `reads_per_molecule` (at least one), spread over the partitions of
+    /// `barcodes`.
+    pub fn new(barcodes: Vec<Vec<u8>>, molecule_length: f64, reads_per_molecule: f64) -> Result<Self, FastqGenError> {
+        if barcodes.is_empty() {
+            return Err(FastqGenError::invalid("Linked reads need at least one partition barcode."));
+        }
+        if molecule_length.is_nan() || molecule_length < 1.0 {
+            return Err(FastqGenError::invalid("Mean molecule length must be at least 1."));
+        }
+        let molecule_length = Exp::new(1.0 / molecule_length).map_err(|e| FastqGenError::invalid(format!("Invalid molecule length: {}", e)))?;
+        let reads_per_molecule = Poisson::new(reads_per_molecule)
+            .map_err(|_| FastqGenError::invalid("Mean reads per molecule must be positive."))?;
+        Ok(LinkedReads { barcodes, molecule_length, reads_per_molecule })
+    }
+
+    pub fn barcodes(&self) -> &[Vec<u8>] {
+        &self.barcodes
+    }
+
+    /// Draws a molecule from `reference`, never shorter than `min_length` (nor
+    /// longer than its contig), and the partition it lands in.
+    pub fn sample(&self, rng: &mut impl Rng, reference: &Reference, min_length: usize) -> LinkedMolecule {
+        let length = (self.molecule_length.sample(rng).round() as usize).max(min_length);
+        let fragment = reference.sample_fragment(rng, length);
+        let barcode = rng.random_range(0..self.barcodes.len());
+        let reads = (self.reads_per_molecule.sample(rng) as u64).max(1);
+        LinkedMolecule { fragment, barcode, reads }
+    }
+}
+
+impl LinkedMolecule {
+    /// Appends a `barcode<TAB>contig<TAB>start<TAB>end<TAB>haplotype<TAB>reads` line:
+    /// the molecule's 0-based, half-open span as in BED, lifted to the reference
+    /// where variants moved it, and the number of reads drawn from it.
+    pub fn write_truth(&self, out: &mut impl Write, linked: &LinkedReads, reference: &Reference, reads: u64) -> io::Result<()> {
+        let Fragment { haplotype, contig, start, seq } = &self.fragment;
+        let lift = |position: usize| reference.reference_position(*haplotype, *contig, position).unwrap_or((*contig, position));
+        let (reference_contig, reference_start) = lift(*start);
+        let (_, last) = lift(start + seq.len().saturating_sub(1));
+        writeln!(
+            out,
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            String::from_utf8_lossy(&linked.barcodes[self.barcode]),
+            reference.contig_name(reference_contig),
+            reference_start,
+            last.max(reference_start) + 1,
+            haplotype + 1,
+            reads
+        )
+    }
+}
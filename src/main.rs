@@ -1,87 +1,1009 @@
-use rand::Rng;
-use rand::distr::{Distribution, Uniform};
-use rand::prelude::IndexedRandom; 
+use rand::{Rng, SeedableRng};
+use rand::rngs::{SmallRng, StdRng};
+use rand::seq::IndexedRandom;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::fmt;
 use std::ops::Range;
-use std::io::{Write, BufWriter};
-use std::fs::File; 
-use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
 
-use clap::{Parser, Subcommand};
+use clap::parser::ValueSource;
+use md5::Md5;
+use sha2::{Digest, Sha256};
+use indicatif::{HumanCount, HumanDuration, ProgressBar, ProgressStyle};
+use clap::{ArgMatches, Command, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 
-struct PairedFastqRecord {
-    id: String,
-    seq: Vec<u8>,
-    mate: Vec<u8>,
-    quality_1: Vec<u8>,
-    quality_2: Vec<u8>,
+use fastqgen::{
+    ErrorModel, FastqGenerator, FastqRead, UbamMate, UbamRecord, write_ubam_header, Alignment, Transcript, read_gtf, FastqReader, read_expression, sample_expression, write_counts, FastqStats, FastqRecord, Molecule, PairedFastqRecord, ReadBuffers, NEXTERA_ADAPTER, TRUSEQ_ADAPTER_1, TRUSEQ_ADAPTER_2, BatchedFastqWriter, FastqWriter, BarcodeDesign, BarcodeErrorCounts, BarcodeWhitelist, ContactModel, LinkedMolecule, LinkedReads, Sample, read_sample_sheet, read_whitelist, Methylation, TumorModel, write_somatic_vcf, Cohort, PARENTAL_HAPLOTYPES, Pedigree, TrioMember, CaptureTargets, random_targets, GUIDE_DOWNSTREAM, GUIDE_UPSTREAM, GuideLibrary, read_guide_abundances, read_guide_library, sample_guide_abundances, MarkerCommunity, V4_FORWARD_PRIMER, V4_REVERSE_PRIMER, amplify_markers, read_marker_abundances, read_marker_fasta, sample_marker_abundances, AtacLibrary, TN5_FORWARD_SHIFT, TN5_REVERSE_SHIFT, read_bed, AmpliconPanel, read_amplicon_bed, read_primer_pairs, Genome, genome_name, read_abundances, read_community, sample_community, write_community_counts, Umi, UmiFamilies, UmiPlacement, Quasispecies, sample_haplotype_frequencies, ANCIENT_FRAGMENT_MEAN, ANCIENT_FRAGMENT_SD, DamageModel, FFPE_FRAGMENT_MEAN, FFPE_FRAGMENT_SD, FfpeArtifacts, GcBias, SignatureMixture, read_signatures, Repertoire, VdjSegments, read_gene_segments, sample_clone_abundances, DEFAULT_ID_WIDTH, IdTemplate, IlluminaNames, NameFormat, ReadNameFields, Preset, QualityModel, read_cycle_qualities, ReadLengths, Reference, Regions,
+    ErrorProfile, ErrorProfiler, FastqGenError, MarkovModel, OutputCompression, MateOrientation, reverse_complement, RandomGenome, write_fasta, write_repeats_bed,
+    IndelLengths, StructuralVariant, SvCounts, Variant, read_vcf, remove_overlapping, remove_within_structural_variants, simulate_structural_variants, write_sv_bedpe, write_sv_vcf, resolve_overlaps, simulate_indels, simulate_snps, write_fastq_record, write_sam_header, write_sam_pair, write_sam_single, write_vcf,
+};
+
+/// Number of read pairs generated from a single RNG stream. Chunks are the unit of
+/// parallel work, and because each chunk's stream depends only on the run seed and
+/// the chunk index, the output is identical regardless of the thread count.
+const CHUNK_SIZE: u64 = 10_000;
+
+/// Derives an independent RNG for one chunk of the read-index space.
+fn chunk_rng(seed: u64, chunk_index: u64) -> StdRng {
+    StdRng::seed_from_u64(chunk_seed(seed, chunk_index))
 }
 
-#[derive(Debug)]
-struct FastqGenerator {
-    bases: &'static [u8],
-    read_length: usize,
-    quality_range: Range<u8>
+/// Seed of the RNG of one chunk of the read-index space.
+fn chunk_seed(seed: u64, chunk_index: u64) -> u64 {
+    seed ^ chunk_index.wrapping_mul(0x9E37_79B9_7F4A_7C15)
+}
+
+/// RNG stream reserved for simulating variants, distinct from every chunk index.
+const VARIANT_STREAM: u64 = u64::MAX;
+/// Chunk index of the RNG stream that draws transcript abundances.
+const EXPRESSION_STREAM: u64 = u64::MAX - 1;
+/// Chunk index of the RNG stream that draws cell barcodes.
+const CELL_STREAM: u64 = u64::MAX - 2;
+/// Chunk index of the RNG stream that draws somatic variants.
+const SOMATIC_STREAM: u64 = u64::MAX - 3;
+/// Chunk index of the RNG stream that seeds the reads of a tumor sample.
+const TUMOR_STREAM: u64 = u64::MAX - 4;
+/// Chunk index of the RNG stream that draws linked-read partition barcodes.
+const PARTITION_STREAM: u64 = u64::MAX - 5;
+/// Chunk index of the RNG stream that draws random ChIP-seq binding sites.
+const SITE_STREAM: u64 = u64::MAX - 6;
+/// Chunk index of the RNG stream that draws the haplotypes of a quasispecies.
+const HAPLOTYPE_STREAM: u64 = u64::MAX - 7;
+/// Chunk index of the RNG stream that draws the sites of FFPE artifacts.
+const ARTIFACT_STREAM: u64 = u64::MAX - 8;
+/// Chunk index of the RNG stream that seeds the reads of each member of a trio or
+/// cohort.
+const MEMBER_STREAM: u64 = u64::MAX - 9;
+/// Chunk index of the RNG stream that draws the crossovers and de novo mutations
+/// of a trio.
+const PEDIGREE_STREAM: u64 = u64::MAX - 10;
+/// Chunk index of the RNG stream that draws the genotypes of a cohort.
+const COHORT_STREAM: u64 = u64::MAX - 11;
+
+/// Which outputs each chunk produces.
+#[derive(Clone, Copy)]
+struct OutputOptions<'a> {
+    /// Which RNG draws the reads of each chunk.
+    rng: RngArg,
+    paired: bool,
+    /// Write both mates of each pair, one after the other, to the R1 stream.
+    interleaved: bool,
+    /// Write unaligned BAM records in this read group instead of FASTQ.
+    ubam_read_group: Option<&'a str>,
+    truth_sam: bool,
+    /// Write the reference span, strand and error count of every read.
+    origin_truth: bool,
+    /// Write the haplotype every read was drawn from.
+    haplotype_truth: bool,
+    /// Write the spike-in genome, or `reference`, every read was drawn from.
+    source_truth: bool,
+    /// Probability that a read is a PCR duplicate of an earlier molecule in its chunk.
+    duplicate_rate: f64,
+    duplicate_truth: bool,
+    /// Fraction of duplicates that are optical, named next to their original on
+    /// the flowcell, and how many pixels away from it they lie at most.
+    optical_duplicates: f64,
+    optical_distance: i64,
+    /// Write each read's UMI to a separate index FASTQ.
+    umi_index: bool,
+    /// Samples of a multiplexed run; every molecule belongs to one of them.
+    samples: &'a [Sample],
+    /// Write the reads of each sample to its own file set.
+    demultiplex: bool,
+    /// Lanes whose reads go to file sets of their own, 1 unless lanes are split.
+    /// Read `i` is in lane `i % lanes`, as in Illumina read names.
+    lanes: u64,
+    /// Number of the first read, added to every read's index in its name.
+    id_start: u64,
+    /// Write the sequenced sample barcodes to index FASTQs.
+    index_reads: bool,
+    /// Probability that each index of a read is that of another sample.
+    index_hopping: f64,
+    /// Write the true sample and the indices every read carries.
+    sample_truth: bool,
+    /// Barcodes that sequenced cell barcodes, or else sample indices, are counted
+    /// against; set when barcodes carry errors.
+    barcode_whitelist: Option<&'a BarcodeWhitelist>,
+    /// Count the reads drawn from each transcript (reference contig).
+    count_transcripts: bool,
+    /// Write the transcript and reference blocks of every read.
+    read_truth: bool,
+    /// Gene annotation whose genome the truth alignments are reported against.
+    annotation: Option<&'a Annotation>,
+    /// Genomes of a metagenome run, whose names label the reads of their contigs.
+    genomes: &'a [Genome],
+    /// Cell barcodes of a single-cell run, whose R1 reads are the barcode and UMI
+    /// and whose R2 reads are the cDNA.
+    single_cell: Option<&'a CellBarcodes>,
+    /// Partitions of a linked-read run, whose read pairs are drawn from long
+    /// molecules and carry the barcode of their molecule's partition.
+    linked_reads: Option<&'a LinkedPartitions>,
+    /// Write the span, partition and read count of every linked-read molecule.
+    molecule_truth: bool,
+    /// Write the loci of the mates of every Hi-C pair.
+    contact_truth: bool,
+    /// Write the Tn5 insertion sites of every ATAC-seq pair.
+    fragment_truth: bool,
+    /// Guide library of a CRISPR screen: every read is of one of its constructs,
+    /// and reads are counted per guide.
+    guides: Option<&'a GuideLibrary>,
+    /// Marker-gene community of an amplicon run: every read is of one of its
+    /// amplicons, or of a chimera of two, and reads are counted per taxon.
+    marker_community: Option<&'a MarkerCommunity>,
+    /// Write the taxon and lineage of every marker-gene read.
+    taxonomy_truth: bool,
+    /// UMI families: every read is a PCR copy of the original molecule of its
+    /// family, and the reads of a family come one after another.
+    umi_families: Option<&'a UmiFamilies>,
+    /// Write the family, strand and original molecule of every read.
+    family_truth: bool,
+    /// Immune repertoire of a VDJ run: every read is of one of its clones, and
+    /// reads are counted per clone.
+    repertoire: Option<&'a Repertoire>,
+}
+
+/// Formatted reads of one output file set.
+#[derive(Default, Clone)]
+struct FileSetOutput {
+    r1: Vec<u8>,
+    /// Empty for single-end and interleaved runs.
+    r2: Vec<u8>,
+    /// Index reads 1 and 2; empty unless requested.
+    i1: Vec<u8>,
+    i2: Vec<u8>,
+    /// Where each read's records end in `r1`, `r2`, `i1` and `i2`, so that the
+    /// output can be split between files at read boundaries.
+    read_ends: Vec<[usize; 4]>,
+}
+
+impl FileSetOutput {
+    /// Marks the end of the records of one read.
+    fn end_read(&mut self) {
+        self.read_ends.push([self.r1.len(), self.r2.len(), self.i1.len(), self.i2.len()]);
+    }
+}
+
+/// The open files of one output file set.
+struct FileSetWriters {
+    r1: FastqWriter,
+    r2: Option<FastqWriter>,
+    i1: Option<FastqWriter>,
+    i2: Option<FastqWriter>,
+    /// Number of the current file of a sharded run, from 1.
+    shard: usize,
+    /// Reads written to the current files.
+    reads: u64,
+    /// Paths of the current files; empty when streaming to stdout.
+    paths: Vec<String>,
+}
+
+impl FileSetWriters {
+    /// Closes the files, returning their paths and the number of reads in them.
+    fn finish(self) -> Result<(Vec<String>, u64), FastqGenError> {
+        self.r1.finish()?;
+        for writer in [self.r2, self.i1, self.i2].into_iter().flatten() {
+            writer.finish()?;
+        }
+        Ok((self.paths, self.reads))
+    }
+}
+
+/// Record of a run written by `--manifest`, so that a simulated dataset describes
+/// how it was made.
+#[derive(Serialize)]
+struct Manifest<'a> {
+    tool: &'static str,
+    version: &'static str,
+    command_line: Vec<String>,
+    seed: u64,
+    parameters: &'a GenerateArgs,
+    reads: u64,
+    files: Vec<ManifestFile>,
+}
+
+#[derive(Serialize)]
+struct ManifestFile {
+    path: String,
+    /// Reads (or pairs) in the file; left out for truth and count files.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reads: Option<u64>,
+    md5: String,
+    sha256: String,
 }
 
-impl FastqGenerator {
-    fn new(read_length: usize) -> Self {
-        let phred_range: Range<u8> = 33u8..74u8;
-        FastqGenerator { 
-            bases: b"ATCG", 
-            read_length, 
-            quality_range: phred_range 
+impl ManifestFile {
+    fn new(path: &str, reads: Option<u64>) -> Result<Self, FastqGenError> {
+        let mut file = File::open(path).map_err(|e| FastqGenError::io(format!("Failed to open {}", path), e))?;
+        let (mut md5, mut sha256) = (Md5::new(), Sha256::new());
+        let mut buffer = vec![0; 1 << 16];
+        loop {
+            let read = file.read(&mut buffer).map_err(|e| FastqGenError::io(format!("Failed to read {}", path), e))?;
+            if read == 0 {
+                break;
+            }
+            md5.update(&buffer[..read]);
+            sha256.update(&buffer[..read]);
         }
+        let hex = |digest: &[u8]| digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+        Ok(ManifestFile { path: path.to_string(), reads, md5: hex(&md5.finalize()), sha256: hex(&sha256.finalize()) })
     }
+}
+
+/// Formatted output of one chunk, ready to be appended to the output files.
+#[derive(Default)]
+struct ChunkOutput {
+    /// One file set per sample when demultiplexing, otherwise one.
+    files: Vec<FileSetOutput>,
+    /// Empty unless truth SAM output was requested.
+    sam: Vec<u8>,
+    /// Reference span of every read; empty unless requested.
+    origins: Vec<u8>,
+    /// `read<TAB>haplotype` lines; empty unless requested.
+    haplotypes: Vec<u8>,
+    /// `read<TAB>source` lines; empty unless requested.
+    sources: Vec<u8>,
+    /// `read<TAB>sample<TAB>barcode<TAB>hopped` lines; empty unless requested.
+    sample_truths: Vec<u8>,
+    /// `duplicate<TAB>original` read names, followed by `optical` or `pcr` when
+    /// there are optical duplicates; empty unless requested.
+    duplicates: Vec<u8>,
+    /// UMI index reads; empty unless requested.
+    umi: Vec<u8>,
+    /// Transcript of origin and blocks of every read; empty unless requested.
+    read_truth: Vec<u8>,
+    /// Mate loci of every Hi-C pair in 4DN pairs format; empty unless requested.
+    contacts: Vec<u8>,
+    /// Tn5-shifted span of every ATAC-seq pair as BED; empty unless requested.
+    fragments: Vec<u8>,
+    /// Taxon, lineage and chimeric partner of every marker-gene read; empty
+    /// unless requested.
+    taxonomy: Vec<u8>,
+    /// Family, strand and original molecule of every read of a UMI family run;
+    /// empty unless requested.
+    families: Vec<u8>,
+    /// Cell and gene of every distinct molecule of a single-cell run.
+    cell_molecules: Vec<(u32, u32)>,
+    /// Span, barcode and read count of every linked-read molecule; empty unless
+    /// requested.
+    linked_molecules: Vec<u8>,
+    /// Sequenced barcodes by their distance from the whitelist.
+    barcode_errors: BarcodeErrorCounts,
+    /// Reads per contig (or guide of a CRISPR screen, clone of a repertoire, or
+    /// taxon of a marker-gene community followed by its chimeras) and sample (one count per sample, or a
+    /// single count when there are no samples, for each contig in turn); empty
+    /// unless requested.
+    counts: Vec<u64>,
+}
 
-    fn sample_quality(&self, rng: &mut impl Rng) -> Vec<u8> {
-        let dist = Uniform::new(self.quality_range.start, self.quality_range.end).unwrap();
+/// Truth files written chunk by chunk alongside the reads.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ChunkTruth {
+    Sam,
+    Duplicates,
+    Origins,
+    Haplotypes,
+    Sources,
+    Samples,
+    Umi,
+    Reads,
+    LinkedMolecules,
+    Contacts,
+    Fragments,
+    Taxonomy,
+    Families,
+}
 
-        (0..self.read_length)
-            .map(|_| dist.sample(rng)) 
-            .collect()
+impl ChunkTruth {
+    /// The part of a chunk's output that goes in the file.
+    fn part(self, chunk: &ChunkOutput) -> &[u8] {
+        match self {
+            ChunkTruth::Sam => &chunk.sam,
+            ChunkTruth::Duplicates => &chunk.duplicates,
+            ChunkTruth::Origins => &chunk.origins,
+            ChunkTruth::Haplotypes => &chunk.haplotypes,
+            ChunkTruth::Sources => &chunk.sources,
+            ChunkTruth::Samples => &chunk.sample_truths,
+            ChunkTruth::Umi => &chunk.umi,
+            ChunkTruth::Reads => &chunk.read_truth,
+            ChunkTruth::LinkedMolecules => &chunk.linked_molecules,
+            ChunkTruth::Contacts => &chunk.contacts,
+            ChunkTruth::Fragments => &chunk.fragments,
+            ChunkTruth::Taxonomy => &chunk.taxonomy,
+            ChunkTruth::Families => &chunk.families,
+        }
     }
+}
 
-    fn sample_seq(&self, rng: &mut impl Rng) -> Vec<u8> {
-        (0..self.read_length)
-        .map(|_| {
-            *self.bases.choose(rng).unwrap()
-        })
-        .collect()
+/// The open chunk truth files of a run.
+#[derive(Default)]
+struct TruthWriters {
+    writers: Vec<(ChunkTruth, FastqWriter)>,
+}
+
+impl TruthWriters {
+    /// Creates the truth file at `path`, if there is one, starting with `header`.
+    fn open(&mut self, truth: ChunkTruth, path: Option<&Path>, header: &[u8]) -> Result<(), FastqGenError> {
+        if let Some(path) = path {
+            let mut writer = create_truth_file(path)?;
+            writer.write_all(header)?;
+            self.writers.push((truth, writer));
+        }
+        Ok(())
+    }
+
+    /// Adds a file opened elsewhere.
+    fn push(&mut self, truth: ChunkTruth, writer: FastqWriter) {
+        self.writers.push((truth, writer));
+    }
+
+    fn contains(&self, truth: ChunkTruth) -> bool {
+        self.writers.iter().any(|(open, _)| *open == truth)
+    }
+
+    /// Appends its part of `chunk` to every file.
+    fn write(&mut self, chunk: &ChunkOutput) -> io::Result<()> {
+        for (truth, writer) in &mut self.writers {
+            writer.write_all(truth.part(chunk))?;
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> io::Result<()> {
+        for (_, writer) in self.writers {
+            writer.finish()?;
+        }
+        Ok(())
+    }
+}
+
+/// The sample barcode of a read as sequenced: bases and qualities of the i7
+/// index read and, for dual-indexed samples, the i5 index read.
+struct IndexReads {
+    i7: (Vec<u8>, Vec<u8>),
+    i5: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+impl IndexReads {
+    fn sequence(generator: &FastqGenerator, rng: &mut impl Rng, indices: &CarriedIndices<'_>) -> Self {
+        let i7 = generator.sequence_barcode(rng, indices.i7);
+        let i5 = indices.i5.map(|i5| generator.sequence_barcode(rng, i5));
+        IndexReads { i7, i5 }
+    }
+
+    /// Bases of both index reads, concatenated.
+    fn concatenated(&self) -> Vec<u8> {
+        [&self.i7.0[..], self.i5.as_ref().map_or(&[][..], |i5| &i5.0)].concat()
+    }
+
+    /// Bases (or, with `qualities`, quality characters) of both index reads joined
+    /// by `separator`.
+    fn joined(&self, qualities: bool, separator: char) -> String {
+        let pick = |read: &(Vec<u8>, Vec<u8>)| String::from_utf8_lossy(if qualities { &read.1 } else { &read.0 }).into_owned();
+        let mut joined = pick(&self.i7);
+        if let Some(i5) = &self.i5 {
+            joined.push(separator);
+            joined.push_str(&pick(i5));
+        }
+        joined
+    }
+}
+
+/// The i7 and i5 indices a read's library molecule carries, which index hopping
+/// can take from another sample's.
+struct CarriedIndices<'a> {
+    i7: &'a [u8],
+    i5: Option<&'a [u8]>,
+    i7_hopped: bool,
+    i5_hopped: bool,
+}
+
+impl<'a> CarriedIndices<'a> {
+    /// The indices of a read of sample `sample`, each swapped with probability
+    /// `hop_rate` for that of another sample, as free adapters do on patterned
+    /// flowcells.
+    fn draw(rng: &mut impl Rng, samples: &'a [Sample], sample: usize, hop_rate: f64) -> Self {
+        let own = &samples[sample];
+        let mut indices = CarriedIndices { i7: &own.i7, i5: own.i5.as_deref(), i7_hopped: false, i5_hopped: false };
+        if hop_rate == 0.0 {
+            return indices;
+        }
+        if rng.random_bool(hop_rate) {
+            indices.i7 = &Self::other_sample(rng, samples, sample).i7;
+            indices.i7_hopped = true;
+        }
+        if indices.i5.is_some()
+            && rng.random_bool(hop_rate)
+            && let Some(i5) = &Self::other_sample(rng, samples, sample).i5
+        {
+            indices.i5 = Some(i5);
+            indices.i5_hopped = true;
+        }
+        indices
+    }
+
+    /// A sample other than `sample`, drawn uniformly.
+    fn other_sample(rng: &mut impl Rng, samples: &'a [Sample], sample: usize) -> &'a Sample {
+        let other = rng.random_range(0..samples.len() - 1);
+        &samples[if other >= sample { other + 1 } else { other }]
+    }
+
+    /// Which indices hopped: `no`, `i7`, `i5` or `both`.
+    fn hopped(&self) -> &'static str {
+        match (self.i7_hopped, self.i5_hopped) {
+            (false, false) => "no",
+            (true, false) => "i7",
+            (false, true) => "i5",
+            (true, true) => "both",
+        }
     }
 
-    fn generate_paired_record(&self, rng: &mut impl Rng, id_index: i32) -> PairedFastqRecord {
-        let seq = self.sample_seq(rng);
-        let qual_1 = self.sample_quality(rng);
+    /// The carried i7 and i5 indices, concatenated.
+    fn concatenated(&self) -> Vec<u8> {
+        [self.i7, self.i5.unwrap_or_default()].concat()
+    }
+
+    /// Appends a `read<TAB>sample<TAB>barcode<TAB>hopped` truth line.
+    fn write_truth(&self, out: &mut Vec<u8>, id: &str, sample: &Sample) {
+        writeln!(out, "{}\t{}\t{}\t{}", id, sample.name, Sample::barcode_string(self.i7, self.i5), self.hopped()).unwrap();
+    }
+}
+
+/// Generates the reads with indices in `reads` and formats them for output. Fails
+/// only on a read name too long for unaligned BAM.
+fn generate_chunk(generator: &FastqGenerator, seed: u64, chunk_index: u64, reads: Range<u64>, options: OutputOptions<'_>) -> Result<ChunkOutput, FastqGenError> {
+    match options.rng {
+        RngArg::Crypto => generate_chunk_with(generator, chunk_rng(seed, chunk_index), reads, options),
+        RngArg::Fast => generate_chunk_with(generator, SmallRng::seed_from_u64(chunk_seed(seed, chunk_index)), reads, options),
+    }
+}
+
+/// [`generate_chunk`] with the chunk's RNG.
+fn generate_chunk_with(generator: &FastqGenerator, mut rng: impl Rng, reads: Range<u64>, options: OutputOptions<'_>) -> Result<ChunkOutput, FastqGenError> {
+    let file_sets = if options.demultiplex { options.samples.len() } else { 1 };
+    let mut out = ChunkOutput { files: vec![FileSetOutput::default(); file_sets * options.lanes as usize], ..Default::default() };
+    let count_columns = options.samples.len().max(1);
+    if options.count_transcripts {
+        out.counts = vec![0; generator.reference().map_or(0, |reference| reference.contigs().count()) * count_columns];
+    } else if let Some(guides) = options.guides {
+        out.counts = vec![0; guides.guides().len() * count_columns];
+    } else if let Some(repertoire) = options.repertoire {
+        out.counts = vec![0; repertoire.clones().len() * count_columns];
+    } else if let Some(community) = options.marker_community {
+        out.counts = vec![0; (community.taxa().len() + 1) * count_columns];
+    }
+    let paired = options.paired;
+    // Single-cell R2 reads are single-end reads of the cDNA.
+    let paired_molecules = paired && options.single_cell.is_none();
+    // Truth alignments of spliced reads are reported against the genome.
+    let alignment_reference = options.annotation.map(|annotation| &annotation.genome).or(generator.reference());
+    let truth_reference = alignment_reference.filter(|_| options.truth_sam);
+
+    let name_format = generator.name_format();
+    let quality_offset = generator.quality_offset();
+    let (comment_1, comment_2) = if paired {
+        (name_format.comment(Some(1)), name_format.comment(Some(2)))
+    } else {
+        (name_format.comment(None), None)
+    };
+    let id_template = match name_format {
+        NameFormat::Template(template) => Some(template),
+        _ => None,
+    };
+    // The barcode of each sample, for `{barcode}` in read name templates.
+    let sample_barcodes: Vec<String> = match id_template {
+        Some(_) => options.samples.iter().map(|sample| Sample::barcode_string(&sample.i7, sample.i5.as_deref())).collect(),
+        None => Vec::new(),
+    };
+
+    // Molecules sequenced so far in this chunk, with their sample, their cell (or
+    // linked-read partition) and the name and index of their first read.
+    let mut originals: Vec<(Molecule, usize, usize, String, u64)> = Vec::new();
+    // Reused from read to read, so the hot loop allocates little once they have
+    // grown to fit the longest read.
+    let mut molecule = Molecule::default();
+    let mut buffers = ReadBuffers::default();
+    let (mut pair, mut single) = (PairedFastqRecord::default(), FastqRecord::default());
+    // The long molecule linked reads are being drawn from, and how many so far.
+    let mut linked_molecule: Option<(LinkedMolecule, u64)> = None;
+    let mut construct = Vec::new();
+    // The original molecule of the current UMI family, with its sample, its ID and
+    // the reads left to draw from it.
+    let mut umi_family: Option<(Molecule, usize, u64, u64)> = None;
+    // The family and strand of the read being drawn.
+    let mut family_read = None;
+
+    for i in reads {
+        let original = if options.duplicate_rate > 0.0 && !originals.is_empty() && rng.random_bool(options.duplicate_rate) {
+            Some(rng.random_range(0..originals.len()))
+        } else {
+            None
+        };
+        let (sample, cell) = match (original, options.umi_families) {
+            (Some(original), _) => {
+                molecule = originals[original].0.clone();
+                (originals[original].1, originals[original].2)
+            }
+            (None, Some(families)) => {
+                let (parent, sample, family, left) = match umi_family.take() {
+                    Some(family) if family.3 > 0 => family,
+                    _ => {
+                        let sample = if options.samples.is_empty() { 0 } else { rng.random_range(0..options.samples.len()) };
+                        let mut parent = Molecule::default();
+                        generator.sample_molecule_into(&mut rng, paired_molecules, &mut parent);
+                        let size = families.size(&mut rng);
+                        (parent, sample, options.id_start + i, size)
+                    }
+                };
+                let bottom = families.sample_strand(&mut rng);
+                generator.copy_molecule_into(&mut rng, &parent, bottom, families.pcr_error_rate(), &mut molecule);
+                family_read = Some((family, bottom));
+                umi_family = Some((parent, sample, family, left - 1));
+                (sample, 0)
+            }
+            (None, None) => {
+                let sample = if options.samples.is_empty() { 0 } else { rng.random_range(0..options.samples.len()) };
+                let cell = match (options.linked_reads, options.guides, options.marker_community, options.repertoire, generator.reference()) {
+                    (Some(partitions), _, _, _, Some(reference)) => {
+                        let (parent, drawn) = match linked_molecule.take() {
+                            Some((parent, drawn)) if drawn < parent.reads => (parent, drawn),
+                            finished => {
+                                if let Some((parent, drawn)) = finished.filter(|_| options.molecule_truth) {
+                                    parent.write_truth(&mut out.linked_molecules, &partitions.library, reference, drawn).unwrap();
+                                }
+                                (partitions.library.sample(&mut rng, reference, generator.min_fragment_length()), 0)
+                            }
+                        };
+                        generator.sample_molecule_within(&mut rng, paired_molecules, &parent.fragment, &mut molecule);
+                        let partition = parent.barcode;
+                        linked_molecule = Some((parent, drawn + 1));
+                        partition
+                    }
+                    (_, Some(guides), _, _, _) => {
+                        let guide = guides.sample(&mut rng);
+                        guides.construct(&mut rng, guide, &mut construct);
+                        generator.sample_construct_into(&mut rng, paired_molecules, &construct, &mut molecule);
+                        guide
+                    }
+                    (_, _, Some(community), _, _) => {
+                        let key = community.sample(&mut rng, &mut construct);
+                        generator.sample_construct_into(&mut rng, paired_molecules, &construct, &mut molecule);
+                        key
+                    }
+                    (_, _, _, Some(repertoire), _) => {
+                        let clone = repertoire.sample(&mut rng);
+                        generator.sample_construct_into(&mut rng, paired_molecules, &repertoire.clones()[clone].seq, &mut molecule);
+                        clone
+                    }
+                    _ => {
+                        let cell = options.single_cell.map_or(0, |cells| rng.random_range(0..cells.barcodes.len()));
+                        generator.sample_molecule_into(&mut rng, paired_molecules, &mut molecule);
+                        cell
+                    }
+                };
+                (sample, cell)
+            }
+        };
+        // Optical duplicates lie next to their original, in its lane.
+        let optical = match original {
+            Some(original) if options.optical_duplicates > 0.0 && rng.random_bool(options.optical_duplicates) => Some(originals[original].4),
+            _ => None,
+        };
+        let sample_set = if options.demultiplex { sample } else { 0 };
+        // Reads are named, and dealt over lanes, from --id-start on.
+        let i = options.id_start + i;
+        let file_set = sample_set * options.lanes as usize + (optical.unwrap_or(i) % options.lanes) as usize;
+        let files = &mut out.files[file_set];
+        let (r1, r2) = (&mut files.r1, &mut files.r2);
+
+        let id = if paired_molecules {
+            let record = &mut pair;
+            generator.sequence_paired_into(&mut rng, &molecule, i, &mut buffers, record);
+            let transcript = record.alignment_1.as_ref().map(|alignment| alignment.contig);
+            if let Some(annotation) = options.annotation {
+                splice_alignment(annotation, &mut record.alignment_1);
+                splice_alignment(annotation, &mut record.alignment_2);
+            }
+            let carried = (!options.samples.is_empty()).then(|| CarriedIndices::draw(&mut rng, options.samples, sample, options.index_hopping));
+            let index = carried.as_ref().map(|indices| IndexReads::sequence(generator, &mut rng, indices));
+            if let (Some(whitelist), Some(carried), Some(index), None) = (options.barcode_whitelist, &carried, &index, options.single_cell) {
+                whitelist.record(&carried.concatenated(), &index.concatenated(), &mut out.barcode_errors);
+            }
+            let partition_barcode = options.linked_reads.map(|partitions| &partitions.library.barcodes()[cell][..]);
+            if let (Some(partitions), Some(barcode)) = (options.linked_reads, partition_barcode)
+                && partitions.placement == LinkedBarcodeArg::Read
+            {
+                let mut template = barcode.to_vec();
+                template.extend((0..LINKED_SPACER_LENGTH).map(|_| b"ACGT"[rng.random_range(0..4)]));
+                let (barcode_seq, barcode_quality) = generator.sequence_barcode(&mut rng, &template);
+                if let Some(whitelist) = options.barcode_whitelist {
+                    whitelist.record(barcode, &barcode_seq[..barcode.len()], &mut out.barcode_errors);
+                }
+                record.seq.splice(0..0, barcode_seq);
+                record.quality_1.splice(0..0, barcode_quality);
+                if let Some(alignment) = record.alignment_1.as_mut() {
+                    alignment.clip_read_start(template.len());
+                }
+            }
+            if let (Some(original), NameFormat::Illumina(names)) = (optical, name_format) {
+                let offset = optical_offset(&mut rng, options.optical_distance);
+                rename_read(generator, record.umi.as_ref(), &mut record.id, |id| names.write_optical_duplicate_id(original, offset, id));
+            }
+            if let Some(template) = id_template {
+                let origin = alignment_reference.and_then(|reference| read_origin(reference, &[record.alignment_1.as_ref(), record.alignment_2.as_ref()]));
+                let umi = record.umi.as_ref().map(|umi| &umi.seq[..]);
+                let barcode = match partition_barcode {
+                    Some(barcode) => Some(std::str::from_utf8(barcode).expect("partition barcodes are ASCII")),
+                    None => sample_barcodes.get(sample).map(String::as_str),
+                };
+                let fields = ReadNameFields { index: i, umi, barcode, origin };
+                rename_read(generator, record.umi.as_ref(), &mut record.id, |id| template.write(&fields, id));
+            }
+            let barcode_comments = match (options.linked_reads, partition_barcode) {
+                (Some(partitions), Some(barcode)) if partitions.placement == LinkedBarcodeArg::Tag => {
+                    Some((with_bx_tag(comment_1.as_deref(), barcode), with_bx_tag(comment_2.as_deref(), barcode)))
+                }
+                _ => index.as_ref().map(|index| {
+                    let barcode = index.joined(false, '+');
+                    (name_format.comment_with_barcode(Some(1), &barcode), name_format.comment_with_barcode(Some(2), &barcode))
+                }),
+            };
+            let (comment_1, comment_2) = match &barcode_comments {
+                Some((comment_1, comment_2)) => (comment_1.as_deref(), comment_2.as_deref()),
+                None => (comment_1.as_deref(), comment_2.as_deref()),
+            };
+            if let Some(read_group) = options.ubam_read_group {
+                let barcode_tags = index.as_ref().map(|index| (index.joined(false, '-'), index.joined(true, ' ')));
+                let tags = ubam_tags(read_group, record.umi.as_ref(), barcode_tags.as_ref());
+                let read_1 = UbamRecord { name: &record.id, mate: UbamMate::First, seq: &record.seq, quality: &record.quality_1, quality_offset, tags: &tags };
+                read_1.write(r1)?;
+                UbamRecord { mate: UbamMate::Second, seq: &record.mate, quality: &record.quality_2, ..read_1 }.write(r1)?;
+            } else {
+                write_fastq_record(r1, &record.id, comment_1, &record.seq, &record.quality_1);
+                let mate_out = if options.interleaved { r1 } else { r2 };
+                write_fastq_record(mate_out, &record.id, comment_2, &record.mate, &record.quality_2);
+            }
+            if let Some(reference) = truth_reference {
+                write_sam_pair(&mut out.sam, reference, record, quality_offset);
+            }
+            if let Some(reference) = alignment_reference.filter(|_| options.origin_truth) {
+                write_origin(&mut out.origins, &record.id, Some(1), reference, record.alignment_1.as_ref());
+                write_origin(&mut out.origins, &record.id, Some(2), reference, record.alignment_2.as_ref());
+            }
+            if let (Some(reference), Some(alignment_1), Some(alignment_2)) = (alignment_reference.filter(|_| options.contact_truth), &record.alignment_1, &record.alignment_2) {
+                write_contact(&mut out.contacts, &record.id, reference, alignment_1, alignment_2);
+            }
+            if let (Some(reference), Some(alignment_1), Some(alignment_2)) = (alignment_reference.filter(|_| options.fragment_truth), &record.alignment_1, &record.alignment_2) {
+                write_atac_fragment(&mut out.fragments, &record.id, reference, alignment_1, alignment_2);
+            }
+            if let Some(alignment) = record.alignment_1.as_ref().filter(|_| options.haplotype_truth) {
+                writeln!(out.haplotypes, "{}\t{}", record.id, alignment.haplotype + 1).unwrap();
+            }
+            if options.source_truth {
+                writeln!(out.sources, "{}\t{}", record.id, read_source(generator, transcript)).unwrap();
+            }
+            if let Some(carried) = carried.as_ref().filter(|_| options.sample_truth) {
+                carried.write_truth(&mut out.sample_truths, &record.id, &options.samples[sample]);
+            }
+            if let Some(transcript) = transcript.filter(|_| options.count_transcripts) {
+                out.counts[transcript * count_columns + sample] += 1;
+            }
+            if let (Some(transcript), Some(reference)) = (transcript.filter(|_| options.read_truth), alignment_reference) {
+                let alignments = [record.alignment_1.as_ref(), record.alignment_2.as_ref()];
+                write_read_truth(&mut out.read_truth, &record.id, generator, options, transcript, reference, &alignments);
+            }
+            if let Some(umi) = record.umi.as_ref().filter(|_| options.umi_index) {
+                write_fastq_record(&mut out.umi, &record.id, None, &umi.seq, &umi.quality);
+            }
+            if let Some(index) = index.as_ref().filter(|_| options.index_reads) {
+                write_index_reads(files, &record.id, comment_1, index);
+            }
+            &record.id
+        } else {
+            let record = &mut single;
+            generator.sequence_single_into(&mut rng, &molecule, i, &mut buffers, record);
+            let transcript = record.alignment.as_ref().map(|alignment| alignment.contig);
+            if let Some(annotation) = options.annotation {
+                splice_alignment(annotation, &mut record.alignment);
+            }
+            let carried = (!options.samples.is_empty()).then(|| CarriedIndices::draw(&mut rng, options.samples, sample, options.index_hopping));
+            let index = carried.as_ref().map(|indices| IndexReads::sequence(generator, &mut rng, indices));
+            if let (Some(whitelist), Some(carried), Some(index), None) = (options.barcode_whitelist, &carried, &index, options.single_cell) {
+                whitelist.record(&carried.concatenated(), &index.concatenated(), &mut out.barcode_errors);
+            }
+            if let (Some(original), NameFormat::Illumina(names)) = (optical, name_format) {
+                let offset = optical_offset(&mut rng, options.optical_distance);
+                rename_read(generator, record.umi.as_ref(), &mut record.id, |id| names.write_optical_duplicate_id(original, offset, id));
+            }
+            if let Some(template) = id_template {
+                let origin = alignment_reference.and_then(|reference| read_origin(reference, &[record.alignment.as_ref()]));
+                let umi = record.umi.as_ref().map(|umi| &umi.seq[..]);
+                let barcode = match options.single_cell {
+                    Some(cells) => Some(std::str::from_utf8(&cells.barcodes[cell]).expect("cell barcodes are ASCII")),
+                    None => sample_barcodes.get(sample).map(String::as_str),
+                };
+                let fields = ReadNameFields { index: i, umi, barcode, origin };
+                rename_read(generator, record.umi.as_ref(), &mut record.id, |id| template.write(&fields, id));
+            }
+            let barcode_comment = index.as_ref().map(|index| name_format.comment_with_barcode(None, &index.joined(false, '+')));
+            let comment = match &barcode_comment {
+                Some(comment) => comment.as_deref(),
+                None => comment_1.as_deref(),
+            };
+            if let Some(cells) = options.single_cell {
+                let mut template = cells.barcodes[cell].clone();
+                template.extend_from_slice(record.umi.as_ref().map_or(&[][..], |umi| &umi.seq));
+                let (barcode_seq, barcode_quality) = generator.sequence_barcode(&mut rng, &template);
+                if let Some(whitelist) = options.barcode_whitelist {
+                    let barcode = &cells.barcodes[cell];
+                    whitelist.record(barcode, &barcode_seq[..barcode.len()], &mut out.barcode_errors);
+                }
+                write_fastq_record(r1, &record.id, comment_1.as_deref(), &barcode_seq, &barcode_quality);
+                let cdna_out = if options.interleaved { r1 } else { r2 };
+                write_fastq_record(cdna_out, &record.id, comment_2.as_deref(), &record.seq, &record.quality);
+                if let (None, Some(transcript)) = (original, transcript) {
+                    out.cell_molecules.push((cell as u32, cells.genes[transcript] as u32));
+                }
+            } else if let Some(read_group) = options.ubam_read_group {
+                let barcode_tags = index.as_ref().map(|index| (index.joined(false, '-'), index.joined(true, ' ')));
+                let tags = ubam_tags(read_group, record.umi.as_ref(), barcode_tags.as_ref());
+                UbamRecord { name: &record.id, mate: UbamMate::Single, seq: &record.seq, quality: &record.quality, quality_offset, tags: &tags }
+                    .write(r1)?;
+            } else {
+                write_fastq_record(r1, &record.id, comment, &record.seq, &record.quality);
+            }
+            if let Some(reference) = truth_reference {
+                write_sam_single(&mut out.sam, reference, record, quality_offset);
+            }
+            if let Some(reference) = alignment_reference.filter(|_| options.origin_truth) {
+                write_origin(&mut out.origins, &record.id, None, reference, record.alignment.as_ref());
+            }
+            if let Some(alignment) = record.alignment.as_ref().filter(|_| options.haplotype_truth) {
+                writeln!(out.haplotypes, "{}\t{}", record.id, alignment.haplotype + 1).unwrap();
+            }
+            if options.source_truth {
+                writeln!(out.sources, "{}\t{}", record.id, read_source(generator, transcript)).unwrap();
+            }
+            if let Some(carried) = carried.as_ref().filter(|_| options.sample_truth) {
+                carried.write_truth(&mut out.sample_truths, &record.id, &options.samples[sample]);
+            }
+            if let Some(transcript) = transcript.filter(|_| options.count_transcripts) {
+                out.counts[transcript * count_columns + sample] += 1;
+            }
+            if let (Some(transcript), Some(reference)) = (transcript.filter(|_| options.read_truth), alignment_reference) {
+                write_read_truth(&mut out.read_truth, &record.id, generator, options, transcript, reference, &[record.alignment.as_ref()]);
+            }
+            if let Some(umi) = record.umi.as_ref().filter(|_| options.umi_index) {
+                write_fastq_record(&mut out.umi, &record.id, None, &umi.seq, &umi.quality);
+            }
+            if let Some(index) = index.as_ref().filter(|_| options.index_reads) {
+                write_index_reads(files, &record.id, comment, index);
+            }
+            &record.id
+        };
+        if options.guides.is_some() || options.repertoire.is_some() {
+            out.counts[cell * count_columns + sample] += 1;
+        }
+        if let Some(community) = options.marker_community {
+            // Chimeras are counted together, after the taxa.
+            let row = match community.parents(cell) {
+                (taxon, None) => taxon,
+                (_, Some(_)) => community.taxa().len(),
+            };
+            out.counts[row * count_columns + sample] += 1;
+            if options.taxonomy_truth {
+                community.write_read_truth(&mut out.taxonomy, id, cell).unwrap();
+            }
+        }
+        if let (Some(families), Some((family, bottom)), Some((parent, ..))) = (options.umi_families.filter(|_| options.family_truth), family_read, &umi_family) {
+            families.write_truth(&mut out.families, id, family, bottom, parent).unwrap();
+        }
+        out.files[file_set].end_read();
 
-        let mate = reverse_complement(&seq);
-        
-        let qual_2: Vec<u8> = qual_1.iter().rev().copied().collect();
+        match original {
+            Some(original) if options.duplicate_truth => {
+                write!(out.duplicates, "{}\t{}", id, originals[original].3).unwrap();
+                match (options.optical_duplicates > 0.0, optical) {
+                    (false, _) => writeln!(out.duplicates),
+                    (true, Some(_)) => writeln!(out.duplicates, "\toptical"),
+                    (true, None) => writeln!(out.duplicates, "\tpcr"),
+                }
+                .unwrap();
+            }
+            Some(_) => {}
+            None if options.duplicate_rate > 0.0 => originals.push((molecule.clone(), sample, cell, id.clone(), i)),
+            None => {}
+        }
+    }
+    if options.molecule_truth
+        && let (Some(partitions), Some(reference), Some((parent, drawn))) = (options.linked_reads, generator.reference(), linked_molecule)
+    {
+        parent.write_truth(&mut out.linked_molecules, &partitions.library, reference, drawn).unwrap();
+    }
+
+    Ok(out)
+}
+
+/// The contig, span and strand a read (or pair, from the span of its mates on the
+/// contig of the first) was drawn from, for read name templates.
+fn read_origin<'a>(reference: &'a Reference, alignments: &[Option<&Alignment>]) -> Option<(&'a str, usize, usize, bool)> {
+    let first = alignments.iter().flatten().next()?;
+    let mates = alignments.iter().flatten().filter(|alignment| alignment.contig == first.contig);
+    let (start, end) = mates.fold((usize::MAX, 0), |(start, end), alignment| (start.min(alignment.position), end.max(alignment.end())));
+    Some((reference.contig_name(first.contig), start, end, first.reverse))
+}
+
+/// Renames a read with `write`, once its barcode, origin or original are known,
+/// keeping the UMI the generator appends when UMIs go in the name.
+fn rename_read(generator: &FastqGenerator, umi: Option<&Umi>, id: &mut String, write: impl FnOnce(&mut String)) {
+    id.clear();
+    write(id);
+    if let (Some(umi), Some(UmiPlacement::Name)) = (umi, generator.umi_placement()) {
+        id.push(':');
+        id.push_str(std::str::from_utf8(&umi.seq).expect("UMI bases are ASCII"));
+    }
+}
+
+/// Draws `count` barcodes for the cells or partitions (`what`) of a run from the
+/// whitelist at `path`, or as random 16-mers without one. Returns them and, when
+/// sequenced barcodes carry errors, the whitelist they are counted against.
+fn draw_barcodes(rng: &mut impl Rng, count: usize, what: &str, path: Option<&Path>, barcode_errors: bool) -> Result<(Vec<Vec<u8>>, Option<BarcodeWhitelist>), FastqGenError> {
+    Ok(match path {
+        Some(path) => {
+            let whitelist = read_whitelist(path)?;
+            if whitelist.len() < count {
+                return Err(FastqGenError::invalid(format!("Whitelist {} has {} barcodes, fewer than the {} {} requested.", path.display(), whitelist.len(), count, what)));
+            }
+            let barcodes: Vec<Vec<u8>> = whitelist.choose_multiple(rng, count).cloned().collect();
+            (barcodes, barcode_errors.then(|| BarcodeWhitelist::new(whitelist)))
+        }
+        None => {
+            let barcodes: Vec<Vec<u8>> = (0..count).map(|_| (0..CELL_BARCODE_LENGTH).map(|_| b"ACGT"[rng.random_range(0..4)]).collect()).collect();
+            let whitelist = barcode_errors.then(|| BarcodeWhitelist::new(barcodes.clone()));
+            (barcodes, whitelist)
+        }
+    })
+}
+
+/// A read comment carrying the partition `barcode` of a linked read in a `BX` tag,
+/// as Long Ranger writes it, after any other `comment`.
+fn with_bx_tag(comment: Option<&str>, barcode: &[u8]) -> Option<String> {
+    let tag = format!("BX:Z:{}-1", String::from_utf8_lossy(barcode));
+    Some(match comment {
+        Some(comment) => format!("{} {}", comment, tag),
+        None => tag,
+    })
+}
 
-        PairedFastqRecord { 
-            id: format!("READ_{:06}", id_index), 
-            seq, 
-            mate, 
-            quality_1: qual_1,
-            quality_2: qual_2
+/// Draws how far an optical duplicate lies from its original, in x and y pixels,
+/// at most `distance` in each and never on top of it.
+fn optical_offset(rng: &mut impl Rng, distance: i64) -> (i64, i64) {
+    loop {
+        let offset = (rng.random_range(-distance..=distance), rng.random_range(-distance..=distance));
+        if offset != (0, 0) {
+            return offset;
         }
     }
 }
 
+/// Appends the i7 and, if any, i5 index reads of the read named `id`. Like
+/// bcl2fastq, index reads carry the same name and comment as read 1.
+fn write_index_reads(out: &mut FileSetOutput, id: &str, comment: Option<&str>, index: &IndexReads) {
+    write_fastq_record(&mut out.i1, id, comment, &index.i7.0, &index.i7.1);
+    if let Some((seq, quality)) = &index.i5 {
+        write_fastq_record(&mut out.i2, id, comment, seq, quality);
+    }
+}
+
+/// Appends `read<TAB>[mate<TAB>]contig<TAB>start<TAB>end<TAB>strand<TAB>errors` for a
+/// read with a truth alignment: its 0-based, half-open reference span as in BED,
+/// the strand it was sequenced from and its number of sequencing errors. The mate
+/// column is only written for pairs.
+fn write_origin(out: &mut Vec<u8>, id: &str, mate: Option<u8>, reference: &Reference, alignment: Option<&Alignment>) {
+    let Some(alignment) = alignment else {
+        return;
+    };
+    let mate = mate.map_or(String::new(), |mate| format!("\t{}", mate));
+    let strand = if alignment.reverse { '-' } else { '+' };
+    let contig = reference.contig_name(alignment.contig);
+    writeln!(out, "{}{}\t{}\t{}\t{}\t{}\t{}", id, mate, contig, alignment.position, alignment.end(), strand, alignment.edit_distance).unwrap();
+}
+
+/// Appends a `contig<TAB>start<TAB>end<TAB>name` BED line for an ATAC-seq pair:
+/// the Tn5 insertion sites at either end of its fragment, the forward read's
+/// start shifted by +4 and the reverse read's end by -5 as ATAC-seq pipelines
+/// shift them.
+fn write_atac_fragment(out: &mut Vec<u8>, id: &str, reference: &Reference, alignment_1: &Alignment, alignment_2: &Alignment) {
+    let (forward, reverse) = if alignment_1.reverse { (alignment_2, alignment_1) } else { (alignment_1, alignment_2) };
+    let start = forward.position + TN5_FORWARD_SHIFT;
+    let end = reverse.end().saturating_sub(TN5_REVERSE_SHIFT).max(start);
+    writeln!(out, "{}\t{}\t{}\t{}", reference.contig_name(forward.contig), start, end, id).unwrap();
+}
+
+/// Appends a line of the 4DN pairs format for a Hi-C pair: its name, the contig
+/// and 1-based 5' position of each mate, and their strands.
+fn write_contact(out: &mut Vec<u8>, id: &str, reference: &Reference, alignment_1: &Alignment, alignment_2: &Alignment) {
+    let five_prime = |alignment: &Alignment| if alignment.reverse { alignment.end() } else { alignment.position + 1 };
+    let strand = |alignment: &Alignment| if alignment.reverse { '-' } else { '+' };
+    writeln!(
+        out,
+        "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+        id,
+        reference.contig_name(alignment_1.contig),
+        five_prime(alignment_1),
+        reference.contig_name(alignment_2.contig),
+        five_prime(alignment_2),
+        strand(alignment_1),
+        strand(alignment_2)
+    )
+    .unwrap();
+}
 
-fn complement(base: u8) -> u8 {
-    match base {
-        b'A' => b'T',
-        b'T' => b'A',
-        b'C' => b'G',
-        b'G' => b'C',
-        _ => base
+/// The spike-in genome a read drawn from `contig` came from, or `reference`.
+fn read_source(generator: &FastqGenerator, contig: Option<usize>) -> &str {
+    contig
+        .and_then(|contig| generator.reference()?.spike_in_name(contig))
+        .unwrap_or("reference")
+}
+
+/// Moves a truth alignment from its transcript to the genome.
+fn splice_alignment(annotation: &Annotation, alignment: &mut Option<Alignment>) {
+    if let Some(alignment) = alignment {
+        *alignment = annotation.transcripts[alignment.contig].to_genome(alignment);
+    }
+}
+
+/// Appends `read<TAB>transcript<TAB>gene` (or `read<TAB>contig<TAB>genome` in a
+/// metagenome run) and the blocks of each mate's truth alignment against
+/// `reference` (as `contig:start-end`, 1-based and inclusive, comma-separated) to
+/// the read truth table.
+fn write_read_truth(
+    out: &mut Vec<u8>,
+    id: &str,
+    generator: &FastqGenerator,
+    options: OutputOptions<'_>,
+    transcript: usize,
+    reference: &Reference,
+    alignments: &[Option<&Alignment>],
+) {
+    let transcript_id = generator.reference().map_or("", |transcriptome| transcriptome.contig_name(transcript));
+    let spike_in = generator.reference().and_then(|transcriptome| transcriptome.spike_in_name(transcript));
+    let gene_id = match (options.annotation, spike_in) {
+        (Some(annotation), _) => &annotation.transcripts[transcript].gene_id,
+        (None, Some(spike_in)) => spike_in,
+        (None, None) if !options.genomes.is_empty() => &options.genomes[options.genomes.partition_point(|genome| genome.contigs.end <= transcript)].name,
+        (None, None) => transcript_id,
+    };
+    write!(out, "{}\t{}\t{}", id, transcript_id, gene_id).unwrap();
+    for alignment in alignments {
+        let blocks: Vec<String> = alignment.map_or_else(Vec::new, |alignment| {
+            let contig = reference.contig_name(alignment.contig);
+            alignment.blocks().iter().map(|(start, end)| format!("{}:{}-{}", contig, start + 1, end)).collect()
+        });
+        write!(out, "\t{}", blocks.join(",")).unwrap();
     }
+    writeln!(out).unwrap();
 }
 
-fn reverse_complement(seq: &[u8]) -> Vec<u8> {
-    seq.iter().rev()
-    .map(|base| complement(*base))
-    .collect()
+/// uBAM tags of a read: its read group, its UMI as `RX` with qualities as `QX`,
+/// and its sample barcode as `BC` with qualities as `QT`.
+fn ubam_tags<'a>(read_group: &'a str, umi: Option<&'a Umi>, barcode: Option<&'a (String, String)>) -> Vec<(&'static str, &'a str)> {
+    let mut tags = vec![("RG", read_group)];
+    if let Some(umi) = umi {
+        tags.push(("RX", std::str::from_utf8(&umi.seq).expect("UMI bases are ASCII")));
+        tags.push(("QX", std::str::from_utf8(&umi.quality).expect("qualities are ASCII")));
+    }
+    if let Some((bases, qualities)) = barcode {
+        tags.push(("BC", bases));
+        tags.push(("QT", qualities));
+    }
+    tags
 }
 
 #[derive(Parser, Debug)]
@@ -94,80 +1016,3776 @@ struct Cli {
 #[derive(Subcommand, Debug)]
 enum Commands {
     /// Generates synthetic paired-end FASTQ reads with specified parameters.
-    Generate(GenerateArgs),
+    Generate(Box<GenerateArgs>),
+    /// Simulates RNA-seq reads from a transcriptome FASTA (-r) at known expression
+    /// levels, writing the true read count of each transcript.
+    Rnaseq(Box<RnaseqArgs>),
+    /// Simulates droplet single-cell RNA-seq (10x Genomics 3' style): R1 is the
+    /// cell barcode and UMI, R2 the cDNA.
+    SingleCell(Box<SingleCellArgs>),
+    /// Simulates a microbial community from one FASTA per genome at known relative
+    /// abundances, labelling every read with its source genome.
+    Metagenome(Box<MetagenomeArgs>),
+    /// Simulates a matched tumor/normal pair: the tumor carries somatic variants at
+    /// known allele fractions on top of the shared germline variants.
+    Somatic(Box<SomaticArgs>),
+    /// Simulates a mother/father/child trio: the child inherits a recombined
+    /// haplotype from each parent, plus de novo mutations of its own.
+    Trio(Box<TrioArgs>),
+    /// Simulates a cohort of diploid samples sharing variant sites at frequencies
+    /// from a site-frequency spectrum, optionally across diverged populations.
+    Cohort(Box<CohortArgs>),
+    /// Simulates linked reads (10x Genomics Chromium, stLFR): read pairs drawn from
+    /// long molecules carry the barcode of the partition each molecule landed in.
+    LinkedReads(Box<LinkedReadsArgs>),
+    /// Simulates ATAC-seq read pairs: Tn5 fragments with nucleosomal lengths, most
+    /// of them cut in the peaks of a BED file.
+    Atac(Box<AtacArgs>),
+    /// Simulates ChIP-seq reads: fragments enriched around protein binding sites
+    /// over a background from the whole reference.
+    Chip(Box<ChipArgs>),
+    /// Simulates the reads of a pooled CRISPR screen: guide RNAs from a library,
+    /// drawn by abundance and read in their vector.
+    Crispr(Box<CrisprArgs>),
+    /// Simulates marker-gene amplicon sequencing (16S, 18S, ITS) of a microbial
+    /// community: primers amplify a region of full-length references, drawn by
+    /// abundance, with PCR chimeras between them.
+    AmpliconCommunity(Box<AmpliconCommunityArgs>),
+    /// Simulates immune repertoire sequencing: B- or T-cell receptors recombined
+    /// from V, D and J segments, drawn by clone abundance.
+    Vdj(Box<VdjArgs>),
+    /// Simulates UMI families for consensus callers: every original molecule is
+    /// read several times, from PCR copies with errors of their own and, for
+    /// duplex libraries, from both strands.
+    UmiFamilies(Box<UmiFamiliesArgs>),
+    /// Simulates a viral quasispecies: haplotypes mutated from a reference (-r)
+    /// along a random genealogy, read deeply at known frequencies.
+    Quasispecies(Box<QuasispeciesArgs>),
+    /// Simulates Hi-C read pairs whose mates come from two ligated loci, mostly on
+    /// one contig at power-law distances and some on different contigs.
+    Hic(Box<HicArgs>),
+    /// Reports read count, length distribution, per-position quality, GC content and
+    /// N fraction of FASTQ files.
+    Stats(StatsArgs),
+    /// Learns per-cycle quality distributions and mismatch rates by quality from
+    /// real FASTQ files, for generate --profile, or a Markov sequence model from a
+    /// reference, for generate --markov-model.
+    Profile(ProfileArgs),
+    /// Checks that FASTQ files are well-formed and, given two files, that their
+    /// records pair up.
+    Validate(ValidateArgs),
+    /// Keeps a random subset of the reads of FASTQ files, reproducibly with a seed
+    /// and keeping mates together.
+    Downsample(DownsampleArgs),
+    /// Degrades real reads with the error, quality and artifact models of generate,
+    /// for testing pipelines against noisier versions of real data.
+    Mutate(MutateArgs),
+    /// Tiles the contigs of a FASTA into overlapping reads at a fixed step, for
+    /// aligner truth sets and k-mer databases.
+    Shred(ShredArgs),
+    /// Writes a random genome FASTA, optionally with interspersed repeats, for
+    /// self-contained reference-based simulations.
+    Genome(GenomeArgs),
+    /// Writes random barcodes a minimum Hamming or edit distance apart, as a
+    /// whitelist for single-cell --whitelist or a sample sheet for --sample-sheet.
+    Barcodes(BarcodesArgs),
+    /// Merges paired R1 and R2 files into one interleaved FASTQ.
+    Interleave(InterleaveArgs),
+    /// Splits an interleaved FASTQ into R1 and R2 files.
+    Deinterleave(DeinterleaveArgs),
+    /// Generates reads with the options of generate but discards them, reporting
+    /// reads/s and MB/s, to size runs and measure the speed of a parameter set.
+    Bench(Box<GenerateArgs>),
+}
+
+impl Commands {
+    /// The options shared with generate, for the subcommands that simulate reads.
+    fn generate_args(&mut self) -> Option<&mut GenerateArgs> {
+        match self {
+            Commands::Generate(args) | Commands::Bench(args) => Some(args),
+            Commands::Rnaseq(args) => Some(&mut args.generate),
+            Commands::SingleCell(args) => Some(&mut args.generate),
+            Commands::Metagenome(args) => Some(&mut args.generate),
+            Commands::Somatic(args) => Some(&mut args.generate),
+            Commands::Trio(args) => Some(&mut args.generate),
+            Commands::Cohort(args) => Some(&mut args.generate),
+            Commands::LinkedReads(args) => Some(&mut args.generate),
+            Commands::Atac(args) => Some(&mut args.generate),
+            Commands::Chip(args) => Some(&mut args.generate),
+            Commands::Crispr(args) => Some(&mut args.generate),
+            Commands::AmpliconCommunity(args) => Some(&mut args.generate),
+            Commands::Vdj(args) => Some(&mut args.generate),
+            Commands::UmiFamilies(args) => Some(&mut args.generate),
+            Commands::Quasispecies(args) => Some(&mut args.generate),
+            Commands::Hic(args) => Some(&mut args.generate),
+            Commands::Stats(_)
+            | Commands::Profile(_)
+            | Commands::Validate(_)
+            | Commands::Downsample(_)
+            | Commands::Mutate(_)
+            | Commands::Shred(_)
+            | Commands::Genome(_)
+            | Commands::Barcodes(_)
+            | Commands::Interleave(_)
+            | Commands::Deinterleave(_) => None,
+        }
+    }
 }
 
 #[derive(Parser, Debug)]
-#[command(arg_required_else_help = true)]
-struct GenerateArgs {
+struct RnaseqArgs {
+    #[command(flatten)]
+    generate: GenerateArgs,
 
-    #[arg(index = 1, help = "Number of reads.",required=true)]
-    n: i32,
+    #[arg(long, value_name = "TSV", help = "Transcript abundance (TPM or any relative unit) per line as transcript<TAB>abundance. Without it, abundances are drawn from a log-normal.")]
+    expression: Option<PathBuf>,
 
-    #[arg(short, long, default_value_t = String::from("synthetic_reads"), help = "Output file prefix.")]
-    outfile: String,
-    
-    #[arg(short = 'l', default_value_t = 150, help = "Read length.")]
-    read_len: i32
+    #[arg(long, default_value_t = 2.0, help = "Log-scale standard deviation of the abundances drawn without --expression.")]
+    expression_sd: f64,
+
+    #[arg(long, value_name = "TSV", help = "Write the true TPM and read count of every transcript (one count column per sample).")]
+    counts: PathBuf,
+
+    #[arg(long, value_name = "GTF", help = "Gene annotation of the genome given with -r. Reads are drawn from the spliced transcripts, and truth alignments span the introns.")]
+    gtf: Option<PathBuf>,
+
+    #[arg(long, value_name = "TSV", help = "Write the transcript, gene and reference blocks of every read.")]
+    read_truth: Option<PathBuf>,
 }
 
+/// Transcript expression of an RNA-seq run.
+struct Expression {
+    table: Option<PathBuf>,
+    sd: f64,
+    counts: Option<PathBuf>,
+    gtf: Option<PathBuf>,
+    read_truth: Option<PathBuf>,
+    /// Set for single-cell runs.
+    cells: Option<CellOptions>,
+    /// Genome FASTA files of a metagenome run, which are read instead of -r. The
+    /// abundance table and standard deviation then apply to genomes.
+    genomes: Vec<PathBuf>,
+}
 
-fn run_generate(args: GenerateArgs) -> Result<(), Box<dyn Error>> {
-    let output_file_prefix = args.outfile;
-    let num_reads = args.n;
-    let read_length = args.read_len;
+#[derive(Parser, Debug)]
+struct SingleCellArgs {
+    #[command(flatten)]
+    generate: GenerateArgs,
 
-    if num_reads <= 0 || read_length <= 0 {
-        return Err("Number of reads and read length must be positive.".into());
-    }
+    #[arg(long, value_name = "TSV", help = "Transcript abundance per line as transcript<TAB>abundance. Without it, abundances are drawn from a log-normal.")]
+    expression: Option<PathBuf>,
 
-    let read_length_usize = read_length as usize;
-    let num_reads_i32 = num_reads; 
+    #[arg(long, default_value_t = 2.0, help = "Log-scale standard deviation of the abundances drawn without --expression.")]
+    expression_sd: f64,
 
-    let generator = FastqGenerator::new(read_length_usize);
+    #[arg(long, value_name = "GTF", help = "Gene annotation of the genome given with -r, so reads are spliced and counted per gene.")]
+    gtf: Option<PathBuf>,
 
-    let mut rng = rand::rng();
-    
-    let r1_filepath = format!("{}_R1.fastq", output_file_prefix);
-    let r2_filepath = format!("{}_R2.fastq", output_file_prefix);
+    #[arg(long, default_value_t = 1000, help = "Number of cells.")]
+    cells: usize,
 
-    let r1_file = File::create(&r1_filepath)?;
-    let mut r1_writer = BufWriter::new(r1_file);
+    #[arg(long, value_name = "FILE", help = "Barcode whitelist, one per line (optionally gzipped), such as the 10x 3M-february-2018.txt. Cells are drawn from it; without one, random 16-base barcodes are used.")]
+    whitelist: Option<PathBuf>,
 
-    let r2_file = File::create(&r2_filepath)?;
-    let mut r2_writer = BufWriter::new(r2_file);
+    #[arg(long, value_name = "TSV", help = "Write the true number of UMIs (distinct molecules) per cell and gene.")]
+    counts: PathBuf,
+}
 
-    println!("Starting generation of {} paired reads (Length: {})", num_reads, read_length);
+#[derive(Parser, Debug)]
+struct LinkedReadsArgs {
+    #[command(flatten)]
+    generate: GenerateArgs,
 
-    for i in 0..num_reads_i32 {
-        let record = generator.generate_paired_record(&mut rng, i);
+    #[arg(long, default_value_t = 10000, help = "Number of partitions (droplets or beads), each with its own barcode.")]
+    partitions: usize,
 
-        write!(r1_writer, "@{} /1\n", record.id)?;
-        r1_writer.write_all(&record.seq)?;
-        r1_writer.write_all(b"\n+\n")?; 
-        r1_writer.write_all(&record.quality_1)?;
-        r1_writer.write_all(b"\n")?;
+    #[arg(long, value_name = "FILE", help = "Barcode whitelist, one per line (optionally gzipped), such as the 10x 4M-with-alts-february-2016.txt. Partitions are drawn from it; without one, random 16-base barcodes are used.")]
+    whitelist: Option<PathBuf>,
 
-        write!(r2_writer, "@{} /2\n", record.id)?; 
-        r2_writer.write_all(&record.mate)?;
-        r2_writer.write_all(b"\n+\n")?;
-        r2_writer.write_all(&record.quality_2)?;
-        r2_writer.write_all(b"\n")?;
-    }
+    #[arg(long, default_value_t = 50000.0, help = "Mean length of the long molecules read pairs are drawn from; lengths are exponentially distributed.")]
+    molecule_length: f64,
 
-    r1_writer.flush()?;
-    r2_writer.flush()?;
+    #[arg(long, default_value_t = 40.0, help = "Mean number of read pairs drawn from each molecule (Poisson).")]
+    reads_per_molecule: f64,
 
-    println!("🦀 Wrote {} paired reads of length {} to {}_R[12].fastq", num_reads, read_length, output_file_prefix);
+    #[arg(long, value_enum, default_value_t = LinkedBarcodeArg::Read, help = "Where read pairs carry their partition barcode.")]
+    barcode_placement: LinkedBarcodeArg,
 
-    Ok(())
+    #[arg(long, value_name = "TSV", help = "Write the partition barcode, reference span, haplotype and read count of every molecule.")]
+    molecule_truth: Option<PathBuf>,
 }
 
+#[derive(Parser, Debug)]
+struct AtacArgs {
+    #[command(flatten)]
+    generate: GenerateArgs,
 
-fn main() -> Result<(), Box<dyn Error>> { 
-    let cli = Cli::parse();
+    #[arg(long, value_name = "BED", help = "Open-chromatin peaks that most fragments are cut in.")]
+    peaks: PathBuf,
 
-    match cli.command {
-        Commands::Generate(args) => run_generate(args),
+    #[arg(long, default_value_t = 0.3, help = "Fraction of fragments cut in the peaks (FRiP); the rest come from anywhere on the reference.")]
+    in_peaks: f64,
+
+    #[arg(long, default_value_t = 200, help = "Distance between nucleosomes, which sets the fragment-length periodicity.")]
+    nucleosome_spacing: usize,
+
+    #[arg(long, value_name = "BED", help = "Write the Tn5 insertion sites at either end of every fragment as a BED file.")]
+    fragments: Option<PathBuf>,
+}
+
+#[derive(Parser, Debug)]
+struct ChipArgs {
+    #[command(flatten)]
+    generate: GenerateArgs,
+
+    #[arg(long, value_name = "BED", help = "Binding sites that fragments are enriched around. Without it, --random-sites are drawn.")]
+    sites: Option<PathBuf>,
+
+    #[arg(long, value_name = "N", default_value_t = 1000, conflicts_with = "sites", help = "Number of binding sites to draw at random positions of the reference.")]
+    random_sites: usize,
+
+    #[arg(long, default_value_t = 100, conflicts_with = "sites", help = "Width of the random binding sites.")]
+    site_width: usize,
+
+    #[arg(long, default_value_t = 20.0, help = "Coverage in the middle of a binding site relative to the background.")]
+    fold_enrichment: f64,
+
+    #[arg(long, value_name = "BED", help = "Write the binding sites as a BED file.")]
+    site_truth: Option<PathBuf>,
+}
+
+#[derive(Parser, Debug)]
+struct CrisprArgs {
+    #[command(flatten)]
+    generate: GenerateArgs,
+
+    #[arg(long, value_name = "CSV", help = "Guide library as id,sequence,gene lines, as MAGeCK takes it.")]
+    library: PathBuf,
+
+    #[arg(long, value_name = "TSV", help = "Abundance of each guide per line as guide<TAB>abundance. Without it, abundances are drawn from a log-normal.")]
+    abundance: Option<PathBuf>,
+
+    #[arg(long, default_value_t = 0.5, help = "Log-scale standard deviation of the abundances drawn without --abundance.")]
+    abundance_sd: f64,
+
+    #[arg(long, value_name = "SEQ", default_value_t = String::from_utf8_lossy(GUIDE_UPSTREAM).into_owned(), help = "Vector sequence read before the guide.")]
+    upstream: String,
+
+    #[arg(long, value_name = "SEQ", default_value_t = String::from_utf8_lossy(GUIDE_DOWNSTREAM).into_owned(), help = "Vector sequence read after the guide.")]
+    downstream: String,
+
+    #[arg(long, default_value_t = 0, help = "Most random bases read before the vector, as staggered PCR primers add.")]
+    stagger: usize,
+
+    #[arg(long, value_name = "TSV", help = "Write the true read count of every guide as a MAGeCK count table (one count column per sample).")]
+    counts: Option<PathBuf>,
+}
+
+#[derive(Parser, Debug)]
+struct AmpliconCommunityArgs {
+    #[command(flatten)]
+    generate: GenerateArgs,
+
+    #[arg(long, value_name = "FASTA", help = "Full-length marker-gene references (optionally gzipped), each header an ID followed by its taxonomy, as in SILVA or UNITE.")]
+    references: PathBuf,
+
+    #[arg(long, value_name = "SEQ", default_value_t = String::from_utf8_lossy(V4_FORWARD_PRIMER).into_owned(), help = "Forward primer, 5' to 3', with IUPAC codes for degenerate positions.")]
+    forward_primer: String,
+
+    #[arg(long, value_name = "SEQ", default_value_t = String::from_utf8_lossy(V4_REVERSE_PRIMER).into_owned(), help = "Reverse primer, 5' to 3', with IUPAC codes for degenerate positions.")]
+    reverse_primer: String,
+
+    #[arg(long, value_name = "TSV", help = "Relative abundance of each reference per line as id<TAB>abundance. Without it, abundances are drawn from a log-normal.")]
+    abundance: Option<PathBuf>,
+
+    #[arg(long, default_value_t = 1.0, help = "Log-scale standard deviation of the abundances drawn without --abundance.")]
+    abundance_sd: f64,
+
+    #[arg(long, default_value_t = 0.05, help = "Fraction of molecules that are PCR chimeras of two taxa.")]
+    chimera_rate: f64,
+
+    #[arg(long, value_name = "TSV", help = "Write the relative abundance and true read count of every taxon, and of the chimeras (one count column per sample).")]
+    counts: Option<PathBuf>,
+
+    #[arg(long, value_name = "TSV", help = "Write the taxon, taxonomy and, for chimeras, the second taxon of every read.")]
+    read_truth: Option<PathBuf>,
+}
+
+#[derive(Parser, Debug)]
+struct VdjArgs {
+    #[command(flatten)]
+    generate: GenerateArgs,
+
+    #[arg(long, value_name = "FASTA", help = "V gene segments, as IMGT/GENE-DB FASTA (gapped or not).")]
+    v_genes: PathBuf,
+
+    #[arg(long, value_name = "FASTA", help = "D gene segments, for heavy and beta chains. Without them, V segments join J segments directly.")]
+    d_genes: Option<PathBuf>,
+
+    #[arg(long, value_name = "FASTA", help = "J gene segments.")]
+    j_genes: PathBuf,
+
+    #[arg(long, default_value_t = 1000, help = "Number of clones in the repertoire.")]
+    clones: usize,
+
+    #[arg(long, default_value_t = 1.5, help = "Log-scale standard deviation of the log-normal clone abundances.")]
+    clone_sd: f64,
+
+    #[arg(long, default_value_t = 3.0, help = "Mean number of bases deleted from each end joined in recombination.")]
+    deletion_mean: f64,
+
+    #[arg(long, default_value_t = 4.0, help = "Mean number of non-templated (N) bases inserted at each join.")]
+    insertion_mean: f64,
+
+    #[arg(long, help = "Keep clonotypes whose junction is out of frame or has a stop codon.")]
+    unproductive: bool,
+
+    #[arg(long, value_name = "TSV", help = "Write the segments, junction, abundance and true read count of every clone (one count column per sample).")]
+    clonotypes: Option<PathBuf>,
+}
+
+#[derive(Parser, Debug)]
+struct UmiFamiliesArgs {
+    #[command(flatten)]
+    generate: GenerateArgs,
+
+    #[arg(long, default_value_t = 4.0, help = "Mean number of reads per family: the original molecule and a Poisson number of PCR copies.")]
+    family_size: f64,
+
+    #[arg(long, help = "Simulate duplex families: a UMI at each end of every molecule, and copies of both of its strands.")]
+    duplex: bool,
+
+    #[arg(long, default_value_t = 1e-4, help = "Per-base rate of substitutions in every PCR copy of a molecule, UMIs included.")]
+    pcr_error_rate: f64,
+
+    #[arg(long, value_name = "TSV", help = "Write the family, strand, true UMI and true molecule sequence of every read.")]
+    family_truth: Option<PathBuf>,
+}
+
+#[derive(Parser, Debug)]
+struct QuasispeciesArgs {
+    #[command(flatten)]
+    generate: GenerateArgs,
+
+    #[arg(long, default_value_t = 5, help = "Number of haplotypes in the quasispecies, unless --frequencies gives theirs.")]
+    haplotypes: usize,
+
+    #[arg(long, value_delimiter = ',', help = "Frequency of every haplotype, one per haplotype, scaled to sum to one. Drawn from a log-normal by default.")]
+    frequencies: Option<Vec<f64>>,
+
+    #[arg(long, default_value_t = 1.0, help = "Log-scale standard deviation of the log-normal haplotype frequencies.")]
+    frequency_sd: f64,
+
+    #[arg(long, default_value_t = 0.005, help = "Per-base rate of the SNVs each haplotype gains over the one it descends from.")]
+    divergence: f64,
+
+    #[arg(long, default_value_t = 2.0, help = "Ratio of transitions to transversions among the SNVs of the haplotypes.")]
+    ts_tv: f64,
+
+    #[arg(long, value_name = "VCF", help = "Write every haplotype SNV with its frequency in the quasispecies and the haplotypes carrying it.")]
+    variant_truth: Option<PathBuf>,
+
+    #[arg(long, value_name = "FASTA", help = "Write the sequence of every haplotype, with its frequency and parent in the header.")]
+    haplotype_fasta: Option<PathBuf>,
+}
+
+#[derive(Parser, Debug)]
+struct HicArgs {
+    #[command(flatten)]
+    generate: GenerateArgs,
+
+    #[arg(long, default_value_t = 0.2, help = "Fraction of pairs whose mates lie on different contigs.")]
+    trans_fraction: f64,
+
+    #[arg(long, default_value_t = 1.0, help = "Exponent of the power law, P(s) ~ s^-exponent, that cis contact distances follow.")]
+    contact_exponent: f64,
+
+    #[arg(long, default_value_t = 1000, help = "Shortest distance between the mates of a cis pair.")]
+    min_contact_distance: usize,
+
+    #[arg(long, value_name = "PAIRS", help = "Write the true loci and strands of the mates of every pair in 4DN pairs format.")]
+    contact_truth: Option<PathBuf>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum LinkedBarcodeArg {
+    /// Prepended to read 1 with a 7-base spacer, as sequenced in 10x Genomics
+    /// Chromium libraries.
+    Read,
+    /// Written to a BX:Z tag in the read comments, as Long Ranger basic and stLFR
+    /// barcode splitting tools output.
+    Tag,
+}
+
+#[derive(Parser, Debug)]
+struct MetagenomeArgs {
+    #[command(flatten)]
+    generate: GenerateArgs,
+
+    #[arg(long, required = true, num_args = 1.., value_name = "FASTA", help = "FASTA of every genome of the community (optionally gzipped), named after its file without extensions.")]
+    genomes: Vec<PathBuf>,
+
+    #[arg(long, value_name = "TSV", help = "Relative abundance (cells, not reads) of each genome per line as genome<TAB>abundance. Without it, abundances are drawn from a log-normal.")]
+    abundance: Option<PathBuf>,
+
+    #[arg(long, default_value_t = 1.0, help = "Log-scale standard deviation of the abundances drawn without --abundance.")]
+    abundance_sd: f64,
+
+    #[arg(long, value_name = "TSV", help = "Write the relative abundance and true read count of every genome (one count column per sample).")]
+    counts: Option<PathBuf>,
+
+    #[arg(long, value_name = "TSV", help = "Write the source contig, genome and reference blocks of every read.")]
+    read_truth: Option<PathBuf>,
+}
+
+#[derive(Parser, Debug)]
+struct SomaticArgs {
+    #[command(flatten)]
+    generate: GenerateArgs,
+
+    #[arg(long, default_value_t = 1e-6, help = "Per-base rate of somatic SNVs.")]
+    somatic_snv_rate: f64,
+
+    #[arg(long, default_value_t = 1e-7, help = "Per-base rate of somatic indels, with the --indel-mean-length and --indel-max-length of germline indels.")]
+    somatic_indel_rate: f64,
+
+    #[arg(long, value_name = "VCF", help = "Spike the variants of a VCF into the tumor as somatic variants, at allele fractions drawn from --vafs.")]
+    somatic_vcf: Option<PathBuf>,
+
+    #[arg(long, value_delimiter = ',', default_values_t = [0.5, 0.25, 0.1, 0.05], help = "Allele fractions of somatic variants in a pure tumor; each variant gets one at random. The fractions on each germline haplotype may add up to at most 0.5.")]
+    vafs: Vec<f64>,
+
+    #[arg(long, default_value_t = 1.0, help = "Fraction of tumor cells in the tumor sample; the rest are normal.")]
+    purity: f64,
+
+    #[arg(long, value_name = "VCF", help = "Write every somatic variant with its allele fraction in the tumor sample.")]
+    somatic_truth_vcf: PathBuf,
+
+    #[arg(long, value_name = "NAME:EXPOSURE", value_delimiter = ',', value_parser = parse_exposure, requires = "signature_matrix", help = "Draw somatic SNVs from a mixture of SBS signatures at these exposures, e.g. SBS1:0.6,SBS4:0.4, in the trinucleotide contexts of the reference.")]
+    signature: Vec<(String, f64)>,
+
+    #[arg(long, value_name = "TSV", requires = "signature", help = "SBS signature matrix the --signature names come from, as COSMIC distributes it (e.g. COSMIC_v3.4_SBS_GRCh38.txt).")]
+    signature_matrix: Option<PathBuf>,
+
+    #[arg(long, value_name = "TSV", requires = "signature", help = "Write the exposure of every signature and the number of somatic SNVs it caused.")]
+    exposure_truth: Option<PathBuf>,
+}
+
+#[derive(Parser, Debug)]
+struct TrioArgs {
+    #[command(flatten)]
+    generate: GenerateArgs,
+
+    #[arg(long, default_value_t = 1e-8, help = "Per-base rate of crossovers in each parent's meiosis.")]
+    recombination_rate: f64,
+
+    #[arg(long, default_value_t = 70, help = "Number of de novo SNVs of the child.")]
+    de_novo: usize,
+
+    #[arg(long, value_name = "VCF", help = "Write every variant with the phased genotypes of the mother, father and child, de novo mutations flagged.")]
+    pedigree_vcf: PathBuf,
+}
+
+#[derive(Parser, Debug)]
+struct CohortArgs {
+    #[command(flatten)]
+    generate: GenerateArgs,
+
+    #[arg(long, default_value_t = 10, help = "Number of samples.")]
+    samples: usize,
+
+    #[arg(long, default_value_t = 1, help = "Number of populations the samples are split into, in order.")]
+    populations: usize,
+
+    #[arg(long, default_value_t = 0.1, help = "FST between populations: how far each population's allele frequencies drift from the ancestral ones.")]
+    fst: f64,
+
+    #[arg(long, value_name = "VCF", help = "Write every variant with its cohort frequency, the phased genotype of every sample and the population of each.")]
+    cohort_vcf: PathBuf,
+}
+
+/// Somatic variants of the tumor sample of a tumor/normal run.
+struct Somatic {
+    model: TumorModel,
+    snv_rate: f64,
+    indel_rate: f64,
+    vcf: Option<PathBuf>,
+    truth_vcf: PathBuf,
+    /// Signatures the SNVs are drawn from, rather than uniformly.
+    signatures: Option<SignatureMixture>,
+    exposure_truth: Option<PathBuf>,
+}
+
+/// Cell settings of a single-cell run.
+struct CellOptions {
+    cells: usize,
+    whitelist: Option<PathBuf>,
+    counts: PathBuf,
+}
+
+/// Settings of the subcommands that simulate a specialised library with the
+/// options of generate.
+enum Library {
+    Linked(LinkedOptions),
+    HiC(HicOptions),
+    Atac(AtacOptions),
+    Chip(ChipOptions),
+    Crispr(CrisprOptions),
+    Marker(MarkerOptions),
+    Families(FamilyOptions),
+    Vdj(VdjOptions),
+    Quasispecies(QuasispeciesOptions),
+    Trio(TrioOptions),
+    Cohort(CohortOptions),
+}
+
+impl Library {
+    /// Truth and count files the library writes, besides those of every run.
+    fn outputs(&self) -> Vec<&PathBuf> {
+        let outputs = match self {
+            Library::Linked(options) => vec![options.molecule_truth.as_ref()],
+            Library::HiC(options) => vec![options.contact_truth.as_ref()],
+            Library::Atac(options) => vec![options.fragments.as_ref()],
+            Library::Chip(options) => vec![options.site_truth.as_ref()],
+            Library::Crispr(options) => vec![options.counts.as_ref()],
+            Library::Marker(options) => vec![options.counts.as_ref(), options.read_truth.as_ref()],
+            Library::Families(options) => vec![options.family_truth.as_ref()],
+            Library::Vdj(options) => vec![options.clonotypes.as_ref()],
+            Library::Quasispecies(options) => vec![options.variant_truth.as_ref(), options.haplotype_fasta.as_ref()],
+            Library::Trio(options) => vec![options.pedigree_vcf.as_ref()],
+            Library::Cohort(options) => vec![options.cohort_vcf.as_ref()],
+        };
+        outputs.into_iter().flatten().collect()
+    }
+
+    /// Sets up what the library samples its reads from, once the reference is
+    /// loaded. ChIP-seq binding sites are placed earlier, by [`setup_chip`], before
+    /// spike-ins join the reference; trios, cohorts and quasispecies change the
+    /// germline variants, in [`Library::simulate_variants`].
+    fn setup(&self, generator: FastqGenerator, args: &GenerateArgs, seed: u64) -> Result<(FastqGenerator, LibrarySetup), FastqGenError> {
+        match self {
+            Library::Linked(options) => setup_linked(options, generator, args, seed),
+            Library::HiC(options) => setup_hic(options, generator, args),
+            Library::Atac(options) => setup_atac(options, generator, args),
+            Library::Crispr(options) => setup_crispr(options, generator, args, seed),
+            Library::Marker(options) => setup_marker(options, generator, args, seed),
+            Library::Families(options) => setup_families(options, generator, args),
+            Library::Vdj(options) => setup_vdj(options, generator, seed),
+            Library::Chip(_) | Library::Quasispecies(_) | Library::Trio(_) | Library::Cohort(_) => Ok((generator, LibrarySetup::default())),
+        }
+    }
+
+    /// Turns the germline `variants` into those of the sample a trio, cohort or
+    /// quasispecies run sequences, writing the library's variant truth. Returns the
+    /// ploidy of the sample, if the library changes it, and the haplotypes of a
+    /// quasispecies.
+    fn simulate_variants(
+        &self,
+        reference: &Reference,
+        seed: u64,
+        variants: &mut Vec<Variant>,
+        structural: &mut [StructuralVariant],
+    ) -> Result<(Option<usize>, Option<Quasispecies>), FastqGenError> {
+        match self {
+            Library::Trio(options) => {
+                simulate_trio(options, reference, seed, variants, structural)?;
+                Ok((Some(2), None))
+            }
+            Library::Cohort(options) => {
+                simulate_cohort(options, reference, seed, variants)?;
+                Ok((Some(2), None))
+            }
+            Library::Quasispecies(options) => {
+                let population = simulate_quasispecies(options, reference, seed, variants, structural)?;
+                Ok((Some(population.frequencies().len()), Some(population)))
+            }
+            _ => Ok((None, None)),
+        }
+    }
+
+    /// Opens the truth file the library writes chunk by chunk, if it writes one.
+    fn open_truth(&self, truth: &mut TruthWriters, reference: Option<&Reference>) -> Result<(), FastqGenError> {
+        match self {
+            Library::Linked(options) => {
+                truth.open(ChunkTruth::LinkedMolecules, options.molecule_truth.as_deref(), b"barcode\tcontig\tstart\tend\thaplotype\treads\n")
+            }
+            Library::HiC(options) => {
+                let mut header = String::from("## pairs format v1.0\n");
+                for (name, length) in reference.into_iter().flat_map(Reference::contigs) {
+                    header.push_str(&format!("#chromsize: {} {}\n", name, length));
+                }
+                header.push_str("#columns: readID chr1 pos1 chr2 pos2 strand1 strand2\n");
+                truth.open(ChunkTruth::Contacts, options.contact_truth.as_deref(), header.as_bytes())
+            }
+            Library::Atac(options) => truth.open(ChunkTruth::Fragments, options.fragments.as_deref(), b""),
+            Library::Marker(options) => truth.open(ChunkTruth::Taxonomy, options.read_truth.as_deref(), b"read\ttaxon\ttaxonomy\tchimera\n"),
+            Library::Families(options) => truth.open(ChunkTruth::Families, options.family_truth.as_deref(), b"read\tfamily\tstrand\tumi\tmolecule\n"),
+            _ => Ok(()),
+        }
     }
-}
\ No newline at end of file
+}
+
+/// What a library adds to a run once it is set up.
+#[derive(Default)]
+struct LibrarySetup {
+    linked_partitions: Option<LinkedPartitions>,
+    guides: Option<GuideLibrary>,
+    marker_community: Option<MarkerCommunity>,
+    umi_families: Option<UmiFamilies>,
+    repertoire: Option<Repertoire>,
+    /// Where the reads counted per guide, taxon or clone are written.
+    counts: Option<PathBuf>,
+}
+
+impl LibrarySetup {
+    /// Writes the reads counted per guide of a CRISPR screen, taxon of a
+    /// marker-gene community or clone of a repertoire, if asked to.
+    fn write_counts(&self, columns: &[&str], counts: &[u64]) -> Result<(), FastqGenError> {
+        let Some(path) = &self.counts else {
+            return Ok(());
+        };
+        write_truth_file(path, |writer| {
+            if let Some(guides) = &self.guides {
+                guides.write_counts(writer, columns, counts)
+            } else if let Some(community) = &self.marker_community {
+                community.write_counts(writer, columns, counts)
+            } else if let Some(repertoire) = &self.repertoire {
+                repertoire.write_clonotypes(writer, columns, counts)
+            } else {
+                Ok(())
+            }
+        })
+    }
+}
+
+/// Peak and fragment settings of an ATAC-seq run.
+struct AtacOptions {
+    peaks: PathBuf,
+    in_peaks: f64,
+    nucleosome_spacing: usize,
+    fragments: Option<PathBuf>,
+}
+
+/// Binding sites of a ChIP-seq run and their enrichment.
+struct ChipOptions {
+    sites: Option<PathBuf>,
+    random_sites: usize,
+    site_width: usize,
+    fold_enrichment: f64,
+    site_truth: Option<PathBuf>,
+}
+
+/// Guide library of a CRISPR screen and the vector it is read in.
+struct CrisprOptions {
+    library: PathBuf,
+    abundance: Option<PathBuf>,
+    abundance_sd: f64,
+    upstream: String,
+    downstream: String,
+    stagger: usize,
+    counts: Option<PathBuf>,
+}
+
+/// References, primers and community of a marker-gene amplicon run.
+struct MarkerOptions {
+    references: PathBuf,
+    forward_primer: String,
+    reverse_primer: String,
+    abundance: Option<PathBuf>,
+    abundance_sd: f64,
+    chimera_rate: f64,
+    counts: Option<PathBuf>,
+    read_truth: Option<PathBuf>,
+}
+
+/// Gene segments and clones of an immune repertoire run.
+struct VdjOptions {
+    v_genes: PathBuf,
+    d_genes: Option<PathBuf>,
+    j_genes: PathBuf,
+    clones: usize,
+    clone_sd: f64,
+    deletion_mean: f64,
+    insertion_mean: f64,
+    unproductive: bool,
+    clonotypes: Option<PathBuf>,
+}
+
+/// Inheritance of one member of a trio run.
+struct TrioOptions {
+    member: TrioMember,
+    recombination_rate: f64,
+    de_novo: usize,
+    /// Written by one member's run only.
+    pedigree_vcf: Option<PathBuf>,
+}
+
+/// One sample of a cohort run.
+struct CohortOptions {
+    cohort: Cohort,
+    sample: usize,
+    /// Written by one sample's run only.
+    cohort_vcf: Option<PathBuf>,
+}
+
+/// Haplotypes and mutations of a quasispecies run.
+struct QuasispeciesOptions {
+    haplotypes: usize,
+    frequencies: Option<Vec<f64>>,
+    frequency_sd: f64,
+    divergence: f64,
+    ts_tv: f64,
+    variant_truth: Option<PathBuf>,
+    haplotype_fasta: Option<PathBuf>,
+}
+
+/// Family settings of a UMI family run.
+struct FamilyOptions {
+    family_size: f64,
+    duplex: bool,
+    pcr_error_rate: f64,
+    family_truth: Option<PathBuf>,
+}
+
+/// Contact settings of a Hi-C run.
+struct HicOptions {
+    trans_fraction: f64,
+    exponent: f64,
+    min_distance: usize,
+    contact_truth: Option<PathBuf>,
+}
+
+/// Partition settings of a linked-read run.
+struct LinkedOptions {
+    partitions: usize,
+    whitelist: Option<PathBuf>,
+    molecule_length: f64,
+    reads_per_molecule: f64,
+    placement: LinkedBarcodeArg,
+    molecule_truth: Option<PathBuf>,
+}
+
+/// The partitions of a linked-read run and where reads carry their barcodes.
+struct LinkedPartitions {
+    library: LinkedReads,
+    placement: LinkedBarcodeArg,
+    /// The whitelist sequenced barcodes are counted against, when they carry errors.
+    whitelist: Option<BarcodeWhitelist>,
+}
+
+/// The cells of a single-cell run and the gene of every transcript.
+struct CellBarcodes {
+    barcodes: Vec<Vec<u8>>,
+    /// The whitelist sequenced barcodes are counted against, when they carry errors.
+    whitelist: Option<BarcodeWhitelist>,
+    /// Index into `gene_names` of each transcript of the reference.
+    genes: Vec<usize>,
+    gene_names: Vec<String>,
+}
+
+/// Length of random cell and partition barcodes, as in 10x Genomics chemistries.
+const CELL_BARCODE_LENGTH: usize = 16;
+/// Default UMI length of single-cell runs, as in 10x Genomics 3' v3 chemistry.
+const CELL_UMI_LENGTH: usize = 12;
+/// Default length of the UMIs of UMI family runs, at each end of duplex molecules.
+const FAMILY_UMI_LENGTH: usize = 8;
+/// Length of the random spacer between the barcode and the insert of linked-read
+/// R1 reads, as in 10x Genomics Chromium genome libraries.
+const LINKED_SPACER_LENGTH: usize = 7;
+
+/// The gene annotation of a spliced RNA-seq run. Reads are sequenced from the
+/// spliced transcripts and reported against the genome.
+struct Annotation {
+    genome: Reference,
+    /// In the order of the contigs of the transcriptome reference.
+    transcripts: Vec<Transcript>,
+}
+
+#[derive(Parser, Debug)]
+struct ValidateArgs {
+    #[arg(value_name = "R1", help = "FASTQ file to check (optionally gzipped), or - for standard input.")]
+    r1: PathBuf,
+
+    #[arg(value_name = "R2", help = "Mate file whose records must pair with R1's.")]
+    r2: Option<PathBuf>,
+
+    #[arg(long, default_value_t = 33, help = "ASCII offset the quality scores are declared in (33 or 64).")]
+    phred_offset: u8,
+}
+
+#[derive(Parser, Debug)]
+struct DownsampleArgs {
+    #[arg(value_name = "R1", help = "FASTQ file to downsample (optionally gzipped), or - for standard input.")]
+    r1: PathBuf,
+
+    #[arg(value_name = "R2", help = "Mate file of R1; pairs are kept or dropped together.")]
+    r2: Option<PathBuf>,
+
+    #[arg(short, long, help = "Output prefix: <outfile>_R1.fastq and <outfile>_R2.fastq for pairs, <outfile>.fastq for single reads, or - for standard output (pairs interleaved).")]
+    outfile: String,
+
+    #[arg(long, required_unless_present = "reads", conflicts_with = "reads", help = "Keep each read (or pair) with this probability.")]
+    fraction: Option<f64>,
+
+    #[arg(long, value_name = "N", value_parser = parse_count, help = "Keep exactly N reads (or pairs), chosen uniformly. R1 is read twice, so it cannot be standard input.")]
+    reads: Option<u64>,
+
+    #[arg(long, help = "Seed for the random number generator. Identical seeds and inputs keep the same reads.")]
+    seed: Option<u64>,
+
+    #[command(flatten)]
+    compression: CompressionArgs,
+}
+
+#[derive(Parser, Debug)]
+struct MutateArgs {
+    #[arg(value_name = "R1", help = "FASTQ file to degrade (optionally gzipped), or - for standard input.")]
+    r1: PathBuf,
+
+    #[arg(value_name = "R2", help = "Mate file of R1, whose records must pair with R1's.")]
+    r2: Option<PathBuf>,
+
+    #[arg(short, long, help = "Output prefix: <outfile>_R1.fastq and <outfile>_R2.fastq for pairs, <outfile>.fastq for single reads, or - for standard output (pairs interleaved).")]
+    outfile: String,
+
+    #[arg(long, default_value_t = 0.0, help = "Per-base substitution error rate.")]
+    sub_rate: f64,
+
+    #[arg(long, default_value_t = 0.0, help = "Per-base insertion error rate.")]
+    ins_rate: f64,
+
+    #[arg(long, default_value_t = 0.0, help = "Per-base deletion error rate.")]
+    del_rate: f64,
+
+    #[arg(long, value_name = "JSON", conflicts_with = "sub_rate", help = "Substitute bases at the rate their (shifted) quality has in a fastqgen profile, instead of --sub-rate.")]
+    profile: Option<PathBuf>,
+
+    #[arg(long, default_value_t = 0, allow_negative_numbers = true, help = "Phred points added to every quality score before errors are injected; negative to lower them.")]
+    qual_shift: i16,
+
+    #[arg(long, default_value_t = 1.0, help = "Factor by which the error rates of read 2 exceed those of read 1.")]
+    r2_error_multiplier: f64,
+
+    #[arg(long, default_value_t = 0.0, help = "Fraction of reads whose signal drops out at a random cycle, leaving a poly-G tail.")]
+    poly_g_rate: f64,
+
+    #[arg(long, default_value_t = 0.0, help = "Fraction of bases replaced with no-calls (N at quality 2).")]
+    n_rate: f64,
+
+    #[arg(long, default_value_t = 33, help = "ASCII offset of the quality scores, kept in the output.")]
+    phred_offset: u8,
+
+    #[arg(long, help = "Seed for the random number generator. Identical seeds and inputs produce identical output.")]
+    seed: Option<u64>,
+
+    #[command(flatten)]
+    compression: CompressionArgs,
+}
+
+#[derive(Parser, Debug)]
+struct ShredArgs {
+    #[arg(value_name = "FASTA", help = "Reference FASTA to tile (optionally gzipped).")]
+    reference: PathBuf,
+
+    #[arg(short, long, help = "Output prefix, written to <outfile>.fastq, or - for standard output.")]
+    outfile: String,
+
+    #[arg(short = 'l', long, default_value_t = 150, help = "Read length. Contigs shorter than it become one read.")]
+    read_length: usize,
+
+    #[arg(long, value_name = "BASES", help = "Distance between the starts of consecutive reads [default: the read length].")]
+    step: Option<usize>,
+
+    #[arg(long, conflicts_with = "step", help = "Reads covering each base, which sets the step to the read length divided by it.")]
+    coverage: Option<f64>,
+
+    #[arg(long, help = "Also write the reverse complement of every read, named with an _rc suffix.")]
+    both_strands: bool,
+
+    #[arg(long, default_value_t = 0.0, help = "Per-base substitution error rate.")]
+    sub_rate: f64,
+
+    #[arg(long, default_value_t = 0.0, help = "Per-base insertion error rate.")]
+    ins_rate: f64,
+
+    #[arg(long, default_value_t = 0.0, help = "Per-base deletion error rate.")]
+    del_rate: f64,
+
+    #[arg(long, default_value_t = 40, help = "Phred score of error-free bases; bases with errors get low scores.")]
+    max_quality: u8,
+
+    #[arg(long, default_value_t = 33, help = "ASCII offset of the quality scores: 33 or 64.")]
+    phred_offset: u8,
+
+    #[arg(long, help = "Seed for the random number generator, which only matters with error rates.")]
+    seed: Option<u64>,
+
+    #[command(flatten)]
+    compression: CompressionArgs,
+}
+
+#[derive(Parser, Debug)]
+struct GenomeArgs {
+    #[arg(long, value_parser = parse_count, help = "Genome size in bases; k, M and G suffixes are accepted, e.g. 5M.")]
+    length: u64,
+
+    #[arg(long, default_value_t = 1, help = "Number of contigs, named chr1, chr2 and so on, of equal length.")]
+    contigs: usize,
+
+    #[arg(long, default_value_t = 0.5, help = "GC content.")]
+    gc: f64,
+
+    #[arg(short, long, value_name = "FASTA", help = "Write the genome to this file.")]
+    outfile: PathBuf,
+
+    #[arg(long, default_value_t = 0.0, help = "Fraction of the genome covered by interspersed repeat copies.")]
+    repeat_fraction: f64,
+
+    #[arg(long, default_value_t = 300, help = "Length of each repeat family.")]
+    repeat_length: usize,
+
+    #[arg(long, default_value_t = 5, help = "Number of distinct repeat families.")]
+    repeat_families: usize,
+
+    #[arg(long, default_value_t = 0.02, help = "Fraction of bases in which each repeat copy differs from its family.")]
+    repeat_divergence: f64,
+
+    #[arg(long, value_name = "BED", help = "Write the location, family and strand of every repeat copy.")]
+    repeats_bed: Option<PathBuf>,
+
+    #[arg(long, help = "Seed for the random number generator. Identical seeds and parameters produce identical genomes.")]
+    seed: Option<u64>,
+}
+
+#[derive(Parser, Debug)]
+struct BarcodesArgs {
+    #[arg(help = "Number of barcodes to generate.")]
+    count: usize,
+
+    #[arg(short, long, default_value_t = 16, help = "Length of each barcode.")]
+    length: usize,
+
+    #[arg(long, default_value_t = 3, help = "Least distance between any two barcodes; 3 lets one error be corrected, 2 only detected.")]
+    min_distance: usize,
+
+    #[arg(long, help = "Measure --min-distance as edit (Levenshtein) distance instead of Hamming distance, keeping barcodes apart under indels.")]
+    edit_distance: bool,
+
+    #[arg(long, default_value_t = 0.0, help = "Lowest GC content of a barcode.")]
+    gc_min: f64,
+
+    #[arg(long, default_value_t = 1.0, help = "Highest GC content of a barcode.")]
+    gc_max: f64,
+
+    #[arg(short, long, value_name = "FILE", help = "Write the barcodes to this file, one per line, as a whitelist.")]
+    outfile: Option<PathBuf>,
+
+    #[arg(long, value_name = "CSV", help = "Write the barcodes as the i7 indices of a sample sheet of samples S1, S2 and so on.")]
+    sample_sheet: Option<PathBuf>,
+
+    #[arg(long, help = "Seed for the random number generator. Identical seeds and parameters produce identical barcodes.")]
+    seed: Option<u64>,
+}
+
+#[derive(Parser, Debug)]
+struct InterleaveArgs {
+    #[arg(value_name = "R1", help = "Read 1 FASTQ file (optionally gzipped), or - for standard input.")]
+    r1: PathBuf,
+
+    #[arg(value_name = "R2", help = "Read 2 FASTQ file, whose records must pair with R1's.")]
+    r2: PathBuf,
+
+    #[arg(short, long, help = "Output prefix, written to <outfile>.fastq, or - for standard output.")]
+    outfile: String,
+
+    #[command(flatten)]
+    compression: CompressionArgs,
+}
+
+#[derive(Parser, Debug)]
+struct DeinterleaveArgs {
+    #[arg(value_name = "FASTQ", help = "Interleaved FASTQ file (optionally gzipped), or - for standard input.")]
+    input: PathBuf,
+
+    #[arg(short, long, help = "Output prefix, written to <outfile>_R1.fastq and <outfile>_R2.fastq.")]
+    outfile: String,
+
+    #[command(flatten)]
+    compression: CompressionArgs,
+}
+
+#[derive(Parser, Debug)]
+struct StatsArgs {
+    #[arg(required = true, value_name = "FASTQ", help = "FASTQ files to summarise (optionally gzipped), or - for standard input.")]
+    files: Vec<PathBuf>,
+
+    #[arg(long, help = "Print the statistics as JSON instead of tables.")]
+    json: bool,
+
+    #[arg(long, default_value_t = 33, help = "ASCII offset of the quality scores.")]
+    phred_offset: u8,
+}
+
+#[derive(Parser, Debug)]
+struct ProfileArgs {
+    #[arg(value_name = "R1", required_unless_present = "reference", help = "FASTQ file to learn from (optionally gzipped), or - for standard input.")]
+    r1: Option<PathBuf>,
+
+    #[arg(value_name = "R2", help = "Mate file of R1. Mismatch rates are measured where mates overlap; without it they follow the quality scores.")]
+    r2: Option<PathBuf>,
+
+    #[arg(short, long, value_name = "JSON", help = "Write the profile to this file.")]
+    outfile: PathBuf,
+
+    #[arg(long, default_value_t = 33, help = "ASCII offset of the quality scores.")]
+    phred_offset: u8,
+
+    #[arg(short, long, value_name = "FASTA", conflicts_with = "r1", help = "Train a Markov sequence model on a reference FASTA (optionally gzipped) instead of profiling FASTQ files.")]
+    reference: Option<PathBuf>,
+
+    #[arg(long, default_value_t = 3, requires = "reference", help = "Order of the Markov model: the number of preceding bases each base depends on.")]
+    order: usize,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum QualityModelArg {
+    /// Uniform Phred scores between Q0 and --max-quality.
+    Uniform,
+    /// Quality decays toward the 3' end of each read.
+    IlluminaDecay,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum LengthDistArg {
+    /// Every read is -l bases long.
+    Fixed,
+    /// Normal with mean -l and sd --length-sd.
+    Normal,
+    /// Uniform between --length-min and --length-max.
+    Uniform,
+    /// Heavy-tailed lognormal with mean -l and sd --length-sd.
+    Lognormal,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum OutputFormatArg {
+    /// FASTQ files, one per mate.
+    Fastq,
+    /// A single unaligned BAM with both mates, as GATK-style pipelines expect.
+    Ubam,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum NamingArg {
+    /// <outfile>_R1.fastq, or <outfile>_<sample>_R1.fastq when demultiplexing.
+    Default,
+    /// <outfile>_S1_L001_R1_001.fastq, or <outfile>_<sample>_S<n>_L001_R1_001.fastq when demultiplexing;
+    /// the lane is left out of merged multi-lane runs.
+    Bcl2fastq,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum OrientationArg {
+    /// Mates face inward, as in paired-end libraries.
+    Fr,
+    /// Mates face outward, as in mate-pair libraries.
+    Rf,
+    /// Both mates read the forward strand of the fragment.
+    Ff,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum UmiPlacementArg {
+    /// Prepended to read 1.
+    Read,
+    /// Appended to the read name after a colon.
+    Name,
+    /// Written to a separate <outfile>_UMI.fastq index file.
+    Index,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum NameFormatArg {
+    /// READ_000001 /1
+    Simple,
+    /// Casava 1.8+ headers, e.g. SIM:1:FCX:1:1101:1000:2000 1:N:0:ATCACG
+    Illumina,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum PresetArg {
+    /// 2x250 bp, unbinned qualities.
+    Miseq,
+    /// 2x125 bp, 8-level binned qualities.
+    Hiseq,
+    /// 2x150 bp, 4-level binned qualities, poly-G tails.
+    Novaseq,
+    /// 2x150 bp, noisier binned qualities, more poly-G tails.
+    Nextseq,
+    /// Single-end long reads, median ~10 kb, indel-heavy errors.
+    Nanopore,
+    /// PacBio HiFi: single-end 10-25 kb reads at Q30+.
+    Hifi,
+}
+
+impl PresetArg {
+    fn preset(self) -> Preset {
+        match self {
+            PresetArg::Miseq => Preset::miseq(),
+            PresetArg::Hiseq => Preset::hiseq(),
+            PresetArg::Novaseq => Preset::novaseq(),
+            PresetArg::Nextseq => Preset::nextseq(),
+            PresetArg::Nanopore => Preset::nanopore(),
+            PresetArg::Hifi => Preset::hifi(),
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum RngArg {
+    /// ChaCha12, a cryptographically strong generator.
+    Crypto,
+    /// Xoshiro256++, several times faster, with random bases drawn 32 to a number.
+    Fast,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum CompressArg {
+    /// Plain text.
+    None,
+    /// Gzip (.fastq.gz).
+    Gzip,
+    /// Blocked gzip (.fastq.gz), readable by gzip and indexable by htslib tools.
+    Bgzf,
+    /// Zstandard (.fastq.zst).
+    Zstd,
+}
+
+/// Compression options of the commands that write FASTQ.
+#[derive(clap::Args, Debug, Clone, Serialize)]
+struct CompressionArgs {
+    #[arg(long, conflicts_with = "compress", help = "Write gzip-compressed output (.fastq.gz), as --compress gzip.")]
+    gzip: bool,
+
+    #[arg(long, value_enum, value_name = "FORMAT", help = "Compress the output.")]
+    compress: Option<CompressArg>,
+
+    #[arg(long, value_name = "LEVEL", help = "Compression level: 0-9 for gzip and bgzf [default: 6], 1-22 for zstd [default: 3].")]
+    compress_level: Option<i32>,
+
+    #[arg(long, value_name = "N", default_value_t = 1, help = "Threads to compress the output with, separate from the threads generating reads.")]
+    compress_threads: usize,
+}
+
+/// Compression resolved from [`CompressionArgs`], with the thread pool shared by
+/// every file it opens.
+struct Compressor {
+    compression: OutputCompression,
+    pool: Option<Arc<rayon::ThreadPool>>,
+}
+
+impl Compressor {
+    fn create(&self, path: impl AsRef<Path>) -> Result<FastqWriter, FastqGenError> {
+        let path = path.as_ref();
+        match &self.pool {
+            Some(pool) => FastqWriter::create_parallel(path, self.compression, Arc::clone(pool)),
+            None => FastqWriter::create(path, self.compression),
+        }
+        .map_err(|e| FastqGenError::io(format!("Failed to create {}", path.display()), e))
+    }
+
+    fn stdout(&self) -> io::Result<FastqWriter> {
+        match &self.pool {
+            Some(pool) => FastqWriter::stdout_parallel(self.compression, Arc::clone(pool)),
+            None => FastqWriter::stdout(self.compression),
+        }
+    }
+
+    fn sink(&self) -> io::Result<FastqWriter> {
+        FastqWriter::sink(self.compression, self.pool.clone())
+    }
+
+    fn extension(&self) -> &'static str {
+        self.compression.fastq_extension()
+    }
+}
+
+impl CompressionArgs {
+    fn compressor(&self) -> Result<Compressor, FastqGenError> {
+        if self.compress_threads == 0 {
+            return Err(FastqGenError::invalid("--compress-threads must be positive."));
+        }
+        let pool = match self.compress_threads {
+            1 => None,
+            threads => Some(Arc::new(rayon::ThreadPoolBuilder::new().num_threads(threads).build()
+                .map_err(|e| FastqGenError::invalid(format!("Failed to start {} compression threads: {}", threads, e)))?)),
+        };
+        Ok(Compressor { compression: self.compression()?, pool })
+    }
+
+    fn compression(&self) -> Result<OutputCompression, FastqGenError> {
+        let format = match (self.gzip, self.compress) {
+            (true, _) => CompressArg::Gzip,
+            (false, format) => format.unwrap_or(CompressArg::None),
+        };
+        Ok(match format {
+            CompressArg::None if self.compress_level.is_some() => return Err(FastqGenError::invalid("--compress-level needs --compress or --gzip.")),
+            CompressArg::None => OutputCompression::None,
+            CompressArg::Gzip | CompressArg::Bgzf => {
+                let level = self.compress_level.unwrap_or(6);
+                if !(0..=9).contains(&level) {
+                    return Err(FastqGenError::invalid("gzip and bgzf compression levels run from 0 to 9."));
+                }
+                if matches!(format, CompressArg::Gzip) { OutputCompression::Gzip(level as u32) } else { OutputCompression::Bgzf(level as u32) }
+            }
+            CompressArg::Zstd => {
+                let level = self.compress_level.unwrap_or(3);
+                if !(1..=22).contains(&level) {
+                    return Err(FastqGenError::invalid("zstd compression levels run from 1 to 22."));
+                }
+                OutputCompression::Zstd(level)
+            }
+        })
+    }
+}
+
+#[derive(Parser, Debug, Clone, Serialize)]
+#[command(arg_required_else_help = true)]
+struct GenerateArgs {
+
+    #[arg(index = 1, value_parser = parse_count, help = "Number of reads; k, M and G suffixes are accepted, e.g. 10M.", required_unless_present_any = ["coverage", "total_bases"])]
+    n: Option<u64>,
+
+    #[arg(short = 'x', long, conflicts_with = "n", requires = "reference", help = "Mean depth of coverage to simulate over the reference, instead of a read count.")]
+    coverage: Option<f64>,
+
+    #[arg(long, visible_alias = "bases", value_parser = parse_count, conflicts_with_all = ["n", "coverage"], help = "Total bases to sequence, e.g. 30G, instead of a read count. The read count is worked out from the mean of the read length distribution.")]
+    total_bases: Option<u64>,
+
+    #[arg(short, long, default_value_t = String::from("synthetic_reads"), help = "Output file prefix, or - to stream to stdout (paired reads are interleaved).")]
+    outfile: String,
+
+    #[arg(long, value_name = "DIR", help = "Directory to write the reads to, created if missing; -o then names the files within it.")]
+    output_dir: Option<PathBuf>,
+
+    #[arg(long, help = "Overwrite existing output files instead of refusing to run.")]
+    force: bool,
+
+    #[arg(long, help = "Write <outfile>.manifest.json recording the parameters, seed, read counts and MD5 and SHA-256 checksums of the outputs.")]
+    manifest: bool,
+
+    /// Set by the bench subcommand: reads are compressed and discarded rather than
+    /// written to files.
+    #[arg(skip)]
+    #[serde(skip)]
+    bench: bool,
+    
+    #[arg(short = 'l', default_value_t = 150, help = "Read length, or mean read length for normal and lognormal distributions.")]
+    read_len: i32,
+
+    #[arg(long, value_name = "LENGTH", help = "Length of read 1, instead of -l.")]
+    read_len1: Option<usize>,
+
+    #[arg(long, value_name = "LENGTH", conflicts_with = "single_end", help = "Length of read 2, instead of -l.")]
+    read_len2: Option<usize>,
+
+    #[arg(long, value_enum, default_value_t = LengthDistArg::Fixed, help = "Read length distribution.")]
+    length_dist: LengthDistArg,
+
+    #[arg(long, default_value_t = 0.0, help = "Standard deviation of read lengths (normal, lognormal).")]
+    length_sd: f64,
+
+    #[arg(long, default_value_t = 1, help = "Shortest read length drawn from a distribution.")]
+    length_min: usize,
+
+    #[arg(long, help = "Longest read length drawn from a distribution (required for uniform).")]
+    length_max: Option<usize>,
+
+    #[arg(long, value_name = "FILE", help = "Read options from a TOML (or .yaml/.yml) file of option = value pairs. Flags given on the command line take precedence.")]
+    config: Option<PathBuf>,
+
+    #[arg(long, value_enum, visible_alias = "platform", help = "Instrument preset for read lengths, quality profile and binning, error rates and poly-G artifacts. Explicit flags override it.")]
+    preset: Option<PresetArg>,
+
+    #[arg(long, help = "Seed for the random number generator. Identical seeds and parameters produce identical output.")]
+    seed: Option<u64>,
+
+    #[arg(long, value_enum, default_value_t = RngArg::Crypto, help = "Random number generator the reads are drawn with. A seed gives different reads with each.")]
+    rng: RngArg,
+
+    #[arg(long, value_enum, default_value_t = OutputFormatArg::Fastq, help = "Output format. ubam writes a single <outfile>.bam.")]
+    format: OutputFormatArg,
+
+    #[arg(long, value_name = "ID", default_value_t = String::from("A"), help = "Read group ID of uBAM records.")]
+    read_group: String,
+
+    #[arg(long, value_name = "NAME", default_value_t = String::from("SIMULATED"), help = "Sample name of the uBAM read group.")]
+    sample_name: String,
+
+    #[command(flatten)]
+    compression: CompressionArgs,
+
+    #[arg(long, value_name = "N", value_parser = parse_count, help = "Split the output into numbered files of N reads (or pairs) each, <outfile>_R1_001.fastq and so on, as bcl2fastq does.")]
+    reads_per_file: Option<u64>,
+
+    #[arg(long, value_enum, default_value_t = NamingArg::Default, help = "Output file naming scheme.")]
+    naming: NamingArg,
+
+    #[arg(short, long, value_name = "FASTA", help = "Sample reads from a reference FASTA (optionally gzipped) instead of random sequence.")]
+    reference: Option<PathBuf>,
+
+    #[arg(long, value_name = "FRACTION", conflicts_with = "reference", help = "GC fraction of random sequence.")]
+    gc: Option<f64>,
+
+    #[arg(long, value_name = "A,C,G,T", value_delimiter = ',', conflicts_with_all = ["reference", "gc"], help = "Relative weights of A, C, G and T in random sequence.")]
+    base_weights: Option<Vec<f64>>,
+
+    #[arg(long, value_name = "JSON", conflicts_with_all = ["reference", "gc", "base_weights"], help = "Draw random sequence from a Markov model trained with fastqgen profile -r, for realistic k-mer composition.")]
+    markov_model: Option<PathBuf>,
+
+    #[arg(long, default_value_t = 0.0, help = "Per-base substitution error rate.")]
+    sub_rate: f64,
+
+    #[arg(long, default_value_t = 0.0, help = "Per-base insertion error rate.")]
+    ins_rate: f64,
+
+    #[arg(long, default_value_t = 0.0, help = "Per-base deletion error rate.")]
+    del_rate: f64,
+
+    #[arg(long, value_name = "FACTOR", help = "Multiply the indel rates by 1 + FACTOR x (run length - 1) inside homopolymers, where insertions repeat the run's base, as on Ion Torrent and Nanopore.")]
+    homopolymer_indels: Option<f64>,
+
+    #[arg(long, default_value_t = 0, conflicts_with = "single_end", help = "Phred points taken off every quality score of read 2, which is typically worse than read 1.")]
+    r2_quality_penalty: u8,
+
+    #[arg(long, default_value_t = 1.0, conflicts_with = "single_end", help = "Factor by which the error rates of read 2 exceed those of read 1.")]
+    r2_error_multiplier: f64,
+
+    #[arg(long, default_value_t = 0.0, help = "Fraction of reads whose signal drops out at a random cycle, as on two-colour NovaSeq/NextSeq chemistry: the rest of the read is G at high quality.")]
+    poly_g_rate: f64,
+
+    #[arg(long, default_value_t = 0.0, help = "Fraction of bases called N, at quality 2, as instruments do where they cannot call a base.")]
+    n_rate: f64,
+
+    #[arg(long, value_name = "RATE", help = "Fraction of bases called N in the first --n-early-cycles cycles, which see more no-calls [default: --n-rate].")]
+    n_rate_early: Option<f64>,
+
+    #[arg(long, value_name = "N", default_value_t = 5, help = "Cycles at the start of every read that --n-rate-early applies to.")]
+    n_early_cycles: usize,
+
+    #[arg(long, help = "Mean fragment (insert) length. R1 and R2 are read from opposite ends of each fragment.")]
+    insert_mean: Option<f64>,
+
+    #[arg(long, default_value_t = 0.0, requires = "insert_mean", help = "Standard deviation of the fragment length.")]
+    insert_sd: f64,
+
+    #[arg(long, value_enum, default_value_t = OrientationArg::Fr, help = "Orientation of the mates on their fragment.")]
+    orientation: OrientationArg,
+
+    #[arg(long, requires = "insert_mean", help = "Let fragments be shorter than the read length, so reads run through into the adapter.")]
+    adapter_read_through: bool,
+
+    #[arg(long, value_name = "SEQ", default_value_t = String::from_utf8_lossy(TRUSEQ_ADAPTER_1).into_owned(), help = "Adapter sequence read into by read 1 (and single-end reads).")]
+    adapter1: String,
+
+    #[arg(long, value_name = "SEQ", default_value_t = String::from_utf8_lossy(TRUSEQ_ADAPTER_2).into_owned(), help = "Adapter sequence read into by read 2.")]
+    adapter2: String,
+
+    #[arg(long, default_value_t = 0.0, help = "Fraction of reads that are PCR duplicates of an earlier fragment, sequenced with independent errors.")]
+    duplicate_rate: f64,
+
+    #[arg(long, value_name = "TSV", help = "Write the name of every duplicate read and of its original to a TSV file.")]
+    duplicate_truth: Option<PathBuf>,
+
+    #[arg(long, value_name = "F", default_value_t = 0.0, help = "Fraction of duplicates that are optical: named in the tile of their original, within --optical-distance pixels of it. Needs --name-format illumina.")]
+    optical_duplicates: f64,
+
+    #[arg(long, value_name = "PIXELS", default_value_t = 100, help = "Largest x and y distance between an optical duplicate and its original, as Picard's OPTICAL_DUPLICATE_PIXEL_DISTANCE (2500 for patterned flowcells).")]
+    optical_distance: i64,
+
+    #[arg(long, value_name = "N", help = "Give every fragment a random UMI of N bases, shared by its duplicates.")]
+    umi_length: Option<usize>,
+
+    #[arg(long, value_enum, default_value_t = UmiPlacementArg::Name, requires = "umi_length", help = "Where to write the UMI. uBAM output always stores it in the RX tag.")]
+    umi_placement: UmiPlacementArg,
+
+    #[arg(long, value_name = "CSV", help = "Sample sheet of sample,i7[,i5] lines. Reads are spread evenly over the samples and their index reads are simulated.")]
+    sample_sheet: Option<PathBuf>,
+
+    #[arg(long, requires = "sample_sheet", help = "Write each sample's reads to <outfile>_<sample> files instead of one multiplexed run.")]
+    demultiplex: bool,
+
+    #[arg(long, default_value_t = 0.0, help = "Per-base substitution rate of the index reads and single-cell barcodes. The run reports how many mutated barcodes stay within Hamming distance 1 of the whitelist.")]
+    barcode_error_rate: f64,
+
+    #[arg(long, requires = "sample_sheet", help = "Write the index reads to <outfile>_I1 (and _I2) FASTQs, as bcl2fastq --create-fastq-for-index-reads does.")]
+    index_reads: bool,
+
+    #[arg(long, value_name = "RATE", default_value_t = 0.0, requires = "sample_sheet", help = "Probability that a read's i7, and independently its i5, index is that of another sample, as index hopping on patterned flowcells swaps them.")]
+    index_hopping: f64,
+
+    #[arg(long, value_name = "TSV", requires = "sample_sheet", help = "Write the true sample of every read, the indices it carries and which of them hopped to a TSV file.")]
+    sample_truth: Option<PathBuf>,
+
+    #[arg(short, long, default_value_t = 1, help = "Number of worker threads used for generation.")]
+    threads: usize,
+
+    #[arg(short, long, help = "Do not print progress or status messages.")]
+    quiet: bool,
+
+    #[arg(long, value_enum, default_value_t = QualityModelArg::Uniform, help = "Quality score model.")]
+    quality_model: QualityModelArg,
+
+    #[arg(long, value_name = "TSV", conflicts_with_all = ["quality_model", "profile"], help = "Draw each cycle's quality from the mean and standard deviation given for it in a table of read, cycle, mean and sd columns, with read 2 rows for read 2.")]
+    quality_profile: Option<PathBuf>,
+
+    #[arg(long, value_name = "Q", conflicts_with_all = ["quality_model", "profile", "quality_profile"], help = "Give every base Phred score Q, instead of drawing qualities from a model.")]
+    fixed_quality: Option<u8>,
+
+    #[arg(long, conflicts_with_all = ["sub_rate", "ins_rate", "del_rate", "homopolymer_indels", "poly_g_rate", "n_rate", "n_rate_early", "barcode_error_rate", "profile"], help = "Write reads without sequencing errors, no-calls or poly-G tails, overriding any preset, e.g. to validate pipeline plumbing.")]
+    error_free: bool,
+
+    #[arg(long, help = "Substitute bases at --sub-rate regardless of their quality. Otherwise, with illumina-decay or --quality-profile qualities, a base of quality Q is substituted with probability 10^(-Q/10).")]
+    independent_errors: bool,
+
+    #[arg(long, default_value_t = 33, help = "ASCII offset of the quality scores: 33 (Sanger, Illumina 1.8+) or 64 (Illumina 1.3-1.7).")]
+    phred_offset: u8,
+
+    #[arg(long, default_value_t = 40, help = "Highest Phred score generated, e.g. 41 for recent Illumina software.")]
+    max_quality: u8,
+
+    #[arg(long, value_name = "JSON", conflicts_with = "sub_rate", help = "Draw qualities and quality-dependent substitutions from a fastqgen profile of real data, instead of --quality-model and --sub-rate.")]
+    profile: Option<PathBuf>,
+
+    #[arg(long, default_value_t = 38.0, help = "Mean Phred score at the 5' end (illumina-decay).")]
+    quality_start: f64,
+
+    #[arg(long, default_value_t = 28.0, help = "Mean Phred score at the 3' end (illumina-decay).")]
+    quality_end: f64,
+
+    #[arg(long, default_value_t = 3.0, help = "Standard deviation of per-position quality noise (illumina-decay).")]
+    quality_noise: f64,
+
+    #[arg(long, help = "Generate single-end reads into one <outfile>.fastq file.")]
+    single_end: bool,
+
+    #[arg(long, conflicts_with = "single_end", help = "Write R1 and R2 records alternately into one <outfile>.fastq file.")]
+    interleaved: bool,
+
+    #[arg(long, value_enum, default_value_t = NameFormatArg::Simple, help = "Read name format.")]
+    name_format: NameFormatArg,
+
+    #[arg(long, value_name = "TEMPLATE", conflicts_with = "name_format", help = "Build read names from a template such as \"SIM_{run}_{index:08}_{barcode}\". Placeholders: index, instrument, run, flowcell, lane, tile, x, y, umi, barcode, contig, start, end and strand.")]
+    id_template: Option<String>,
+
+    #[arg(long, value_name = "N", default_value_t = 0, help = "Number the reads from N, so that the reads of several runs can be concatenated without name collisions.")]
+    id_start: u64,
+
+    #[arg(long, value_name = "DIGITS", default_value_t = DEFAULT_ID_WIDTH, help = "Zero-pad the read numbers of simple read names to this many digits.")]
+    id_pad: usize,
+
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=8), help = "Spread the reads over this many flowcell lanes, recorded in Illumina read names.")]
+    lanes: u32,
+
+    #[arg(long, help = "Write the reads of each lane to their own files, <outfile>_L001_R1.fastq and so on.")]
+    split_lanes: bool,
+
+    #[arg(long, value_name = "SAM", requires = "reference", help = "Write the true alignment of every read to a SAM file.")]
+    truth_sam: Option<PathBuf>,
+
+    #[arg(long, value_name = "TSV", requires = "reference", help = "Write the contig, 0-based start and end, strand and error count of every read to a TSV file, a lighter alternative to --truth-sam.")]
+    truth_tsv: Option<PathBuf>,
+
+    #[arg(long, value_name = "VCF", requires = "reference", help = "Spike the variants in a VCF into the haplotypes, at their GT or AF genotypes.")]
+    vcf: Option<PathBuf>,
+
+    #[arg(long, default_value_t = 0.0, requires = "reference", help = "Per-base rate of SNPs injected into the diploid haplotypes before sampling.")]
+    snp_rate: f64,
+
+    #[arg(long, default_value_t = 0.0, requires = "reference", help = "Per-base rate of small insertions and deletions injected into the haplotypes.")]
+    indel_rate: f64,
+
+    #[arg(long, default_value_t = 2.0, help = "Mean length of injected indels (geometric distribution).")]
+    indel_mean_length: f64,
+
+    #[arg(long, default_value_t = 50, help = "Maximum length of injected indels.")]
+    indel_max_length: usize,
+
+    #[arg(long, default_value_t = 1.0 / 3.0, help = "Fraction of injected variants that are homozygous.")]
+    hom_fraction: f64,
+
+    #[arg(long, default_value_t = 2, help = "Number of haplotypes injected variants are assigned to and reads are drawn from, equally.")]
+    ploidy: usize,
+
+    #[arg(long, value_name = "TSV", requires = "reference", help = "Write the haplotype (1-based, in the order of the truth VCF genotypes) every read was drawn from to a TSV file.")]
+    haplotype_truth: Option<PathBuf>,
+
+    #[arg(long, value_name = "VCF", requires = "reference", help = "Write every injected variant to a VCF file.")]
+    truth_vcf: Option<PathBuf>,
+
+    #[arg(long, default_value_t = 0, requires = "reference", help = "Number of deletions injected into the haplotypes.")]
+    deletions: usize,
+
+    #[arg(long, default_value_t = 0, requires = "reference", help = "Number of tandem duplications injected into the haplotypes.")]
+    duplications: usize,
+
+    #[arg(long, default_value_t = 0, requires = "reference", help = "Number of inversions injected into the haplotypes.")]
+    inversions: usize,
+
+    #[arg(long, default_value_t = 0, requires = "reference", help = "Number of reciprocal translocations between two contigs injected into the haplotypes.")]
+    translocations: usize,
+
+    #[arg(long, default_value_t = 1000, help = "Minimum length of injected deletions, duplications and inversions.")]
+    sv_min_length: usize,
+
+    #[arg(long, default_value_t = 100_000, help = "Maximum length of injected deletions, duplications and inversions (log-uniform between the two).")]
+    sv_max_length: usize,
+
+    #[arg(long, value_name = "FILE", requires = "reference", help = "Write every structural variant to a VCF file, or to BEDPE if the name ends in .bedpe.")]
+    truth_sv: Option<PathBuf>,
+
+    #[arg(long, requires = "reference", help = "Simulate a directional bisulfite library: unmethylated cytosines of the sequenced strand read as T.")]
+    bisulfite: bool,
+
+    #[arg(long, default_value_t = 0.75, requires = "bisulfite", help = "Methylation rate of cytosines in CpG context.")]
+    cpg_methylation: f64,
+
+    #[arg(long, default_value_t = 0.02, requires = "bisulfite", help = "Methylation rate of cytosines in CHG context.")]
+    chg_methylation: f64,
+
+    #[arg(long, default_value_t = 0.01, requires = "bisulfite", help = "Methylation rate of cytosines in CHH context.")]
+    chh_methylation: f64,
+
+    #[arg(long, value_name = "BEDGRAPH", requires = "bisulfite", help = "Methylation percentage of individual cytosines, as in a Bismark bedGraph; the others use the context rates.")]
+    methylation: Option<PathBuf>,
+
+    #[arg(long, value_name = "BEDGRAPH", requires = "bisulfite", help = "Write the true methylation level of every CpG (and bedGraph) cytosine.")]
+    methylation_truth: Option<PathBuf>,
+
+    #[arg(long, requires = "reference", help = "Simulate ancient DNA: short fragments (unless --insert-mean is given), read through into the adapter, whose ends carry deamination damage: C→T at 5' ends and G→A at 3' ends.")]
+    ancient_dna: bool,
+
+    #[arg(long, default_value_t = 0.3, requires = "ancient_dna", help = "Rate of deamination at the terminal base of a molecule, on top of --damage-interior.")]
+    damage_terminal: f64,
+
+    #[arg(long, default_value_t = 0.3, requires = "ancient_dna", help = "Fraction by which the terminal deamination rate falls with every base into the molecule.")]
+    damage_decay: f64,
+
+    #[arg(long, default_value_t = 0.01, requires = "ancient_dna", help = "Rate of deamination throughout the molecule, from nicks.")]
+    damage_interior: f64,
+
+    #[arg(long, requires = "ancient_dna", help = "Damage molecules as a single-stranded library reads them: C→T at both ends and no G→A.")]
+    single_stranded: bool,
+
+    #[arg(long, requires = "reference", help = "Simulate an FFPE sample: shorter fragments (unless --insert-mean is given), read through into the adapter, with C>T artifacts on one strand at low allele fractions.")]
+    ffpe: bool,
+
+    #[arg(long, default_value_t = 1e-4, requires = "ffpe", help = "Per-base rate of the C and G sites formalin damaged.")]
+    ffpe_site_rate: f64,
+
+    #[arg(long, default_value_t = 0.05, requires = "ffpe", help = "Highest allele fraction of an FFPE artifact; each site's is drawn uniformly below it.")]
+    ffpe_max_fraction: f64,
+
+    #[arg(long, value_name = "VCF", requires = "ffpe", help = "Write every FFPE artifact site with its allele fraction and the pair orientation (F1R2 or F2R1) it shows in.")]
+    ffpe_truth: Option<PathBuf>,
+
+    #[arg(long, value_name = "BED", requires = "reference", conflicts_with = "primers", help = "Amplicons of a targeted panel; reads start at their first and last 20 bases, taken as the primers.")]
+    amplicons: Option<PathBuf>,
+
+    #[arg(long, value_name = "TSV", requires = "reference", help = "Primer pairs of a targeted panel as name<TAB>forward<TAB>reverse; each amplicon spans the reference between its primers.")]
+    primers: Option<PathBuf>,
+
+    #[arg(long, default_value_t = 0.0, help = "Fraction of panel fragments drawn from anywhere on the reference (mispriming).")]
+    off_target_rate: f64,
+
+    #[arg(long, default_value_t = 0.0, help = "Fraction of panel fragments that are primer dimers.")]
+    primer_dimer_rate: f64,
+
+    #[arg(long, value_name = "BED", requires = "reference", conflicts_with_all = ["amplicons", "primers"], help = "Capture targets (e.g. exome intervals): fragments overlap them, apart from the off-target fraction. -x then sets the approximate depth over the targets.")]
+    targets: Option<PathBuf>,
+
+    #[arg(long, default_value_t = 0.85, requires = "targets", help = "Fraction of fragments captured from the targets.")]
+    on_target: f64,
+
+    #[arg(long, value_name = "BED", requires = "reference", conflicts_with_all = ["amplicons", "primers"], help = "Draw fragments only from within these intervals of the reference, e.g. to make a small test set from a large genome. -x then sets the depth over the regions.")]
+    regions: Option<PathBuf>,
+
+    #[arg(long, requires = "regions", help = "Weight the --regions by the score in their fifth BED column, for depth in proportion to it, rather than evenly.")]
+    region_score: bool,
+
+    #[arg(long, requires = "reference", help = "Bias coverage by fragment GC content, as PCR does: fragments are kept with the probability a GC bias curve gives them.")]
+    gc_bias: bool,
+
+    #[arg(long, default_value_t = 0.45, requires = "gc_bias", help = "GC fraction at which the GC bias curve peaks.")]
+    gc_bias_optimum: f64,
+
+    #[arg(long, default_value_t = 0.15, requires = "gc_bias", help = "Width (standard deviation, in GC fraction) of the GC bias curve.")]
+    gc_bias_width: f64,
+
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true, requires = "gc_bias", help = "Skew of the GC bias curve, between -1 and 1: positive values favour GC-rich over AT-rich fragments.")]
+    gc_bias_skew: f64,
+
+    #[arg(long, value_name = "TSV", requires = "gc_bias", help = "Write the GC bias curve: the relative chance of a fragment being sequenced at every percent of GC content.")]
+    gc_bias_curve: Option<PathBuf>,
+
+    #[arg(long, default_value_t = 0.0, requires = "phix_reference", help = "Fraction of reads drawn from the PhiX control genome, as spiked into Illumina runs. -x still sets the depth of the reference.")]
+    phix: f64,
+
+    #[arg(long, value_name = "FASTA", help = "PhiX174 genome (e.g. RefSeq NC_001422.1) that --phix reads come from; the truth SAM aligns them to its contig.")]
+    phix_reference: Option<PathBuf>,
+
+    #[arg(long, value_name = "FASTA:FRACTION", value_parser = parse_contaminant, help = "Draw this fraction of the reads from a contaminant genome, e.g. host.fa:0.05. Repeat for several.")]
+    contaminant: Vec<(PathBuf, f64)>,
+
+    #[arg(long, value_name = "TSV", help = "Write the source of every read to a TSV file: the name of its contaminant genome, PhiX, or reference.")]
+    source_truth: Option<PathBuf>,
+}
+
+/// Whether a truth file describes the reads of one sample, or what every sample
+/// of a multi-sample run shares (drawn from their common seed).
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TruthScope {
+    Sample,
+    Shared,
+}
+
+impl GenerateArgs {
+    /// Every truth file of the run, with its scope. A multi-sample run writes the
+    /// per-sample ones under each sample's name and the shared ones once.
+    fn truth_paths(&mut self) -> [(&mut Option<PathBuf>, TruthScope); 11] {
+        [
+            (&mut self.truth_sam, TruthScope::Sample),
+            (&mut self.truth_tsv, TruthScope::Sample),
+            (&mut self.duplicate_truth, TruthScope::Sample),
+            (&mut self.haplotype_truth, TruthScope::Sample),
+            (&mut self.methylation_truth, TruthScope::Sample),
+            (&mut self.source_truth, TruthScope::Sample),
+            (&mut self.sample_truth, TruthScope::Sample),
+            (&mut self.truth_vcf, TruthScope::Shared),
+            (&mut self.truth_sv, TruthScope::Shared),
+            (&mut self.ffpe_truth, TruthScope::Shared),
+            (&mut self.gc_bias_curve, TruthScope::Shared),
+        ]
+    }
+
+    /// Applies `--preset`, if given, to subcommand `name` of the parsed `matches`.
+    fn apply_selected_preset(&mut self, matches: &ArgMatches, name: &str) {
+        if let (Some(preset), Some(matches)) = (self.preset, matches.subcommand_matches(name)) {
+            self.apply_preset(&preset.preset(), matches);
+        }
+    }
+
+    /// Replaces every option the preset covers with the preset's value, unless it
+    /// was given on the command line.
+    fn apply_preset(&mut self, preset: &Preset, matches: &ArgMatches) {
+        let unset = |id: &str| matches.value_source(id) != Some(ValueSource::CommandLine);
+        let (read_len, length_dist, length_sd, length_min, length_max) = match preset.read_lengths {
+            ReadLengths::Fixed(length) => (length as f64, LengthDistArg::Fixed, 0.0, 1, None),
+            ReadLengths::Normal { mean, sd, min, max, .. } => (mean, LengthDistArg::Normal, sd, min, Some(max)),
+            ReadLengths::Uniform { min, max, .. } => ((min + max) as f64 / 2.0, LengthDistArg::Uniform, 0.0, min, Some(max)),
+            ReadLengths::LogNormal { mean, sd, min, max, .. } => (mean, LengthDistArg::Lognormal, sd, min, Some(max)),
+        };
+        if unset("read_len") {
+            self.read_len = read_len.round() as i32;
+        }
+        if unset("length_dist") {
+            self.length_dist = length_dist;
+        }
+        if unset("length_sd") {
+            self.length_sd = length_sd;
+        }
+        if unset("length_min") {
+            self.length_min = length_min;
+        }
+        if unset("length_max") {
+            self.length_max = length_max;
+        }
+        if preset.single_end && !self.interleaved {
+            self.single_end = true;
+        }
+        if unset("quality_model") {
+            self.quality_model = QualityModelArg::IlluminaDecay;
+        }
+        if unset("quality_start") {
+            self.quality_start = preset.quality_start;
+        }
+        if unset("quality_end") {
+            self.quality_end = preset.quality_end;
+        }
+        if unset("quality_noise") {
+            self.quality_noise = preset.quality_noise;
+        }
+        if unset("sub_rate") {
+            self.sub_rate = preset.sub_rate;
+        }
+        if unset("ins_rate") {
+            self.ins_rate = preset.ins_rate;
+        }
+        if unset("del_rate") {
+            self.del_rate = preset.del_rate;
+        }
+        if unset("poly_g_rate") {
+            self.poly_g_rate = preset.poly_g_rate;
+        }
+    }
+}
+
+/// A value in a `--config` file.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ConfigValue {
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+    List(Vec<ConfigValue>),
+}
+
+impl fmt::Display for ConfigValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigValue::Bool(value) => write!(f, "{}", value),
+            ConfigValue::Integer(value) => write!(f, "{}", value),
+            ConfigValue::Float(value) => write!(f, "{}", value),
+            ConfigValue::String(value) => write!(f, "{}", value),
+            ConfigValue::List(values) => {
+                let values: Vec<String> = values.iter().map(ToString::to_string).collect();
+                write!(f, "{}", values.join(","))
+            }
+        }
+    }
+}
+
+/// Translates a config file into command-line arguments for `command`. Keys are
+/// option names (`read_len`, `insert-mean`, `n`, ...); options already given on the
+/// command line, as recorded in `matches`, are skipped so that they take precedence.
+fn config_args(path: &Path, command: &Command, matches: &ArgMatches) -> Result<Vec<OsString>, FastqGenError> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| FastqGenError::io(format!("Failed to read config {}", path.display()), e))?;
+    let is_yaml = path.extension().is_some_and(|ext| ext == "yaml" || ext == "yml");
+    let values: BTreeMap<String, ConfigValue> = if is_yaml {
+        serde_yaml::from_str(&text).map_err(|e| FastqGenError::Config(format!("Invalid config {}: {}", path.display(), e)))?
+    } else {
+        toml::from_str(&text).map_err(|e| FastqGenError::Config(format!("Invalid config {}: {}", path.display(), e)))?
+    };
+
+    let mut args = Vec::new();
+    for (key, value) in values {
+        let id = key.replace('-', "_");
+        let arg = command.get_arguments()
+            .find(|arg| arg.get_id() == id.as_str() || arg.get_long() == Some(key.as_str()))
+            .filter(|arg| arg.get_id() != "config")
+            .ok_or_else(|| FastqGenError::Config(format!("Unknown option {} in config {}.", key, path.display())))?;
+        if matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine) {
+            continue;
+        }
+
+        let name = match (arg.get_long(), arg.get_short()) {
+            (Some(long), _) => Some(format!("--{}", long)),
+            (None, Some(short)) => Some(format!("-{}", short)),
+            (None, None) => None,
+        };
+        if !arg.get_action().takes_values() {
+            match value {
+                ConfigValue::Bool(true) => args.extend(name.map(OsString::from)),
+                ConfigValue::Bool(false) => {}
+                _ => return Err(FastqGenError::Config(format!("Option {} in config {} must be true or false.", key, path.display()))),
+            }
+            continue;
+        }
+        args.extend(name.map(OsString::from));
+        args.push(OsString::from(value.to_string()));
+    }
+    Ok(args)
+}
+
+/// Creates `path` for writing, naming it in the error should that fail.
+fn create_file(path: impl AsRef<Path>) -> Result<BufWriter<File>, FastqGenError> {
+    let path = path.as_ref();
+    File::create(path).map(BufWriter::new).map_err(|e| FastqGenError::io(format!("Failed to create {}", path.display()), e))
+}
+
+/// Creates a truth or count file, compressed as its extension asks (`.gz`, `.zst`).
+fn create_truth_file(path: &Path) -> Result<FastqWriter, FastqGenError> {
+    FastqWriter::create(path, OutputCompression::from_path(path)).map_err(|e| FastqGenError::io(format!("Failed to create {}", path.display()), e))
+}
+
+/// Creates a truth or count file, fills it with `write` and closes it.
+fn write_truth_file(path: &Path, write: impl FnOnce(&mut FastqWriter) -> io::Result<()>) -> Result<(), FastqGenError> {
+    let mut writer = create_truth_file(path)?;
+    write(&mut writer)
+        .and_then(|()| writer.finish())
+        .map_err(|e| FastqGenError::io(format!("Failed to write {}", path.display()), e))
+}
+
+/// Column names of a count table: one per sample, or `reads` without samples.
+fn count_columns(samples: &[Sample]) -> Vec<&str> {
+    if samples.is_empty() {
+        vec!["reads"]
+    } else {
+        samples.iter().map(|sample| sample.name.as_str()).collect()
+    }
+}
+
+/// Fails if `path` already exists, unless `force` allows it to be overwritten.
+fn check_overwrite(path: impl AsRef<Path>, force: bool) -> Result<(), FastqGenError> {
+    let path = path.as_ref();
+    if !force && path.exists() {
+        return Err(FastqGenError::invalid(format!("{} already exists; use --force to overwrite it.", path.display())));
+    }
+    Ok(())
+}
+
+/// Number of reads (or read pairs) of `bases_per_read` bases on average needed to
+/// cover a genome of `genome_size` bases to a mean depth of `coverage`.
+fn reads_for_coverage(coverage: f64, genome_size: usize, bases_per_read: f64) -> Result<u64, FastqGenError> {
+    if coverage <= 0.0 {
+        return Err(FastqGenError::invalid("Coverage must be positive."));
+    }
+    let reads = (coverage * genome_size as f64 / bases_per_read).ceil();
+    if reads >= u64::MAX as f64 {
+        return Err(FastqGenError::invalid(format!("Coverage {}x requires {} reads, more than the supported maximum of {}.", coverage, reads, u64::MAX)));
+    }
+    Ok(reads as u64)
+}
+
+/// Draws the partitions of a linked-read run and their barcodes.
+fn setup_linked(options: &LinkedOptions, generator: FastqGenerator, args: &GenerateArgs, seed: u64) -> Result<(FastqGenerator, LibrarySetup), FastqGenError> {
+    if args.single_end || args.format == OutputFormatArg::Ubam || args.sample_sheet.is_some() {
+        return Err(FastqGenError::invalid("linked-reads runs write paired FASTQ without a sample sheet."));
+    }
+    if generator.reference().is_none() {
+        return Err(FastqGenError::invalid("linked-reads runs draw their molecules from a reference (-r)."));
+    }
+    let mut rng = chunk_rng(seed, PARTITION_STREAM);
+    let (barcodes, whitelist) = draw_barcodes(&mut rng, options.partitions, "partitions", options.whitelist.as_deref(), args.barcode_error_rate > 0.0)?;
+    let library = LinkedReads::new(barcodes, options.molecule_length, options.reads_per_molecule)?;
+    let partitions = LinkedPartitions { library, placement: options.placement, whitelist };
+    Ok((generator, LibrarySetup { linked_partitions: Some(partitions), ..LibrarySetup::default() }))
+}
+
+/// Ligates the mates of every Hi-C pair from two loci.
+fn setup_hic(options: &HicOptions, generator: FastqGenerator, args: &GenerateArgs) -> Result<(FastqGenerator, LibrarySetup), FastqGenError> {
+    if args.single_end || generator.reference().is_none() {
+        return Err(FastqGenError::invalid("hic runs write read pairs drawn from a reference (-r)."));
+    }
+    let contacts = ContactModel::new(options.trans_fraction, options.exponent, options.min_distance)?;
+    Ok((generator.with_hic(contacts), LibrarySetup::default()))
+}
+
+/// Draws ATAC-seq fragments from the peaks and between them.
+fn setup_atac(options: &AtacOptions, generator: FastqGenerator, args: &GenerateArgs) -> Result<(FastqGenerator, LibrarySetup), FastqGenError> {
+    let Some(reference) = generator.reference().filter(|_| !args.single_end) else {
+        return Err(FastqGenError::invalid("atac runs write read pairs drawn from a reference (-r)."));
+    };
+    let library = AtacLibrary::new(read_bed(&options.peaks, reference, "peak BED")?, options.in_peaks, options.nucleosome_spacing)?;
+    // Tn5 fragments are often shorter than the reads, which then run into the adapter.
+    let generator = generator
+        .with_atac(library)
+        .with_adapters(args.adapter1.to_ascii_uppercase().into_bytes(), args.adapter2.to_ascii_uppercase().into_bytes());
+    Ok((generator, LibrarySetup::default()))
+}
+
+/// Reads the guide library of a CRISPR screen and draws the guides' abundances,
+/// unless they are given.
+fn setup_crispr(options: &CrisprOptions, generator: FastqGenerator, args: &GenerateArgs, seed: u64) -> Result<(FastqGenerator, LibrarySetup), FastqGenError> {
+    let guides = read_guide_library(&options.library)?;
+    let abundances = match &options.abundance {
+        Some(path) => {
+            let (abundances, unknown) = read_guide_abundances(path, &guides)?;
+            if unknown > 0 && !args.quiet {
+                eprintln!("Ignored {} guides of {} that are not in the library.", unknown, path.display());
+            }
+            abundances
+        }
+        None => sample_guide_abundances(&guides, &mut chunk_rng(seed, EXPRESSION_STREAM), options.abundance_sd)?,
+    };
+    let (upstream, downstream) = (options.upstream.to_ascii_uppercase().into_bytes(), options.downstream.to_ascii_uppercase().into_bytes());
+    let guides = GuideLibrary::new(guides, &abundances)?.with_vector(upstream, downstream).with_stagger(options.stagger);
+    Ok((generator, LibrarySetup { guides: Some(guides), counts: options.counts.clone(), ..LibrarySetup::default() }))
+}
+
+/// Amplifies the marker gene of every reference and draws the taxa's abundances,
+/// unless they are given.
+fn setup_marker(options: &MarkerOptions, generator: FastqGenerator, args: &GenerateArgs, seed: u64) -> Result<(FastqGenerator, LibrarySetup), FastqGenError> {
+    let (forward, reverse) = (options.forward_primer.to_ascii_uppercase().into_bytes(), options.reverse_primer.to_ascii_uppercase().into_bytes());
+    let (taxa, missed) = amplify_markers(read_marker_fasta(&options.references)?, &forward, &reverse);
+    if taxa.is_empty() {
+        return Err(FastqGenError::parse(format!("The primers amplify none of the references in {}.", options.references.display())));
+    }
+    if !missed.is_empty() && !args.quiet {
+        eprintln!("The primers do not amplify {} of the references in {}, which are left out.", missed.len(), options.references.display());
+    }
+    let abundances = match &options.abundance {
+        Some(path) => {
+            let (abundances, unknown) = read_marker_abundances(path, &taxa)?;
+            if unknown > 0 && !args.quiet {
+                eprintln!("Ignored {} taxa of {} that are not amplified references.", unknown, path.display());
+            }
+            abundances
+        }
+        None => sample_marker_abundances(&taxa, &mut chunk_rng(seed, EXPRESSION_STREAM), options.abundance_sd)?,
+    };
+    let community = MarkerCommunity::new(taxa, abundances, options.chimera_rate)?;
+    Ok((generator, LibrarySetup { marker_community: Some(community), counts: options.counts.clone(), ..LibrarySetup::default() }))
+}
+
+/// Sets up the families of PCR copies every original molecule is read from.
+fn setup_families(options: &FamilyOptions, generator: FastqGenerator, args: &GenerateArgs) -> Result<(FastqGenerator, LibrarySetup), FastqGenError> {
+    if args.duplicate_rate > 0.0 {
+        return Err(FastqGenError::invalid("The reads of UMI families are the duplicates of their molecules; leave out --duplicate-rate."));
+    }
+    let families = UmiFamilies::new(options.family_size, options.pcr_error_rate)?;
+    let (generator, families) = if !options.duplex {
+        (generator, families)
+    } else if args.single_end || matches!(args.umi_placement, UmiPlacementArg::Index) {
+        return Err(FastqGenError::invalid("Duplex families are read pairs with their UMIs in the reads or the read names."));
+    } else {
+        (generator.with_duplex_umis(), families.with_duplex())
+    };
+    Ok((generator, LibrarySetup { umi_families: Some(families), ..LibrarySetup::default() }))
+}
+
+/// Recombines the clonotypes of an immune repertoire from its gene segments and
+/// draws their abundances.
+fn setup_vdj(options: &VdjOptions, generator: FastqGenerator, seed: u64) -> Result<(FastqGenerator, LibrarySetup), FastqGenError> {
+    let d = match &options.d_genes {
+        Some(path) => read_gene_segments(path)?,
+        None => Vec::new(),
+    };
+    let segments = VdjSegments::new(read_gene_segments(&options.v_genes)?, d, read_gene_segments(&options.j_genes)?, options.deletion_mean, options.insertion_mean)?;
+    let mut rng = chunk_rng(seed, EXPRESSION_STREAM);
+    let clones = segments.sample_clonotypes(&mut rng, options.clones, options.unproductive)?;
+    let abundances = sample_clone_abundances(clones.len(), &mut rng, options.clone_sd)?;
+    let repertoire = Repertoire::new(segments, clones, abundances)?;
+    Ok((generator, LibrarySetup { repertoire: Some(repertoire), counts: options.clonotypes.clone(), ..LibrarySetup::default() }))
+}
+
+/// Places the binding sites of a ChIP-seq run, read from a BED file or drawn at
+/// random, which the reads are enriched around.
+fn setup_chip(options: &ChipOptions, reference: &Reference, args: &GenerateArgs, seed: u64) -> Result<CaptureTargets, FastqGenError> {
+    let sites = match &options.sites {
+        Some(path) => read_bed(path, reference, "site BED")?,
+        None => random_targets(&mut chunk_rng(seed, SITE_STREAM), reference, options.random_sites, options.site_width)?,
+    };
+    if let Some(path) = &options.site_truth {
+        write_truth_file(path, |writer| {
+            for (index, site) in sites.iter().enumerate() {
+                let name = site.name.clone().unwrap_or_else(|| format!("site_{}", index + 1));
+                writeln!(writer, "{}\t{}\t{}\t{}", reference.contig_name(site.contig), site.start, site.end, name)?;
+            }
+            Ok(())
+        })?;
+    }
+    let fragment_length = args.insert_mean.unwrap_or(args.read_len as f64);
+    CaptureTargets::enriched(sites, options.fold_enrichment, fragment_length, reference.total_length())
+}
+
+/// Draws the somatic variants of the tumor of a tumor/normal run, writing their
+/// truth, and adds them to the germline `variants`, which are expanded to the
+/// tumor's haplotypes.
+fn simulate_somatic(
+    somatic: &Somatic,
+    reference: &Reference,
+    seed: u64,
+    args: &GenerateArgs,
+    variants: &mut Vec<Variant>,
+    structural: &mut [StructuralVariant],
+) -> Result<(), FastqGenError> {
+    let mut rng = chunk_rng(seed, SOMATIC_STREAM);
+    let mut somatic_variants = match &somatic.vcf {
+        Some(vcf_path) => read_vcf(vcf_path, reference, &mut rng, args.ploidy)?,
+        None => Vec::new(),
+    };
+    // The signature that caused each SNV, by contig, position and alternate base.
+    let mut causes = HashMap::new();
+    let mut simulated = match &somatic.signatures {
+        Some(mixture) => mixture.simulate_snvs(reference, &mut rng, somatic.snv_rate)?
+            .into_iter()
+            .map(|(variant, signature)| {
+                causes.insert((variant.contig, variant.position, variant.alternate[0]), signature);
+                variant
+            })
+            .collect(),
+        None => simulate_snps(reference, &mut rng, somatic.snv_rate, 0.0, 1)?,
+    };
+    let indel_lengths = IndelLengths { mean: args.indel_mean_length, max: args.indel_max_length };
+    simulated.extend(simulate_indels(reference, &mut rng, somatic.indel_rate, indel_lengths, 0.0, 1)?);
+    resolve_overlaps(&mut simulated);
+    remove_overlapping(&mut simulated, &somatic_variants);
+    somatic_variants.extend(simulated);
+    // Germline variants take precedence where a somatic one would overlap them.
+    remove_overlapping(&mut somatic_variants, variants);
+    remove_within_structural_variants(&mut somatic_variants, structural);
+    somatic_variants.sort_by_key(|variant| (variant.contig, variant.position));
+
+    let vafs: Vec<f64> = somatic_variants.iter_mut()
+        .map(|variant| somatic.model.observed_vaf(somatic.model.place_somatic(&mut rng, variant)))
+        .collect();
+    write_truth_file(&somatic.truth_vcf, |writer| write_somatic_vcf(writer, reference, &somatic_variants, &vafs))?;
+    if let (Some(mixture), Some(path)) = (&somatic.signatures, &somatic.exposure_truth) {
+        let mut mutations = vec![0; mixture.signatures().len()];
+        for variant in somatic_variants.iter().filter(|variant| variant.reference.len() == 1 && variant.alternate.len() == 1) {
+            if let Some(&signature) = causes.get(&(variant.contig, variant.position, variant.alternate[0])) {
+                mutations[signature] += 1;
+            }
+        }
+        write_truth_file(path, |writer| mixture.write_exposures(writer, &mutations))?;
+    }
+
+    variants.iter_mut().for_each(|variant| somatic.model.expand_germline(&mut variant.genotype));
+    structural.iter_mut().for_each(|variant| somatic.model.expand_germline(&mut variant.genotype));
+    variants.extend(somatic_variants);
+    variants.sort_by_key(|variant| (variant.contig, variant.position));
+    Ok(())
+}
+
+/// Passes the parents' variants on to the member of a trio being sequenced, along
+/// with de novo mutations for the child.
+fn simulate_trio(options: &TrioOptions, reference: &Reference, seed: u64, variants: &mut Vec<Variant>, structural: &[StructuralVariant]) -> Result<(), FastqGenError> {
+    let mut rng = chunk_rng(seed, PEDIGREE_STREAM);
+    let pedigree = Pedigree::simulate(reference, &mut rng, options.recombination_rate)?;
+    let mut de_novo = Pedigree::simulate_de_novo(reference, &mut rng, options.de_novo, variants)?;
+    remove_within_structural_variants(&mut de_novo, structural);
+    if let Some(path) = &options.pedigree_vcf {
+        write_truth_file(path, |writer| pedigree.write_vcf(writer, reference, variants, &de_novo))?;
+    }
+
+    variants.iter_mut().for_each(|variant| variant.genotype = pedigree.genotype(options.member, variant.contig, variant.position, &variant.genotype));
+    variants.retain(|variant| variant.genotype.contains(&true));
+    if options.member == TrioMember::Child {
+        variants.extend(de_novo);
+        variants.sort_by_key(|variant| (variant.contig, variant.position));
+    }
+    Ok(())
+}
+
+/// Draws the genotypes of the cohort at every variant site and keeps those of the
+/// sample being sequenced.
+fn simulate_cohort(options: &CohortOptions, reference: &Reference, seed: u64, variants: &mut Vec<Variant>) -> Result<(), FastqGenError> {
+    let mut rng = chunk_rng(seed, COHORT_STREAM);
+    for variant in variants.iter_mut() {
+        variant.genotype = options.cohort.sample_genotype(&mut rng)?;
+    }
+    if let Some(path) = &options.cohort_vcf {
+        write_truth_file(path, |writer| options.cohort.write_vcf(writer, reference, variants))?;
+    }
+
+    variants.iter_mut().for_each(|variant| variant.genotype = options.cohort.genotype(options.sample, &variant.genotype));
+    variants.retain(|variant| variant.genotype.contains(&true));
+    Ok(())
+}
+
+/// Draws the haplotypes of a quasispecies and their mutations, which are added to
+/// `variants`.
+fn simulate_quasispecies(
+    options: &QuasispeciesOptions,
+    reference: &Reference,
+    seed: u64,
+    variants: &mut Vec<Variant>,
+    structural: &mut [StructuralVariant],
+) -> Result<Quasispecies, FastqGenError> {
+    let mut rng = chunk_rng(seed, HAPLOTYPE_STREAM);
+    let frequencies = match &options.frequencies {
+        Some(frequencies) => frequencies.clone(),
+        None => sample_haplotype_frequencies(options.haplotypes, &mut rng, options.frequency_sd)?,
+    };
+    let population = Quasispecies::new(&mut rng, frequencies)?;
+    let mut mutations = population.simulate_mutations(reference, &mut rng, options.divergence, options.ts_tv, variants)?;
+    remove_within_structural_variants(&mut mutations, structural);
+    if let Some(path) = &options.variant_truth {
+        write_truth_file(path, |writer| population.write_vcf(writer, reference, &mutations))?;
+    }
+
+    // Variants of the consensus are carried by every haplotype.
+    let ploidy = population.frequencies().len();
+    variants.iter_mut().for_each(|variant| variant.genotype = vec![variant.genotype[0]; ploidy]);
+    structural.iter_mut().for_each(|variant| variant.genotype = vec![variant.genotype[0]; ploidy]);
+    variants.extend(mutations);
+    variants.sort_by_key(|variant| (variant.contig, variant.position));
+    Ok(population)
+}
+
+fn run_generate(mut args: GenerateArgs, expression: Option<Expression>, somatic: Option<&Somatic>, library: Option<Library>) -> Result<(), FastqGenError> {
+    let mut output_file_prefix = args.outfile.clone();
+    let read_length = args.read_len;
+
+    if read_length <= 0 {
+        return Err(FastqGenError::invalid("Read length must be positive."));
+    }
+    if args.error_free {
+        // Drop the error rates a preset may have set, too.
+        (args.sub_rate, args.ins_rate, args.del_rate, args.homopolymer_indels) = (0.0, 0.0, 0.0, None);
+        (args.poly_g_rate, args.n_rate, args.n_rate_early, args.barcode_error_rate) = (0.0, 0.0, None, 0.0);
+    }
+    if args.fixed_quality.is_some_and(|phred| phred > args.max_quality) {
+        return Err(FastqGenError::invalid(format!("--fixed-quality must be at most --max-quality ({}).", args.max_quality)));
+    }
+    if args.threads == 0 {
+        return Err(FastqGenError::invalid("Number of threads must be positive."));
+    }
+    if !(0.0..1.0).contains(&args.duplicate_rate) {
+        return Err(FastqGenError::invalid("Duplicate rate must be at least 0 and less than 1."));
+    }
+    if !(0.0..=1.0).contains(&args.optical_duplicates) {
+        return Err(FastqGenError::invalid("--optical-duplicates must be between 0 and 1."));
+    }
+    if args.optical_duplicates > 0.0 && (!matches!(args.name_format, NameFormatArg::Illumina) || args.id_template.is_some()) {
+        return Err(FastqGenError::invalid("--optical-duplicates places duplicates by the tile and x/y coordinates of Illumina read names and needs --name-format illumina."));
+    }
+    if args.optical_distance < 1 {
+        return Err(FastqGenError::invalid("--optical-distance must be at least 1 pixel."));
+    }
+    // Read files are checked as they are opened; the truth and count files are
+    // checked up front, before any of them is written.
+    let truth_paths: Vec<PathBuf> = args.truth_paths().into_iter().filter_map(|(path, _)| path.clone()).collect();
+    let expression_paths = expression.iter()
+        .flat_map(|expression| [expression.counts.as_ref(), expression.read_truth.as_ref(), expression.cells.as_ref().map(|cells| &cells.counts)]);
+    let mut other_outputs: Vec<PathBuf> = truth_paths.iter()
+        .chain(expression_paths.flatten())
+        .chain(somatic.iter().flat_map(|somatic| [Some(&somatic.truth_vcf), somatic.exposure_truth.as_ref()]).flatten())
+        .chain(library.iter().flat_map(Library::outputs))
+        .cloned()
+        .collect();
+    for path in &other_outputs {
+        check_overwrite(path, args.force)?;
+    }
+
+    let read_length_usize = read_length as usize;
+    let length_max = args.length_max.unwrap_or(usize::MAX);
+    if args.read_len1 == Some(0) || args.read_len2 == Some(0) {
+        return Err(FastqGenError::invalid("Read length must be positive."));
+    }
+    let single_cell = expression.as_ref().is_some_and(|expression| expression.cells.is_some());
+    if single_cell && args.read_len1.is_some() {
+        return Err(FastqGenError::invalid("The read 1 of single-cell runs is the cell barcode and UMI; set the cDNA length with -l or --read-len2."));
+    }
+    // Single-cell runs sequence their cDNA with the length of read 2.
+    let fixed_length = if single_cell { args.read_len2 } else { args.read_len1 };
+    let read_lengths = match (fixed_length, args.length_dist) {
+        (Some(length), _) => ReadLengths::Fixed(length),
+        (None, LengthDistArg::Fixed) => ReadLengths::Fixed(read_length_usize),
+        (None, LengthDistArg::Normal) => ReadLengths::normal(read_length as f64, args.length_sd, args.length_min, length_max)?,
+        (None, LengthDistArg::Uniform) => {
+            let max = args.length_max.ok_or_else(|| FastqGenError::invalid("A uniform read length distribution requires --length-max."))?;
+            ReadLengths::uniform(args.length_min, max)?
+        }
+        (None, LengthDistArg::Lognormal) => ReadLengths::lognormal(read_length as f64, args.length_sd, args.length_min, length_max)?,
+    };
+
+    let mut error_model = ErrorModel::new(args.sub_rate, args.ins_rate, args.del_rate)?;
+    let profile = args.profile.as_deref().map(ErrorProfile::from_json).transpose()?;
+    let (cycle_qualities, mate_cycle_qualities) = match &args.quality_profile {
+        Some(path) => {
+            let (read_1, read_2) = read_cycle_qualities(path)?;
+            (Some(read_1), read_2)
+        }
+        None => (None, None),
+    };
+    let quality_model = match (&profile, cycle_qualities, args.fixed_quality, args.quality_model) {
+        (Some(profile), _, _, _) => profile.quality_model(false),
+        (None, Some(model), _, _) => model,
+        (None, None, Some(phred), _) => QualityModel::Fixed(phred),
+        (None, None, None, QualityModelArg::Uniform) => QualityModel::Uniform,
+        (None, None, None, QualityModelArg::IlluminaDecay) => QualityModel::illumina_decay(args.quality_start, args.quality_end, args.quality_noise)?,
+    };
+    let shaped_qualities = matches!(quality_model, QualityModel::IlluminaDecay { .. } | QualityModel::PerCycle { .. });
+    if let Some(profile) = &profile {
+        error_model = error_model.with_phred_substitution_rates(profile.substitution_rates())?;
+    } else if args.sub_rate > 0.0 && shaped_qualities && !args.independent_errors {
+        error_model = error_model.with_phred_errors();
+    }
+    if let Some(factor) = args.homopolymer_indels {
+        error_model = error_model.with_homopolymer_indels(factor)?;
+    }
+    let illumina_names = IlluminaNames { lanes: args.lanes, ..IlluminaNames::default() };
+    let name_format = match (&args.id_template, args.name_format) {
+        (Some(template), _) => NameFormat::Template(IdTemplate::parse(template, illumina_names)?),
+        (None, NameFormatArg::Simple) => NameFormat::Simple { width: args.id_pad },
+        (None, NameFormatArg::Illumina) => NameFormat::Illumina(illumina_names),
+    };
+    let mate_read_lengths = args.read_len2.filter(|_| !single_cell).map(ReadLengths::Fixed);
+    let mut generator = FastqGenerator::new(read_length_usize)
+        .with_quality_encoding(args.phred_offset, args.max_quality)?
+        .with_read_lengths(read_lengths.clone())
+        .with_quality_model(quality_model)
+        .with_error_model(error_model.clone())
+        .with_name_format(name_format);
+    if let Some(profile) = &profile {
+        generator = generator.with_mate_quality_model(profile.quality_model(true));
+    }
+    if let Some(model) = mate_cycle_qualities {
+        generator = generator.with_mate_quality_model(model);
+    }
+    if args.r2_error_multiplier != 1.0 {
+        generator = generator.with_mate_error_model(error_model.scaled(args.r2_error_multiplier)?);
+    }
+    generator = generator.with_mate_quality_penalty(args.r2_quality_penalty);
+    if let Some(lengths) = &mate_read_lengths {
+        generator = generator.with_mate_read_lengths(lengths.clone());
+    }
+    if let Some(bins) = args.preset.and_then(|preset| preset.preset().quality_bins) {
+        generator = generator.with_quality_bins(bins);
+    }
+    generator = generator.with_poly_g_rate(args.poly_g_rate)?;
+    generator = generator.with_n_rate(args.n_rate, args.n_rate_early.unwrap_or(args.n_rate), args.n_early_cycles)?;
+    if args.rng == RngArg::Fast {
+        generator = generator.with_packed_bases();
+    }
+    if let Some(mean) = args.insert_mean {
+        generator = generator.with_insert_size(mean, args.insert_sd)?;
+    } else if args.ancient_dna {
+        generator = generator.with_insert_size(ANCIENT_FRAGMENT_MEAN, ANCIENT_FRAGMENT_SD)?;
+    } else if args.ffpe {
+        generator = generator.with_insert_size(FFPE_FRAGMENT_MEAN, FFPE_FRAGMENT_SD)?;
+    }
+    generator = generator.with_orientation(match args.orientation {
+        OrientationArg::Fr => MateOrientation::Fr,
+        OrientationArg::Rf => MateOrientation::Rf,
+        OrientationArg::Ff => MateOrientation::Ff,
+    });
+    // Ancient and FFPE molecules are often shorter than the reads, which run into
+    // the adapter.
+    if args.adapter_read_through || args.ancient_dna || args.ffpe {
+        generator = generator.with_adapters(args.adapter1.to_ascii_uppercase().into_bytes(), args.adapter2.to_ascii_uppercase().into_bytes());
+    }
+    if single_cell {
+        if args.single_end || args.format == OutputFormatArg::Ubam || args.sample_sheet.is_some() {
+            return Err(FastqGenError::invalid("single-cell runs write paired FASTQ without a sample sheet."));
+        }
+        // The UMI is read in R1 after the cell barcode rather than placed by --umi-placement.
+        generator = generator.with_umi(args.umi_length.unwrap_or(CELL_UMI_LENGTH), UmiPlacement::Index)?;
+    } else if let Some(length) = args.umi_length.or(matches!(library, Some(Library::Families(_))).then_some(FAMILY_UMI_LENGTH)) {
+        let placement = match args.umi_placement {
+            UmiPlacementArg::Read => UmiPlacement::Read,
+            UmiPlacementArg::Name => UmiPlacement::Name,
+            UmiPlacementArg::Index => UmiPlacement::Index,
+        };
+        generator = generator.with_umi(length, placement)?;
+    }
+    generator = generator.with_barcode_error_rate(args.barcode_error_rate)?;
+    let samples = match &args.sample_sheet {
+        Some(path) => read_sample_sheet(path)?,
+        None => Vec::new(),
+    };
+    if !(0.0..=1.0).contains(&args.index_hopping) {
+        return Err(FastqGenError::invalid("--index-hopping must be between 0 and 1."));
+    }
+    if args.index_hopping > 0.0 && samples.len() < 2 {
+        return Err(FastqGenError::invalid("--index-hopping needs at least two samples in the sample sheet to swap indices between."));
+    }
+    if let Some(gc) = args.gc {
+        generator = generator.with_gc_content(gc)?;
+    }
+    if let Some(weights) = &args.base_weights {
+        let weights: [f64; 4] = weights.as_slice().try_into()
+            .map_err(|_| FastqGenError::invalid("--base-weights takes exactly four comma-separated weights for A, C, G and T."))?;
+        generator = generator.with_base_weights(weights)?;
+    }
+    if let Some(path) = &args.markov_model {
+        generator = generator.with_markov_model(MarkovModel::from_json(path)?);
+    }
+    let seed = args.seed.unwrap_or_else(|| StdRng::from_os_rng().random());
+    // A tumor shares its seed with its normal, for the same germline variants, but
+    // not its reads; so do the members of a trio or cohort.
+    let member_seed = |member: usize| chunk_rng(seed, MEMBER_STREAM).random_iter().nth(member).expect("endless seeds");
+    let read_seed = match (somatic, &library) {
+        (Some(_), _) => chunk_rng(seed, TUMOR_STREAM).random(),
+        (None, Some(Library::Trio(trio))) => member_seed(trio.member as usize),
+        (None, Some(Library::Cohort(cohort))) => member_seed(cohort.sample),
+        (None, _) => seed,
+    };
+
+    let mut genome_size = 0;
+    let mut transcript_tpm = Vec::new();
+    let mut annotation = None;
+    if args.ploidy == 0 {
+        return Err(FastqGenError::invalid("--ploidy must be at least 1."));
+    }
+    if args.amplicons.is_none() && args.primers.is_none() && (args.off_target_rate > 0.0 || args.primer_dimer_rate > 0.0) {
+        return Err(FastqGenError::invalid("--off-target-rate and --primer-dimer-rate need an amplicon panel (--amplicons or --primers)."));
+    }
+    let mut community: Option<(Vec<Genome>, Vec<f64>)> = None;
+    let genome_paths = expression.as_ref().map_or(&[][..], |expression| &expression.genomes);
+    let gtf = expression.as_ref().and_then(|expression| expression.gtf.as_ref());
+    let reference = match (&args.reference, gtf) {
+        (None, _) if !genome_paths.is_empty() => {
+            let (reference, genomes) = read_community(genome_paths, generator.min_fragment_length())?;
+            community = Some((genomes, Vec::new()));
+            Some(reference)
+        }
+        (None, _) => None,
+        (Some(path), Some(gtf)) => {
+            let genome = Reference::from_fasta(path, 1)?;
+            let mut transcripts = read_gtf(gtf, &genome)?;
+            transcripts.retain(|transcript| transcript.len() >= generator.min_fragment_length());
+            let sequences = transcripts.iter().map(|transcript| (transcript.id.clone(), transcript.spliced_seq(&genome))).collect();
+            let transcriptome = Reference::from_sequences(sequences, 1)
+                .map_err(|e| FastqGenError::parse(format!("Annotation {}: {}", gtf.display(), e)))?;
+            annotation = Some(Annotation { genome, transcripts });
+            Some(transcriptome)
+        }
+        (Some(path), None) => Some(Reference::from_fasta(path, generator.min_fragment_length())?),
+    };
+    if reference.is_none() && (args.phix_reference.is_some() || !args.contaminant.is_empty() || args.source_truth.is_some()) {
+        return Err(FastqGenError::invalid("--phix, --contaminant and --source-truth need a reference (-r)."));
+    }
+    if args.regions.is_some() && (expression.is_some() || matches!(library, Some(Library::Linked(_) | Library::HiC(_)))) {
+        return Err(FastqGenError::invalid("--regions does not apply to rnaseq, single-cell, metagenome, linked-reads or hic runs."));
+    }
+    if matches!(library, Some(Library::Chip(_))) && (reference.is_none() || args.targets.is_some() || args.amplicons.is_some() || args.primers.is_some()) {
+        return Err(FastqGenError::invalid("chip runs draw their reads from a reference (-r), around binding sites rather than targets or amplicons."));
+    }
+    if let Some(mut reference) = reference {
+        genome_size = reference.total_length();
+
+        // The germline variants of a trio are the parents', on all four of their
+        // haplotypes; a cohort draws the genotypes of its sites itself.
+        let germline_ploidy = match &library {
+            Some(Library::Trio(_)) => PARENTAL_HAPLOTYPES,
+            Some(Library::Cohort(_)) => 1,
+            _ => args.ploidy,
+        };
+        let mut rng = chunk_rng(seed, VARIANT_STREAM);
+        let mut variants = match &args.vcf {
+            Some(vcf_path) => read_vcf(vcf_path, &reference, &mut rng, args.ploidy)?,
+            None => Vec::new(),
+        };
+        let mut simulated = simulate_snps(&reference, &mut rng, args.snp_rate, args.hom_fraction, germline_ploidy)?;
+        let indel_lengths = IndelLengths { mean: args.indel_mean_length, max: args.indel_max_length };
+        simulated.extend(simulate_indels(&reference, &mut rng, args.indel_rate, indel_lengths, args.hom_fraction, germline_ploidy)?);
+        resolve_overlaps(&mut simulated);
+        remove_overlapping(&mut simulated, &variants);
+        variants.extend(simulated);
+        variants.sort_by_key(|variant| (variant.contig, variant.position));
+        let sv_counts = SvCounts {
+            deletions: args.deletions,
+            duplications: args.duplications,
+            inversions: args.inversions,
+            translocations: args.translocations,
+        };
+        let mut structural = simulate_structural_variants(&reference, &mut rng, sv_counts, args.sv_min_length..=args.sv_max_length, args.hom_fraction, args.ploidy)?;
+        remove_within_structural_variants(&mut variants, &structural);
+        if let Some(sv_path) = &args.truth_sv {
+            let bedpe = sv_path.file_name().is_some_and(|name| name.to_string_lossy().split('.').skip(1).any(|ext| ext == "bedpe"));
+            write_truth_file(sv_path, |writer| {
+                if bedpe {
+                    write_sv_bedpe(writer, &reference, &structural)
+                } else {
+                    write_sv_vcf(writer, &reference, &structural)
+                }
+            })?;
+        }
+        if let Some(vcf_path) = &args.truth_vcf {
+            write_truth_file(vcf_path, |writer| write_vcf(writer, &reference, &variants))?;
+        }
+        let mut ploidy = args.ploidy;
+        if let Some(somatic) = somatic {
+            simulate_somatic(somatic, &reference, seed, &args, &mut variants, &mut structural)?;
+            ploidy = somatic.model.ploidy();
+        }
+        let mut haplotypes = None;
+        if let Some(library) = &library {
+            let (library_ploidy, population) = library.simulate_variants(&reference, seed, &mut variants, &mut structural)?;
+            ploidy = library_ploidy.unwrap_or(ploidy);
+            haplotypes = population;
+        }
+        if !variants.is_empty() || !structural.is_empty() || haplotypes.is_some() {
+            reference.apply_variants(&variants, ploidy)?;
+            reference.apply_structural_variants(&structural)?;
+            if let Some(somatic) = somatic {
+                reference.set_haplotype_weights(&somatic.model.tumor_weights())?;
+            }
+            if let Some(population) = &haplotypes {
+                reference.set_haplotype_weights(population.frequencies())?;
+            }
+        }
+        if let (Some(population), Some(Library::Quasispecies(QuasispeciesOptions { haplotype_fasta: Some(path), .. }))) = (&haplotypes, &library) {
+            write_truth_file(path, |writer| population.write_fasta(writer, &reference))?;
+        }
+        if let (Some(expression), Some((genomes, abundances))) = (&expression, community.as_mut()) {
+            *abundances = match &expression.table {
+                Some(table) => {
+                    let (abundances, unknown) = read_abundances(table, genomes)?;
+                    if unknown > 0 && !args.quiet {
+                        eprintln!("Ignored {} genomes of {} that are not in the community.", unknown, table.display());
+                    }
+                    abundances
+                }
+                None => sample_community(genomes, &mut chunk_rng(seed, EXPRESSION_STREAM), expression.sd)?,
+            };
+            // Each contig is weighted by its length, so genomes yield reads in
+            // proportion to abundance times genome size.
+            let mut weights = vec![0.0; reference.contigs().count()];
+            for (genome, abundance) in genomes.iter().zip(abundances.iter()) {
+                weights[genome.contigs.clone()].fill(*abundance);
+            }
+            reference.set_expression(&weights)?;
+        } else if let Some(expression) = &expression {
+            let tpm = match &expression.table {
+                Some(table) => {
+                    let (tpm, unknown) = read_expression(table, &reference)?;
+                    if unknown > 0 && !args.quiet {
+                        eprintln!("Ignored {} transcripts of {} that are not in the transcriptome (or are shorter than a fragment).", unknown, table.display());
+                    }
+                    tpm
+                }
+                None => sample_expression(&reference, &mut chunk_rng(seed, EXPRESSION_STREAM), expression.sd)?,
+            };
+            reference.set_expression(&tpm)?;
+            transcript_tpm = tpm;
+        }
+        let amplicons = match (&args.amplicons, &args.primers) {
+            (Some(bed), _) => Some(read_amplicon_bed(bed, &reference)?),
+            (None, Some(primers)) => Some(read_primer_pairs(primers, &reference)?),
+            (None, None) => None,
+        };
+        if let Some(amplicons) = amplicons {
+            generator = generator.with_amplicons(AmpliconPanel::new(amplicons, args.off_target_rate, args.primer_dimer_rate)?);
+        }
+        if args.gc_bias {
+            let bias = GcBias::new(args.gc_bias_optimum, args.gc_bias_width, args.gc_bias_skew)?;
+            if let Some(path) = &args.gc_bias_curve {
+                write_truth_file(path, |writer| bias.write_curve(writer))?;
+            }
+            generator = generator.with_gc_bias(bias);
+        }
+        if let Some(path) = &args.regions {
+            let regions = Regions::new(&reference, read_bed(path, &reference, "region BED")?, args.region_score)?;
+            genome_size = regions.total_length();
+            generator = generator.with_regions(regions);
+        }
+        if let Some(path) = &args.targets {
+            let capture = CaptureTargets::new(read_bed(path, &reference, "target BED")?, args.on_target)?;
+            // Coverage is aimed at the targets, which receive the on-target share of the reads.
+            genome_size = (capture.total_length() as f64 / capture.on_target().max(f64::MIN_POSITIVE)) as usize;
+            generator = generator.with_capture(capture);
+        }
+        if let Some(Library::Chip(chip)) = &library {
+            generator = generator.with_capture(setup_chip(chip, &reference, &args, seed)?);
+        }
+        let phix = args.phix_reference.as_ref().map(|path| (path, "PhiX".to_string(), args.phix));
+        let contaminants = args.contaminant.iter().map(|(path, fraction)| (path, genome_name(path), *fraction));
+        for (path, name, fraction) in phix.into_iter().chain(contaminants) {
+            if expression.as_ref().is_some_and(|expression| expression.genomes.is_empty()) {
+                return Err(FastqGenError::invalid("--phix and --contaminant are not supported by rnaseq and single-cell."));
+            }
+            let genome = Reference::from_fasta(path, 1)?;
+            let sequences = genome.contigs().enumerate().map(|(contig, (name, _))| (name.to_string(), genome.contig_seq(contig).to_vec())).collect();
+            reference.add_spike_in(&name, sequences, fraction)?;
+        }
+        // The reference receives the reads that are not spiked in.
+        genome_size = (genome_size as f64 / (1.0 - reference.spike_in_fraction()).max(f64::MIN_POSITIVE)) as usize;
+        if args.bisulfite {
+            let mut methylation = Methylation::new(&reference, args.cpg_methylation, args.chg_methylation, args.chh_methylation)?;
+            if let Some(path) = &args.methylation {
+                let unknown = methylation.read_bedgraph(path, &reference)?;
+                if unknown > 0 && !args.quiet {
+                    eprintln!("Ignored {} lines of {} on contigs that are not in the reference.", unknown, path.display());
+                }
+            }
+            if let Some(path) = &args.methylation_truth {
+                write_truth_file(path, |writer| methylation.write_bedgraph(writer, &reference))?;
+            }
+            generator = generator.with_bisulfite(methylation);
+        }
+        if args.ffpe {
+            let artifacts = FfpeArtifacts::simulate(&reference, &mut chunk_rng(seed, ARTIFACT_STREAM), args.ffpe_site_rate, args.ffpe_max_fraction)?;
+            if let Some(path) = &args.ffpe_truth {
+                write_truth_file(path, |writer| artifacts.write_vcf(writer, &reference))?;
+            }
+            generator = generator.with_ffpe(artifacts);
+        }
+        if args.ancient_dna {
+            let damage = DamageModel::new(args.damage_terminal, args.damage_decay, args.damage_interior)?;
+            generator = generator.with_damage(if args.single_stranded { damage.with_single_stranded() } else { damage });
+        }
+        generator = generator.with_reference(reference);
+    }
+    if expression.is_some() && generator.reference().is_none() {
+        return Err(FastqGenError::invalid("RNA-seq runs need a transcriptome FASTA (-r)."));
+    }
+    let (generator, setup) = match &library {
+        Some(library) => library.setup(generator, &args, seed)?,
+        None => (generator, LibrarySetup::default()),
+    };
+    let cell_barcodes = match (expression.as_ref().and_then(|expression| expression.cells.as_ref()), generator.reference()) {
+        (Some(options), Some(transcriptome)) => {
+            let mut rng = chunk_rng(seed, CELL_STREAM);
+            let (barcodes, whitelist) = draw_barcodes(&mut rng, options.cells, "cells", options.whitelist.as_deref(), args.barcode_error_rate > 0.0)?;
+            let mut gene_names: Vec<String> = Vec::new();
+            let mut gene_indices: HashMap<String, usize> = HashMap::new();
+            let genes = (0..transcriptome.contigs().count())
+                .map(|transcript| {
+                    let gene = match &annotation {
+                        Some(annotation) => &annotation.transcripts[transcript].gene_id,
+                        None => transcriptome.contig_name(transcript),
+                    };
+                    *gene_indices.entry(gene.to_string()).or_insert_with(|| {
+                        gene_names.push(gene.to_string());
+                        gene_names.len() - 1
+                    })
+                })
+                .collect();
+            Some(CellBarcodes { barcodes, whitelist, genes, gene_names })
+        }
+        _ => None,
+    };
+
+    let paired = !args.single_end;
+    let bases_per_read = || {
+        let mate_mean = if paired { mate_read_lengths.as_ref().unwrap_or(&read_lengths).mean() } else { 0.0 };
+        read_lengths.mean() + mate_mean
+    };
+    let num_reads = match (args.n, args.coverage, args.total_bases) {
+        (Some(n), _, _) => n,
+        (None, Some(coverage), _) => reads_for_coverage(coverage, genome_size, bases_per_read())?,
+        (None, None, Some(bases)) => (bases as f64 / bases_per_read()).ceil() as u64,
+        (None, None, None) => unreachable!("clap requires a read count, coverage or base total"),
+    };
+    if num_reads == 0 {
+        return Err(FastqGenError::invalid("Number of reads must be positive."));
+    }
+
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(args.threads).build()
+        .map_err(|e| FastqGenError::invalid(format!("Failed to start {} threads: {}", args.threads, e)))?;
+
+    if args.bench && args.manifest {
+        return Err(FastqGenError::invalid("bench writes no read files for --manifest to record."));
+    }
+    if let Some(dir) = args.output_dir.as_ref().filter(|_| !args.bench) {
+        if output_file_prefix == "-" {
+            return Err(FastqGenError::invalid("--output-dir cannot be used with -o -."));
+        }
+        fs::create_dir_all(dir).map_err(|e| FastqGenError::io(format!("Failed to create output directory {}", dir.display()), e))?;
+        output_file_prefix = dir.join(&output_file_prefix).to_string_lossy().into_owned();
+    }
+    let ubam = args.format == OutputFormatArg::Ubam;
+    let compressor = args.compression.compressor()?;
+    let extension = compressor.extension();
+    // Standard output is a single stream, so pairs written there are interleaved.
+    let to_stdout = output_file_prefix == "-";
+    let interleaved = args.interleaved || (paired && (to_stdout || ubam));
+    if args.index_reads && (ubam || to_stdout) {
+        return Err(FastqGenError::invalid("--index-reads writes separate FASTQ files and cannot be used with uBAM output (which stores barcodes in the BC tag) or -o -."));
+    }
+    let dual_index = samples.first().is_some_and(|sample| sample.i5.is_some());
+    if args.reads_per_file == Some(0) {
+        return Err(FastqGenError::invalid("--reads-per-file must be positive."));
+    }
+    if args.reads_per_file.is_some() && to_stdout {
+        return Err(FastqGenError::invalid("--reads-per-file writes numbered files and cannot be used with -o -."));
+    }
+    let bcl2fastq = args.naming == NamingArg::Bcl2fastq;
+    if bcl2fastq && to_stdout {
+        return Err(FastqGenError::invalid("--naming bcl2fastq names output files and cannot be used with -o -."));
+    }
+    if args.split_lanes && to_stdout {
+        return Err(FastqGenError::invalid("--split-lanes writes one file set per lane and cannot be used with -o -."));
+    }
+    let manifest_path = format!("{}.manifest.json", output_file_prefix);
+    if args.manifest {
+        if to_stdout {
+            return Err(FastqGenError::invalid("--manifest checksums the output files and cannot be used with -o -."));
+        }
+        check_overwrite(&manifest_path, args.force)?;
+    }
+    // Sharded runs start at file _001, which bcl2fastq names always carry.
+    let first_shard = if args.reads_per_file.is_some() || bcl2fastq { 1 } else { 0 };
+    // Opens the outputs of one file set, or of one of its shards, and describes them.
+    let create = |path: &str| -> Result<FastqWriter, FastqGenError> {
+        if args.bench {
+            return Ok(compressor.sink()?);
+        }
+        check_overwrite(path, args.force)?;
+        compressor.create(path)
+    };
+    let open_outputs = |prefix: &str, sample_name: &str, shard: usize| -> Result<(FileSetWriters, String), FastqGenError> {
+        let suffix = if shard > 0 { format!("_{:03}", shard) } else { String::new() };
+        let mut paths = Vec::new();
+        let mut open = |path: String| -> Result<FastqWriter, FastqGenError> {
+            let writer = create(&path)?;
+            paths.push(path);
+            Ok(writer)
+        };
+        let (i1, i2) = if args.index_reads {
+            let i1 = open(format!("{}_I1{}.{}", prefix, suffix, extension))?;
+            let i2 = if dual_index { Some(open(format!("{}_I2{}.{}", prefix, suffix, extension))?) } else { None };
+            (Some(i1), i2)
+        } else {
+            (None, None)
+        };
+        let (r1, r2, description) = if ubam {
+            let filepath = format!("{}{}.bam", prefix, suffix);
+            if !to_stdout && !args.bench {
+                check_overwrite(&filepath, args.force)?;
+                paths.push(filepath.clone());
+            }
+            let mut writer = if args.bench {
+                FastqWriter::sink(OutputCompression::Bgzf(6), None)?
+            } else {
+                FastqWriter::bgzf(Some(filepath.as_str()).filter(|_| !to_stdout))?
+            };
+            write_ubam_header(&mut writer, &args.read_group, sample_name)?;
+            (writer, None, if to_stdout { String::from("stdout") } else { filepath })
+        } else if to_stdout {
+            (if args.bench { compressor.sink()? } else { compressor.stdout()? }, None, String::from("stdout"))
+        } else if paired && !interleaved {
+            let r1 = open(format!("{}_R1{}.{}", prefix, suffix, extension))?;
+            let r2 = open(format!("{}_R2{}.{}", prefix, suffix, extension))?;
+            (r1, Some(r2), format!("{}_R[12]{}.{}", prefix, suffix, extension))
+        } else {
+            // bcl2fastq names single-end and interleaved reads as read 1.
+            let read = if bcl2fastq { "_R1" } else { "" };
+            let filepath = format!("{}{}{}.{}", prefix, read, suffix, extension);
+            (open(filepath.clone())?, None, filepath)
+        };
+        let description = match description.rfind(&suffix) {
+            Some(at) if args.reads_per_file.is_some() => format!("{}_###{}", &description[..at], &description[at + suffix.len()..]),
+            _ => description,
+        };
+        Ok((FileSetWriters { r1, r2, i1, i2, shard, reads: 0, paths }, description))
+    };
+    // The file name, read group sample name and sample number of every sample's
+    // files, or of the whole run's.
+    let set_names = if args.demultiplex {
+        if to_stdout {
+            return Err(FastqGenError::invalid("--demultiplex writes one file set per sample and cannot be used with -o -."));
+        }
+        samples.iter().enumerate().map(|(number, sample)| (format!("{}_{}", output_file_prefix, sample.name), sample.name.clone(), number + 1)).collect()
+    } else {
+        vec![(output_file_prefix.clone(), args.sample_name.clone(), 1)]
+    };
+    let split_lanes = if args.split_lanes { args.lanes } else { 1 };
+    // bcl2fastq leaves the lane out of the names of merged multi-lane runs.
+    let lane_names = args.split_lanes || (bcl2fastq && args.lanes == 1);
+    // The prefix and read group sample name of every file set, lane by lane within
+    // each sample.
+    let mut file_sets = Vec::new();
+    let mut writers = Vec::new();
+    let mut output_files = String::new();
+    for (file_name, sample_name, number) in &set_names {
+        for lane in 1..=split_lanes {
+            let mut prefix = file_name.clone();
+            if bcl2fastq {
+                prefix.push_str(&format!("_S{}", number));
+            }
+            if lane_names {
+                prefix.push_str(&format!("_L{:03}", lane));
+            }
+            let (set_writers, files) = open_outputs(&prefix, sample_name, first_shard)?;
+            writers.push(set_writers);
+            output_files = files;
+            file_sets.push((prefix, sample_name.clone()));
+        }
+    }
+    if let Some((file_name, _, number)) = set_names.last().filter(|_| args.demultiplex) {
+        output_files = output_files.replacen(file_name, &format!("{}_<sample>", output_file_prefix), 1).replacen(&format!("_S{}_", number), "_S<n>_", 1);
+    }
+    if split_lanes > 1 {
+        output_files = output_files.replacen(&format!("_L{:03}", split_lanes), "_L<lane>", 1);
+    }
+    if args.demultiplex {
+        output_files = format!("{} for {} samples", output_files, samples.len());
+    }
+    // uBAM records carry their own mate flags, so pairs are not reported as interleaved.
+    let layout = match (paired, interleaved && !ubam) {
+        (false, _) => "single-end",
+        (true, false) => "paired",
+        (true, true) => "interleaved paired",
+    };
+
+    let mut truth = TruthWriters::default();
+    if let Some(path) = &args.truth_sam {
+        let mut header = Vec::new();
+        if let Some(reference) = annotation.as_ref().map(|annotation| &annotation.genome).or(generator.reference()) {
+            write_sam_header(&mut header, reference)?;
+        }
+        truth.open(ChunkTruth::Sam, Some(path), &header)?;
+    }
+    truth.open(ChunkTruth::Duplicates, args.duplicate_truth.as_deref(), b"")?;
+    let mate = if paired && !single_cell { "mate\t" } else { "" };
+    truth.open(ChunkTruth::Origins, args.truth_tsv.as_deref(), format!("read\t{}contig\tstart\tend\tstrand\terrors\n", mate).as_bytes())?;
+    truth.open(ChunkTruth::Haplotypes, args.haplotype_truth.as_deref(), b"read\thaplotype\n")?;
+    truth.open(ChunkTruth::Sources, args.source_truth.as_deref(), b"read\tsource\n")?;
+    truth.open(ChunkTruth::Samples, args.sample_truth.as_deref(), b"read\tsample\tbarcode\thopped\n")?;
+    if let Some(expression) = &expression {
+        let mates = if paired { "blocks_1\tblocks_2" } else { "blocks" };
+        let source = if community.is_some() { "contig\tgenome" } else { "transcript\tgene" };
+        truth.open(ChunkTruth::Reads, expression.read_truth.as_deref(), format!("read\t{}\t{}\n", source, mates).as_bytes())?;
+    }
+    if let Some(library) = &library {
+        library.open_truth(&mut truth, generator.reference())?;
+    }
+    if matches!(args.umi_placement, UmiPlacementArg::Index) && args.umi_length.is_some() && !ubam && !single_cell {
+        if to_stdout {
+            return Err(FastqGenError::invalid("--umi-placement index writes a separate file and cannot be used with -o -."));
+        }
+        let path = format!("{}_UMI.{}", output_file_prefix, extension);
+        truth.push(ChunkTruth::Umi, create(&path)?);
+        other_outputs.push(PathBuf::from(path));
+    }
+    // Sample barcodes are counted as both indices together.
+    let sample_whitelist = (args.barcode_error_rate > 0.0 && !samples.is_empty())
+        .then(|| BarcodeWhitelist::new(samples.iter().map(|sample| [&sample.i7[..], sample.i5.as_deref().unwrap_or_default()].concat())));
+    let options = OutputOptions {
+        rng: args.rng,
+        paired,
+        interleaved,
+        ubam_read_group: Some(args.read_group.as_str()).filter(|_| ubam),
+        truth_sam: truth.contains(ChunkTruth::Sam),
+        origin_truth: truth.contains(ChunkTruth::Origins),
+        haplotype_truth: truth.contains(ChunkTruth::Haplotypes),
+        source_truth: truth.contains(ChunkTruth::Sources),
+        duplicate_rate: args.duplicate_rate,
+        duplicate_truth: truth.contains(ChunkTruth::Duplicates),
+        optical_duplicates: args.optical_duplicates,
+        optical_distance: args.optical_distance,
+        umi_index: truth.contains(ChunkTruth::Umi),
+        samples: &samples,
+        demultiplex: args.demultiplex,
+        lanes: split_lanes as u64,
+        id_start: args.id_start,
+        index_reads: args.index_reads,
+        index_hopping: args.index_hopping,
+        barcode_whitelist: cell_barcodes.as_ref().and_then(|cells| cells.whitelist.as_ref())
+            .or(setup.linked_partitions.as_ref().and_then(|partitions| partitions.whitelist.as_ref()))
+            .or(sample_whitelist.as_ref()),
+        sample_truth: truth.contains(ChunkTruth::Samples),
+        count_transcripts: expression.is_some(),
+        read_truth: truth.contains(ChunkTruth::Reads),
+        annotation: annotation.as_ref(),
+        genomes: community.as_ref().map_or(&[][..], |(genomes, _)| genomes),
+        single_cell: cell_barcodes.as_ref(),
+        linked_reads: setup.linked_partitions.as_ref(),
+        molecule_truth: truth.contains(ChunkTruth::LinkedMolecules),
+        contact_truth: truth.contains(ChunkTruth::Contacts),
+        fragment_truth: truth.contains(ChunkTruth::Fragments),
+        guides: setup.guides.as_ref(),
+        marker_community: setup.marker_community.as_ref(),
+        taxonomy_truth: truth.contains(ChunkTruth::Taxonomy),
+        umi_families: setup.umi_families.as_ref(),
+        family_truth: truth.contains(ChunkTruth::Families),
+        repertoire: setup.repertoire.as_ref(),
+    };
+    let length_description = match &mate_read_lengths {
+        Some(mate_lengths) => format!("{} + {}", read_lengths, mate_lengths),
+        None => read_lengths.to_string(),
+    };
+    let mut transcript_counts = Vec::new();
+    let mut cell_molecules: Vec<(u32, u32)> = Vec::new();
+    let mut barcode_errors = BarcodeErrorCounts::default();
+
+    if !args.quiet {
+        eprintln!("Starting generation of {} {} reads (Length: {})", num_reads, layout, length_description);
+    }
+    let progress = if args.quiet { ProgressBar::hidden() } else { ProgressBar::new(num_reads) };
+    progress.set_style(
+        ProgressStyle::with_template("{bar:40} {human_pos}/{human_len} reads ({msg}) ETA {eta}")
+            .expect("progress template is valid"),
+    );
+    let mut bytes_written = 0u64;
+    // Paths and read counts of every finished file set, for the manifest.
+    let mut read_files = Vec::new();
+
+    let num_chunks = num_reads.div_ceil(CHUNK_SIZE);
+    // Generate a few chunks per thread at a time, then write them out in order.
+    let batch_size = (args.threads * 4) as u64;
+
+    for batch_start in (0..num_chunks).step_by(batch_size as usize) {
+        let batch_end = (batch_start + batch_size).min(num_chunks);
+        let chunks: Vec<ChunkOutput> = pool.install(|| {
+            (batch_start..batch_end)
+                .into_par_iter()
+                .map(|chunk_index| {
+                    let start = chunk_index * CHUNK_SIZE;
+                    let end = start.saturating_add(CHUNK_SIZE).min(num_reads);
+                    generate_chunk(&generator, read_seed, chunk_index, start..end, options)
+                })
+                .collect::<Result<_, _>>()
+        })?;
+
+        for chunk in chunks {
+            for (index, files) in chunk.files.iter().enumerate() {
+                // Write the reads in runs that fit in the current shard, moving on to the
+                // next shard whenever one fills up.
+                let (mut written, mut from) = (0, [0; 4]);
+                while written < files.read_ends.len() {
+                    let room = args.reads_per_file.map_or(u64::MAX, |limit| limit - writers[index].reads);
+                    if room == 0 {
+                        let (prefix, sample_name) = &file_sets[index];
+                        let (next, _) = open_outputs(prefix, sample_name, writers[index].shard + 1)?;
+                        read_files.push(std::mem::replace(&mut writers[index], next).finish()?);
+                        continue;
+                    }
+                    let count = (room as usize).min(files.read_ends.len() - written);
+                    let to = files.read_ends[written + count - 1];
+                    let set = &mut writers[index];
+                    bytes_written += (to[0] - from[0] + to[1] - from[1]) as u64;
+                    set.r1.write_all(&files.r1[from[0]..to[0]])?;
+                    let others = [(&mut set.r2, &files.r2, 1), (&mut set.i1, &files.i1, 2), (&mut set.i2, &files.i2, 3)];
+                    for (writer, records, column) in others {
+                        if let Some(writer) = writer.as_mut() {
+                            writer.write_all(&records[from[column]..to[column]])?;
+                        }
+                    }
+                    set.reads += count as u64;
+                    (written, from) = (written + count, to);
+                }
+            }
+            truth.write(&chunk)?;
+            cell_molecules.extend_from_slice(&chunk.cell_molecules);
+            barcode_errors += chunk.barcode_errors;
+            if transcript_counts.is_empty() {
+                transcript_counts = chunk.counts;
+            } else {
+                transcript_counts.iter_mut().zip(&chunk.counts).for_each(|(total, count)| *total += count);
+            }
+        }
+        let written = (batch_end * CHUNK_SIZE).min(num_reads);
+        let seconds = progress.elapsed().as_secs_f64().max(1e-3);
+        progress.set_message(format!(
+            "{} reads/s, {:.1} MB/s",
+            HumanCount((written as f64 / seconds) as u64),
+            bytes_written as f64 / 1e6 / seconds
+        ));
+        progress.set_position(written);
+    }
+    progress.finish_and_clear();
+
+    for writers in writers {
+        read_files.push(writers.finish()?);
+    }
+    truth.finish()?;
+    if let (Some(cells), Some(options)) = (&cell_barcodes, expression.as_ref().and_then(|expression| expression.cells.as_ref())) {
+        cell_molecules.sort_unstable();
+        write_truth_file(&options.counts, |writer| {
+            writeln!(writer, "cell\tgene\tumis")?;
+            for group in cell_molecules.chunk_by(|a, b| a == b) {
+                let (cell, gene) = group[0];
+                writeln!(writer, "{}\t{}\t{}", String::from_utf8_lossy(&cells.barcodes[cell as usize]), cells.gene_names[gene as usize], group.len())?;
+            }
+            Ok(())
+        })?;
+    }
+    let columns = count_columns(&samples);
+    if let (Some(counts), Some(reference)) = (expression.as_ref().and_then(|expression| expression.counts.as_ref()), generator.reference()) {
+        write_truth_file(counts, |writer| match &community {
+            Some((genomes, abundances)) => write_community_counts(writer, genomes, abundances, &columns, &transcript_counts),
+            None => write_counts(writer, reference, &transcript_tpm, &columns, &transcript_counts),
+        })?;
+    }
+    setup.write_counts(&columns, &transcript_counts)?;
+    if args.manifest {
+        let mut files = Vec::new();
+        for (paths, reads) in &read_files {
+            for path in paths {
+                files.push(ManifestFile::new(path, Some(*reads))?);
+            }
+        }
+        // Some truth files are only written for some kinds of run.
+        for path in other_outputs.iter().filter(|path| path.exists()) {
+            files.push(ManifestFile::new(&path.to_string_lossy(), None)?);
+        }
+        let manifest = Manifest {
+            tool: env!("CARGO_PKG_NAME"),
+            version: env!("CARGO_PKG_VERSION"),
+            command_line: std::env::args_os().map(|arg| arg.to_string_lossy().into_owned()).collect(),
+            seed,
+            parameters: &args,
+            reads: num_reads,
+            files,
+        };
+        let mut writer = create_file(&manifest_path)?;
+        serde_json::to_writer_pretty(&mut writer, &manifest).map_err(io::Error::from)?;
+        writeln!(writer)?;
+        writer.flush()?;
+    }
+
+    if args.bench {
+        let seconds = progress.elapsed().as_secs_f64().max(1e-3);
+        println!("reads\t{}", num_reads);
+        println!("megabytes\t{:.1}", bytes_written as f64 / 1e6);
+        println!("seconds\t{:.3}", seconds);
+        println!("reads_per_second\t{:.0}", num_reads as f64 / seconds);
+        println!("megabytes_per_second\t{:.1}", bytes_written as f64 / 1e6 / seconds);
+        println!("threads\t{}", args.threads);
+    } else if !args.quiet {
+        eprintln!(
+            "🦀 Wrote {} {} reads (Length: {}) to {} in {}",
+            num_reads,
+            layout,
+            length_description,
+            output_files,
+            HumanDuration(progress.elapsed())
+        );
+        if barcode_errors.barcodes > 0 {
+            eprintln!(
+                "{} of {} barcodes carry sequencing errors; {} of those are within Hamming distance 1 of the whitelist, and {} correct back to their true barcode.",
+                barcode_errors.mutated, barcode_errors.barcodes, barcode_errors.within_one, barcode_errors.correctable
+            );
+        }
+    }
+
+    Ok(())
+}
+
+
+fn run_stats(args: StatsArgs) -> Result<(), FastqGenError> {
+    let mut reports = Vec::new();
+    for path in &args.files {
+        let mut reader = FastqReader::open(path)?;
+        let mut stats = FastqStats::new(args.phred_offset);
+        let mut record = FastqRead::default();
+        while reader.read_into(&mut record)? {
+            stats.add(&record);
+        }
+        reports.push(stats.report(reader.source()));
+    }
+
+    let mut out = BufWriter::new(std::io::stdout().lock());
+    if args.json {
+        serde_json::to_writer_pretty(&mut out, &reports).map_err(io::Error::from)?;
+        writeln!(out)?;
+    } else {
+        for (index, report) in reports.iter().enumerate() {
+            if index > 0 {
+                writeln!(out)?;
+            }
+            report.write_table(&mut out)?;
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn run_profile(args: ProfileArgs) -> Result<(), FastqGenError> {
+    if let Some(path) = &args.reference {
+        let reference = Reference::from_fasta(path, 1)?;
+        let model = MarkovModel::train(args.order, (0..reference.contigs().count()).map(|contig| reference.contig_seq(contig)))?;
+        let mut writer = create_file(&args.outfile)?;
+        model.write_json(&mut writer)?;
+        writer.flush()?;
+        println!("Trained an order-{} Markov model on {} bases into {}", model.order(), reference.total_length(), args.outfile.display());
+        return Ok(());
+    }
+    let r1 = args.r1.as_deref().expect("clap requires R1 without a reference");
+    let mut reader_1 = FastqReader::open(r1)?;
+    let mut reader_2 = args.r2.as_deref().map(FastqReader::open).transpose()?;
+    let mut profiler = ErrorProfiler::new(args.phred_offset);
+    let (mut record_1, mut record_2) = (FastqRead::default(), FastqRead::default());
+    while reader_1.read_into(&mut record_1)? {
+        match reader_2.as_mut() {
+            Some(reader_2) => {
+                if !reader_2.read_into(&mut record_2)? {
+                    return Err(FastqGenError::parse(format!("{} has fewer records than {}.", reader_2.source(), reader_1.source())));
+                }
+                if record_1.pair_name() != record_2.pair_name() {
+                    return Err(FastqGenError::parse(format!("{}:{}: read {} does not pair with {}", reader_2.source(), reader_2.line_number() - 3, record_2.name(), record_1.name())));
+                }
+                profiler.add_pair(&record_1, &record_2);
+            }
+            None => profiler.add_single(&record_1),
+        }
+    }
+    if let Some(reader_2) = reader_2.as_mut()
+        && reader_2.read_into(&mut record_2)?
+    {
+        return Err(FastqGenError::parse(format!("{} has more records than {}.", reader_2.source(), reader_1.source())));
+    }
+
+    let profile = profiler.finish();
+    if profile.reads == 0 {
+        return Err(FastqGenError::parse(format!("{} has no records to profile.", reader_1.source())));
+    }
+    let mut writer = create_file(&args.outfile)?;
+    profile.write_json(&mut writer)?;
+    writer.flush()?;
+    println!(
+        "Profiled {} reads ({} overlapping pairs) into {}",
+        profile.reads,
+        profile.overlapping_pairs,
+        args.outfile.display()
+    );
+    Ok(())
+}
+
+/// Reads the next record of `reader_1` and, given a mate file, the next record of
+/// `reader_2`, checking that the two pair up. Returns false once both are exhausted.
+fn read_mates(
+    reader_1: &mut FastqReader,
+    reader_2: Option<&mut FastqReader>,
+    record_1: &mut FastqRead,
+    record_2: &mut FastqRead,
+) -> Result<bool, FastqGenError> {
+    let more_1 = reader_1.read_into(record_1)?;
+    let Some(reader_2) = reader_2 else {
+        return Ok(more_1);
+    };
+    match (more_1, reader_2.read_into(record_2)?) {
+        (true, false) => Err(FastqGenError::parse(format!("{} has fewer records than {}.", reader_2.source(), reader_1.source()))),
+        (false, true) => Err(FastqGenError::parse(format!("{} has more records than {}.", reader_2.source(), reader_1.source()))),
+        (true, true) if record_1.pair_name() != record_2.pair_name() => {
+            Err(FastqGenError::parse(format!("{}:{}: read {} does not pair with {}", reader_2.source(), reader_2.line_number() - 3, record_2.name(), record_1.name())))
+        }
+        (more, _) => Ok(more),
+    }
+}
+
+/// Opens `<outfile>_R1.fastq` and `<outfile>_R2.fastq` for `paired` reads, or
+/// `<outfile>.fastq`, or standard output (where pairs are interleaved) for `-`.
+/// Returns the writers and a description of the files.
+fn open_fastq_outputs(outfile: &str, paired: bool, compression: &CompressionArgs) -> Result<(BatchedFastqWriter, Option<BatchedFastqWriter>, String), FastqGenError> {
+    let compressor = compression.compressor()?;
+    let extension = compressor.extension();
+    Ok(if outfile == "-" {
+        (BatchedFastqWriter::new(compressor.stdout()?), None, String::from("stdout"))
+    } else if paired {
+        (
+            BatchedFastqWriter::new(compressor.create(format!("{}_R1.{}", outfile, extension))?),
+            Some(BatchedFastqWriter::new(compressor.create(format!("{}_R2.{}", outfile, extension))?)),
+            format!("{}_R[12].{}", outfile, extension),
+        )
+    } else {
+        let filepath = format!("{}.{}", outfile, extension);
+        (BatchedFastqWriter::new(compressor.create(&filepath)?), None, filepath)
+    })
+}
+
+/// Keeps exactly `wanted` of the `remaining` records still to come: each is kept
+/// with probability (still wanted) / (still to come), which makes every subset
+/// of that size equally likely.
+struct ExactSample {
+    wanted: u64,
+    remaining: u64,
+}
+
+impl ExactSample {
+    fn keep(&mut self, rng: &mut impl Rng) -> bool {
+        let keep = rng.random_range(0..self.remaining) < self.wanted;
+        self.remaining -= 1;
+        if keep {
+            self.wanted -= 1;
+        }
+        keep
+    }
+}
+
+fn run_downsample(args: DownsampleArgs) -> Result<(), FastqGenError> {
+    if let Some(fraction) = args.fraction
+        && !(0.0..=1.0).contains(&fraction)
+    {
+        return Err(FastqGenError::invalid("--fraction must be between 0 and 1."));
+    }
+    // Keeping exactly N reads needs the record count up front.
+    let mut exact = None;
+    if let Some(wanted) = args.reads {
+        if args.r1 == Path::new("-") {
+            return Err(FastqGenError::invalid("--reads reads R1 twice and cannot downsample standard input; use --fraction."));
+        }
+        let mut reader = FastqReader::open(&args.r1)?;
+        let mut record = FastqRead::default();
+        let mut remaining = 0;
+        while reader.read_into(&mut record)? {
+            remaining += 1;
+        }
+        if wanted > remaining {
+            return Err(FastqGenError::invalid(format!("Cannot keep {} reads: {} has only {}.", wanted, reader.source(), remaining)));
+        }
+        exact = Some(ExactSample { wanted, remaining });
+    }
+    let seed = args.seed.unwrap_or_else(|| StdRng::from_os_rng().random());
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let paired = args.r2.is_some();
+    let (mut writer_1, mut writer_2, output_files) = open_fastq_outputs(&args.outfile, paired, &args.compression)?;
+
+    let mut reader_1 = FastqReader::open(&args.r1)?;
+    let mut reader_2 = args.r2.as_deref().map(FastqReader::open).transpose()?;
+    let (mut record_1, mut record_2) = (FastqRead::default(), FastqRead::default());
+    let (mut records, mut kept) = (0u64, 0u64);
+    while read_mates(&mut reader_1, reader_2.as_mut(), &mut record_1, &mut record_2)? {
+        records += 1;
+        let keep = match &mut exact {
+            Some(exact) => exact.keep(&mut rng),
+            None => rng.random_bool(args.fraction.expect("clap requires --fraction without --reads")),
+        };
+        if !keep {
+            continue;
+        }
+        kept += 1;
+        writer_1.write_record(&record_1.header, &record_1.seq, &record_1.quality)?;
+        if paired {
+            // Without a mate file, pairs are interleaved on standard output.
+            writer_2.as_mut().unwrap_or(&mut writer_1).write_record(&record_2.header, &record_2.seq, &record_2.quality)?;
+        }
+    }
+    writer_1.finish()?;
+    if let Some(writer_2) = writer_2 {
+        writer_2.finish()?;
+    }
+    let unit = if paired { "read pairs" } else { "reads" };
+    eprintln!("Kept {} of {} {} from {} in {}", kept, records, unit, reader_1.source(), output_files);
+    Ok(())
+}
+
+fn run_mutate(args: MutateArgs) -> Result<(), FastqGenError> {
+    let mut error_model = ErrorModel::new(args.sub_rate, args.ins_rate, args.del_rate)?;
+    if let Some(path) = &args.profile {
+        error_model = error_model.with_phred_substitution_rates(ErrorProfile::from_json(path)?.substitution_rates())?;
+    }
+    let paired = args.r2.is_some();
+    let mut generator = FastqGenerator::new(1)
+        .with_quality_encoding(args.phred_offset, b'~'.saturating_sub(args.phred_offset))?
+        .with_error_model(error_model.clone())
+        .with_poly_g_rate(args.poly_g_rate)?
+        .with_n_rate(args.n_rate, args.n_rate, 0)?;
+    if args.r2_error_multiplier != 1.0 {
+        generator = generator.with_mate_error_model(error_model.scaled(args.r2_error_multiplier)?);
+    }
+    let seed = args.seed.unwrap_or_else(|| StdRng::from_os_rng().random());
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let (mut writer_1, mut writer_2, output_files) = open_fastq_outputs(&args.outfile, paired, &args.compression)?;
+
+    let mut reader_1 = FastqReader::open(&args.r1)?;
+    let mut reader_2 = args.r2.as_deref().map(FastqReader::open).transpose()?;
+    let (mut record_1, mut record_2) = (FastqRead::default(), FastqRead::default());
+    let mut records = 0u64;
+    while read_mates(&mut reader_1, reader_2.as_mut(), &mut record_1, &mut record_2)? {
+        records += 1;
+        let seq = generator.degrade(&mut rng, &record_1.seq, &mut record_1.quality, args.qual_shift, false);
+        writer_1.write_record(&record_1.header, &seq, &record_1.quality)?;
+        if paired {
+            let seq = generator.degrade(&mut rng, &record_2.seq, &mut record_2.quality, args.qual_shift, true);
+            // Without a mate file, pairs are interleaved on standard output.
+            writer_2.as_mut().unwrap_or(&mut writer_1).write_record(&record_2.header, &seq, &record_2.quality)?;
+        }
+    }
+    writer_1.finish()?;
+    if let Some(writer_2) = writer_2 {
+        writer_2.finish()?;
+    }
+    let unit = if paired { "read pairs" } else { "reads" };
+    eprintln!("Degraded {} {} from {} into {}", records, unit, reader_1.source(), output_files);
+    Ok(())
+}
+
+/// Writes reads starting every `step` bases of each contig, plus one ending at the
+/// contig's end so that every base is covered. Reads are named after the 1-based,
+/// inclusive span they cover, e.g. `chr1:1-150`.
+fn run_shred(args: ShredArgs) -> Result<(), FastqGenError> {
+    if args.read_length == 0 {
+        return Err(FastqGenError::invalid("Read length must be positive."));
+    }
+    let step = match (args.step, args.coverage) {
+        (Some(0), _) => return Err(FastqGenError::invalid("--step must be positive.")),
+        (Some(step), _) => step,
+        (None, Some(coverage)) if coverage.is_nan() || coverage <= 0.0 => return Err(FastqGenError::invalid("--coverage must be positive.")),
+        (None, Some(coverage)) => ((args.read_length as f64 / coverage).round() as usize).max(1),
+        (None, None) => args.read_length,
+    };
+    let generator = FastqGenerator::new(args.read_length)
+        .with_quality_encoding(args.phred_offset, args.max_quality)?
+        .with_error_model(ErrorModel::new(args.sub_rate, args.ins_rate, args.del_rate)?);
+    let seed = args.seed.unwrap_or_else(|| StdRng::from_os_rng().random());
+    let mut rng = StdRng::seed_from_u64(seed);
+    let reference = Reference::from_fasta(&args.reference, 1)?;
+    let (mut writer, _, output_file) = open_fastq_outputs(&args.outfile, false, &args.compression)?;
+
+    let top_quality = args.phred_offset + args.max_quality;
+    let mut reads = 0u64;
+    for contig in 0..reference.contigs().count() {
+        let (name, seq) = (reference.contig_name(contig), reference.contig_seq(contig));
+        let last_start = seq.len().saturating_sub(args.read_length);
+        let starts = (0..last_start).step_by(step).chain(std::iter::once(last_start));
+        for start in starts {
+            let template = &seq[start..(start + args.read_length).min(seq.len())];
+            let id = format!("{}:{}-{}", name, start + 1, start + template.len());
+            let mut quality = vec![top_quality; template.len()];
+            let read = generator.degrade(&mut rng, template, &mut quality, 0, false);
+            writer.write_record(&id, &read, &quality)?;
+            reads += 1;
+            if args.both_strands {
+                let mut quality = vec![top_quality; template.len()];
+                let read = generator.degrade(&mut rng, &reverse_complement(template), &mut quality, 0, false);
+                writer.write_record(&format!("{}_rc", id), &read, &quality)?;
+                reads += 1;
+            }
+        }
+    }
+    writer.finish()?;
+    eprintln!("Shredded {} bases into {} reads (Length: {}, step: {}) in {}", reference.total_length(), reads, args.read_length, step, output_file);
+    Ok(())
+}
+
+/// Parses a count of reads or bases with an optional k, M or G suffix (powers of
+/// 1000), e.g. `5M` or `2.5k`.
+fn parse_count(value: &str) -> Result<u64, String> {
+    let (number, multiplier) = match value.char_indices().last() {
+        Some((at, 'k' | 'K')) => (&value[..at], 1e3),
+        Some((at, 'm' | 'M')) => (&value[..at], 1e6),
+        Some((at, 'g' | 'G')) => (&value[..at], 1e9),
+        _ => (value, 1.0),
+    };
+    let bases = number.parse::<f64>().map_err(|_| format!("{:?} is not a number, such as 5000, 2.5M or 30G", value))? * multiplier;
+    if !(bases >= 0.0 && bases.fract() == 0.0 && bases <= u64::MAX as f64) {
+        return Err(format!("{:?} is not a whole number", value));
+    }
+    Ok(bases as u64)
+}
+
+fn run_genome(args: GenomeArgs) -> Result<(), FastqGenError> {
+    let genome = RandomGenome::new(args.length, args.contigs, args.gc)?
+        .with_repeats(args.repeat_fraction, args.repeat_length, args.repeat_families, args.repeat_divergence)?;
+    let seed = args.seed.unwrap_or_else(|| StdRng::from_os_rng().random());
+    let (contigs, repeats) = genome.generate(&mut StdRng::seed_from_u64(seed));
+
+    let mut writer = create_file(&args.outfile)?;
+    write_fasta(&mut writer, &contigs)?;
+    writer.flush()?;
+    if let Some(path) = &args.repeats_bed {
+        let mut writer = create_file(path)?;
+        write_repeats_bed(&mut writer, &contigs, &repeats)?;
+        writer.flush()?;
+    }
+    println!("Wrote a {} bp genome of {} contigs with {} repeat copies to {}", args.length, contigs.len(), repeats.len(), args.outfile.display());
+    Ok(())
+}
+
+fn run_barcodes(args: BarcodesArgs) -> Result<(), FastqGenError> {
+    if args.outfile.is_none() && args.sample_sheet.is_none() {
+        return Err(FastqGenError::invalid("Give an output file (-o), a sample sheet (--sample-sheet) or both."));
+    }
+    let mut design = BarcodeDesign::new(args.length, args.min_distance)?.with_gc(args.gc_min, args.gc_max)?;
+    if args.edit_distance {
+        design = design.with_edit_distance();
+    }
+    let seed = args.seed.unwrap_or_else(|| StdRng::from_os_rng().random());
+    let barcodes = design.generate(&mut StdRng::seed_from_u64(seed), args.count)?;
+
+    if let Some(path) = &args.outfile {
+        let mut writer = create_file(path)?;
+        for barcode in &barcodes {
+            writer.write_all(barcode)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+    }
+    if let Some(path) = &args.sample_sheet {
+        let mut writer = create_file(path)?;
+        writeln!(writer, "sample,i7")?;
+        for (index, barcode) in barcodes.iter().enumerate() {
+            writeln!(writer, "S{},{}", index + 1, String::from_utf8_lossy(barcode))?;
+        }
+        writer.flush()?;
+    }
+    let destinations: Vec<String> = [&args.outfile, &args.sample_sheet].into_iter().flatten().map(|path| path.display().to_string()).collect();
+    let metric = if args.edit_distance { "edit" } else { "Hamming" };
+    println!("Wrote {} barcodes of {} bases at {} distance {} or more to {}", barcodes.len(), args.length, metric, args.min_distance, destinations.join(" and "));
+    Ok(())
+}
+
+fn run_interleave(args: InterleaveArgs) -> Result<(), FastqGenError> {
+    let compressor = args.compression.compressor()?;
+    let output_file = if args.outfile == "-" { String::from("stdout") } else { format!("{}.{}", args.outfile, compressor.extension()) };
+    let mut writer = BatchedFastqWriter::new(if args.outfile == "-" { compressor.stdout()? } else { compressor.create(&output_file)? });
+    let mut reader_1 = FastqReader::open(&args.r1)?;
+    let mut reader_2 = FastqReader::open(&args.r2)?;
+    let (mut record_1, mut record_2) = (FastqRead::default(), FastqRead::default());
+    let mut pairs = 0u64;
+    while read_mates(&mut reader_1, Some(&mut reader_2), &mut record_1, &mut record_2)? {
+        pairs += 1;
+        writer.write_record(&record_1.header, &record_1.seq, &record_1.quality)?;
+        writer.write_record(&record_2.header, &record_2.seq, &record_2.quality)?;
+    }
+    writer.finish()?;
+    eprintln!("Interleaved {} read pairs from {} and {} into {}", pairs, reader_1.source(), reader_2.source(), output_file);
+    Ok(())
+}
+
+/// Reads the next two records of an interleaved `reader`, checking that they pair
+/// up. Returns false at the end of the input.
+fn read_interleaved(reader: &mut FastqReader, record_1: &mut FastqRead, record_2: &mut FastqRead) -> Result<bool, FastqGenError> {
+    if !reader.read_into(record_1)? {
+        return Ok(false);
+    }
+    if !reader.read_into(record_2)? {
+        return Err(FastqGenError::parse(format!("{}: read {} at the end of the file has no mate.", reader.source(), record_1.name())));
+    }
+    if record_1.pair_name() != record_2.pair_name() {
+        return Err(FastqGenError::parse(format!("{}:{}: read {} does not pair with the preceding {}", reader.source(), reader.line_number() - 3, record_2.name(), record_1.name())));
+    }
+    Ok(true)
+}
+
+fn run_deinterleave(args: DeinterleaveArgs) -> Result<(), FastqGenError> {
+    let compressor = args.compression.compressor()?;
+    let extension = compressor.extension();
+    let mut writer_1 = BatchedFastqWriter::new(compressor.create(format!("{}_R1.{}", args.outfile, extension))?);
+    let mut writer_2 = BatchedFastqWriter::new(compressor.create(format!("{}_R2.{}", args.outfile, extension))?);
+    let mut reader = FastqReader::open(&args.input)?;
+    let (mut record_1, mut record_2) = (FastqRead::default(), FastqRead::default());
+    let mut pairs = 0u64;
+    while read_interleaved(&mut reader, &mut record_1, &mut record_2)? {
+        pairs += 1;
+        writer_1.write_record(&record_1.header, &record_1.seq, &record_1.quality)?;
+        writer_2.write_record(&record_2.header, &record_2.seq, &record_2.quality)?;
+    }
+    writer_1.finish()?;
+    writer_2.finish()?;
+    eprintln!("Split {} read pairs from {} into {}_R[12].{}", pairs, reader.source(), args.outfile, extension);
+    Ok(())
+}
+
+/// Checks the parts of a record [`FastqReader`] does not: legal sequence and quality
+/// characters. `line` is the record's header line.
+fn validate_record(reader: &FastqReader, record: &FastqRead, phred_offset: u8, line: u64) -> Result<(), FastqGenError> {
+    if let Some(position) = record.seq.iter().position(|base| !base.is_ascii_alphabetic()) {
+        return Err(FastqGenError::parse(format!("{}:{}: illegal base '{}' at position {}", reader.source(), line + 1, record.seq[position].escape_ascii(), position + 1)));
+    }
+    if let Some(position) = record.quality.iter().position(|&score| score < phred_offset || score > b'~') {
+        return Err(FastqGenError::parse(format!(
+            "{}:{}: quality character '{}' at position {} is outside the Phred+{} range",
+            reader.source(),
+            line + 3,
+            record.quality[position].escape_ascii(),
+            position + 1,
+            phred_offset
+        )));
+    }
+    Ok(())
+}
+
+fn run_validate(args: ValidateArgs) -> Result<(), FastqGenError> {
+    if args.phred_offset != 33 && args.phred_offset != 64 {
+        return Err(FastqGenError::invalid("Phred offset must be 33 or 64."));
+    }
+    let mut reader_1 = FastqReader::open(&args.r1)?;
+    let mut reader_2 = args.r2.as_deref().map(FastqReader::open).transpose()?;
+    let (mut record_1, mut record_2) = (FastqRead::default(), FastqRead::default());
+    let mut records = 0u64;
+
+    loop {
+        let more_1 = reader_1.read_into(&mut record_1)?;
+        let line_1 = reader_1.line_number().saturating_sub(3);
+        if more_1 {
+            validate_record(&reader_1, &record_1, args.phred_offset, line_1)?;
+        }
+
+        if let Some(reader_2) = reader_2.as_mut() {
+            let more_2 = reader_2.read_into(&mut record_2)?;
+            let line_2 = reader_2.line_number().saturating_sub(3);
+            match (more_1, more_2) {
+                (true, true) => {
+                    validate_record(reader_2, &record_2, args.phred_offset, line_2)?;
+                    if record_1.pair_name() != record_2.pair_name() {
+                        return Err(FastqGenError::parse(format!(
+                            "{}:{}: read {} does not pair with {} at {}:{}",
+                            reader_2.source(), line_2, record_2.name(), record_1.name(), reader_1.source(), line_1
+                        )));
+                    }
+                }
+                (true, false) => {
+                    return Err(FastqGenError::parse(format!("{}: ends after {} records, but {} continues at line {}", reader_2.source(), records, reader_1.source(), line_1)));
+                }
+                (false, true) => {
+                    return Err(FastqGenError::parse(format!("{}: ends after {} records, but {} continues at line {}", reader_1.source(), records, reader_2.source(), line_2)));
+                }
+                (false, false) => {}
+            }
+        }
+
+        if !more_1 {
+            break;
+        }
+        records += 1;
+    }
+
+    match &reader_2 {
+        Some(reader_2) => println!("{} and {}: {} valid read pairs", reader_1.source(), reader_2.source(), records),
+        None => println!("{}: {} valid records", reader_1.source(), records),
+    }
+    Ok(())
+}
+
+/// Writes the normal sample to `<outfile>_normal` files and the tumor sample to
+/// `<outfile>_tumor` files. Both runs share a seed, so they carry the same
+/// germline variants.
+fn run_somatic(args: SomaticArgs) -> Result<(), FastqGenError> {
+    let SomaticArgs { mut generate, somatic_snv_rate, somatic_indel_rate, somatic_vcf, vafs, purity, somatic_truth_vcf, signature, signature_matrix, exposure_truth } = args;
+    if generate.reference.is_none() {
+        return Err(FastqGenError::invalid("somatic needs a reference (-r)."));
+    }
+    if generate.outfile == "-" {
+        return Err(FastqGenError::invalid("somatic writes a normal and a tumor sample and cannot stream to standard output."));
+    }
+    if generate.ploidy != 2 {
+        return Err(FastqGenError::invalid("somatic simulates a diploid germline; --ploidy must be 2."));
+    }
+    generate.seed.get_or_insert_with(|| StdRng::from_os_rng().random());
+    let somatic = Somatic {
+        model: TumorModel::new(vafs, purity)?,
+        snv_rate: somatic_snv_rate,
+        indel_rate: somatic_indel_rate,
+        vcf: somatic_vcf,
+        truth_vcf: somatic_truth_vcf,
+        signatures: match &signature_matrix {
+            Some(path) => Some(SignatureMixture::new(read_signatures(path)?, &signature)?),
+            None => None,
+        },
+        exposure_truth,
+    };
+
+    // The germline truth VCF, any FFPE artifact sites and the GC bias curve are the
+    // same for both samples, so only the normal writes them.
+    run_generate(member_args(&generate, "normal", true), None, None, None)?;
+    run_generate(member_args(&generate, "tumor", false), None, Some(&somatic), None)
+}
+
+fn run_trio(args: TrioArgs) -> Result<(), FastqGenError> {
+    let TrioArgs { mut generate, recombination_rate, de_novo, pedigree_vcf } = args;
+    if generate.reference.is_none() {
+        return Err(FastqGenError::invalid("trio needs a reference (-r)."));
+    }
+    if generate.outfile == "-" {
+        return Err(FastqGenError::invalid("trio writes a sample for each member and cannot stream to standard output."));
+    }
+    if generate.ploidy != 2 {
+        return Err(FastqGenError::invalid("trio simulates diploid samples; --ploidy must be 2."));
+    }
+    if generate.vcf.is_some() || generate.truth_vcf.is_some() || generate.truth_sv.is_some() {
+        return Err(FastqGenError::invalid("trio simulates the parents' variants itself and writes them to --pedigree-vcf rather than --truth-vcf; --vcf and --truth-sv are not supported."));
+    }
+    if generate.deletions + generate.duplications + generate.inversions + generate.translocations > 0 {
+        return Err(FastqGenError::invalid("trio does not simulate structural variants."));
+    }
+    generate.seed.get_or_insert_with(|| StdRng::from_os_rng().random());
+
+    // The parents' variants and the child's inheritance are drawn from the shared
+    // seed, so every member's run draws the same ones.
+    let mut pedigree_vcf = Some(pedigree_vcf);
+    for member in TrioMember::ALL {
+        let options = TrioOptions { member, recombination_rate, de_novo, pedigree_vcf: pedigree_vcf.take() };
+        run_generate(member_args(&generate, member.name(), member == TrioMember::Mother), None, None, Some(Library::Trio(options)))?;
+    }
+    Ok(())
+}
+
+fn run_cohort(args: CohortArgs) -> Result<(), FastqGenError> {
+    let CohortArgs { mut generate, samples, populations, fst, cohort_vcf } = args;
+    if generate.reference.is_none() {
+        return Err(FastqGenError::invalid("cohort needs a reference (-r)."));
+    }
+    if generate.outfile == "-" {
+        return Err(FastqGenError::invalid("cohort writes every sample to files of its own and cannot stream to standard output."));
+    }
+    if generate.ploidy != 2 {
+        return Err(FastqGenError::invalid("cohort simulates diploid samples; --ploidy must be 2."));
+    }
+    if generate.vcf.is_some() || generate.truth_vcf.is_some() || generate.truth_sv.is_some() {
+        return Err(FastqGenError::invalid("cohort simulates its variants itself and writes them to --cohort-vcf rather than --truth-vcf; --vcf and --truth-sv are not supported."));
+    }
+    if generate.deletions + generate.duplications + generate.inversions + generate.translocations > 0 {
+        return Err(FastqGenError::invalid("cohort does not simulate structural variants."));
+    }
+    let cohort = Cohort::new(samples, populations, fst)?;
+    generate.seed.get_or_insert_with(|| StdRng::from_os_rng().random());
+
+    // Every sample's run draws the same sites and genotypes from the shared seed.
+    let mut cohort_vcf = Some(cohort_vcf);
+    for sample in 0..samples {
+        let options = CohortOptions { cohort: cohort.clone(), sample, cohort_vcf: cohort_vcf.take() };
+        run_generate(member_args(&generate, &cohort.sample_name(sample), sample == 0), None, None, Some(Library::Cohort(options)))?;
+    }
+    Ok(())
+}
+
+/// The generate options of one sample of a tumor/normal pair, trio or cohort: its
+/// reads and per-sample truth files go to paths with its name, and only the
+/// `first` sample writes the shared truth files.
+fn member_args(generate: &GenerateArgs, sample: &str, first: bool) -> GenerateArgs {
+    let mut args = generate.clone();
+    args.outfile = format!("{}_{}", generate.outfile, sample);
+    for (path, scope) in args.truth_paths() {
+        *path = match scope {
+            TruthScope::Sample => path.as_deref().map(|path| sample_path(path, sample)),
+            TruthScope::Shared if first => path.take(),
+            TruthScope::Shared => None,
+        };
+    }
+    args
+}
+
+/// Parses a `--contaminant` value: a FASTA path, a colon, and a fraction.
+fn parse_contaminant(value: &str) -> Result<(PathBuf, f64), String> {
+    let (path, fraction) = value.rsplit_once(':')
+        .ok_or_else(|| format!("expected FASTA:FRACTION, found {}", value))?;
+    let fraction = fraction.parse::<f64>().ok().filter(|fraction| (0.0..=1.0).contains(fraction))
+        .ok_or_else(|| format!("the fraction of {} must be between 0 and 1", path))?;
+    Ok((PathBuf::from(path), fraction))
+}
+
+/// Parses a `--signature` value: a signature name, a colon, and its exposure.
+fn parse_exposure(value: &str) -> Result<(String, f64), String> {
+    let (name, exposure) = value.rsplit_once(':')
+        .ok_or_else(|| format!("expected NAME:EXPOSURE, found {}", value))?;
+    let exposure = exposure.parse::<f64>().ok().filter(|exposure| *exposure >= 0.0 && exposure.is_finite())
+        .ok_or_else(|| format!("the exposure of {} must be a number of at least 0", name))?;
+    Ok((name.to_string(), exposure))
+}
+
+/// `path` with `_<sample>` added to its file name before the extension.
+fn sample_path(path: &Path, sample: &str) -> PathBuf {
+    let stem = path.file_stem().map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+    let name = match path.extension() {
+        Some(extension) => format!("{}_{}.{}", stem, sample, extension.to_string_lossy()),
+        None => format!("{}_{}", stem, sample),
+    };
+    path.with_file_name(name)
+}
+
+/// Exit status for a failed run, after sysexits.h: 64 for invalid parameters, 65
+/// for malformed input, 74 for I/O failures and 78 for configuration errors.
+fn exit_code(error: &FastqGenError) -> u8 {
+    match error {
+        FastqGenError::InvalidParameter(_) => 64,
+        FastqGenError::Parse(_) => 65,
+        FastqGenError::Io { .. } => 74,
+        FastqGenError::Config(_) => 78,
+    }
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            ExitCode::from(exit_code(&error))
+        }
+    }
+}
+
+fn run() -> Result<(), FastqGenError> {
+    let mut argv: Vec<OsString> = std::env::args_os().collect();
+    // A lenient first pass finds the config file, whose options are spliced in after
+    // the subcommand before the real parse.
+    let partial = Cli::command().ignore_errors(true).try_get_matches_from(&argv);
+    if let Ok(partial) = partial
+        && let Some((name @ ("generate" | "rnaseq" | "single-cell" | "metagenome" | "somatic" | "trio" | "cohort" | "linked-reads" | "hic" | "atac" | "chip" | "crispr" | "amplicon-community" | "vdj" | "umi-families" | "quasispecies" | "bench"), sub_matches)) = partial.subcommand()
+        && let Some(path) = sub_matches.get_one::<PathBuf>("config")
+    {
+        let command = Cli::command();
+        let subcommand = command.find_subcommand(name).expect("simulation subcommand");
+        let extra = config_args(path, subcommand, sub_matches)?;
+        let position = argv.iter().position(|arg| arg == name).map_or(argv.len(), |index| index + 1);
+        argv.splice(position..position, extra);
+    }
+
+    let matches = Cli::command().get_matches_from(argv);
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    if let (Some(args), Some(name)) = (cli.command.generate_args(), matches.subcommand_name()) {
+        args.apply_selected_preset(&matches, name);
+    }
+
+    match cli.command {
+        Commands::Generate(args) => run_generate(*args, None, None, None),
+        Commands::Rnaseq(args) => {
+            let RnaseqArgs { generate, expression, expression_sd, counts, gtf, read_truth } = *args;
+            run_generate(generate, Some(Expression { table: expression, sd: expression_sd, counts: Some(counts), gtf, read_truth, cells: None, genomes: Vec::new() }), None, None)
+        }
+        Commands::SingleCell(args) => {
+            let SingleCellArgs { generate, expression, expression_sd, gtf, cells, whitelist, counts } = *args;
+            let cells = Some(CellOptions { cells, whitelist, counts });
+            run_generate(generate, Some(Expression { table: expression, sd: expression_sd, counts: None, gtf, read_truth: None, cells, genomes: Vec::new() }), None, None)
+        }
+        Commands::LinkedReads(args) => {
+            let LinkedReadsArgs { generate, partitions, whitelist, molecule_length, reads_per_molecule, barcode_placement, molecule_truth } = *args;
+            let linked = LinkedOptions { partitions, whitelist, molecule_length, reads_per_molecule, placement: barcode_placement, molecule_truth };
+            run_generate(generate, None, None, Some(Library::Linked(linked)))
+        }
+        Commands::Atac(mut args) => {
+            if let Some(matches) = matches.subcommand_matches("atac") {
+                // Tn5 ligates Nextera adapters to both ends of every fragment.
+                for (id, adapter) in [("adapter1", &mut args.generate.adapter1), ("adapter2", &mut args.generate.adapter2)] {
+                    if matches.value_source(id) != Some(ValueSource::CommandLine) {
+                        *adapter = String::from_utf8_lossy(NEXTERA_ADAPTER).into_owned();
+                    }
+                }
+            }
+            let AtacArgs { generate, peaks, in_peaks, nucleosome_spacing, fragments } = *args;
+            let atac = AtacOptions { peaks, in_peaks, nucleosome_spacing, fragments };
+            run_generate(generate, None, None, Some(Library::Atac(atac)))
+        }
+        Commands::Chip(args) => {
+            let ChipArgs { generate, sites, random_sites, site_width, fold_enrichment, site_truth } = *args;
+            let chip = ChipOptions { sites, random_sites, site_width, fold_enrichment, site_truth };
+            run_generate(generate, None, None, Some(Library::Chip(chip)))
+        }
+        Commands::Crispr(args) => {
+            if args.generate.reference.is_some() {
+                return Err(FastqGenError::invalid("crispr reads its guides from --library rather than -r."));
+            }
+            let CrisprArgs { generate, library, abundance, abundance_sd, upstream, downstream, stagger, counts } = *args;
+            let crispr = CrisprOptions { library, abundance, abundance_sd, upstream, downstream, stagger, counts };
+            run_generate(generate, None, None, Some(Library::Crispr(crispr)))
+        }
+        Commands::AmpliconCommunity(args) => {
+            if args.generate.reference.is_some() {
+                return Err(FastqGenError::invalid("amplicon-community reads its references from --references rather than -r."));
+            }
+            let AmpliconCommunityArgs { generate, references, forward_primer, reverse_primer, abundance, abundance_sd, chimera_rate, counts, read_truth } = *args;
+            let marker = MarkerOptions { references, forward_primer, reverse_primer, abundance, abundance_sd, chimera_rate, counts, read_truth };
+            run_generate(generate, None, None, Some(Library::Marker(marker)))
+        }
+        Commands::Vdj(args) => {
+            if args.generate.reference.is_some() {
+                return Err(FastqGenError::invalid("vdj recombines its reads from --v-genes, --d-genes and --j-genes rather than -r."));
+            }
+            let VdjArgs { generate, v_genes, d_genes, j_genes, clones, clone_sd, deletion_mean, insertion_mean, unproductive, clonotypes } = *args;
+            let vdj = VdjOptions { v_genes, d_genes, j_genes, clones, clone_sd, deletion_mean, insertion_mean, unproductive, clonotypes };
+            run_generate(generate, None, None, Some(Library::Vdj(vdj)))
+        }
+        Commands::UmiFamilies(args) => {
+            let UmiFamiliesArgs { generate, family_size, duplex, pcr_error_rate, family_truth } = *args;
+            let families = FamilyOptions { family_size, duplex, pcr_error_rate, family_truth };
+            run_generate(generate, None, None, Some(Library::Families(families)))
+        }
+        Commands::Quasispecies(mut args) => {
+            if let Some(matches) = matches.subcommand_matches("quasispecies") {
+                // Variants given or drawn for the reference make up the consensus,
+                // a single haplotype the quasispecies descends from.
+                if matches.value_source("ploidy") != Some(ValueSource::CommandLine) {
+                    args.generate.ploidy = 1;
+                }
+            }
+            if args.generate.reference.is_none() {
+                return Err(FastqGenError::invalid("quasispecies needs a reference (-r) to mutate its haplotypes from."));
+            }
+            if args.generate.ploidy != 1 {
+                return Err(FastqGenError::invalid("quasispecies draws the variants of its consensus on one haplotype; --ploidy must be 1."));
+            }
+            let QuasispeciesArgs { generate, haplotypes, frequencies, frequency_sd, divergence, ts_tv, variant_truth, haplotype_fasta } = *args;
+            let quasispecies = QuasispeciesOptions { haplotypes, frequencies, frequency_sd, divergence, ts_tv, variant_truth, haplotype_fasta };
+            run_generate(generate, None, None, Some(Library::Quasispecies(quasispecies)))
+        }
+        Commands::Hic(args) => {
+            let HicArgs { generate, trans_fraction, contact_exponent, min_contact_distance, contact_truth } = *args;
+            let hic = HicOptions { trans_fraction, exponent: contact_exponent, min_distance: min_contact_distance, contact_truth };
+            run_generate(generate, None, None, Some(Library::HiC(hic)))
+        }
+        Commands::Metagenome(args) => {
+            if args.generate.reference.is_some() {
+                return Err(FastqGenError::invalid("metagenome reads its genomes from --genomes rather than -r."));
+            }
+            let MetagenomeArgs { generate, genomes, abundance, abundance_sd, counts, read_truth } = *args;
+            run_generate(generate, Some(Expression { table: abundance, sd: abundance_sd, counts, gtf: None, read_truth, cells: None, genomes }), None, None)
+        }
+        Commands::Somatic(args) => run_somatic(*args),
+        Commands::Trio(args) => run_trio(*args),
+        Commands::Cohort(args) => run_cohort(*args),
+        Commands::Stats(args) => run_stats(args),
+        Commands::Profile(args) => run_profile(args),
+        Commands::Validate(args) => run_validate(args),
+        Commands::Downsample(args) => run_downsample(args),
+        Commands::Mutate(args) => run_mutate(args),
+        Commands::Shred(args) => run_shred(args),
+        Commands::Genome(args) => run_genome(args),
+        Commands::Barcodes(args) => run_barcodes(args),
+        Commands::Interleave(args) => run_interleave(args),
+        Commands::Deinterleave(args) => run_deinterleave(args),
+        Commands::Bench(mut args) => {
+            args.bench = true;
+            run_generate(*args, None, None, None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fastq(names: &[&str]) -> FastqReader {
+        let text: String = names.iter().map(|name| format!("@{}\nACGT\n+\nIIII\n", name)).collect();
+        FastqReader::new(Box::new(io::Cursor::new(text.into_bytes())), "test.fq")
+    }
+
+    #[test]
+    fn exit_codes_follow_sysexits() {
+        assert_eq!(exit_code(&FastqGenError::invalid("--threads must be positive.")), 64);
+        assert_eq!(exit_code(&FastqGenError::parse("reads.fq:5: truncated record")), 65);
+        assert_eq!(exit_code(&FastqGenError::io("Failed to create out.fq", io::Error::from(io::ErrorKind::NotFound))), 74);
+        assert_eq!(exit_code(&FastqGenError::from(io::Error::from(io::ErrorKind::BrokenPipe))), 74);
+        assert_eq!(exit_code(&FastqGenError::Config("Unknown option".to_string())), 78);
+    }
+
+    #[test]
+    fn read_mates_pairs_reads_by_name() {
+        let (mut record_1, mut record_2) = (FastqRead::default(), FastqRead::default());
+        let (mut reader_1, mut reader_2) = (fastq(&["a/1", "b extra"]), fastq(&["a/2", "b"]));
+        assert!(read_mates(&mut reader_1, Some(&mut reader_2), &mut record_1, &mut record_2).unwrap());
+        assert!(read_mates(&mut reader_1, Some(&mut reader_2), &mut record_1, &mut record_2).unwrap());
+        assert!(!read_mates(&mut reader_1, Some(&mut reader_2), &mut record_1, &mut record_2).unwrap());
+
+        let (mut reader_1, mut reader_2) = (fastq(&["a/1", "b/1"]), fastq(&["a/2", "c/2"]));
+        assert!(read_mates(&mut reader_1, Some(&mut reader_2), &mut record_1, &mut record_2).unwrap());
+        let error = read_mates(&mut reader_1, Some(&mut reader_2), &mut record_1, &mut record_2).unwrap_err();
+        assert_eq!(error.to_string(), "test.fq:5: read c/2 does not pair with b/1");
+        assert_eq!(exit_code(&error), 65);
+
+        let (mut reader_1, mut reader_2) = (fastq(&["a", "b"]), fastq(&["a"]));
+        read_mates(&mut reader_1, Some(&mut reader_2), &mut record_1, &mut record_2).unwrap();
+        assert!(matches!(read_mates(&mut reader_1, Some(&mut reader_2), &mut record_1, &mut record_2), Err(FastqGenError::Parse(_))));
+    }
+
+    #[test]
+    fn read_interleaved_needs_a_mate_for_every_read() {
+        let (mut record_1, mut record_2) = (FastqRead::default(), FastqRead::default());
+        let mut reader = fastq(&["a/1", "a/2", "b/1"]);
+        assert!(read_interleaved(&mut reader, &mut record_1, &mut record_2).unwrap());
+        assert_eq!((record_1.name(), record_2.name()), ("a/1", "a/2"));
+        let error = read_interleaved(&mut reader, &mut record_1, &mut record_2).unwrap_err();
+        assert_eq!(error.to_string(), "test.fq: read b/1 at the end of the file has no mate.");
+        assert_eq!(exit_code(&error), 65);
+
+        let mut reader = fastq(&["a/1", "b/2"]);
+        assert!(matches!(read_interleaved(&mut reader, &mut record_1, &mut record_2), Err(FastqGenError::Parse(_))));
+        assert!(!read_interleaved(&mut fastq(&[]), &mut record_1, &mut record_2).unwrap());
+    }
+
+    #[test]
+    fn parse_count_applies_suffixes() {
+        assert_eq!(parse_count("5000"), Ok(5000));
+        assert_eq!(parse_count("2.5k"), Ok(2500));
+        assert_eq!(parse_count("5M"), Ok(5_000_000));
+        assert_eq!(parse_count("30g"), Ok(30_000_000_000));
+        assert_eq!(parse_count("0"), Ok(0));
+    }
+
+    #[test]
+    fn parse_count_rejects_fractions_and_non_numbers() {
+        for value in ["1.5", "1.0001k", "-3", "", "k", "5T", "NaN", "1e30G"] {
+            assert!(parse_count(value).is_err(), "{} was accepted", value);
+        }
+    }
+
+    #[test]
+    fn exact_sample_keeps_exactly_the_wanted_number() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for (wanted, total) in [(0, 10), (3, 10), (10, 10), (250, 1000)] {
+            let mut exact = ExactSample { wanted, remaining: total };
+            let kept = (0..total).filter(|_| exact.keep(&mut rng)).count() as u64;
+            assert_eq!(kept, wanted);
+        }
+    }
+}
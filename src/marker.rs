@@ -0,0 +1,237 @@
+use rand::Rng;
+use rand::distr::Distribution;
+use rand::distr::weighted::WeightedIndex;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use crate::amplicon::MAX_AMPLICON_LENGTH;
+use crate::error::FastqGenError;
+use crate::expression::{read_abundance_table, sample_abundances};
+use crate::reference::open_text;
+use crate::seq::reverse_complement;
+
+/// Forward primer of the V4 region of the 16S rRNA gene (515F, Parada et al.),
+/// as used by the Earth Microbiome Project.
+pub const V4_FORWARD_PRIMER: &[u8] = b"GTGYCAGCMGCCGCGGTAA";
+/// Reverse primer of the V4 region of the 16S rRNA gene (806R, Apprill et al.).
+pub const V4_REVERSE_PRIMER: &[u8] = b"GGACTACNVGGGTWTCTAAT";
+
+/// Most mismatches between a primer and the site it binds.
+pub const MAX_PRIMER_MISMATCHES: usize = 2;
+
+/// A marker-gene sequence (16S, 18S or ITS) of one taxon and the amplicon its
+/// primers produce.
+#[derive(Debug, Clone)]
+pub struct MarkerTaxon {
+    pub id: String,
+    /// Lineage, e.g. `d__Bacteria;p__Proteobacteria;...`, or empty when unknown.
+    pub taxonomy: String,
+    /// The amplified region, primers included, 5' to 3' from the forward primer.
+    pub amplicon: Vec<u8>,
+}
+
+/// Reads marker-gene references from a FASTA file (optionally gzipped), such as
+/// SILVA, Greengenes2 or UNITE, whose headers give an ID followed by its taxonomy,
+/// e.g. `>AB001234.1 Bacteria;Proteobacteria;...`. Returns `(id, taxonomy,
+/// sequence)` of every record, with sequences upper-cased and `U` read as `T`.
+pub fn read_marker_fasta(path: &Path) -> Result<Vec<(String, String, Vec<u8>)>, FastqGenError> {
+    let reader = open_text(path)
+        .map_err(|e| FastqGenError::io(format!("Failed to open marker references {}", path.display()), e))?;
+    let mut records: Vec<(String, String, Vec<u8>)> = Vec::new();
+
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim_end();
+        if let Some(header) = line.strip_prefix('>') {
+            let (id, taxonomy) = header.split_once(char::is_whitespace).unwrap_or((header, ""));
+            records.push((id.to_string(), taxonomy.trim().to_string(), Vec::new()));
+        } else if !line.is_empty() {
+            let (_, _, seq) = records.last_mut()
+                .ok_or_else(|| FastqGenError::parse(format!("{} is not a FASTA file: sequence found before the first header.", path.display())))?;
+            seq.extend(line.bytes().map(|base| match base.to_ascii_uppercase() {
+                b'U' => b'T',
+                base => base,
+            }));
+        }
+    }
+    Ok(records)
+}
+
+/// Amplifies every reference of `(id, taxonomy, sequence)` with a primer pair,
+/// both primers 5' to 3' and possibly degenerate (IUPAC codes). Each primer binds
+/// the first site with at most [`MAX_PRIMER_MISMATCHES`] mismatches, the forward
+/// primer on either strand and the reverse primer downstream on the other, at
+/// most 2000 bases in all. The amplicon carries the primers' bases, with
+/// degenerate positions taken from the template. Returns the taxa that amplify
+/// and the IDs of those that do not.
+pub fn amplify_markers(references: Vec<(String, String, Vec<u8>)>, forward: &[u8], reverse: &[u8]) -> (Vec<MarkerTaxon>, Vec<String>) {
+    let mut taxa = Vec::new();
+    let mut missed = Vec::new();
+    for (id, taxonomy, seq) in references {
+        let amplicon = amplify(&seq, forward, reverse).or_else(|| amplify(&reverse_complement(&seq), forward, reverse));
+        match amplicon {
+            Some(amplicon) => taxa.push(MarkerTaxon { id, taxonomy, amplicon }),
+            None => missed.push(id),
+        }
+    }
+    (taxa, missed)
+}
+
+/// The amplicon of `seq` between `forward` and the reverse complement of
+/// `reverse`, if both bind its forward strand in that order.
+fn amplify(seq: &[u8], forward: &[u8], reverse: &[u8]) -> Option<Vec<u8>> {
+    let start = find_site(seq, forward)?;
+    let template = &seq[start..seq.len().min(start + MAX_AMPLICON_LENGTH)];
+    // The reverse primer binds the other strand, read 5' to 3'.
+    let other = reverse_complement(template);
+    let site = find_site(&other, reverse).filter(|&site| other.len() - site - reverse.len() >= forward.len())?;
+    let end = other.len() - site;
+
+    let mut amplicon = resolve(forward, &template[..forward.len()]);
+    amplicon.extend_from_slice(&template[forward.len()..end - reverse.len()]);
+    amplicon.extend(reverse_complement(&resolve(reverse, &other[site..site + reverse.len()])));
+    Some(amplicon)
+}
+
+/// Position of the first site of `seq` that `primer` binds.
+fn find_site(seq: &[u8], primer: &[u8]) -> Option<usize> {
+    seq.windows(primer.len()).position(|site| {
+        primer.iter().zip(site).filter(|&(&code, &base)| !iupac_bases(code).contains(&base)).count() <= MAX_PRIMER_MISMATCHES
+    })
+}
+
+/// The sequence a primer leaves on the amplicon: its own bases, with each
+/// degenerate position resolved to the template's base at `site`.
+fn resolve(primer: &[u8], site: &[u8]) -> Vec<u8> {
+    primer.iter().zip(site).map(|(&code, &base)| if b"ACGT".contains(&code) { code } else { base }).collect()
+}
+
+/// The bases an IUPAC nucleotide code stands for.
+fn iupac_bases(code: u8) -> &'static [u8] {
+    match code {
+        b'A' => b"A",
+        b'C' => b"C",
+        b'G' => b"G",
+        b'T' | b'U' => b"T",
+        b'R' => b"AG",
+        b'Y' => b"CT",
+        b'S' => b"CG",
+        b'W' => b"AT",
+        b'K' => b"GT",
+        b'M' => b"AC",
+        b'B' => b"CGT",
+        b'D' => b"AGT",
+        b'H' => b"ACT",
+        b'V' => b"ACG",
+        b'N' => b"ACGT",
+        _ => b"",
+    }
+}
+
+/// Reads the relative abundance of every taxon from `id<TAB>abundance` lines, in
+/// the format of [`crate::read_expression`]. Taxa missing from the table are
+/// absent from the community; the number of names that match no taxon is
+/// returned alongside. Abundances are scaled to sum to one.
+pub fn read_marker_abundances(path: &Path, taxa: &[MarkerTaxon]) -> Result<(Vec<f64>, usize), FastqGenError> {
+    let reader = open_text(path)
+        .map_err(|e| FastqGenError::io(format!("Failed to open abundance table {}", path.display()), e))?;
+    let (tpm, unknown) = read_abundance_table(reader, path, taxa.len(), |name| taxa.iter().position(|taxon| taxon.id == name))?;
+    Ok((tpm.iter().map(|tpm| tpm / 1e6).collect(), unknown))
+}
+
+/// Draws the relative abundance of every taxon from a log-normal with log-scale
+/// standard deviation `sd`, scaled to sum to one.
+pub fn sample_marker_abundances(taxa: &[MarkerTaxon], rng: &mut impl Rng, sd: f64) -> Result<Vec<f64>, FastqGenError> {
+    let tpm = sample_abundances(taxa.len(), rng, sd)?;
+    Ok(tpm.iter().map(|tpm| tpm / 1e6).collect())
+}
+
+/// The amplicons of a microbial community, drawn by abundance. A fraction
+/// `chimera_rate` of the molecules are PCR chimeras: an incompletely extended
+/// amplicon of one taxon primes on that of another, so the molecule starts as the
+/// first and ends as the second, switching at the same relative position.
+///
+/// Every molecule is identified by a key that [`Self::parents`] turns back into
+/// the taxon it starts as and, for chimeras, the taxon it ends as.
+#[derive(Debug, Clone)]
+pub struct MarkerCommunity {
+    taxa: Vec<MarkerTaxon>,
+    abundances: Vec<f64>,
+    weights: WeightedIndex<f64>,
+    chimera_rate: f64,
+}
+
+impl MarkerCommunity {
+    pub fn new(taxa: Vec<MarkerTaxon>, abundances: Vec<f64>, chimera_rate: f64) -> Result<Self, FastqGenError> {
+        if !(0.0..=1.0).contains(&chimera_rate) {
+            return Err(FastqGenError::invalid("The chimera rate must be between 0 and 1."));
+        }
+        if taxa.is_empty() || abundances.len() != taxa.len() {
+            return Err(FastqGenError::invalid("A community needs one abundance per taxon, and at least one taxon."));
+        }
+        let weights = WeightedIndex::new(&abundances)
+            .map_err(|_| FastqGenError::invalid("At least one taxon must be abundant."))?;
+        Ok(MarkerCommunity { taxa, abundances, weights, chimera_rate })
+    }
+
+    pub fn taxa(&self) -> &[MarkerTaxon] {
+        &self.taxa
+    }
+
+    /// Draws a molecule into `template` and returns its key.
+    pub fn sample(&self, rng: &mut impl Rng, template: &mut Vec<u8>) -> usize {
+        template.clear();
+        let first = self.weights.sample(rng);
+        let amplicon = &self.taxa[first].amplicon;
+        if self.taxa.len() < 2 || !rng.random_bool(self.chimera_rate) {
+            template.extend_from_slice(amplicon);
+            return first;
+        }
+        let second = loop {
+            let second = self.weights.sample(rng);
+            if second != first {
+                break second;
+            }
+        };
+        let other = &self.taxa[second].amplicon;
+        let switch = rng.random_range(0.0..1.0);
+        template.extend_from_slice(&amplicon[..(switch * amplicon.len() as f64) as usize]);
+        template.extend_from_slice(&other[(switch * other.len() as f64) as usize..]);
+        first + self.taxa.len() * (second + 1)
+    }
+
+    /// The taxon a molecule starts as and, for a chimera, the taxon it ends as.
+    pub fn parents(&self, key: usize) -> (usize, Option<usize>) {
+        (key % self.taxa.len(), (key / self.taxa.len()).checked_sub(1))
+    }
+
+    /// Appends a `read<TAB>taxon<TAB>taxonomy<TAB>chimera` truth line: the taxon
+    /// and lineage the molecule of key `key` starts as, and the ID of the taxon a
+    /// chimera ends as, or `.`.
+    pub fn write_read_truth(&self, out: &mut impl Write, id: &str, key: usize) -> io::Result<()> {
+        let (first, second) = self.parents(key);
+        let taxon = &self.taxa[first];
+        let chimera = second.map_or(".", |second| self.taxa[second].id.as_str());
+        let taxonomy = if taxon.taxonomy.is_empty() { "." } else { &taxon.taxonomy };
+        writeln!(out, "{}\t{}\t{}\t{}", id, taxon.id, taxonomy, chimera)
+    }
+
+    /// Writes the relative abundance and true read count of every taxon as a TSV:
+    /// ID, taxonomy, abundance, then one count column per name in `columns`, and a
+    /// last `chimeras` line. `counts` holds the counts of each taxon in turn, one
+    /// per column, followed by those of the chimeras.
+    pub fn write_counts(&self, out: &mut impl Write, columns: &[&str], counts: &[u64]) -> io::Result<()> {
+        writeln!(out, "taxon\ttaxonomy\tabundance\t{}", columns.join("\t"))?;
+        let mut rows = counts.chunks(columns.len());
+        for ((taxon, abundance), counts) in self.taxa.iter().zip(&self.abundances).zip(rows.by_ref()) {
+            let counts: Vec<String> = counts.iter().map(u64::to_string).collect();
+            let taxonomy = if taxon.taxonomy.is_empty() { "." } else { &taxon.taxonomy };
+            writeln!(out, "{}\t{}\t{:.6}\t{}", taxon.id, taxonomy, abundance, counts.join("\t"))?;
+        }
+        if let Some(counts) = rows.next() {
+            let counts: Vec<String> = counts.iter().map(u64::to_string).collect();
+            writeln!(out, "chimeras\t.\t.\t{}", counts.join("\t"))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,144 @@
+use rand::Rng;
+use rand::distr::Distribution;
+use rand::distr::weighted::WeightedIndex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufReader, Write};
+use std::path::Path;
+
+use crate::error::FastqGenError;
+
+/// Highest supported order: 4^8 contexts keep a model small enough to share.
+pub const MAX_MARKOV_ORDER: usize = 8;
+
+const BASES: &[u8; 4] = b"ACGT";
+
+/// An order-k Markov chain over A, C, G and T, so that random sequence has the
+/// k+1-mer composition of the sequence it was trained on.
+#[derive(Debug, Clone)]
+pub struct MarkovModel {
+    order: usize,
+    /// Occurrences of each base after each k-base context, indexed by the context
+    /// encoded two bits per base, first base most significant.
+    counts: Vec<[u64; 4]>,
+    /// Next-base distribution of each context; uniform for contexts never seen.
+    transitions: Vec<WeightedIndex<u64>>,
+    /// Distribution of the first k bases of a sequence.
+    initial: WeightedIndex<u64>,
+}
+
+/// The JSON form of a [`MarkovModel`]: transition counts keyed by context.
+#[derive(Serialize, Deserialize)]
+struct MarkovCounts {
+    order: usize,
+    /// Counts of A, C, G and T after each context seen.
+    transitions: BTreeMap<String, [u64; 4]>,
+}
+
+fn base_code(base: u8) -> Option<usize> {
+    match base.to_ascii_uppercase() {
+        b'A' => Some(0),
+        b'C' => Some(1),
+        b'G' => Some(2),
+        b'T' => Some(3),
+        _ => None,
+    }
+}
+
+impl MarkovModel {
+    /// Counts every k+1-mer of `sequences`; windows spanning a base other than
+    /// A, C, G or T are skipped.
+    pub fn train<'a>(order: usize, sequences: impl IntoIterator<Item = &'a [u8]>) -> Result<Self, FastqGenError> {
+        if order > MAX_MARKOV_ORDER {
+            return Err(FastqGenError::invalid(format!("Markov order must be at most {}.", MAX_MARKOV_ORDER)));
+        }
+        let contexts = 1 << (2 * order);
+        let mut counts = vec![[0; 4]; contexts];
+        for seq in sequences {
+            let mut context = 0;
+            let mut valid = 0;
+            for &base in seq {
+                let Some(code) = base_code(base) else {
+                    valid = 0;
+                    continue;
+                };
+                if valid >= order {
+                    counts[context][code] += 1;
+                }
+                context = (context * 4 + code) % contexts;
+                valid += 1;
+            }
+        }
+        Self::from_counts(order, counts)
+    }
+
+    fn from_counts(order: usize, counts: Vec<[u64; 4]>) -> Result<Self, FastqGenError> {
+        let initial = WeightedIndex::new(counts.iter().map(|next| next.iter().sum::<u64>()))
+            .map_err(|_| FastqGenError::parse(format!("No {}-mers of A, C, G and T to train a Markov model on.", order + 1)))?;
+        let transitions = counts.iter()
+            .map(|next| WeightedIndex::new(next).unwrap_or_else(|_| WeightedIndex::new([1; 4]).unwrap()))
+            .collect();
+        Ok(MarkovModel { order, counts, transitions, initial })
+    }
+
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    pub fn from_json(path: &Path) -> Result<Self, FastqGenError> {
+        let file = File::open(path).map_err(|e| FastqGenError::io(format!("Failed to open Markov model {}", path.display()), e))?;
+        let model: MarkovCounts = serde_json::from_reader(BufReader::new(file))
+            .map_err(|e| FastqGenError::parse(format!("Invalid Markov model {}: {}", path.display(), e)))?;
+        if model.order > MAX_MARKOV_ORDER {
+            return Err(FastqGenError::parse(format!("Markov model {} has order {}, more than the supported {}.", path.display(), model.order, MAX_MARKOV_ORDER)));
+        }
+        let mut counts = vec![[0; 4]; 1 << (2 * model.order)];
+        for (context, next) in model.transitions {
+            let codes: Option<Vec<usize>> = context.bytes().map(base_code).collect();
+            let index = codes.filter(|codes| codes.len() == model.order)
+                .ok_or_else(|| FastqGenError::parse(format!("Markov model {}: context {:?} is not {} bases of A, C, G and T.", path.display(), context, model.order)))?
+                .into_iter()
+                .fold(0, |index, code| index * 4 + code);
+            counts[index] = next;
+        }
+        Self::from_counts(model.order, counts)
+            .map_err(|e| FastqGenError::parse(format!("Markov model {}: {}", path.display(), e)))
+    }
+
+    pub fn write_json(&self, out: &mut impl Write) -> Result<(), FastqGenError> {
+        let transitions = self.counts.iter().enumerate()
+            .filter(|(_, next)| next.iter().any(|&count| count > 0))
+            .map(|(index, next)| (self.context(index), *next))
+            .collect();
+        serde_json::to_writer_pretty(&mut *out, &MarkovCounts { order: self.order, transitions }).map_err(io::Error::from)?;
+        writeln!(out)?;
+        Ok(())
+    }
+
+    /// The bases of the context with index `index`.
+    fn context(&self, index: usize) -> String {
+        (0..self.order).rev().map(|shift| BASES[(index >> (2 * shift)) & 3] as char).collect()
+    }
+
+    /// Draws `length` bases: a k-mer from the training composition, then each base
+    /// given the k before it.
+    pub fn sample(&self, rng: &mut impl Rng, length: usize) -> Vec<u8> {
+        let mut seq = Vec::with_capacity(length);
+        self.sample_into(rng, length, &mut seq);
+        seq
+    }
+
+    /// Like [`Self::sample`], but appends the bases to `out`.
+    pub fn sample_into(&self, rng: &mut impl Rng, length: usize, out: &mut Vec<u8>) {
+        let contexts = self.counts.len();
+        let mut context = self.initial.sample(rng);
+        let end = out.len() + length;
+        out.extend((0..self.order).rev().map(|shift| BASES[(context >> (2 * shift)) & 3]).take(length));
+        while out.len() < end {
+            let code = self.transitions[context].sample(rng);
+            out.push(BASES[code]);
+            context = (context * 4 + code) % contexts;
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use rand::Rng;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::error::FastqGenError;
+use crate::expression::{read_abundance_table, sample_abundances};
+use crate::reference::{Reference, open_text};
+
+/// One genome of a simulated microbial community.
+#[derive(Debug, Clone)]
+pub struct Genome {
+    /// The genome's FASTA file name without its extensions, e.g. `E_coli` for
+    /// `E_coli.fna.gz`.
+    pub name: String,
+    /// Indices of the genome's contigs in the combined reference.
+    pub contigs: Range<usize>,
+    /// Total length of those contigs.
+    pub length: usize,
+}
+
+/// The name of the genome in `path`: its file name without a `.gz` extension and
+/// then a FASTA extension.
+pub fn genome_name(path: &Path) -> String {
+    let name = path.file_name().map_or_else(|| path.display().to_string(), |name| name.to_string_lossy().into_owned());
+    let name = name.strip_suffix(".gz").unwrap_or(&name);
+    [".fasta", ".fa", ".fna", ".fas"]
+        .iter()
+        .find_map(|extension| name.strip_suffix(extension))
+        .unwrap_or(name)
+        .to_string()
+}
+
+/// Loads the genomes of a community, one FASTA each, into one reference of all
+/// their contigs at least `min_length` bases long. Genome names and contig names
+/// must be unique across the community.
+pub fn read_community(paths: &[PathBuf], min_length: usize) -> Result<(Reference, Vec<Genome>), FastqGenError> {
+    let mut sequences = Vec::new();
+    let mut genomes: Vec<Genome> = Vec::with_capacity(paths.len());
+    let mut contig_genomes: HashMap<String, usize> = HashMap::new();
+
+    for path in paths {
+        let name = genome_name(path);
+        if genomes.iter().any(|genome| genome.name == name) {
+            return Err(FastqGenError::parse(format!("Two genomes are named {}; rename one of their files.", name)));
+        }
+        let genome = Reference::from_fasta(path, min_length)?;
+        let start = sequences.len();
+        for (contig, (contig_name, _)) in genome.contigs().enumerate() {
+            if let Some(&other) = contig_genomes.get(contig_name) {
+                return Err(FastqGenError::parse(format!("Contig {} is in both {} and {}.", contig_name, genomes[other].name, name)));
+            }
+            contig_genomes.insert(contig_name.to_string(), genomes.len());
+            sequences.push((contig_name.to_string(), genome.contig_seq(contig).to_vec()));
+        }
+        genomes.push(Genome { name, contigs: start..sequences.len(), length: genome.total_length() });
+    }
+
+    Ok((Reference::from_sequences(sequences, 1)?, genomes))
+}
+
+/// Reads the relative abundance of every genome from `genome<TAB>abundance` lines,
+/// in the format of [`crate::read_expression`]. Genomes missing from the table are
+/// absent from the community; the number of names that match no genome is
+/// returned alongside. Abundances are scaled to sum to one.
+pub fn read_abundances(path: &Path, genomes: &[Genome]) -> Result<(Vec<f64>, usize), FastqGenError> {
+    let reader = open_text(path)
+        .map_err(|e| FastqGenError::io(format!("Failed to open abundance table {}", path.display()), e))?;
+    let (tpm, unknown) = read_abundance_table(reader, path, genomes.len(), |name| genomes.iter().position(|genome| genome.name == name))?;
+    Ok((tpm.iter().map(|tpm| tpm / 1e6).collect(), unknown))
+}
+
+/// Draws the relative abundance of every genome from a log-normal with log-scale
+/// standard deviation `sd`, scaled to sum to one.
+pub fn sample_community(genomes: &[Genome], rng: &mut impl Rng, sd: f64) -> Result<Vec<f64>, FastqGenError> {
+    let tpm = sample_abundances(genomes.len(), rng, sd)?;
+    Ok(tpm.iter().map(|tpm| tpm / 1e6).collect())
+}
+
+/// Writes the relative abundance and true read count of every genome as a TSV:
+/// genome, length, abundance, then one count column per name in `columns`.
+/// `contig_counts` holds the counts of each contig of the combined reference in
+/// turn, one per column.
+pub fn write_community_counts(out: &mut impl Write, genomes: &[Genome], abundances: &[f64], columns: &[&str], contig_counts: &[u64]) -> io::Result<()> {
+    writeln!(out, "genome\tlength\tabundance\t{}", columns.join("\t"))?;
+    for (genome, abundance) in genomes.iter().zip(abundances) {
+        let mut counts = vec![0; columns.len()];
+        for contig in genome.contigs.clone() {
+            for (total, count) in counts.iter_mut().zip(&contig_counts[contig * columns.len()..]) {
+                *total += count;
+            }
+        }
+        let counts: Vec<String> = counts.iter().map(u64::to_string).collect();
+        writeln!(out, "{}\t{}\t{:.6}\t{}", genome.name, genome.length, abundance, counts.join("\t"))?;
+    }
+    Ok(())
+}
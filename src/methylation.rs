@@ -0,0 +1,157 @@
+use rand::Rng;
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use crate::error::FastqGenError;
+use crate::reference::{Fragment, Reference, open_text};
+use crate::seq::{complement, reverse_complement};
+
+/// Sequence context of a cytosine, read 5' to 3' on its own strand: `CG`, `CHG`
+/// or `CHH`, where H is any base but G.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CytosineContext {
+    Cg,
+    Chg,
+    Chh,
+}
+
+impl CytosineContext {
+    /// The context of a cytosine followed by `next` and then `after` on its strand.
+    fn of(next: Option<u8>, after: Option<u8>) -> Self {
+        match (next, after) {
+            (Some(b'G'), _) => CytosineContext::Cg,
+            (_, Some(b'G')) => CytosineContext::Chg,
+            _ => CytosineContext::Chh,
+        }
+    }
+}
+
+/// Methylation levels of the cytosines of a reference, for bisulfite sequencing.
+/// Each cytosine is methylated with the probability its context is given, or the
+/// level a bedGraph sets for its position.
+#[derive(Debug, Clone)]
+pub struct Methylation {
+    /// Methylation probability in CG, CHG and CHH context.
+    rates: [f64; 3],
+    /// Levels set for individual reference positions, per contig.
+    sites: Vec<HashMap<usize, f64>>,
+}
+
+impl Methylation {
+    /// Methylates cytosines of `reference` with probability `cg`, `chg` or `chh`
+    /// by context.
+    pub fn new(reference: &Reference, cg: f64, chg: f64, chh: f64) -> Result<Self, FastqGenError> {
+        if [cg, chg, chh].iter().any(|rate| !(0.0..=1.0).contains(rate)) {
+            return Err(FastqGenError::invalid("Methylation rates must be between 0 and 1."));
+        }
+        Ok(Methylation { rates: [cg, chg, chh], sites: vec![HashMap::new(); reference.contigs().count()] })
+    }
+
+    /// Sets the level of the cytosines listed in a bedGraph of
+    /// `contig<TAB>start<TAB>end<TAB>percent` lines (0-based, half-open, as written
+    /// by Bismark), optionally gzipped. A position applies to the cytosine on
+    /// whichever strand has one there. `track` lines are skipped. Returns the number
+    /// of lines on contigs that are not in the reference, which are ignored.
+    pub fn read_bedgraph(&mut self, path: &Path, reference: &Reference) -> Result<usize, FastqGenError> {
+        let reader = open_text(path)
+            .map_err(|e| FastqGenError::io(format!("Failed to open methylation bedGraph {}", path.display()), e))?;
+        let mut unknown = 0;
+
+        for (line_number, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.starts_with('#') || line.starts_with("track") || line.trim().is_empty() {
+                continue;
+            }
+            let location = format!("{}:{}", path.display(), line_number + 1);
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 4 {
+                return Err(FastqGenError::parse(format!("{}: expected contig, start, end and methylation percentage.", location)));
+            }
+            let Some(contig) = reference.contig_index(fields[0]) else {
+                unknown += 1;
+                continue;
+            };
+            let length = reference.contig_seq(contig).len();
+            let start = fields[1].parse::<usize>().ok().filter(|&start| start < length)
+                .ok_or_else(|| FastqGenError::parse(format!("{}: invalid start {}.", location, fields[1])))?;
+            let end = fields[2].parse::<usize>().ok().filter(|&end| end > start && end <= length)
+                .ok_or_else(|| FastqGenError::parse(format!("{}: invalid end {} for {} of length {}.", location, fields[2], fields[0], length)))?;
+            let level = fields[3].parse::<f64>().ok().filter(|percent| (0.0..=100.0).contains(percent))
+                .ok_or_else(|| FastqGenError::parse(format!("{}: methylation percentage must be between 0 and 100, found {}.", location, fields[3])))?;
+            for position in start..end {
+                self.sites[contig].insert(position, level / 100.0);
+            }
+        }
+        Ok(unknown)
+    }
+
+    /// The context of the cytosine at `position` of `seq`, on the forward strand
+    /// for a C and on the reverse strand for a G; `None` for other bases.
+    fn context(seq: &[u8], position: usize) -> Option<CytosineContext> {
+        match seq[position] {
+            b'C' => Some(CytosineContext::of(seq.get(position + 1).copied(), seq.get(position + 2).copied())),
+            b'G' => {
+                let upstream = |offset: usize| position.checked_sub(offset).map(|position| complement(seq[position]));
+                Some(CytosineContext::of(upstream(1), upstream(2)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Methylation probability of a cytosine in `context` at reference contig and
+    /// position `site`, if it maps onto the reference.
+    fn level(&self, site: Option<(usize, usize)>, context: CytosineContext) -> f64 {
+        site
+            .and_then(|(contig, position)| self.sites[contig].get(&position))
+            .copied()
+            .unwrap_or(self.rates[context as usize])
+    }
+
+    /// Bisulfite-converts `fragment` as read from its forward strand, or from the
+    /// reverse strand when `reverse`, as in a directional library: every cytosine
+    /// of that strand that is not methylated reads as thymine. Returns the
+    /// converted strand 5' to 3'.
+    pub fn convert(&self, rng: &mut impl Rng, reference: &Reference, fragment: &Fragment, reverse: bool) -> Vec<u8> {
+        let seq = reference.haplotype_seq(fragment.haplotype, fragment.contig);
+        let (cytosine, converted) = if reverse { (b'G', b'A') } else { (b'C', b'T') };
+        let mut strand = fragment.seq.clone();
+        let has_sites = self.sites.iter().any(|sites| !sites.is_empty());
+        for (offset, base) in strand.iter_mut().enumerate() {
+            if *base != cytosine {
+                continue;
+            }
+            let position = fragment.start + offset;
+            let context = Self::context(seq, position).expect("fragment bases match the haplotype");
+            let site = if has_sites {
+                reference.reference_position(fragment.haplotype, fragment.contig, position)
+            } else {
+                None
+            };
+            if !rng.random_bool(self.level(site, context)) {
+                *base = converted;
+            }
+        }
+        if reverse { reverse_complement(&strand) } else { strand }
+    }
+
+    /// Writes the true methylation level of every CpG cytosine of the reference,
+    /// and of every other cytosine given a level by a bedGraph, in the bedGraph
+    /// format [`Self::read_bedgraph`] reads.
+    pub fn write_bedgraph(&self, out: &mut impl Write, reference: &Reference) -> io::Result<()> {
+        writeln!(out, "track type=bedGraph")?;
+        for (contig, (name, _)) in reference.contigs().enumerate() {
+            let seq = reference.contig_seq(contig);
+            for position in 0..seq.len() {
+                let Some(context) = Self::context(seq, position) else {
+                    continue;
+                };
+                if context == CytosineContext::Cg || self.sites[contig].contains_key(&position) {
+                    let level = self.level(Some((contig, position)), context);
+                    writeln!(out, "{}\t{}\t{}\t{}", name, position, position + 1, level * 100.0)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
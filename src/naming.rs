@@ -0,0 +1,334 @@
+use std::fmt::Write;
+
+use crate::error::FastqGenError;
+
+/// Digits simple read names are zero-padded to by default.
+pub const DEFAULT_ID_WIDTH: usize = 10;
+
+/// How read names, and the comment written after them, are formatted.
+#[derive(Debug, Clone)]
+pub enum NameFormat {
+    /// `READ_0000000042`, with the index zero-padded to `width` digits and a `/1`
+    /// or `/2` comment on paired reads.
+    Simple { width: usize },
+    /// Casava 1.8+ style, e.g. `SIM:1:FCX:1:1101:10123:20456 1:N:0:ATCACG`.
+    Illumina(IlluminaNames),
+    /// Names built from a template, with `/1` or `/2` comments like `Simple`.
+    Template(IdTemplate),
+}
+
+/// Run-level fields of Casava 1.8+ read names. Tile and x/y coordinates are
+/// simulated per read.
+#[derive(Debug, Clone)]
+pub struct IlluminaNames {
+    pub instrument: String,
+    pub run: u32,
+    pub flowcell: String,
+    /// First lane of the run.
+    pub lane: u32,
+    /// Lanes the reads are dealt over in turn, numbered up from `lane`.
+    pub lanes: u32,
+    pub index: String,
+}
+
+impl Default for IlluminaNames {
+    fn default() -> Self {
+        IlluminaNames {
+            instrument: String::from("SIM"),
+            run: 1,
+            flowcell: String::from("FCX"),
+            lane: 1,
+            lanes: 1,
+            index: String::from("ATCACG"),
+        }
+    }
+}
+
+/// A read name template such as `SIM_{run}_{index:08}_{barcode}`. Placeholders
+/// are `{index}`, `{instrument}`, `{run}`, `{flowcell}`, `{lane}`, `{tile}`,
+/// `{x}` and `{y}` as in Illumina names, `{umi}`, `{barcode}`, and the origin of
+/// the read (or pair) on the reference: `{contig}`, `{start}` and `{end}`
+/// (1-based, inclusive) and `{strand}`. Numbers can be zero-padded to a width,
+/// as in `{index:08}`, and `{{` and `}}` stand for literal braces. Values a read
+/// does not have, such as the origin of a random read, are left empty.
+#[derive(Debug, Clone)]
+pub struct IdTemplate {
+    parts: Vec<TemplatePart>,
+    /// Run-level fields and lanes of the Illumina placeholders.
+    names: IlluminaNames,
+}
+
+#[derive(Debug, Clone)]
+enum TemplatePart {
+    Text(String),
+    /// A placeholder, with the width numbers are zero-padded to.
+    Field(TemplateField, usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TemplateField {
+    Index,
+    Instrument,
+    Run,
+    Flowcell,
+    Lane,
+    Tile,
+    X,
+    Y,
+    Umi,
+    Barcode,
+    Contig,
+    Start,
+    End,
+    Strand,
+}
+
+impl TemplateField {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "index" => TemplateField::Index,
+            "instrument" => TemplateField::Instrument,
+            "run" => TemplateField::Run,
+            "flowcell" => TemplateField::Flowcell,
+            "lane" => TemplateField::Lane,
+            "tile" => TemplateField::Tile,
+            "x" => TemplateField::X,
+            "y" => TemplateField::Y,
+            "umi" => TemplateField::Umi,
+            "barcode" => TemplateField::Barcode,
+            "contig" => TemplateField::Contig,
+            "start" => TemplateField::Start,
+            "end" => TemplateField::End,
+            "strand" => TemplateField::Strand,
+            _ => return None,
+        })
+    }
+
+    fn is_numeric(self) -> bool {
+        matches!(self, TemplateField::Index | TemplateField::Run | TemplateField::Lane | TemplateField::Tile | TemplateField::X | TemplateField::Y | TemplateField::Start | TemplateField::End)
+    }
+}
+
+/// The values of one read's [`IdTemplate`] placeholders, beyond those derived
+/// from its index.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReadNameFields<'a> {
+    pub index: u64,
+    pub umi: Option<&'a [u8]>,
+    /// Sample barcode, or cell barcode of single-cell reads.
+    pub barcode: Option<&'a str>,
+    /// Contig, 0-based half-open span and strand (`true` for reverse) the read
+    /// or pair came from.
+    pub origin: Option<(&'a str, usize, usize, bool)>,
+}
+
+impl IdTemplate {
+    /// Parses `template`, taking the run-level fields from `names`.
+    pub fn parse(template: &str, names: IlluminaNames) -> Result<Self, FastqGenError> {
+        let invalid = |reason: &str| FastqGenError::invalid(format!("Invalid read name template {:?}: {}.", template, reason));
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '}' => return Err(invalid("unmatched }")),
+                '{' => {
+                    let mut placeholder = String::new();
+                    let mut closed = false;
+                    for c in chars.by_ref() {
+                        if c == '}' {
+                            closed = true;
+                            break;
+                        }
+                        placeholder.push(c);
+                    }
+                    if !closed {
+                        return Err(invalid("unmatched {"));
+                    }
+                    let (name, width) = placeholder.split_once(':').unwrap_or((&placeholder, ""));
+                    let field = TemplateField::parse(name).ok_or_else(|| invalid(&format!("unknown placeholder {{{}}}", name)))?;
+                    let width = match width {
+                        "" => 0,
+                        width if field.is_numeric() => width.parse().map_err(|_| invalid(&format!("width {:?} of {{{}}} is not a number", width, name)))?,
+                        _ => return Err(invalid(&format!("{{{}}} is not a number and cannot be padded", name))),
+                    };
+                    if !text.is_empty() {
+                        parts.push(TemplatePart::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(TemplatePart::Field(field, width));
+                }
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(TemplatePart::Text(text));
+        }
+        let has = |field| parts.iter().any(|part| matches!(part, TemplatePart::Field(f, _) if *f == field));
+        let unique = has(TemplateField::Index) || [TemplateField::Tile, TemplateField::X, TemplateField::Y].into_iter().all(has);
+        if !unique {
+            return Err(invalid("names would not be unique without {index}, or {tile}, {x} and {y}"));
+        }
+        Ok(IdTemplate { parts, names })
+    }
+
+    /// Appends the name of the read described by `fields` to `out`.
+    pub fn write(&self, fields: &ReadNameFields<'_>, out: &mut String) {
+        let lanes = self.names.lanes.max(1) as u64;
+        let (tile, x, y) = IlluminaNames::coordinates(fields.index / lanes);
+        for part in &self.parts {
+            let (field, width) = match part {
+                TemplatePart::Text(text) => {
+                    out.push_str(text);
+                    continue;
+                }
+                TemplatePart::Field(field, width) => (*field, *width),
+            };
+            let number = match field {
+                TemplateField::Index => Some(fields.index),
+                TemplateField::Run => Some(self.names.run as u64),
+                TemplateField::Lane => Some(self.names.lane as u64 + fields.index % lanes),
+                TemplateField::Tile => Some(tile),
+                TemplateField::X => Some(x),
+                TemplateField::Y => Some(y),
+                TemplateField::Start => fields.origin.map(|(_, start, _, _)| start as u64 + 1),
+                TemplateField::End => fields.origin.map(|(_, _, end, _)| end as u64),
+                TemplateField::Instrument => {
+                    out.push_str(&self.names.instrument);
+                    continue;
+                }
+                TemplateField::Flowcell => {
+                    out.push_str(&self.names.flowcell);
+                    continue;
+                }
+                TemplateField::Umi => {
+                    out.push_str(std::str::from_utf8(fields.umi.unwrap_or_default()).expect("UMI bases are ASCII"));
+                    continue;
+                }
+                TemplateField::Barcode => {
+                    out.push_str(fields.barcode.unwrap_or_default());
+                    continue;
+                }
+                TemplateField::Contig => {
+                    out.push_str(fields.origin.map_or("", |(contig, ..)| contig));
+                    continue;
+                }
+                TemplateField::Strand => {
+                    out.push_str(fields.origin.map_or("", |(.., reverse)| if reverse { "-" } else { "+" }));
+                    continue;
+                }
+            };
+            if let Some(number) = number {
+                write!(out, "{:0width$}", number, width = width).unwrap();
+            }
+        }
+    }
+}
+
+const X_RANGE: (u64, u64) = (1000, 30000);
+const Y_RANGE: (u64, u64) = (1000, 40000);
+/// Tiles per lane: 2 surfaces x 2 swaths x 16 tiles.
+const TILES: u64 = 64;
+/// Multiplier of the affine bijection that scatters reads over the lane. It is
+/// prime and coprime to the number of tile positions, so no two reads share
+/// coordinates.
+const SCATTER: u64 = 2_654_435_761;
+const SCATTER_OFFSET: u64 = 123_456_789;
+
+impl IlluminaNames {
+    /// Deterministically places read `index` pseudo-randomly on a tile and its x/y
+    /// grid. Tiles are numbered `<surface><swath><tile>`: 1101..1116, 1201..1216,
+    /// 2101..2116 and 2201..2216.
+    fn coordinates(index: u64) -> (u64, u64, u64) {
+        let width = X_RANGE.1 - X_RANGE.0;
+        let positions = (TILES * width * (Y_RANGE.1 - Y_RANGE.0)) as u128;
+        let index = index as u128;
+        // Past the capacity of the lane, keep counting upward so names stay unique.
+        let scattered = ((index % positions * SCATTER as u128 + SCATTER_OFFSET as u128) % positions + index / positions * positions) as u64;
+
+        let tile_index = scattered % TILES;
+        let tile = (tile_index / 32 + 1) * 1000 + (tile_index / 16 % 2 + 1) * 100 + tile_index % 16 + 1;
+        let position = scattered / TILES;
+
+        (tile, X_RANGE.0 + position % width, Y_RANGE.0 + position / width)
+    }
+
+    /// Appends the name of an optical duplicate of read `index` to `out`: in the
+    /// same lane and tile, `offset` pixels in x and y away from it, so that
+    /// duplicate markers such as Picard's classify it as optical.
+    pub fn write_optical_duplicate_id(&self, index: u64, offset: (i64, i64), out: &mut String) {
+        let lanes = self.lanes.max(1) as u64;
+        let lane = self.lane as u64 + index % lanes;
+        let (tile, x, y) = IlluminaNames::coordinates(index / lanes);
+        let shift = |coordinate: u64, offset: i64, range: (u64, u64)| coordinate.saturating_add_signed(offset).clamp(range.0, range.1 - 1);
+        let (x, y) = (shift(x, offset.0, X_RANGE), shift(y, offset.1, Y_RANGE));
+        write!(out, "{}:{}:{}:{}:{}:{}:{}", self.instrument, self.run, self.flowcell, lane, tile, x, y).unwrap();
+    }
+}
+
+impl Default for NameFormat {
+    fn default() -> Self {
+        NameFormat::Simple { width: DEFAULT_ID_WIDTH }
+    }
+}
+
+impl NameFormat {
+    pub fn read_id(&self, index: u64) -> String {
+        let mut id = String::new();
+        self.write_read_id(index, &mut id);
+        id
+    }
+
+    /// Appends the name of read `index` to `out`, without allocating once `out`
+    /// has room for it.
+    pub fn write_read_id(&self, index: u64, out: &mut String) {
+        match self {
+            NameFormat::Simple { width } => write!(out, "READ_{:0width$}", index, width = width).unwrap(),
+            NameFormat::Illumina(names) => {
+                let lanes = names.lanes.max(1) as u64;
+                let lane = names.lane as u64 + index % lanes;
+                let (tile, x, y) = IlluminaNames::coordinates(index / lanes);
+                write!(out, "{}:{}:{}:{}:{}:{}:{}", names.instrument, names.run, names.flowcell, lane, tile, x, y).unwrap();
+            }
+            NameFormat::Template(template) => template.write(&ReadNameFields { index, ..ReadNameFields::default() }, out),
+        }
+    }
+
+    /// The comment following the read name for `mate` 1 or 2, or `None` for
+    /// single-end reads.
+    pub fn comment(&self, mate: Option<u8>) -> Option<String> {
+        match self {
+            NameFormat::Simple { .. } | NameFormat::Template(_) => mate.map(|mate| format!("/{}", mate)),
+            NameFormat::Illumina(names) => Some(format!("{}:N:0:{}", mate.unwrap_or(1), names.index)),
+        }
+    }
+
+    /// As [`Self::comment`], but reporting the observed `barcode` of the read
+    /// instead of the run's fixed index.
+    pub fn comment_with_barcode(&self, mate: Option<u8>, barcode: &str) -> Option<String> {
+        match self {
+            NameFormat::Simple { .. } | NameFormat::Template(_) => self.comment(mate),
+            NameFormat::Illumina(_) => Some(format!("{}:N:0:{}", mate.unwrap_or(1), barcode)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_unmatched_braces() {
+        let names = IlluminaNames::default();
+        assert!(IdTemplate::parse("ab{index}", names.clone()).is_ok());
+        assert!(IdTemplate::parse("ab{index", names.clone()).is_err());
+        assert!(IdTemplate::parse("ab{index}}", names).is_err());
+    }
+}
@@ -0,0 +1,281 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use rayon::ThreadPool;
+use rayon::prelude::*;
+
+use crate::bam::{BGZF_BLOCK_SIZE, BGZF_EOF, BgzfWriter, bgzf_block};
+
+/// Uncompressed bytes in each gzip member written by a [`ParallelWriter`].
+const GZIP_BLOCK_SIZE: usize = 1 << 20;
+
+/// Records a [`BatchedFastqWriter`] formats before handing them to its writer.
+const WRITE_BATCH_RECORDS: usize = 4096;
+
+/// Appends one FASTQ record. The optional `comment` (e.g. `/1`) is written after
+/// the read name, separated by a space.
+pub fn write_fastq_record(out: &mut Vec<u8>, id: &str, comment: Option<&str>, seq: &[u8], quality: &[u8]) {
+    out.push(b'@');
+    out.extend_from_slice(id.as_bytes());
+    if let Some(comment) = comment {
+        out.push(b' ');
+        out.extend_from_slice(comment.as_bytes());
+    }
+    out.push(b'\n');
+    out.extend_from_slice(seq);
+    out.extend_from_slice(b"\n+\n");
+    out.extend_from_slice(quality);
+    out.push(b'\n');
+}
+
+/// How an output is compressed, with the compression level where there is one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputCompression {
+    None,
+    /// Gzip at level 0-9.
+    Gzip(u32),
+    /// Blocked gzip at level 0-9: readable by gzip, and indexable by htslib tools.
+    Bgzf(u32),
+    /// Zstandard at level 1-22.
+    Zstd(i32),
+}
+
+impl OutputCompression {
+    /// Compression implied by a file name: BGZF for `.gz`, so that compressed VCF
+    /// and BED files can be indexed, Zstandard for `.zst` and none otherwise.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("gz" | "bgz") => OutputCompression::Bgzf(6),
+            Some("zst") => OutputCompression::Zstd(3),
+            _ => OutputCompression::None,
+        }
+    }
+
+    /// Extension of FASTQ files written with this compression.
+    pub fn fastq_extension(self) -> &'static str {
+        match self {
+            OutputCompression::None => "fastq",
+            OutputCompression::Gzip(_) | OutputCompression::Bgzf(_) => "fastq.gz",
+            OutputCompression::Zstd(_) => "fastq.zst",
+        }
+    }
+}
+
+/// Compresses its input as independent blocks on a thread pool, as pigz does, and
+/// writes them out in order. Blocks are BGZF blocks, or gzip members of 1 MiB of
+/// input each, which gzip tools read as a single stream.
+pub struct ParallelWriter<W: Write> {
+    inner: W,
+    pool: Arc<ThreadPool>,
+    bgzf: bool,
+    level: Compression,
+    buffer: Vec<u8>,
+    /// Whether any block has been written yet.
+    started: bool,
+}
+
+impl<W: Write> ParallelWriter<W> {
+    pub fn gzip(inner: W, level: Compression, pool: Arc<ThreadPool>) -> Self {
+        ParallelWriter { inner, pool, bgzf: false, level, buffer: Vec::new(), started: false }
+    }
+
+    pub fn bgzf(inner: W, level: Compression, pool: Arc<ThreadPool>) -> Self {
+        ParallelWriter { inner, pool, bgzf: true, level, buffer: Vec::new(), started: false }
+    }
+
+    fn block_size(&self) -> usize {
+        if self.bgzf { BGZF_BLOCK_SIZE } else { GZIP_BLOCK_SIZE }
+    }
+
+    /// Compresses and writes out the full blocks in the buffer, and with `all` the
+    /// partial last one too.
+    fn compress(&mut self, all: bool) -> io::Result<()> {
+        let block_size = self.block_size();
+        let end = if all { self.buffer.len() } else { self.buffer.len() / block_size * block_size };
+        let (bgzf, level, data) = (self.bgzf, self.level, &self.buffer[..end]);
+        let blocks: Vec<Vec<u8>> = self.pool.install(|| {
+            data.par_chunks(block_size)
+                .map(|block| if bgzf { bgzf_block(block, level) } else { gzip_member(block, level) })
+                .collect::<io::Result<_>>()
+        })?;
+        for block in &blocks {
+            self.inner.write_all(block)?;
+        }
+        self.started |= !blocks.is_empty();
+        self.buffer.drain(..end);
+        Ok(())
+    }
+
+    /// Writes any buffered data and, for BGZF, the end-of-file marker, returning
+    /// the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.compress(true)?;
+        if self.bgzf {
+            self.inner.write_all(&BGZF_EOF)?;
+        } else if !self.started {
+            // An empty gzip file still needs one member.
+            self.inner.write_all(&gzip_member(&[], self.level)?)?;
+        }
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for ParallelWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        // Gather a couple of blocks per thread before compressing them together.
+        if self.buffer.len() >= 2 * self.block_size() * self.pool.current_num_threads() {
+            self.compress(false)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn gzip_member(data: &[u8], level: Compression) -> io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), level);
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+/// A FASTQ output file or standard output, optionally compressed. BGZF streams
+/// also carry BAM records.
+pub enum FastqWriter {
+    Plain(BufWriter<Box<dyn Write>>),
+    Gzip(GzEncoder<BufWriter<Box<dyn Write>>>),
+    Bgzf(BgzfWriter<BufWriter<Box<dyn Write>>>),
+    Zstd(zstd::Encoder<'static, BufWriter<Box<dyn Write>>>),
+    Parallel(ParallelWriter<BufWriter<Box<dyn Write>>>),
+}
+
+impl FastqWriter {
+    pub fn create(path: impl AsRef<Path>, compression: OutputCompression) -> io::Result<Self> {
+        Self::new(Box::new(File::create(path)?), compression, None)
+    }
+
+    pub fn stdout(compression: OutputCompression) -> io::Result<Self> {
+        Self::new(Box::new(io::stdout()), compression, None)
+    }
+
+    /// Like [`FastqWriter::create`], but gzip and BGZF blocks are compressed on
+    /// `pool`, and Zstandard with one worker per thread of the pool.
+    pub fn create_parallel(path: impl AsRef<Path>, compression: OutputCompression, pool: Arc<ThreadPool>) -> io::Result<Self> {
+        Self::new(Box::new(File::create(path)?), compression, Some(pool))
+    }
+
+    pub fn stdout_parallel(compression: OutputCompression, pool: Arc<ThreadPool>) -> io::Result<Self> {
+        Self::new(Box::new(io::stdout()), compression, Some(pool))
+    }
+
+    /// Compresses like [`FastqWriter::create`] (on `pool`, when given, like
+    /// [`FastqWriter::create_parallel`]) but discards the output, for benchmarks.
+    pub fn sink(compression: OutputCompression, pool: Option<Arc<ThreadPool>>) -> io::Result<Self> {
+        Self::new(Box::new(io::sink()), compression, pool)
+    }
+
+    /// A BGZF-compressed file, or standard output when `path` is `None`.
+    pub fn bgzf(path: Option<&str>) -> io::Result<Self> {
+        let inner: Box<dyn Write> = match path {
+            Some(path) => Box::new(File::create(path)?),
+            None => Box::new(io::stdout()),
+        };
+        Ok(FastqWriter::Bgzf(BgzfWriter::new(BufWriter::new(inner))))
+    }
+
+    fn new(inner: Box<dyn Write>, compression: OutputCompression, pool: Option<Arc<ThreadPool>>) -> io::Result<Self> {
+        let inner = BufWriter::new(inner);
+        Ok(match (compression, pool) {
+            (OutputCompression::None, _) => FastqWriter::Plain(inner),
+            (OutputCompression::Gzip(level), None) => FastqWriter::Gzip(GzEncoder::new(inner, Compression::new(level))),
+            (OutputCompression::Gzip(level), Some(pool)) => FastqWriter::Parallel(ParallelWriter::gzip(inner, Compression::new(level), pool)),
+            (OutputCompression::Bgzf(level), None) => FastqWriter::Bgzf(BgzfWriter::with_level(inner, Compression::new(level))),
+            (OutputCompression::Bgzf(level), Some(pool)) => FastqWriter::Parallel(ParallelWriter::bgzf(inner, Compression::new(level), pool)),
+            (OutputCompression::Zstd(level), pool) => {
+                let mut encoder = zstd::Encoder::new(inner, level)?;
+                if let Some(pool) = pool {
+                    encoder.multithread(pool.current_num_threads() as u32)?;
+                }
+                FastqWriter::Zstd(encoder)
+            }
+        })
+    }
+
+    /// Flushes buffered output and, for compressed streams, writes the trailer.
+    pub fn finish(self) -> io::Result<()> {
+        match self {
+            FastqWriter::Plain(mut w) => w.flush(),
+            FastqWriter::Gzip(w) => w.finish()?.flush(),
+            FastqWriter::Bgzf(w) => w.finish()?.flush(),
+            FastqWriter::Zstd(w) => w.finish()?.flush(),
+            FastqWriter::Parallel(w) => w.finish()?.flush(),
+        }
+    }
+}
+
+impl Write for FastqWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            FastqWriter::Plain(w) => w.write(buf),
+            FastqWriter::Gzip(w) => w.write(buf),
+            FastqWriter::Bgzf(w) => w.write(buf),
+            FastqWriter::Zstd(w) => w.write(buf),
+            FastqWriter::Parallel(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            FastqWriter::Plain(w) => w.flush(),
+            FastqWriter::Gzip(w) => w.flush(),
+            FastqWriter::Bgzf(w) => w.flush(),
+            FastqWriter::Zstd(w) => w.flush(),
+            FastqWriter::Parallel(w) => w.flush(),
+        }
+    }
+}
+
+/// Formats FASTQ records into a buffer and writes them a few thousand at a time,
+/// so that writes (and parallel compression) see large blocks rather than
+/// one record each.
+pub struct BatchedFastqWriter {
+    writer: FastqWriter,
+    buffer: Vec<u8>,
+    records: usize,
+}
+
+impl BatchedFastqWriter {
+    pub fn new(writer: FastqWriter) -> Self {
+        BatchedFastqWriter { writer, buffer: Vec::new(), records: 0 }
+    }
+
+    /// Appends one record, as [`write_fastq_record`] without a comment, writing
+    /// the batch once it is full.
+    pub fn write_record(&mut self, id: &str, seq: &[u8], quality: &[u8]) -> io::Result<()> {
+        write_fastq_record(&mut self.buffer, id, None, seq, quality);
+        self.records += 1;
+        if self.records == WRITE_BATCH_RECORDS {
+            self.write_batch()?;
+        }
+        Ok(())
+    }
+
+    fn write_batch(&mut self) -> io::Result<()> {
+        self.writer.write_all(&self.buffer)?;
+        self.buffer.clear();
+        self.records = 0;
+        Ok(())
+    }
+
+    /// Writes the last, partial batch and finishes the underlying writer.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.write_batch()?;
+        self.writer.finish()
+    }
+}
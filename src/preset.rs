@@ -0,0 +1,123 @@
+use crate::length::ReadLengths;
+use crate::quality::QualityBins;
+
+/// Typical settings for one sequencing instrument: read lengths, quality profile
+/// and binning, error rates and the rate of poly-G artifacts. Two-colour
+/// instruments (NovaSeq, NextSeq) report G when no signal is detected, so some
+/// reads end in a run of high-quality Gs.
+#[derive(Debug, Clone)]
+pub struct Preset {
+    pub read_lengths: ReadLengths,
+    /// Whether the platform only produces single-end reads.
+    pub single_end: bool,
+    /// Mean Phred score at the 5' and 3' ends of each read, and per-position noise.
+    pub quality_start: f64,
+    pub quality_end: f64,
+    pub quality_noise: f64,
+    pub quality_bins: Option<QualityBins>,
+    pub sub_rate: f64,
+    pub ins_rate: f64,
+    pub del_rate: f64,
+    /// Fraction of reads whose signal drops out partway, leaving a poly-G tail.
+    pub poly_g_rate: f64,
+}
+
+impl Preset {
+    /// MiSeq v3 chemistry: long, unbinned four-colour reads.
+    pub fn miseq() -> Self {
+        Preset {
+            read_lengths: ReadLengths::Fixed(250),
+            single_end: false,
+            quality_start: 37.0,
+            quality_end: 27.0,
+            quality_noise: 3.0,
+            quality_bins: None,
+            sub_rate: 0.002,
+            ins_rate: 0.00005,
+            del_rate: 0.00005,
+            poly_g_rate: 0.0,
+        }
+    }
+
+    /// HiSeq 2500 with 8-level quality binning.
+    pub fn hiseq() -> Self {
+        Preset {
+            read_lengths: ReadLengths::Fixed(125),
+            single_end: false,
+            quality_start: 38.0,
+            quality_end: 30.0,
+            quality_noise: 2.0,
+            quality_bins: Some(QualityBins::illumina_8_level()),
+            sub_rate: 0.002,
+            ins_rate: 0.00002,
+            del_rate: 0.00002,
+            poly_g_rate: 0.0,
+        }
+    }
+
+    /// NovaSeq 6000: 4-level binned qualities and two-colour poly-G tails.
+    pub fn novaseq() -> Self {
+        Preset {
+            read_lengths: ReadLengths::Fixed(150),
+            single_end: false,
+            quality_start: 37.0,
+            quality_end: 31.0,
+            quality_noise: 3.0,
+            quality_bins: Some(QualityBins::novaseq()),
+            sub_rate: 0.001,
+            ins_rate: 0.00002,
+            del_rate: 0.00002,
+            poly_g_rate: 0.005,
+        }
+    }
+
+    /// NextSeq 500/550: noisier two-colour chemistry with more poly-G tails.
+    pub fn nextseq() -> Self {
+        Preset {
+            read_lengths: ReadLengths::Fixed(150),
+            single_end: false,
+            quality_start: 35.0,
+            quality_end: 26.0,
+            quality_noise: 4.0,
+            quality_bins: Some(QualityBins::nextseq()),
+            sub_rate: 0.003,
+            ins_rate: 0.00005,
+            del_rate: 0.00005,
+            poly_g_rate: 0.01,
+        }
+    }
+
+    /// Oxford Nanopore R9-style reads: long lognormal lengths (median about 10 kb),
+    /// low qualities and an indel-heavy error profile.
+    pub fn nanopore() -> Self {
+        Preset {
+            read_lengths: ReadLengths::lognormal(13_000.0, 10_000.0, 200, 200_000).unwrap(),
+            single_end: true,
+            quality_start: 14.0,
+            quality_end: 12.0,
+            quality_noise: 4.0,
+            quality_bins: None,
+            sub_rate: 0.03,
+            ins_rate: 0.02,
+            del_rate: 0.03,
+            poly_g_rate: 0.0,
+        }
+    }
+
+    /// PacBio HiFi circular consensus reads: size-selected 10-25 kb lengths,
+    /// Q30+ qualities and very few residual errors.
+    pub fn hifi() -> Self {
+        Preset {
+            read_lengths: ReadLengths::normal(17_500.0, 3_000.0, 10_000, 25_000).unwrap(),
+            single_end: true,
+            quality_start: 39.0,
+            quality_end: 37.0,
+            quality_noise: 2.0,
+            quality_bins: None,
+            sub_rate: 0.0005,
+            ins_rate: 0.0002,
+            del_rate: 0.0002,
+            poly_g_rate: 0.0,
+        }
+    }
+}
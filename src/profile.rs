@@ -0,0 +1,275 @@
+use rand::distr::weighted::WeightedIndex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufReader, Write};
+use std::path::Path;
+
+use crate::error::FastqGenError;
+use crate::input::FastqRead;
+use crate::quality::QualityModel;
+use crate::seq::reverse_complement;
+use crate::stats::MAX_PHRED;
+
+/// Shortest mate overlap taken as evidence that two reads cover the same bases.
+const MIN_OVERLAP: usize = 30;
+/// Largest fraction of mismatching bases in an accepted mate overlap.
+const MAX_OVERLAP_MISMATCHES: f64 = 0.1;
+/// Overlapping bases a Phred score needs before its observed mismatch rate is
+/// trusted over the rate the score itself implies.
+const MIN_OBSERVATIONS: u64 = 100;
+
+/// Quality and error characteristics learned from real FASTQ files by
+/// `fastqgen profile`, which `generate --profile` reproduces.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorProfile {
+    pub reads: u64,
+    /// Read pairs whose mates overlap, from which mismatch rates were measured.
+    pub overlapping_pairs: u64,
+    /// Histogram of Phred scores at each cycle of read 1 (or single-end reads).
+    pub read_1: Vec<BTreeMap<u8, u64>>,
+    /// Histogram of Phred scores at each cycle of read 2; empty for single-end input.
+    pub read_2: Vec<BTreeMap<u8, u64>>,
+    /// Fraction of bases of each Phred score that disagree with the overlapping mate.
+    pub mismatch_rates: BTreeMap<u8, f64>,
+}
+
+/// Accumulates an [`ErrorProfile`] over the records of one or two FASTQ files.
+#[derive(Debug, Clone)]
+pub struct ErrorProfiler {
+    quality_offset: u8,
+    reads: u64,
+    overlapping_pairs: u64,
+    read_1: Vec<[u64; MAX_PHRED + 1]>,
+    read_2: Vec<[u64; MAX_PHRED + 1]>,
+    /// Overlapping bases and the mismatches blamed on them, by Phred score.
+    observations: [u64; MAX_PHRED + 1],
+    mismatches: [u64; MAX_PHRED + 1],
+}
+
+impl ErrorProfiler {
+    /// A profiler for quality strings encoded with `quality_offset` (33 for Phred+33).
+    pub fn new(quality_offset: u8) -> Self {
+        ErrorProfiler {
+            quality_offset,
+            reads: 0,
+            overlapping_pairs: 0,
+            read_1: Vec::new(),
+            read_2: Vec::new(),
+            observations: [0; MAX_PHRED + 1],
+            mismatches: [0; MAX_PHRED + 1],
+        }
+    }
+
+    fn phred(&self, score: u8) -> usize {
+        (score.saturating_sub(self.quality_offset) as usize).min(MAX_PHRED)
+    }
+
+    /// Counts the score at every cycle of `quality`, a read 2 quality string if `mate`.
+    fn add_qualities(&mut self, mate: bool, quality: &[u8]) {
+        let offset = self.quality_offset;
+        let cycles = if mate { &mut self.read_2 } else { &mut self.read_1 };
+        if cycles.len() < quality.len() {
+            cycles.resize(quality.len(), [0; MAX_PHRED + 1]);
+        }
+        for (histogram, &score) in cycles.iter_mut().zip(quality) {
+            histogram[(score.saturating_sub(offset) as usize).min(MAX_PHRED)] += 1;
+        }
+    }
+
+    pub fn add_single(&mut self, record: &FastqRead) {
+        self.reads += 1;
+        self.add_qualities(false, &record.quality);
+    }
+
+    /// Adds both mates of a pair. Where the mates overlap, every base is an
+    /// observation at its quality, and a disagreement is blamed on the base of
+    /// lower quality.
+    pub fn add_pair(&mut self, read_1: &FastqRead, read_2: &FastqRead) {
+        self.add_single(read_1);
+        self.add_qualities(true, &read_2.quality);
+
+        let mate = reverse_complement(&read_2.seq);
+        let Some(offset) = mate_overlap(&read_1.seq, &mate) else {
+            return;
+        };
+        self.overlapping_pairs += 1;
+        let mate_quality = read_2.quality.iter().rev();
+        let overlap = read_1.seq[offset..].iter().zip(&read_1.quality[offset..]).zip(mate.iter().zip(mate_quality));
+        for ((&base_1, &score_1), (&base_2, &score_2)) in overlap {
+            if base_1 == b'N' || base_2 == b'N' {
+                continue;
+            }
+            let (phred_1, phred_2) = (self.phred(score_1), self.phred(score_2));
+            self.observations[phred_1] += 1;
+            self.observations[phred_2] += 1;
+            if base_1 != base_2 {
+                self.mismatches[phred_1.min(phred_2)] += 1;
+            }
+        }
+    }
+
+    pub fn finish(&self) -> ErrorProfile {
+        let histograms = |cycles: &[[u64; MAX_PHRED + 1]]| -> Vec<BTreeMap<u8, u64>> {
+            cycles.iter()
+                .map(|histogram| {
+                    histogram.iter().enumerate().filter(|(_, count)| **count > 0).map(|(phred, &count)| (phred as u8, count)).collect()
+                })
+                .collect()
+        };
+        let read_1 = histograms(&self.read_1);
+        let read_2 = histograms(&self.read_2);
+        let mismatch_rates = read_1.iter().chain(&read_2)
+            .flat_map(|histogram| histogram.keys().copied())
+            .map(|phred| {
+                let observed = self.observations[phred as usize];
+                let rate = if observed >= MIN_OBSERVATIONS {
+                    self.mismatches[phred as usize] as f64 / observed as f64
+                } else {
+                    phred_error_rate(phred)
+                };
+                (phred, rate)
+            })
+            .collect();
+        ErrorProfile { reads: self.reads, overlapping_pairs: self.overlapping_pairs, read_1, read_2, mismatch_rates }
+    }
+}
+
+/// Offset in `read_1` at which `mate` (read 2, reverse-complemented) starts, if the
+/// two overlap by at least [`MIN_OVERLAP`] bases with few enough mismatches. The
+/// smallest offset, the longest overlap, that fits is taken.
+fn mate_overlap(read_1: &[u8], mate: &[u8]) -> Option<usize> {
+    for offset in 0..=read_1.len().saturating_sub(MIN_OVERLAP) {
+        let length = (read_1.len() - offset).min(mate.len());
+        if length < MIN_OVERLAP {
+            break;
+        }
+        let limit = (length as f64 * MAX_OVERLAP_MISMATCHES) as usize;
+        let mismatches = read_1[offset..offset + length].iter().zip(mate).filter(|(a, b)| a != b).take(limit + 1).count();
+        if mismatches <= limit {
+            return Some(offset);
+        }
+    }
+    None
+}
+
+/// Error rate a Phred score stands for, capped at the 3/4 of a uniformly random base.
+fn phred_error_rate(phred: u8) -> f64 {
+    10f64.powf(-(phred as f64) / 10.0).min(0.75)
+}
+
+impl ErrorProfile {
+    pub fn from_json(path: &Path) -> Result<Self, FastqGenError> {
+        let file = File::open(path).map_err(|e| FastqGenError::io(format!("Failed to open profile {}", path.display()), e))?;
+        let profile: ErrorProfile = serde_json::from_reader(BufReader::new(file))
+            .map_err(|e| FastqGenError::parse(format!("Invalid profile {}: {}", path.display(), e)))?;
+        if profile.read_1.is_empty() || profile.read_1.iter().chain(&profile.read_2).any(|histogram| histogram.values().all(|&count| count == 0)) {
+            return Err(FastqGenError::parse(format!("Profile {} has cycles without any quality scores.", path.display())));
+        }
+        if profile.mismatch_rates.values().any(|rate| !(0.0..=1.0).contains(rate)) {
+            return Err(FastqGenError::parse(format!("Profile {} has mismatch rates outside [0, 1].", path.display())));
+        }
+        Ok(profile)
+    }
+
+    pub fn write_json(&self, out: &mut impl Write) -> Result<(), FastqGenError> {
+        serde_json::to_writer_pretty(&mut *out, self).map_err(io::Error::from)?;
+        writeln!(out)?;
+        Ok(())
+    }
+
+    /// Quality model drawing each cycle's score from the profiled histogram of
+    /// read 1, or of read 2 when `mate` and the profile has one.
+    pub fn quality_model(&self, mate: bool) -> QualityModel {
+        let cycles = if mate && !self.read_2.is_empty() { &self.read_2 } else { &self.read_1 };
+        let cycles = cycles.iter()
+            .map(|histogram| {
+                let scores: Vec<u8> = histogram.keys().copied().collect();
+                let weights = WeightedIndex::new(histogram.values().copied()).expect("profile cycles have scores");
+                (scores, weights)
+            })
+            .collect();
+        QualityModel::Empirical { cycles }
+    }
+
+    /// Substitution rate of a base of each Phred score from 0 to 93: the profiled
+    /// mismatch rate, or the rate the score implies where it was never seen.
+    pub fn substitution_rates(&self) -> Vec<f64> {
+        (0..=MAX_PHRED as u8)
+            .map(|phred| self.mismatch_rates.get(&phred).copied().unwrap_or_else(|| phred_error_rate(phred)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use std::fs;
+
+    const SEQ: &[u8] = b"ACGGTCATTGCAAGCTTCGAGGATCCATGCAATTGGCCTAGCTAGGACTTAGCCATGAC";
+
+    fn read(seq: &[u8], quality: &[u8]) -> FastqRead {
+        FastqRead { header: "read".to_string(), seq: seq.to_vec(), quality: quality.to_vec() }
+    }
+
+    /// Writes `json` to a temporary file and loads it as a profile.
+    fn load(name: &str, json: &str) -> Result<ErrorProfile, FastqGenError> {
+        let path = std::env::temp_dir().join(format!("fastqgen-{}-{}.json", std::process::id(), name));
+        fs::write(&path, json).unwrap();
+        let profile = ErrorProfile::from_json(&path);
+        fs::remove_file(&path).unwrap();
+        profile
+    }
+
+    #[test]
+    fn profiles_round_trip_into_quality_models() {
+        let mut profiler = ErrorProfiler::new(33);
+        profiler.add_pair(&read(b"ACG", b"I5+"), &read(b"TTTT", b"5555"));
+        let mut json = Vec::new();
+        profiler.finish().write_json(&mut json).unwrap();
+        let profile = load("roundtrip", std::str::from_utf8(&json).unwrap()).unwrap();
+        assert_eq!((profile.reads, profile.read_1.len(), profile.read_2.len()), (1, 3, 4));
+
+        let mut rng = StdRng::seed_from_u64(1);
+        // Cycles past the profiled ones repeat the last.
+        assert_eq!(profile.quality_model(false).sample(&mut rng, 5, &(33..127)), b"I5+++");
+        assert_eq!(profile.quality_model(true).sample(&mut rng, 2, &(33..127)), b"55");
+    }
+
+    #[test]
+    fn rejects_cycles_without_scores() {
+        let json = |cycle: &str| format!(r#"{{"reads":1,"overlapping_pairs":0,"read_1":[{}],"read_2":[],"mismatch_rates":{{}}}}"#, cycle);
+        assert!(load("scored", &json(r#"{"30":2}"#)).is_ok());
+        assert!(load("empty", &json("{}")).is_err());
+        assert!(load("zero", &json(r#"{"30":0}"#)).is_err());
+    }
+
+    #[test]
+    fn mate_overlap_finds_the_longest_overlap() {
+        assert_eq!(mate_overlap(SEQ, SEQ), Some(0));
+        assert_eq!(mate_overlap(SEQ, &SEQ[12..]), Some(12));
+        // Too few shared bases, or too many mismatches, are no overlap.
+        assert_eq!(mate_overlap(SEQ, &SEQ[SEQ.len() - MIN_OVERLAP + 1..]), None);
+        let mismatched: Vec<u8> = SEQ.iter().enumerate().map(|(i, &base)| if i % 5 == 0 { b'N' } else { base }).collect();
+        assert_eq!(mate_overlap(SEQ, &mismatched), None);
+    }
+
+    #[test]
+    fn mismatch_rates_need_enough_observations() {
+        let quality = [b'I'; 40];
+        let mut profiler = ErrorProfiler::new(33);
+        let seq = &SEQ[..40];
+        profiler.add_pair(&read(seq, &quality), &read(&reverse_complement(seq), &quality));
+        // 80 overlapping bases at Q40 fall back to the rate the score implies.
+        assert_eq!(profiler.finish().mismatch_rates[&40], phred_error_rate(40));
+
+        let mut mate = seq.to_vec();
+        mate[3] = b'T';
+        profiler.add_pair(&read(seq, &quality), &read(&reverse_complement(&mate), &quality));
+        let profile = profiler.finish();
+        assert_eq!(profile.overlapping_pairs, 2);
+        assert_eq!(profile.mismatch_rates[&40], 1.0 / 160.0);
+    }
+}
@@ -0,0 +1,204 @@
+use rand::Rng;
+use rand::distr::{Distribution, Uniform};
+use rand::distr::weighted::WeightedIndex;
+use rand_distr::Normal;
+use std::io::BufRead;
+use std::ops::Range;
+use std::path::Path;
+
+use crate::error::FastqGenError;
+use crate::reference::open_text;
+
+/// How per-base quality scores are drawn along a read.
+#[derive(Debug, Clone)]
+pub enum QualityModel {
+    /// Every position is drawn uniformly from the generator's quality range.
+    Uniform,
+    /// Mean Phred score decays from `start` at the 5' end to `end` at the 3' end,
+    /// dropping faster toward the end of the read like Illumina data, with Gaussian
+    /// per-position noise of standard deviation `noise`.
+    IlluminaDecay { start: f64, end: f64, noise: Normal<f64> },
+    /// Each cycle's Phred score is drawn from the scores observed at that cycle
+    /// of real reads, with their weights; cycles past the last reuse it.
+    Empirical { cycles: Vec<(Vec<u8>, WeightedIndex<u64>)> },
+    /// Every position has the same Phred score, capped to the quality range.
+    Fixed(u8),
+    /// Each cycle's Phred score is Gaussian with the mean and noise of that cycle;
+    /// cycles past the last reuse it.
+    PerCycle { cycles: Vec<(f64, Normal<f64>)> },
+}
+
+impl QualityModel {
+    pub fn illumina_decay(start: f64, end: f64, noise: f64) -> Result<Self, FastqGenError> {
+        if !(0.0..=93.0).contains(&start) || !(0.0..=93.0).contains(&end) {
+            return Err(FastqGenError::invalid("Quality means must be between 0 and 93."));
+        }
+        let noise = Normal::new(0.0, noise).map_err(|e| FastqGenError::invalid(format!("Invalid quality noise: {}", e)))?;
+        Ok(QualityModel::IlluminaDecay { start, end, noise })
+    }
+
+    /// Draws the Phred score of every cycle from a normal distribution with the
+    /// `(mean, standard deviation)` given for that cycle.
+    pub fn per_cycle(cycles: &[(f64, f64)]) -> Result<Self, FastqGenError> {
+        if cycles.is_empty() {
+            return Err(FastqGenError::invalid("Per-cycle qualities need at least one cycle."));
+        }
+        let cycles = cycles.iter()
+            .map(|&(mean, sd)| {
+                if !(0.0..=93.0).contains(&mean) {
+                    return Err(FastqGenError::invalid("Quality means must be between 0 and 93."));
+                }
+                let noise = Normal::new(0.0, sd).map_err(|e| FastqGenError::invalid(format!("Invalid quality noise: {}", e)))?;
+                Ok((mean, noise))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(QualityModel::PerCycle { cycles })
+    }
+
+    /// Samples a quality string of `length` ASCII-encoded scores within `range`.
+    pub fn sample(&self, rng: &mut impl Rng, length: usize, range: &Range<u8>) -> Vec<u8> {
+        let mut quality = Vec::with_capacity(length);
+        self.sample_into(rng, length, range, &mut quality);
+        quality
+    }
+
+    /// Like [`Self::sample`], but appends the scores to `out`.
+    pub fn sample_into(&self, rng: &mut impl Rng, length: usize, range: &Range<u8>, out: &mut Vec<u8>) {
+        match self {
+            QualityModel::Uniform => {
+                let dist = Uniform::new(range.start, range.end).unwrap();
+                out.extend((0..length).map(|_| dist.sample(rng)));
+            }
+            QualityModel::IlluminaDecay { start, end, noise } => {
+                let offset = range.start as f64;
+                let max = (range.end - 1) as f64;
+                let last = length.saturating_sub(1).max(1) as f64;
+                out.extend((0..length).map(|position| {
+                    let progress = position as f64 / last;
+                    let mean = start - (start - end) * progress * progress;
+                    (offset + mean + noise.sample(rng)).round().clamp(offset, max) as u8
+                }));
+            }
+            QualityModel::Empirical { cycles } => {
+                let max = range.end - 1;
+                out.extend((0..length).map(|position| {
+                    let (scores, weights) = &cycles[position.min(cycles.len() - 1)];
+                    range.start.saturating_add(scores[weights.sample(rng)]).min(max)
+                }));
+            }
+            QualityModel::PerCycle { cycles } => {
+                let offset = range.start as f64;
+                let max = (range.end - 1) as f64;
+                out.extend((0..length).map(|position| {
+                    let (mean, noise) = &cycles[position.min(cycles.len() - 1)];
+                    (offset + mean + noise.sample(rng)).round().clamp(offset, max) as u8
+                }));
+            }
+            QualityModel::Fixed(phred) => {
+                let score = range.start.saturating_add(*phred).min(range.end - 1);
+                out.extend(std::iter::repeat_n(score, length));
+            }
+        }
+    }
+}
+
+/// Reads a per-cycle quality table, whose lines give a read (1 or 2), a cycle
+/// (from 1) and the mean and standard deviation of the Phred score at that cycle,
+/// separated by tabs, commas or spaces. A header line and lines starting with `#`
+/// are skipped. Returns the quality models of read 1 and, if the table has any
+/// read 2 lines, read 2.
+pub fn read_cycle_qualities(path: &Path) -> Result<(QualityModel, Option<QualityModel>), FastqGenError> {
+    let reader = open_text(path)
+        .map_err(|e| FastqGenError::io(format!("Failed to open quality profile {}", path.display()), e))?;
+    let mut reads: [Vec<Option<(f64, f64)>>; 2] = [Vec::new(), Vec::new()];
+
+    for (line_number, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        let location = format!("{}:{}", path.display(), line_number + 1);
+        let fields: Vec<&str> = line.split(['\t', ',', ' ']).filter(|field| !field.is_empty()).collect();
+        if fields.len() < 4 {
+            return Err(FastqGenError::parse(format!("{}: expected a read, a cycle, a mean and a standard deviation.", location)));
+        }
+        let Ok(read) = fields[0].parse::<usize>() else {
+            if line_number == 0 {
+                continue;
+            }
+            return Err(FastqGenError::parse(format!("{}: invalid read {}.", location, fields[0])));
+        };
+        if !(1..=2).contains(&read) {
+            return Err(FastqGenError::parse(format!("{}: read must be 1 or 2, got {}.", location, read)));
+        }
+        let cycle = fields[1].parse::<usize>().ok().filter(|&cycle| cycle > 0)
+            .ok_or_else(|| FastqGenError::parse(format!("{}: invalid cycle {}.", location, fields[1])))?;
+        let mean = fields[2].parse::<f64>().ok().filter(|mean| (0.0..=93.0).contains(mean))
+            .ok_or_else(|| FastqGenError::parse(format!("{}: invalid mean quality {}.", location, fields[2])))?;
+        let sd = fields[3].parse::<f64>().ok().filter(|sd| *sd >= 0.0 && sd.is_finite())
+            .ok_or_else(|| FastqGenError::parse(format!("{}: invalid standard deviation {}.", location, fields[3])))?;
+        let cycles = &mut reads[read - 1];
+        if cycles.len() < cycle {
+            cycles.resize(cycle, None);
+        }
+        cycles[cycle - 1] = Some((mean, sd));
+    }
+
+    let [read_1, read_2] = reads.map(|cycles| {
+        let cycles: Option<Vec<(f64, f64)>> = cycles.into_iter().collect();
+        cycles.ok_or_else(|| FastqGenError::parse(format!("Quality profile {} skips a cycle; give every cycle from 1 to the last.", path.display())))
+    });
+    let (read_1, read_2) = (read_1?, read_2?);
+    if read_1.is_empty() {
+        return Err(FastqGenError::parse(format!("Quality profile {} has no read 1 cycles.", path.display())));
+    }
+    let read_2 = if read_2.is_empty() { None } else { Some(QualityModel::per_cycle(&read_2)?) };
+    Ok((QualityModel::per_cycle(&read_1)?, read_2))
+}
+
+/// Collapses Phred scores onto the few values an instrument reports, as modern
+/// Illumina software does to make FASTQ files compress better.
+#[derive(Debug, Clone)]
+pub struct QualityBins {
+    /// `(lowest Phred score in the bin, reported score)`, in ascending order.
+    bins: Vec<(u8, u8)>,
+}
+
+impl QualityBins {
+    pub fn new(bins: Vec<(u8, u8)>) -> Result<Self, FastqGenError> {
+        if bins.is_empty() || !bins.windows(2).all(|pair| pair[0].0 < pair[1].0) {
+            return Err(FastqGenError::invalid("Quality bins must be non-empty and sorted by their lower bound."));
+        }
+        Ok(QualityBins { bins })
+    }
+
+    /// The 8-level scheme used by HiSeq 2500/4000 and MiSeq with binning enabled.
+    pub fn illumina_8_level() -> Self {
+        QualityBins { bins: vec![(0, 2), (2, 6), (10, 15), (20, 22), (25, 27), (30, 33), (35, 37), (40, 40)] }
+    }
+
+    /// The 4-level scheme of NovaSeq RTA3.
+    pub fn novaseq() -> Self {
+        QualityBins { bins: vec![(0, 2), (3, 12), (15, 23), (31, 37)] }
+    }
+
+    /// The scheme of NextSeq 500/550 RTA2.
+    pub fn nextseq() -> Self {
+        QualityBins { bins: vec![(0, 2), (3, 14), (18, 21), (25, 27), (30, 32), (34, 36)] }
+    }
+
+    /// The reported score for Phred score `phred`.
+    pub fn bin(&self, phred: u8) -> u8 {
+        let index = self.bins.partition_point(|&(lower, _)| lower <= phred);
+        self.bins[index.saturating_sub(1)].1
+    }
+
+    /// Bins every score of an ASCII quality string whose scores lie in `range`,
+    /// which starts at the encoding offset; reported scores are capped to it.
+    pub(crate) fn apply(&self, quality: &mut [u8], range: &Range<u8>) {
+        let offset = range.start;
+        for score in quality {
+            *score = (self.bin(*score - offset) + offset).min(range.end - 1);
+        }
+    }
+}
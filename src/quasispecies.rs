@@ -0,0 +1,174 @@
+use rand::Rng;
+use rand::distr::Distribution;
+use rand::prelude::IndexedRandom;
+use rand_distr::Geometric;
+use std::collections::HashSet;
+use std::io::{self, Write};
+
+use crate::error::FastqGenError;
+use crate::expression::sample_abundances;
+use crate::random_genome::write_fasta;
+use crate::reference::Reference;
+use crate::variants::Variant;
+
+/// The haplotypes of a viral quasispecies at their frequencies in the host. They
+/// are related by a random genealogy: each descends from the consensus (the
+/// reference with any variants shared by all of them) or from an earlier
+/// haplotype, carrying its ancestors' mutations and its own.
+#[derive(Debug, Clone)]
+pub struct Quasispecies {
+    frequencies: Vec<f64>,
+    /// The haplotype each descends from, or `None` for the consensus.
+    parents: Vec<Option<usize>>,
+}
+
+impl Quasispecies {
+    /// Haplotypes at `frequencies`, scaled to sum to one, with a genealogy in
+    /// which each haplotype descends from the consensus or one of the haplotypes
+    /// before it, uniformly.
+    pub fn new(rng: &mut impl Rng, frequencies: Vec<f64>) -> Result<Self, FastqGenError> {
+        if frequencies.is_empty() || frequencies.iter().any(|frequency| !(frequency.is_finite() && *frequency > 0.0)) {
+            return Err(FastqGenError::invalid("A quasispecies needs at least one haplotype, every one at a positive frequency."));
+        }
+        let total: f64 = frequencies.iter().sum();
+        let frequencies = frequencies.iter().map(|frequency| frequency / total).collect::<Vec<f64>>();
+        let parents = (0..frequencies.len())
+            .map(|haplotype| {
+                let parent = rng.random_range(0..=haplotype);
+                (parent < haplotype).then_some(parent)
+            })
+            .collect();
+        Ok(Quasispecies { frequencies, parents })
+    }
+
+    pub fn frequencies(&self) -> &[f64] {
+        &self.frequencies
+    }
+
+    /// Whether `haplotype` is `ancestor` or descends from it.
+    fn descends_from(&self, mut haplotype: usize, ancestor: usize) -> bool {
+        loop {
+            if haplotype == ancestor {
+                return true;
+            }
+            match self.parents[haplotype] {
+                Some(parent) => haplotype = parent,
+                None => return false,
+            }
+        }
+    }
+
+    /// Draws the SNVs each haplotype gained from its parent at a per-base rate of
+    /// `divergence`, with transitions `ts_tv` times as frequent as transversions,
+    /// and gives them to the haplotype and its descendants. No two mutations hit
+    /// the same base, nor a base in `taken` (the consensus's own variants).
+    pub fn simulate_mutations(&self, reference: &Reference, rng: &mut impl Rng, divergence: f64, ts_tv: f64, taken: &[Variant]) -> Result<Vec<Variant>, FastqGenError> {
+        if !(0.0..=1.0).contains(&divergence) {
+            return Err(FastqGenError::invalid("The divergence between haplotypes must be between 0 and 1."));
+        }
+        if !ts_tv.is_finite() || ts_tv < 0.0 {
+            return Err(FastqGenError::invalid("The transition/transversion ratio must not be negative."));
+        }
+        let mut variants = Vec::new();
+        if divergence == 0.0 {
+            return Ok(variants);
+        }
+        let gap = Geometric::new(divergence).map_err(|e| FastqGenError::invalid(format!("Invalid divergence: {}", e)))?;
+        let transitions = ts_tv / (ts_tv + 1.0);
+        let mut used: HashSet<(usize, usize)> = taken.iter()
+            .flat_map(|variant| (variant.position..variant.position + variant.reference.len()).map(move |position| (variant.contig, position)))
+            .collect();
+
+        for haplotype in 0..self.frequencies.len() {
+            let genotype: Vec<bool> = (0..self.frequencies.len()).map(|other| self.descends_from(other, haplotype)).collect();
+            for contig in 0..reference.contigs().count() {
+                let seq = reference.contig_seq(contig);
+                let mut position = gap.sample(rng) as usize;
+                while position < seq.len() {
+                    let base = seq[position];
+                    if matches!(base, b'A' | b'C' | b'G' | b'T') && used.insert((contig, position)) {
+                        let alternate = if rng.random_bool(transitions) { transition(base) } else { *transversions(base).choose(rng).expect("two transversions") };
+                        variants.push(Variant { contig, position, reference: vec![base], alternate: vec![alternate], genotype: genotype.clone() });
+                    }
+                    position += 1 + gap.sample(rng) as usize;
+                }
+            }
+        }
+        variants.sort_by_key(|variant| (variant.contig, variant.position));
+        Ok(variants)
+    }
+
+    /// Frequency of a variant in the quasispecies: the summed frequency of the
+    /// haplotypes carrying it.
+    pub fn frequency(&self, variant: &Variant) -> f64 {
+        self.frequencies.iter().zip(&variant.genotype).filter(|&(_, &carried)| carried).map(|(frequency, _)| frequency).sum()
+    }
+
+    /// Writes variants as a sites-only VCF with their frequency in the
+    /// quasispecies as `AF` and the (1-based) haplotypes carrying them as `HAP`.
+    pub fn write_vcf(&self, out: &mut impl Write, reference: &Reference, variants: &[Variant]) -> io::Result<()> {
+        writeln!(out, "##fileformat=VCFv4.2")?;
+        writeln!(out, "##source=fastqgen-{}", env!("CARGO_PKG_VERSION"))?;
+        for (name, length) in reference.contigs() {
+            writeln!(out, "##contig=<ID={},length={}>", name, length)?;
+        }
+        writeln!(out, "##INFO=<ID=AF,Number=A,Type=Float,Description=\"Frequency in the quasispecies\">")?;
+        writeln!(out, "##INFO=<ID=HAP,Number=.,Type=Integer,Description=\"Haplotypes carrying the variant\">")?;
+        writeln!(out, "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO")?;
+
+        for variant in variants {
+            let haplotypes: Vec<String> = variant.genotype.iter().enumerate().filter(|&(_, &carried)| carried).map(|(haplotype, _)| (haplotype + 1).to_string()).collect();
+            writeln!(
+                out,
+                "{}\t{}\t.\t{}\t{}\t.\tPASS\tAF={:.4};HAP={}",
+                reference.contig_name(variant.contig),
+                variant.position + 1,
+                String::from_utf8_lossy(&variant.reference),
+                String::from_utf8_lossy(&variant.alternate),
+                self.frequency(variant),
+                haplotypes.join(",")
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes the sequence of every haplotype of `reference`, built with the
+    /// quasispecies' variants, as FASTA records named `hap<N>_<contig>`, with the
+    /// haplotype's frequency and parent (`consensus` or `hap<N>`) in the header.
+    pub fn write_fasta(&self, out: &mut impl Write, reference: &Reference) -> io::Result<()> {
+        for (haplotype, (frequency, parent)) in self.frequencies.iter().zip(&self.parents).enumerate() {
+            let parent = parent.map_or("consensus".to_string(), |parent| format!("hap{}", parent + 1));
+            let sequences: Vec<(String, Vec<u8>)> = reference.contigs()
+                .enumerate()
+                .map(|(contig, (name, _))| (format!("hap{}_{} frequency={:.4} parent={}", haplotype + 1, name, frequency, parent), reference.haplotype_seq(haplotype, contig).to_vec()))
+                .collect();
+            write_fasta(out, &sequences)?;
+        }
+        Ok(())
+    }
+}
+
+/// Draws the frequency of `count` haplotypes from a log-normal with log-scale
+/// standard deviation `sd`, scaled to sum to one.
+pub fn sample_haplotype_frequencies(count: usize, rng: &mut impl Rng, sd: f64) -> Result<Vec<f64>, FastqGenError> {
+    let tpm = sample_abundances(count, rng, sd)?;
+    Ok(tpm.iter().map(|tpm| tpm / 1e6).collect())
+}
+
+/// The base a transition turns `base` into.
+fn transition(base: u8) -> u8 {
+    match base {
+        b'A' => b'G',
+        b'G' => b'A',
+        b'C' => b'T',
+        _ => b'C',
+    }
+}
+
+/// The bases a transversion can turn `base` into.
+fn transversions(base: u8) -> &'static [u8] {
+    match base {
+        b'A' | b'G' => b"CT",
+        _ => b"AG",
+    }
+}
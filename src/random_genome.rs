@@ -0,0 +1,132 @@
+use rand::Rng;
+use rand::distr::Distribution;
+use rand::distr::weighted::WeightedIndex;
+use std::io::{self, Write};
+
+use crate::error::FastqGenError;
+use crate::error_model::substitute;
+use crate::seq::reverse_complement;
+
+/// Bases per line of written FASTA.
+const FASTA_LINE_WIDTH: usize = 60;
+
+/// A synthetic genome of random sequence, optionally carrying interspersed
+/// repeats: diverged copies of a few repeat families, on either strand.
+#[derive(Debug, Clone)]
+pub struct RandomGenome {
+    length: u64,
+    contigs: usize,
+    gc: f64,
+    repeat_fraction: f64,
+    repeat_length: usize,
+    repeat_families: usize,
+    repeat_divergence: f64,
+}
+
+/// One repeat copy placed in a [`RandomGenome`], in 0-based half-open contig
+/// coordinates.
+#[derive(Debug, Clone)]
+pub struct RepeatCopy {
+    pub contig: usize,
+    pub start: usize,
+    pub end: usize,
+    pub family: usize,
+    pub reverse: bool,
+}
+
+impl RandomGenome {
+    /// `length` bases split as evenly as possible over `contigs` contigs, with a
+    /// `gc` fraction of G and C.
+    pub fn new(length: u64, contigs: usize, gc: f64) -> Result<Self, FastqGenError> {
+        if contigs == 0 || length < contigs as u64 {
+            return Err(FastqGenError::invalid("A genome needs at least one contig and one base per contig."));
+        }
+        if !(0.0..=1.0).contains(&gc) {
+            return Err(FastqGenError::invalid("GC content must be between 0 and 1."));
+        }
+        Ok(RandomGenome { length, contigs, gc, repeat_fraction: 0.0, repeat_length: 300, repeat_families: 5, repeat_divergence: 0.0 })
+    }
+
+    /// Covers about `fraction` of the genome with copies of `families` random
+    /// `length`-base repeats, each base of a copy substituted with probability
+    /// `divergence` from its family.
+    pub fn with_repeats(mut self, fraction: f64, length: usize, families: usize, divergence: f64) -> Result<Self, FastqGenError> {
+        if !(0.0..=1.0).contains(&fraction) || !(0.0..=1.0).contains(&divergence) {
+            return Err(FastqGenError::invalid("Repeat fraction and divergence must be between 0 and 1."));
+        }
+        if length == 0 || families == 0 {
+            return Err(FastqGenError::invalid("Repeat length and number of repeat families must be positive."));
+        }
+        self.repeat_fraction = fraction;
+        self.repeat_length = length;
+        self.repeat_families = families;
+        self.repeat_divergence = divergence;
+        Ok(self)
+    }
+
+    fn random_seq(&self, rng: &mut impl Rng, length: usize) -> Vec<u8> {
+        let at = (1.0 - self.gc) / 2.0;
+        let bases = WeightedIndex::new([at, self.gc / 2.0, self.gc / 2.0, at]).expect("base weights sum to 1");
+        (0..length).map(|_| b"ACGT"[bases.sample(rng)]).collect()
+    }
+
+    /// Draws the contigs, named `chr1`, `chr2` and so on, and the repeat copies
+    /// placed in them. Later copies may overwrite parts of earlier ones.
+    pub fn generate(&self, rng: &mut impl Rng) -> (Vec<(String, Vec<u8>)>, Vec<RepeatCopy>) {
+        let base_length = self.length / self.contigs as u64;
+        let longer = (self.length % self.contigs as u64) as usize;
+        let mut contigs: Vec<(String, Vec<u8>)> = (0..self.contigs)
+            .map(|contig| {
+                let length = base_length as usize + usize::from(contig < longer);
+                (format!("chr{}", contig + 1), self.random_seq(rng, length))
+            })
+            .collect();
+
+        let mut copies = Vec::new();
+        if self.repeat_fraction > 0.0 {
+            let families: Vec<Vec<u8>> = (0..self.repeat_families).map(|_| self.random_seq(rng, self.repeat_length)).collect();
+            let count = (self.repeat_fraction * self.length as f64 / self.repeat_length as f64).round() as usize;
+            // Copies land on contigs in proportion to their length, wherever they fit.
+            let Ok(contig_weights) = WeightedIndex::new(contigs.iter().map(|(_, seq)| seq.len().saturating_sub(self.repeat_length) as u64)) else {
+                return (contigs, copies);
+            };
+            for _ in 0..count {
+                let contig = contig_weights.sample(rng);
+                let family = rng.random_range(0..families.len());
+                let reverse = rng.random_bool(0.5);
+                let mut copy = if reverse { reverse_complement(&families[family]) } else { families[family].clone() };
+                for base in copy.iter_mut() {
+                    if rng.random_bool(self.repeat_divergence) {
+                        *base = substitute(rng, *base, b"ACGT");
+                    }
+                }
+                let seq = &mut contigs[contig].1;
+                let start = rng.random_range(0..=seq.len() - copy.len());
+                seq[start..start + copy.len()].copy_from_slice(&copy);
+                copies.push(RepeatCopy { contig, start, end: start + copy.len(), family, reverse });
+            }
+        }
+        (contigs, copies)
+    }
+}
+
+/// Writes `sequences` as FASTA, wrapped at 60 bases per line.
+pub fn write_fasta(out: &mut impl Write, sequences: &[(String, Vec<u8>)]) -> io::Result<()> {
+    for (name, seq) in sequences {
+        writeln!(out, ">{}", name)?;
+        for line in seq.chunks(FASTA_LINE_WIDTH) {
+            out.write_all(line)?;
+            out.write_all(b"\n")?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes the repeat copies as BED, named after their family.
+pub fn write_repeats_bed(out: &mut impl Write, sequences: &[(String, Vec<u8>)], copies: &[RepeatCopy]) -> io::Result<()> {
+    for copy in copies {
+        let strand = if copy.reverse { '-' } else { '+' };
+        writeln!(out, "{}\t{}\t{}\trepeat_{}\t0\t{}", sequences[copy.contig].0, copy.start, copy.end, copy.family + 1, strand)?;
+    }
+    Ok(())
+}
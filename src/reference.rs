@@ -0,0 +1,727 @@
+use rand::Rng;
+use rand::distr::Distribution;
+use rand::distr::weighted::WeightedIndex;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::ops::Range;
+use std::path::Path;
+
+use flate2::read::MultiGzDecoder;
+
+use crate::error::FastqGenError;
+use crate::alignment::{Alignment, Cigar};
+use crate::seq::reverse_complement;
+use crate::structural::{StructuralVariant, SvKind};
+use crate::variants::Variant;
+
+#[derive(Debug)]
+struct Contig {
+    name: String,
+    seq: Vec<u8>,
+}
+
+/// The contig sequences of one simulated haplotype, i.e. the reference with a
+/// set of variants applied.
+#[derive(Debug)]
+struct Haplotype {
+    seqs: Vec<Vec<u8>>,
+    contig_dist: WeightedIndex<usize>,
+    /// Length-changing variants of each contig, sorted by haplotype position, used
+    /// to lift haplotype coordinates back onto the reference.
+    indels: Vec<Vec<IndelEvent>>,
+    /// Once structural variants are applied, the stretches of the unrearranged
+    /// haplotype each contig is assembled from, in order. `indels` then lift
+    /// positions of the unrearranged haplotype.
+    segments: Option<Vec<Vec<Segment>>>,
+}
+
+/// A stretch of one contig of the unrearranged haplotype, placed on a contig of
+/// the rearranged one.
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+    /// Contig the stretch comes from, and its extent on the unrearranged haplotype.
+    contig: usize,
+    start: usize,
+    end: usize,
+    /// Whether the stretch is reverse-complemented, as inside an inversion.
+    reverse: bool,
+    /// Position of its first base on the rearranged contig.
+    offset: usize,
+}
+
+impl Segment {
+    /// Position on the unrearranged haplotype of rearranged base `position`.
+    fn unrearranged(&self, position: usize) -> usize {
+        if self.reverse {
+            self.end - 1 - (position - self.offset)
+        } else {
+            self.start + position - self.offset
+        }
+    }
+}
+
+/// Where a haplotype gains or loses bases relative to the reference.
+#[derive(Debug, Clone, Copy)]
+struct IndelEvent {
+    /// For insertions, the first inserted haplotype base; for deletions, the
+    /// haplotype base immediately after the deleted reference bases.
+    hap_pos: usize,
+    len: usize,
+    insertion: bool,
+    /// Reference position minus haplotype position for bases after this event.
+    delta_after: isize,
+}
+
+/// How a haplotype base maps onto the reference.
+enum HapBase {
+    Reference(usize),
+    Inserted,
+}
+
+impl Haplotype {
+    /// The segment holding base `position` of rearranged `contig`, if structural
+    /// variants are applied.
+    fn segment(&self, contig: usize, position: usize) -> Option<&Segment> {
+        let segments = &self.segments.as_ref()?[contig];
+        let index = segments.partition_point(|segment| segment.offset <= position);
+        segments.get(index.checked_sub(1)?)
+    }
+
+    fn locate(events: &[IndelEvent], hap_pos: usize) -> HapBase {
+        let index = events.partition_point(|event| event.hap_pos <= hap_pos);
+        match index.checked_sub(1).map(|i| events[i]) {
+            None => HapBase::Reference(hap_pos),
+            Some(event) if event.insertion && hap_pos < event.hap_pos + event.len => HapBase::Inserted,
+            Some(event) => HapBase::Reference((hap_pos as isize + event.delta_after) as usize),
+        }
+    }
+
+    /// The haplotype position of reference base `ref_pos`, or of the next base
+    /// the haplotype keeps when `ref_pos` is deleted.
+    fn haplotype_position(events: &[IndelEvent], ref_pos: usize) -> usize {
+        // The first haplotype base after an event lies past the inserted bases.
+        let after = |event: &IndelEvent| if event.insertion { event.hap_pos + event.len } else { event.hap_pos };
+        let index = events.partition_point(|event| (after(event) as isize + event.delta_after) as usize <= ref_pos);
+        let hap_pos = match index.checked_sub(1).map(|i| &events[i]) {
+            None => ref_pos,
+            Some(event) => (ref_pos as isize - event.delta_after) as usize,
+        };
+        // Deleted reference bases lie before the next event's haplotype position.
+        events.get(index).map_or(hap_pos, |next| hap_pos.min(next.hap_pos))
+    }
+
+    /// Number of reference bases deleted immediately before haplotype base `hap_pos`.
+    fn deleted_before(events: &[IndelEvent], hap_pos: usize) -> usize {
+        let start = events.partition_point(|event| event.hap_pos < hap_pos);
+        events[start..].iter()
+            .take_while(|event| event.hap_pos == hap_pos)
+            .filter(|event| !event.insertion)
+            .map(|event| event.len)
+            .sum()
+    }
+}
+
+/// A stretch of reference sequence sampled by [`Reference::sample_fragment`].
+#[derive(Debug, Clone)]
+pub struct Fragment {
+    /// Haplotype the fragment was drawn from (always 0 without variants).
+    pub haplotype: usize,
+    /// Index of the contig the fragment was drawn from.
+    pub contig: usize,
+    /// 0-based start of the fragment on the forward strand of the haplotype.
+    pub start: usize,
+    /// Forward-strand sequence of the fragment.
+    pub seq: Vec<u8>,
+}
+
+/// A genome loaded from a FASTA file that read pairs can be sampled from.
+#[derive(Debug)]
+pub struct Reference {
+    contigs: Vec<Contig>,
+    contig_dist: WeightedIndex<usize>,
+    /// Empty until variants are applied; fragments then come from a haplotype
+    /// chosen uniformly at random rather than from the reference itself.
+    haplotypes: Vec<Haplotype>,
+    /// Contig weights of an expressed transcriptome; fragments are drawn in
+    /// proportion to length when unset.
+    expression: Option<WeightedIndex<f64>>,
+    /// Weights of the haplotypes, which are equally likely when unset.
+    haplotype_weights: Option<WeightedIndex<f64>>,
+    /// Genomes, such as the PhiX control or a contaminant, that fixed fractions of
+    /// fragments come from.
+    spike_ins: Vec<SpikeIn>,
+}
+
+/// Contigs appended to a reference by [`Reference::add_spike_in`].
+#[derive(Debug)]
+struct SpikeIn {
+    name: String,
+    /// Indices of its contigs.
+    contigs: Range<usize>,
+    /// Its contigs weighted by length.
+    contig_dist: WeightedIndex<usize>,
+    fraction: f64,
+}
+
+/// Opens a text file for reading, decompressing it if the name ends in `.gz`.
+pub(crate) fn open_text(path: &Path) -> io::Result<Box<dyn BufRead>> {
+    let file = File::open(path)?;
+    Ok(if path.extension().is_some_and(|ext| ext == "gz") {
+        Box::new(BufReader::new(MultiGzDecoder::new(file)))
+    } else {
+        Box::new(BufReader::new(file))
+    })
+}
+
+impl Reference {
+    /// Loads every contig from a FASTA file (optionally gzip-compressed) that is
+    /// at least `min_length` bases long. Sequences are upper-cased.
+    pub fn from_fasta(path: &Path, min_length: usize) -> Result<Self, FastqGenError> {
+        let reader = open_text(path)
+            .map_err(|e| FastqGenError::io(format!("Failed to open reference {}", path.display()), e))?;
+
+        let mut contigs = Vec::new();
+        let mut current: Option<Contig> = None;
+
+        for line in reader.lines() {
+            let line = line?;
+            let line = line.trim_end();
+            if let Some(header) = line.strip_prefix('>') {
+                contigs.extend(current.take());
+                let name = header.split_whitespace().next().unwrap_or_default().to_string();
+                current = Some(Contig { name, seq: Vec::new() });
+            } else if !line.is_empty() {
+                let contig = current.as_mut()
+                    .ok_or_else(|| FastqGenError::parse(format!("{} is not a FASTA file: sequence found before the first header.", path.display())))?;
+                contig.seq.extend(line.bytes().map(|b| b.to_ascii_uppercase()));
+            }
+        }
+        contigs.extend(current);
+        Self::from_sequences(contigs.into_iter().map(|contig| (contig.name, contig.seq)).collect(), min_length)
+            .map_err(|e| FastqGenError::parse(format!("Reference {}: {}", path.display(), e)))
+    }
+
+    /// Builds a reference from named, upper-case sequences, keeping those at least
+    /// `min_length` bases long.
+    pub fn from_sequences(sequences: Vec<(String, Vec<u8>)>, min_length: usize) -> Result<Self, FastqGenError> {
+        let contigs: Vec<Contig> = sequences.into_iter()
+            .filter(|(_, seq)| seq.len() >= min_length)
+            .map(|(name, seq)| Contig { name, seq })
+            .collect();
+        if contigs.is_empty() {
+            return Err(FastqGenError::parse(format!("no contigs of at least {} bases.", min_length)));
+        }
+
+        let contig_dist = WeightedIndex::new(contigs.iter().map(|contig| contig.seq.len())).map_err(|e| FastqGenError::parse(format!("invalid contig lengths: {}", e)))?;
+
+        Ok(Reference { contigs, contig_dist, haplotypes: Vec::new(), expression: None, haplotype_weights: None, spike_ins: Vec::new() })
+    }
+
+    /// Total number of bases across all loaded contigs.
+    pub fn total_length(&self) -> usize {
+        self.contigs.iter().map(|contig| contig.seq.len()).sum()
+    }
+
+    /// Names and lengths of the loaded contigs, in FASTA order.
+    pub fn contigs(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.contigs.iter().map(|contig| (contig.name.as_str(), contig.seq.len()))
+    }
+
+    pub fn contig_index(&self, name: &str) -> Option<usize> {
+        self.contigs.iter().position(|contig| contig.name == name)
+    }
+
+    pub fn contig_name(&self, index: usize) -> &str {
+        &self.contigs[index].name
+    }
+
+    pub fn contig_seq(&self, index: usize) -> &[u8] {
+        &self.contigs[index].seq
+    }
+
+    /// Forward-strand sequence of `contig` on `haplotype` (the reference itself
+    /// without variants), the sequence [`Self::sample_fragment`] draws from.
+    pub fn haplotype_seq(&self, haplotype: usize, contig: usize) -> &[u8] {
+        match self.haplotypes.get(haplotype) {
+            Some(haplotype) => &haplotype.seqs[contig],
+            None => &self.contigs[contig].seq,
+        }
+    }
+
+    /// The reference contig and position of base `position` of `contig` on
+    /// `haplotype`, or `None` for a base inserted by a variant.
+    pub fn reference_position(&self, haplotype: usize, contig: usize, position: usize) -> Option<(usize, usize)> {
+        let Some(haplotype) = self.haplotypes.get(haplotype) else {
+            return Some((contig, position));
+        };
+        let (contig, position) = match haplotype.segment(contig, position) {
+            Some(segment) => (segment.contig, segment.unrearranged(position)),
+            None => (contig, position),
+        };
+        match Haplotype::locate(&haplotype.indels[contig], position) {
+            HapBase::Reference(position) => Some((contig, position)),
+            HapBase::Inserted => None,
+        }
+    }
+
+    /// Treats the contigs as transcripts expressed at `tpm` (one value per contig):
+    /// fragments are then drawn from each in proportion to TPM times length.
+    pub fn set_expression(&mut self, tpm: &[f64]) -> Result<(), FastqGenError> {
+        let weights = self.contigs.iter().zip(tpm).map(|(contig, tpm)| tpm * contig.seq.len() as f64);
+        self.expression = Some(WeightedIndex::new(weights).map_err(|e| FastqGenError::invalid(format!("Invalid expression levels: {}", e)))?);
+        Ok(())
+    }
+
+    /// Builds `ploidy` haplotypes by applying each variant (sorted by contig and
+    /// position, non-overlapping) to the haplotypes its genotype marks as carrying
+    /// the alternate allele. Reads are sampled from these haplotypes afterwards.
+    pub fn apply_variants(&mut self, variants: &[Variant], ploidy: usize) -> Result<(), FastqGenError> {
+        let mut by_contig: Vec<Vec<&Variant>> = vec![Vec::new(); self.contigs.len()];
+        for variant in variants {
+            by_contig[variant.contig].push(variant);
+        }
+
+        let mut haplotypes = Vec::with_capacity(ploidy);
+        for haplotype in 0..ploidy {
+            let mut seqs = Vec::with_capacity(self.contigs.len());
+            let mut indels = Vec::with_capacity(self.contigs.len());
+
+            for (contig, contig_variants) in self.contigs.iter().zip(&by_contig) {
+                let mut seq = Vec::with_capacity(contig.seq.len());
+                let mut events = Vec::new();
+                let mut delta = 0isize;
+                let mut copied = 0;
+
+                for variant in contig_variants.iter().filter(|v| v.genotype[haplotype]) {
+                    seq.extend_from_slice(&contig.seq[copied..variant.position]);
+                    // Alleles share their leading bases (the VCF anchor base for indels);
+                    // any difference in length is an insertion or deletion after them.
+                    let (ref_len, alt_len) = (variant.reference.len(), variant.alternate.len());
+                    let hap_pos = seq.len() + ref_len.min(alt_len);
+                    if alt_len > ref_len {
+                        delta -= (alt_len - ref_len) as isize;
+                        events.push(IndelEvent { hap_pos, len: alt_len - ref_len, insertion: true, delta_after: delta });
+                    } else if ref_len > alt_len {
+                        delta += (ref_len - alt_len) as isize;
+                        events.push(IndelEvent { hap_pos, len: ref_len - alt_len, insertion: false, delta_after: delta });
+                    }
+                    seq.extend_from_slice(&variant.alternate);
+                    copied = variant.position + ref_len;
+                }
+                seq.extend_from_slice(&contig.seq[copied..]);
+                seqs.push(seq);
+                indels.push(events);
+            }
+
+            let contig_dist = WeightedIndex::new(seqs.iter().map(|seq| seq.len())).map_err(|e| FastqGenError::invalid(format!("Invalid haplotype: {}", e)))?;
+            haplotypes.push(Haplotype { seqs, contig_dist, indels, segments: None });
+        }
+
+        self.haplotypes = haplotypes;
+        self.haplotype_weights = None;
+        Ok(())
+    }
+
+    /// Rearranges the haplotypes built by [`Self::apply_variants`] with structural
+    /// variants (non-overlapping, each contig in at most one translocation, and
+    /// clear of the small variants), each applied to the haplotypes its genotype
+    /// marks as carrying it. Copy-number changes show in the contig lengths reads
+    /// are sampled by.
+    pub fn apply_structural_variants(&mut self, variants: &[StructuralVariant]) -> Result<(), FastqGenError> {
+        if self.haplotypes.is_empty() {
+            return Err(FastqGenError::invalid("Structural variants are applied to the haplotypes built by apply_variants."));
+        }
+        let contigs = self.contigs.len();
+        for (index, haplotype) in self.haplotypes.iter_mut().enumerate() {
+            let carried: Vec<&StructuralVariant> = variants.iter().filter(|variant| variant.genotype[index]).collect();
+            if carried.is_empty() {
+                continue;
+            }
+
+            // Where each contig is cut for a translocation: the index of the first
+            // segment after its breakpoint.
+            let mut tails = vec![0; contigs];
+            let mut segments = Vec::with_capacity(contigs);
+            for (contig, tail) in tails.iter_mut().enumerate() {
+                let events = &haplotype.indels[contig];
+                let mut cuts: Vec<(usize, usize, SvKind)> = Vec::new();
+                for variant in &carried {
+                    if variant.contig == contig {
+                        let start = Haplotype::haplotype_position(events, variant.start);
+                        cuts.push((start, Haplotype::haplotype_position(events, variant.end).max(start), variant.kind));
+                    }
+                    if let Some((partner, breakpoint)) = variant.partner
+                        && partner == contig
+                    {
+                        let breakpoint = Haplotype::haplotype_position(events, breakpoint);
+                        cuts.push((breakpoint, breakpoint, SvKind::Translocation));
+                    }
+                }
+                cuts.sort_by_key(|&(start, _, _)| start);
+
+                let mut list = Vec::new();
+                let push = |list: &mut Vec<Segment>, start: usize, end: usize, reverse: bool| {
+                    if end > start {
+                        list.push(Segment { contig, start, end, reverse, offset: 0 });
+                    }
+                };
+                let mut copied = 0;
+                for (start, end, kind) in cuts {
+                    push(&mut list, copied, start, false);
+                    match kind {
+                        SvKind::Deletion => {}
+                        SvKind::Duplication => {
+                            push(&mut list, start, end, false);
+                            push(&mut list, start, end, false);
+                        }
+                        SvKind::Inversion => push(&mut list, start, end, true),
+                        SvKind::Translocation => *tail = list.len(),
+                    }
+                    copied = end;
+                }
+                push(&mut list, copied, haplotype.seqs[contig].len(), false);
+                segments.push(list);
+            }
+            for variant in &carried {
+                if let Some((partner, _)) = variant.partner {
+                    let tail = segments[variant.contig].split_off(tails[variant.contig]);
+                    let partner_tail = segments[partner].split_off(tails[partner]);
+                    segments[variant.contig].extend(partner_tail);
+                    segments[partner].extend(tail);
+                }
+            }
+
+            let mut seqs = Vec::with_capacity(contigs);
+            for list in &mut segments {
+                let mut seq = Vec::new();
+                for segment in list.iter_mut() {
+                    segment.offset = seq.len();
+                    let bases = &haplotype.seqs[segment.contig][segment.start..segment.end];
+                    if segment.reverse {
+                        seq.extend(reverse_complement(bases));
+                    } else {
+                        seq.extend_from_slice(bases);
+                    }
+                }
+                seqs.push(seq);
+            }
+            haplotype.contig_dist = WeightedIndex::new(seqs.iter().map(|seq| seq.len())).map_err(|e| FastqGenError::invalid(format!("Invalid rearranged haplotype: {}", e)))?;
+            haplotype.seqs = seqs;
+            haplotype.segments = Some(segments);
+        }
+        Ok(())
+    }
+
+    /// Converts an alignment to `haplotype` (0-based `position` and forward-strand
+    /// CIGAR on a haplotype contig) into the equivalent alignment to the
+    /// reference, folding the haplotype's indels into the CIGAR. Across a
+    /// structural variant's breakpoint, the read bases past it are soft-clipped;
+    /// inside an inversion, the alignment changes strand.
+    pub fn lift_alignment(&self, haplotype: usize, mut alignment: Alignment) -> Alignment {
+        let Some(haplotype) = self.haplotypes.get(haplotype) else {
+            return alignment;
+        };
+        if let Some(segment) = haplotype.segment(alignment.contig, alignment.position) {
+            let within = segment.offset + (segment.end - segment.start) - alignment.position;
+            if alignment.cigar.reference_length() > within {
+                alignment.cigar = alignment.cigar.clipped(within);
+            }
+            alignment.contig = segment.contig;
+            if segment.reverse {
+                alignment.position = segment.unrearranged(alignment.position + alignment.cigar.reference_length() - 1);
+                alignment.cigar = alignment.cigar.reversed();
+                alignment.reverse = !alignment.reverse;
+            } else {
+                alignment.position = segment.unrearranged(alignment.position);
+            }
+        }
+        let (position, cigar) = Self::lift_indels(&haplotype.indels[alignment.contig], alignment.position, &alignment.cigar);
+        Alignment { position, cigar, ..alignment }
+    }
+
+    /// Lifts an alignment to the unrearranged haplotype through its `events`.
+    fn lift_indels(events: &[IndelEvent], position: usize, cigar: &Cigar) -> (usize, Cigar) {
+        if events.is_empty() {
+            return (position, cigar.clone());
+        }
+
+        let mut lifted = Cigar::new();
+        let mut ref_start = None;
+        let mut hap_pos = position;
+        let mut started = false;
+
+        for &(length, op) in cigar.ops() {
+            for _ in 0..length {
+                match op {
+                    b'M' | b'D' => {
+                        if started {
+                            lifted.push_run(Haplotype::deleted_before(events, hap_pos), b'D');
+                        }
+                        match Haplotype::locate(events, hap_pos) {
+                            HapBase::Reference(ref_pos) => {
+                                ref_start.get_or_insert(ref_pos);
+                                lifted.push(op);
+                            }
+                            // Read bases over inserted haplotype bases are insertions
+                            // relative to the reference; deleted ones vanish entirely.
+                            HapBase::Inserted if op == b'M' => lifted.push(b'I'),
+                            HapBase::Inserted => {}
+                        }
+                        started = true;
+                        hap_pos += 1;
+                    }
+                    _ => lifted.push(op),
+                }
+            }
+        }
+
+        let ref_start = ref_start.unwrap_or_else(|| match Haplotype::locate(events, hap_pos) {
+            HapBase::Reference(ref_pos) => ref_pos,
+            HapBase::Inserted => position,
+        });
+        (ref_start, lifted)
+    }
+
+    /// Appends the contigs of a spike-in genome `name`, such as the PhiX control of
+    /// Illumina runs or a contaminant, that a `fraction` of the fragments
+    /// [`Self::sample_spike_in`] draws come from. They carry none of the variants
+    /// applied before and are not drawn by [`Self::sample_fragment`]. The fractions
+    /// of all spike-ins may add up to at most 1.
+    pub fn add_spike_in(&mut self, name: &str, sequences: Vec<(String, Vec<u8>)>, fraction: f64) -> Result<(), FastqGenError> {
+        let total = fraction + self.spike_ins.iter().map(|spike_in| spike_in.fraction).sum::<f64>();
+        if !(0.0..=1.0).contains(&fraction) || total > 1.0 {
+            return Err(FastqGenError::invalid("Spike-in fractions must be between 0 and 1 and add up to at most 1."));
+        }
+        if let Some((contig, _)) = sequences.iter().find(|(contig, _)| self.contig_index(contig).is_some()) {
+            return Err(FastqGenError::invalid(format!("Contig {} of spike-in {} is already in the reference.", contig, name)));
+        }
+        let contig_dist = WeightedIndex::new(sequences.iter().map(|(_, seq)| seq.len()))
+            .map_err(|e| FastqGenError::parse(format!("Invalid contigs of spike-in {}: {}", name, e)))?;
+        let first = self.contigs.len();
+        for (name, seq) in sequences {
+            for haplotype in &mut self.haplotypes {
+                if let Some(segments) = &mut haplotype.segments {
+                    segments.push(vec![Segment { contig: self.contigs.len(), start: 0, end: seq.len(), reverse: false, offset: 0 }]);
+                }
+                haplotype.seqs.push(seq.clone());
+                haplotype.indels.push(Vec::new());
+            }
+            self.contigs.push(Contig { name, seq });
+        }
+        self.spike_ins.push(SpikeIn { name: name.to_string(), contigs: first..self.contigs.len(), contig_dist, fraction });
+        Ok(())
+    }
+
+    /// The spike-in genome `contig` belongs to, if any.
+    pub fn spike_in_name(&self, contig: usize) -> Option<&str> {
+        self.spike_ins.iter().find(|spike_in| spike_in.contigs.contains(&contig)).map(|spike_in| spike_in.name.as_str())
+    }
+
+    /// Total fraction of fragments drawn from spike-ins.
+    pub fn spike_in_fraction(&self) -> f64 {
+        self.spike_ins.iter().map(|spike_in| spike_in.fraction).sum()
+    }
+
+    /// With each spike-in's fraction as its probability, a fragment of `length`
+    /// bases from one of its contigs chosen by length, at a uniform position.
+    pub fn sample_spike_in(&self, rng: &mut impl Rng, length: usize) -> Option<Fragment> {
+        if self.spike_ins.is_empty() {
+            return None;
+        }
+        let mut draw: f64 = rng.random();
+        let spike_in = self.spike_ins.iter().find(|spike_in| {
+            draw -= spike_in.fraction;
+            draw < 0.0
+        })?;
+        let contig = spike_in.contigs.start + spike_in.contig_dist.sample(rng);
+        let seq = &self.contigs[contig].seq;
+        let length = length.min(seq.len());
+        let start = rng.random_range(0..=seq.len() - length);
+        Some(Fragment { haplotype: 0, contig, start, seq: seq[start..start + length].to_vec() })
+    }
+
+    /// Draws fragments from each haplotype in proportion to `weights` (one per
+    /// haplotype) rather than uniformly, e.g. to mix tumor clones with normal cells.
+    pub fn set_haplotype_weights(&mut self, weights: &[f64]) -> Result<(), FastqGenError> {
+        if weights.len() != self.haplotypes.len() {
+            return Err(FastqGenError::invalid(format!("Expected {} haplotype weights, found {}.", self.haplotypes.len(), weights.len())));
+        }
+        self.haplotype_weights = Some(WeightedIndex::new(weights).map_err(|e| FastqGenError::invalid(format!("Invalid haplotype weights: {}", e)))?);
+        Ok(())
+    }
+
+    fn sample_haplotype(&self, rng: &mut impl Rng) -> usize {
+        match &self.haplotype_weights {
+            Some(weights) => weights.sample(rng),
+            None => rng.random_range(0..self.haplotypes.len()),
+        }
+    }
+
+    /// The fragment covering reference bases `start..end` of `contig`, on a
+    /// haplotype chosen uniformly at random. Should a structural variant delete
+    /// the start of the region, the fragment comes from the next haplotype that
+    /// keeps it, or from anywhere when none does.
+    pub fn fragment_at(&self, rng: &mut impl Rng, contig: usize, start: usize, end: usize) -> Fragment {
+        if self.haplotypes.is_empty() {
+            return Fragment { haplotype: 0, contig, start, seq: self.contigs[contig].seq[start..end].to_vec() };
+        }
+        let first = self.sample_haplotype(rng);
+        let count = self.haplotypes.len();
+        (0..count)
+            .find_map(|offset| self.haplotype_fragment((first + offset) % count, contig, start, end))
+            .unwrap_or_else(|| self.sample_fragment(rng, end - start))
+    }
+
+    /// The fragment of `haplotype` covering reference bases `start..end` of
+    /// `contig`, unless a structural variant deleted the start of the region.
+    fn haplotype_fragment(&self, index: usize, contig: usize, start: usize, end: usize) -> Option<Fragment> {
+        let haplotype = &self.haplotypes[index];
+        let events = &haplotype.indels[contig];
+        let hap_start = Haplotype::haplotype_position(events, start);
+        let length = Haplotype::haplotype_position(events, end).max(hap_start) - hap_start;
+        let (placed, hap_start) = match &haplotype.segments {
+            None => (contig, hap_start),
+            Some(segments) => segments.iter().enumerate().find_map(|(placed, list)| {
+                list.iter()
+                    .find(|segment| segment.contig == contig && (segment.start..segment.end).contains(&hap_start))
+                    // An inverted segment holds the region's bases in reverse order.
+                    .map(|segment| if segment.reverse {
+                        (placed, (segment.offset + segment.end - hap_start).saturating_sub(length))
+                    } else {
+                        (placed, segment.offset + hap_start - segment.start)
+                    })
+            })?,
+        };
+        let seq = &haplotype.seqs[placed];
+        let hap_end = (hap_start + length).min(seq.len());
+        Some(Fragment { haplotype: index, contig: placed, start: hap_start, seq: seq[hap_start..hap_end].to_vec() })
+    }
+
+    /// Picks a contig with probability proportional to its length (or expression)
+    /// and returns a fragment of `length` bases starting at a uniform position.
+    /// Fragments longer than the chosen contig are truncated to its length.
+    pub fn sample_fragment(&self, rng: &mut impl Rng, length: usize) -> Fragment {
+        let (haplotype, index, contig) = if self.haplotypes.is_empty() {
+            let index = match &self.expression {
+                Some(expression) => expression.sample(rng),
+                None => self.contig_dist.sample(rng),
+            };
+            (0, index, &self.contigs[index].seq)
+        } else {
+            let haplotype = self.sample_haplotype(rng);
+            let index = match &self.expression {
+                Some(expression) => expression.sample(rng),
+                None => self.haplotypes[haplotype].contig_dist.sample(rng),
+            };
+            (haplotype, index, &self.haplotypes[haplotype].seqs[index])
+        };
+        let length = length.min(contig.len());
+        let start = rng.random_range(0..=contig.len() - length);
+        Fragment { haplotype, contig: index, start, seq: contig[start..start + length].to_vec() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::seq::reverse_complement;
+
+    const SEQ: &[u8] = b"ACGGTCATTGCAAGCTTCGA";
+
+    fn reference() -> Reference {
+        Reference::from_sequences(vec![("chr".to_string(), SEQ.to_vec())], 1).unwrap()
+    }
+
+    fn variant(position: usize, reference: &[u8], alternate: &[u8]) -> Variant {
+        Variant { contig: 0, position, reference: reference.to_vec(), alternate: alternate.to_vec(), genotype: vec![true] }
+    }
+
+    fn structural(kind: SvKind, start: usize, end: usize) -> StructuralVariant {
+        StructuralVariant { kind, contig: 0, start, end, partner: None, genotype: vec![true] }
+    }
+
+    fn alignment(position: usize, length: usize) -> Alignment {
+        Alignment { contig: 0, position, reverse: false, cigar: Cigar::matched(length), edit_distance: 0, haplotype: 0 }
+    }
+
+    #[test]
+    fn lifts_reads_across_an_insertion() {
+        let mut reference = reference();
+        reference.apply_variants(&[variant(4, b"T", b"TAAA")], 1).unwrap();
+        assert_eq!(reference.haplotype_seq(0, 0), b"ACGGTAAACATTGCAAGCTTCGA");
+
+        let lifted = reference.lift_alignment(0, alignment(2, 8));
+        assert_eq!((lifted.position, lifted.cigar.to_string()), (2, "3M3I2M".to_string()));
+        // A read starting inside the insertion is placed at the next reference base.
+        let lifted = reference.lift_alignment(0, alignment(6, 4));
+        assert_eq!((lifted.position, lifted.cigar.to_string()), (5, "2I2M".to_string()));
+
+        assert_eq!(reference.reference_position(0, 0, 6), None);
+        assert_eq!(reference.reference_position(0, 0, 8), Some((0, 5)));
+    }
+
+    #[test]
+    fn lifts_reads_across_a_deletion() {
+        let mut reference = reference();
+        reference.apply_variants(&[variant(4, b"TCA", b"T")], 1).unwrap();
+        assert_eq!(reference.haplotype_seq(0, 0), b"ACGGTTTGCAAGCTTCGA");
+
+        let lifted = reference.lift_alignment(0, alignment(2, 6));
+        assert_eq!((lifted.position, lifted.cigar.to_string()), (2, "3M2D3M".to_string()));
+        assert_eq!(reference.reference_position(0, 0, 5), Some((0, 7)));
+    }
+
+    #[test]
+    fn clips_reads_across_a_structural_deletion() {
+        let mut reference = reference();
+        reference.apply_variants(&[], 1).unwrap();
+        reference.apply_structural_variants(&[structural(SvKind::Deletion, 5, 10)]).unwrap();
+        assert_eq!(reference.haplotype_seq(0, 0), b"ACGGTCAAGCTTCGA");
+
+        let lifted = reference.lift_alignment(0, alignment(3, 4));
+        assert_eq!((lifted.position, lifted.cigar.to_string()), (3, "2M2S".to_string()));
+        let lifted = reference.lift_alignment(0, alignment(6, 4));
+        assert_eq!((lifted.position, lifted.cigar.to_string()), (11, "4M".to_string()));
+    }
+
+    #[test]
+    fn places_both_copies_of_a_duplication() {
+        let mut reference = reference();
+        reference.apply_variants(&[], 1).unwrap();
+        reference.apply_structural_variants(&[structural(SvKind::Duplication, 5, 10)]).unwrap();
+        assert_eq!(reference.haplotype_seq(0, 0), b"ACGGTCATTGCATTGCAAGCTTCGA");
+
+        assert_eq!(reference.lift_alignment(0, alignment(5, 3)).position, 5);
+        assert_eq!(reference.lift_alignment(0, alignment(10, 3)).position, 5);
+    }
+
+    #[test]
+    fn flips_the_strand_inside_an_inversion() {
+        let mut reference = reference();
+        reference.apply_variants(&[], 1).unwrap();
+        reference.apply_structural_variants(&[structural(SvKind::Inversion, 5, 15)]).unwrap();
+        let mut expected = SEQ[..5].to_vec();
+        expected.extend(reverse_complement(&SEQ[5..15]));
+        expected.extend_from_slice(&SEQ[15..]);
+        assert_eq!(reference.haplotype_seq(0, 0), expected);
+
+        let lifted = reference.lift_alignment(0, alignment(6, 4));
+        assert_eq!((lifted.position, lifted.reverse, lifted.cigar.to_string()), (10, true, "4M".to_string()));
+        assert_eq!(reference.reference_position(0, 0, 6), Some((0, 13)));
+
+        let fragment = reference.haplotype_fragment(0, 0, 6, 9).unwrap();
+        assert_eq!(fragment.start, 11);
+        assert_eq!(fragment.seq, reverse_complement(&SEQ[6..9]));
+    }
+
+    #[test]
+    fn haplotype_fragment_spans_the_reference_region() {
+        let mut reference = reference();
+        reference.apply_variants(&[variant(4, b"T", b"TAAA")], 1).unwrap();
+        let fragment = reference.haplotype_fragment(0, 0, 2, 8).unwrap();
+        assert_eq!((fragment.start, fragment.seq), (2, b"GGTAAACAT".to_vec()));
+
+        reference.apply_structural_variants(&[structural(SvKind::Deletion, 10, 15)]).unwrap();
+        assert!(reference.haplotype_fragment(0, 0, 11, 13).is_none());
+    }
+}
@@ -0,0 +1,103 @@
+use rand::Rng;
+use rand::distr::Distribution;
+use rand::distr::weighted::WeightedIndex;
+
+use crate::bed::BedInterval;
+use crate::error::FastqGenError;
+use crate::reference::Reference;
+
+/// The intervals of a reference every fragment is drawn from, such as the genes
+/// of a small targeted test set.
+///
+/// A fragment lies wholly within one region, at a uniform position, like a
+/// fragment of a whole contig; one longer than its region is truncated to it.
+/// Regions are chosen by length, for the same depth throughout, or by length
+/// times their BED score, for depth in proportion to the score.
+#[derive(Debug, Clone)]
+pub struct Regions {
+    regions: Vec<BedInterval>,
+    weights: WeightedIndex<f64>,
+}
+
+impl Regions {
+    /// Draws fragments from `regions` of `reference`, weighted by their scores when
+    /// `scored`.
+    pub fn new(reference: &Reference, regions: Vec<BedInterval>, scored: bool) -> Result<Self, FastqGenError> {
+        let mut weights = Vec::with_capacity(regions.len());
+        for region in &regions {
+            let score = match (scored, region.score) {
+                (false, _) => 1.0,
+                (true, Some(score)) if score.is_finite() && score >= 0.0 => score,
+                (true, _) => return Err(FastqGenError::invalid(format!("Region {}:{}-{} needs a score of at least 0 in the fifth BED column.", reference.contig_name(region.contig), region.start, region.end))),
+            };
+            weights.push(score * (region.end - region.start) as f64);
+        }
+        let weights = WeightedIndex::new(&weights)
+            .map_err(|_| FastqGenError::invalid("Reads need at least one region with a positive score."))?;
+        Ok(Regions { regions, weights })
+    }
+
+    /// Total length of the regions, counting overlapping bases once per region.
+    pub fn total_length(&self) -> usize {
+        self.regions.iter().map(|region| region.end - region.start).sum()
+    }
+
+    /// Draws the reference region (contig, start and end) of a fragment of
+    /// `length` bases.
+    pub fn sample(&self, rng: &mut impl Rng, length: usize) -> (usize, usize, usize) {
+        let region = &self.regions[self.weights.sample(rng)];
+        let length = length.clamp(1, region.end - region.start);
+        let start = rng.random_range(region.start..=region.end - length);
+        (region.contig, start, start + length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    fn reference() -> Reference {
+        Reference::from_sequences(vec![("chr1".to_string(), vec![b'A'; 1000]), ("chr2".to_string(), vec![b'C'; 500])], 1).unwrap()
+    }
+
+    fn region(contig: usize, start: usize, end: usize, score: Option<f64>) -> BedInterval {
+        BedInterval { contig, start, end, name: None, score }
+    }
+
+    #[test]
+    fn fragments_lie_within_a_region() {
+        let regions = Regions::new(&reference(), vec![region(0, 100, 400, None), region(1, 50, 80, None)], false).unwrap();
+        assert_eq!(regions.total_length(), 330);
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut drawn = [0; 2];
+        for _ in 0..1000 {
+            let (contig, start, end) = regions.sample(&mut rng, 50);
+            drawn[contig] += 1;
+            match contig {
+                0 => assert!(start >= 100 && end <= 400 && end - start == 50),
+                // Fragments longer than their region are truncated to it.
+                _ => assert_eq!((start, end), (50, 80)),
+            }
+        }
+        // Regions are drawn by length, 300 to 30 bases.
+        assert!(drawn[0] > 8 * drawn[1] && drawn[1] > 0);
+    }
+
+    #[test]
+    fn scores_weight_the_regions() {
+        let regions = vec![region(0, 0, 100, Some(0.0)), region(0, 200, 300, Some(2.5))];
+        let scored = Regions::new(&reference(), regions, true).unwrap();
+        let mut rng = StdRng::seed_from_u64(2);
+        assert!((0..200).all(|_| scored.sample(&mut rng, 10).1 >= 200));
+    }
+
+    #[test]
+    fn rejects_regions_without_scores_or_weight() {
+        assert!(Regions::new(&reference(), vec![region(0, 0, 100, None)], true).is_err());
+        assert!(Regions::new(&reference(), vec![region(0, 0, 100, Some(-1.0))], true).is_err());
+        assert!(Regions::new(&reference(), vec![region(0, 0, 100, Some(0.0))], true).is_err());
+        assert!(Regions::new(&reference(), Vec::new(), false).is_err());
+    }
+}
@@ -0,0 +1,114 @@
+use std::io::{self, Write};
+
+use crate::alignment::Alignment;
+use crate::generator::{FastqRecord, PairedFastqRecord};
+use crate::reference::Reference;
+use crate::seq::reverse_complement;
+
+const PAIRED: u16 = 0x1;
+const PROPER_PAIR: u16 = 0x2;
+const REVERSE: u16 = 0x10;
+const MATE_REVERSE: u16 = 0x20;
+const FIRST_IN_PAIR: u16 = 0x40;
+const SECOND_IN_PAIR: u16 = 0x80;
+
+/// Mapping quality reported for every truth alignment.
+const TRUTH_MAPQ: u8 = 60;
+
+pub fn write_sam_header(out: &mut impl Write, reference: &Reference) -> io::Result<()> {
+    writeln!(out, "@HD\tVN:1.6\tSO:unsorted")?;
+    for (name, length) in reference.contigs() {
+        writeln!(out, "@SQ\tSN:{}\tLN:{}", name, length)?;
+    }
+    writeln!(out, "@PG\tID:fastqgen\tPN:fastqgen\tVN:{}", env!("CARGO_PKG_VERSION"))
+}
+
+/// Appends the true alignments of both mates of `record`, whose qualities are
+/// encoded with `quality_offset`. Records without an alignment (reads not sampled
+/// from a reference) are skipped.
+pub fn write_sam_pair(out: &mut Vec<u8>, reference: &Reference, record: &PairedFastqRecord, quality_offset: u8) {
+    let (Some(aln_1), Some(aln_2)) = (&record.alignment_1, &record.alignment_2) else {
+        return;
+    };
+
+    // Mates on different contigs, as across a translocation, have no template length.
+    let (tlen_1, proper) = if aln_1.contig == aln_2.contig {
+        let span = (aln_1.end().max(aln_2.end()) - aln_1.position.min(aln_2.position)) as i64;
+        (if aln_1.position <= aln_2.position { span } else { -span }, PROPER_PAIR)
+    } else {
+        (0, 0)
+    };
+
+    let flag_1 = PAIRED | proper | FIRST_IN_PAIR | strand_flags(aln_1, aln_2);
+    let flag_2 = PAIRED | proper | SECOND_IN_PAIR | strand_flags(aln_2, aln_1);
+
+    SamLine { name: &record.id, flag: flag_1, aln: aln_1, mate: Some(aln_2), tlen: tlen_1, seq: &record.seq, quality: &record.quality_1, quality_offset }
+        .write(out, reference);
+    SamLine { name: &record.id, flag: flag_2, aln: aln_2, mate: Some(aln_1), tlen: -tlen_1, seq: &record.mate, quality: &record.quality_2, quality_offset }
+        .write(out, reference);
+}
+
+/// Appends the true alignment of a single-end `record`, if it has one.
+pub fn write_sam_single(out: &mut Vec<u8>, reference: &Reference, record: &FastqRecord, quality_offset: u8) {
+    if let Some(aln) = &record.alignment {
+        let flag = if aln.reverse { REVERSE } else { 0 };
+        SamLine { name: &record.id, flag, aln, mate: None, tlen: 0, seq: &record.seq, quality: &record.quality, quality_offset }
+            .write(out, reference);
+    }
+}
+
+fn strand_flags(aln: &Alignment, mate: &Alignment) -> u16 {
+    let mut flags = 0;
+    if aln.reverse {
+        flags |= REVERSE;
+    }
+    if mate.reverse {
+        flags |= MATE_REVERSE;
+    }
+    flags
+}
+
+struct SamLine<'a> {
+    name: &'a str,
+    flag: u16,
+    aln: &'a Alignment,
+    mate: Option<&'a Alignment>,
+    tlen: i64,
+    /// Read sequence and quality in sequencing orientation.
+    seq: &'a [u8],
+    quality: &'a [u8],
+    /// ASCII offset of `quality`; SAM qualities are always Phred+33.
+    quality_offset: u8,
+}
+
+impl SamLine<'_> {
+    fn write(&self, out: &mut Vec<u8>, reference: &Reference) {
+        let aln = self.aln;
+        // SAM stores reverse-strand reads in reference orientation.
+        let (seq, mut quality): (Vec<u8>, Vec<u8>) = if aln.reverse {
+            (reverse_complement(self.seq), self.quality.iter().rev().copied().collect())
+        } else {
+            (self.seq.to_vec(), self.quality.to_vec())
+        };
+        for score in &mut quality {
+            *score = *score - self.quality_offset + 33;
+        }
+        let (rnext, pnext) = match self.mate {
+            Some(mate) if mate.contig == aln.contig => ("=", mate.position + 1),
+            Some(mate) => (reference.contig_name(mate.contig), mate.position + 1),
+            None => ("*", 0),
+        };
+
+        // Writing to a Vec cannot fail.
+        let _ = write!(
+            out,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t",
+            self.name, self.flag, reference.contig_name(aln.contig), aln.position + 1, TRUTH_MAPQ, aln.cigar, rnext, pnext, self.tlen
+        );
+        out.extend_from_slice(&seq);
+        out.push(b'\t');
+        out.extend_from_slice(&quality);
+        let edit_distance = aln.cigar.edit_distance(reference.contig_seq(aln.contig), aln.position, &seq);
+        let _ = writeln!(out, "\tNM:i:{}", edit_distance);
+    }
+}
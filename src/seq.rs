@@ -0,0 +1,126 @@
+/// Complement of every byte: upper-case `A`, `C`, `G` and `T` swap with their
+/// partners and every other byte maps to itself.
+const COMPLEMENT: [u8; 256] = {
+    let mut table = [0; 256];
+    let mut byte = 0;
+    while byte < 256 {
+        table[byte] = byte as u8;
+        byte += 1;
+    }
+    table[b'A' as usize] = b'T';
+    table[b'T' as usize] = b'A';
+    table[b'C' as usize] = b'G';
+    table[b'G' as usize] = b'C';
+    table
+};
+
+/// Returns the Watson-Crick complement of `base`. Anything other than an
+/// upper-case `A`, `C`, `G` or `T` (e.g. `N`) is returned unchanged.
+pub fn complement(base: u8) -> u8 {
+    COMPLEMENT[base as usize]
+}
+
+pub fn reverse_complement(seq: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(seq.len());
+    reverse_complement_into(seq, &mut out);
+    out
+}
+
+/// Appends the reverse complement of `seq` to `out`. On x86-64 CPUs with SSSE3,
+/// 16 bases are handled at a time.
+pub fn reverse_complement_into(seq: &[u8], out: &mut Vec<u8>) {
+    out.reserve(seq.len());
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("ssse3") {
+        // SAFETY: the CPU supports SSSE3.
+        return unsafe { simd::reverse_complement_into(seq, out) };
+    }
+    out.extend(seq.iter().rev().map(|&base| COMPLEMENT[base as usize]));
+}
+
+/// Reverse-complements `seq` in place.
+pub fn reverse_complement_in_place(seq: &mut [u8]) {
+    seq.reverse();
+    for base in seq {
+        *base = COMPLEMENT[*base as usize];
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod simd {
+    use std::arch::x86_64::*;
+
+    use super::COMPLEMENT;
+
+    /// Reverses each 16-base block with a byte shuffle, then swaps A with T and
+    /// C with G by XOR-ing the bases that match with the difference of the pair.
+    #[target_feature(enable = "ssse3")]
+    pub(super) fn reverse_complement_into(seq: &[u8], out: &mut Vec<u8>) {
+        let reverse = _mm_setr_epi8(15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0);
+        let [a, c, g, t] = [b'A', b'C', b'G', b'T'].map(|base| _mm_set1_epi8(base as i8));
+        let (flip_at, flip_cg) = (_mm_set1_epi8((b'A' ^ b'T') as i8), _mm_set1_epi8((b'C' ^ b'G') as i8));
+        let mut block = [0u8; 16];
+        let mut end = seq.len();
+        while end >= 16 {
+            // SAFETY: the 16 bytes before `end` lie in `seq`.
+            let bases = unsafe { _mm_loadu_si128(seq.as_ptr().add(end - 16).cast()) };
+            let reversed = _mm_shuffle_epi8(bases, reverse);
+            let is_at = _mm_or_si128(_mm_cmpeq_epi8(reversed, a), _mm_cmpeq_epi8(reversed, t));
+            let is_cg = _mm_or_si128(_mm_cmpeq_epi8(reversed, c), _mm_cmpeq_epi8(reversed, g));
+            let flip = _mm_or_si128(_mm_and_si128(is_at, flip_at), _mm_and_si128(is_cg, flip_cg));
+            // SAFETY: `block` holds 16 bytes.
+            unsafe { _mm_storeu_si128(block.as_mut_ptr().cast(), _mm_xor_si128(reversed, flip)) };
+            out.extend_from_slice(&block);
+            end -= 16;
+        }
+        out.extend(seq[..end].iter().rev().map(|&base| COMPLEMENT[base as usize]));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scalar_reverse_complement(seq: &[u8]) -> Vec<u8> {
+        seq.iter().rev().map(|&base| COMPLEMENT[base as usize]).collect()
+    }
+
+    #[test]
+    fn reverse_complement_matches_the_table() {
+        let bases = b"ACGTNacgtnRY-.*\x00\xff";
+        for length in [0, 1, 15, 16, 17, 32, 33, 100] {
+            let seq: Vec<u8> = (0..length).map(|i| bases[i * 7 % bases.len()]).collect();
+            let mut out = b"prefix".to_vec();
+            reverse_complement_into(&seq, &mut out);
+            assert_eq!(&out[..6], b"prefix");
+            assert_eq!(out[6..], scalar_reverse_complement(&seq), "length {}", length);
+        }
+        // Every byte value, in blocks of 16 and in the tail.
+        let every: Vec<u8> = (0..=255).chain(0..7).collect();
+        assert_eq!(reverse_complement(&every), scalar_reverse_complement(&every));
+        assert_eq!(reverse_complement(b"AACGTTN"), b"NAACGTT");
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn simd_blocks_match_the_table() {
+        if !is_x86_feature_detected!("ssse3") {
+            return;
+        }
+        for length in [0, 15, 16, 17, 33] {
+            let seq: Vec<u8> = (0..length).map(|i| b"ACGTNacgtX"[i % 10]).collect();
+            let mut out = Vec::new();
+            // SAFETY: the CPU supports SSSE3.
+            unsafe { simd::reverse_complement_into(&seq, &mut out) };
+            assert_eq!(out, scalar_reverse_complement(&seq), "length {}", length);
+        }
+    }
+
+    #[test]
+    fn in_place_matches_the_copy() {
+        let mut seq = b"ACGTNacgtAAC".to_vec();
+        let expected = reverse_complement(&seq);
+        reverse_complement_in_place(&mut seq);
+        assert_eq!(seq, expected);
+    }
+}
@@ -0,0 +1,192 @@
+use rand::Rng;
+use rand::distr::Distribution;
+use rand::distr::weighted::WeightedIndex;
+use rand_distr::Binomial;
+use std::collections::HashSet;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use crate::error::FastqGenError;
+use crate::reference::{Reference, open_text};
+use crate::seq::complement;
+use crate::variants::Variant;
+
+/// Number of single-base substitution channels: six substitutions of a
+/// pyrimidine, each between four 5' and four 3' neighbours.
+pub const SBS_CHANNELS: usize = 96;
+
+/// The substitutions of the SBS channels, from the pyrimidine of the pair.
+const SUBSTITUTIONS: [(u8, u8); 6] = [(b'C', b'A'), (b'C', b'G'), (b'C', b'T'), (b'T', b'A'), (b'T', b'C'), (b'T', b'G')];
+
+/// Most positions drawn for one substitution before giving up on finding its
+/// trinucleotide context in the reference.
+const MAX_CONTEXT_DRAWS: usize = 1_000_000;
+
+/// A single-base substitution (SBS) signature: the probability of each of the 96
+/// channels, in the order of COSMIC (`A[C>A]A`, `A[C>A]C`, ...).
+#[derive(Debug, Clone)]
+pub struct Signature {
+    pub name: String,
+    pub profile: Vec<f64>,
+}
+
+/// Index of a channel: a substitution of the middle base of a trinucleotide,
+/// read on the strand where it is a pyrimidine.
+fn channel(five: u8, reference: u8, alternate: u8, three: u8) -> Option<usize> {
+    let substitution = SUBSTITUTIONS.iter().position(|&pair| pair == (reference, alternate))?;
+    let base = |base: u8| b"ACGT".iter().position(|&other| other == base);
+    Some(substitution * 16 + base(five)? * 4 + base(three)?)
+}
+
+/// The 5' neighbour, reference base, alternate base and 3' neighbour of a channel.
+fn channel_bases(index: usize) -> (u8, u8, u8, u8) {
+    let (reference, alternate) = SUBSTITUTIONS[index / 16];
+    (b"ACGT"[index / 4 % 4], reference, alternate, b"ACGT"[index % 4])
+}
+
+/// Name of a channel as COSMIC writes it, e.g. `A[C>T]G`.
+fn channel_name(index: usize) -> String {
+    let (five, reference, alternate, three) = channel_bases(index);
+    format!("{}[{}>{}]{}", five as char, reference as char, alternate as char, three as char)
+}
+
+/// Reads SBS signatures from a matrix in the format COSMIC distributes them in,
+/// such as `COSMIC_v3.4_SBS_GRCh38.txt`: a header naming the signatures after a
+/// first column of channels, then one line per channel, e.g.
+/// `A[C>A]A<TAB>8.86e-04<TAB>...`. Fields may be separated by tabs or commas.
+pub fn read_signatures(path: &Path) -> Result<Vec<Signature>, FastqGenError> {
+    let reader = open_text(path)
+        .map_err(|e| FastqGenError::io(format!("Failed to open signature matrix {}", path.display()), e))?;
+    let mut lines = reader.lines().enumerate();
+    let header = match lines.next() {
+        Some((_, line)) => line?,
+        None => return Err(FastqGenError::parse(format!("{} is empty.", path.display()))),
+    };
+    let mut signatures: Vec<Signature> = header.trim_end().split(['\t', ','])
+        .skip(1)
+        .map(|name| Signature { name: name.trim().to_string(), profile: vec![0.0; SBS_CHANNELS] })
+        .collect();
+    let mut seen = [false; SBS_CHANNELS];
+
+    for (line_number, line) in lines {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let location = format!("{}:{}", path.display(), line_number + 1);
+        let fields: Vec<&str> = line.trim_end().split(['\t', ',']).collect();
+        let bases = fields[0].trim().as_bytes();
+        let index = match bases {
+            &[five, b'[', reference, b'>', alternate, b']', three] => channel(five, reference, alternate, three),
+            _ => None,
+        }
+        .ok_or_else(|| FastqGenError::parse(format!("{}: {} is not an SBS channel such as A[C>T]G.", location, fields[0])))?;
+        if fields.len() != signatures.len() + 1 {
+            return Err(FastqGenError::parse(format!("{}: expected {} signatures, found {}.", location, signatures.len(), fields.len() - 1)));
+        }
+        for (signature, field) in signatures.iter_mut().zip(&fields[1..]) {
+            signature.profile[index] = field.trim().parse::<f64>().ok().filter(|p| *p >= 0.0 && p.is_finite())
+                .ok_or_else(|| FastqGenError::parse(format!("{}: invalid probability {}.", location, field)))?;
+        }
+        seen[index] = true;
+    }
+    if let Some(missing) = seen.iter().position(|seen| !seen) {
+        return Err(FastqGenError::parse(format!("{} has no {} channel.", path.display(), channel_name(missing))));
+    }
+    Ok(signatures)
+}
+
+/// Somatic substitutions drawn from a mixture of SBS signatures at known
+/// exposures, in the trinucleotide contexts of a reference. Each substitution
+/// comes from a signature chosen by exposure, which chooses its channel; it then
+/// lands on a position of the reference, on either strand, with that context.
+#[derive(Debug, Clone)]
+pub struct SignatureMixture {
+    signatures: Vec<Signature>,
+    exposures: Vec<f64>,
+    choose: WeightedIndex<f64>,
+    channels: Vec<WeightedIndex<f64>>,
+}
+
+impl SignatureMixture {
+    /// The mixture of the signatures of `matrix` named in `exposures`, each with its
+    /// weight. Exposures are scaled to sum to one.
+    pub fn new(matrix: Vec<Signature>, exposures: &[(String, f64)]) -> Result<Self, FastqGenError> {
+        let mut signatures = Vec::with_capacity(exposures.len());
+        for (name, _) in exposures {
+            let signature = matrix.iter().find(|signature| &signature.name == name)
+                .ok_or_else(|| FastqGenError::invalid(format!("Signature {} is not in the signature matrix.", name)))?;
+            signatures.push(signature.clone());
+        }
+        let total: f64 = exposures.iter().map(|(_, exposure)| exposure).sum();
+        let exposures: Vec<f64> = exposures.iter().map(|(_, exposure)| exposure / total).collect();
+        let choose = WeightedIndex::new(&exposures)
+            .map_err(|_| FastqGenError::invalid("At least one signature needs a positive exposure."))?;
+        let channels = signatures.iter()
+            .map(|signature| WeightedIndex::new(&signature.profile)
+                .map_err(|_| FastqGenError::invalid(format!("Signature {} has no positive channel.", signature.name))))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(SignatureMixture { signatures, exposures, choose, channels })
+    }
+
+    pub fn signatures(&self) -> &[Signature] {
+        &self.signatures
+    }
+
+    /// Draws a binomial number of SNVs at `rate` per base of `reference`, each on
+    /// one haplotype, at distinct positions. Returns them sorted by position, with
+    /// the index of the signature behind each.
+    pub fn simulate_snvs(&self, reference: &Reference, rng: &mut impl Rng, rate: f64) -> Result<Vec<(Variant, usize)>, FastqGenError> {
+        if !(0.0..=1.0).contains(&rate) {
+            return Err(FastqGenError::invalid("SNV rate must be between 0 and 1."));
+        }
+        let count = Binomial::new(reference.total_length() as u64, rate)
+            .map_err(|e| FastqGenError::invalid(format!("Invalid SNV rate: {}", e)))?
+            .sample(rng);
+        // Positions with a base on either side, so that each has a context.
+        let contigs = WeightedIndex::new(reference.contigs().map(|(_, length)| length.saturating_sub(2)))
+            .map_err(|e| FastqGenError::invalid(format!("Invalid reference: {}", e)))?;
+        let mut used = HashSet::new();
+        let mut variants = Vec::with_capacity(count as usize);
+
+        for _ in 0..count {
+            let signature = self.choose.sample(rng);
+            let index = self.channels[signature].sample(rng);
+            let (five, reference_base, alternate, three) = channel_bases(index);
+            let mut draws = 0;
+            let variant = loop {
+                draws += 1;
+                if draws > MAX_CONTEXT_DRAWS {
+                    return Err(FastqGenError::invalid(format!("Found no free {} context in the reference for signature {}.", channel_name(index), self.signatures[signature].name)));
+                }
+                let contig = contigs.sample(rng);
+                let seq = reference.contig_seq(contig);
+                let position = rng.random_range(1..seq.len() - 1);
+                let (before, base, after) = (seq[position - 1], seq[position], seq[position + 1]);
+                // Read the context on the strand where the middle base is a pyrimidine.
+                let (context, forward) = match base {
+                    b'C' | b'T' => ((before, base, after), true),
+                    _ => ((complement(after), complement(base), complement(before)), false),
+                };
+                if context == (five, reference_base, three) && used.insert((contig, position)) {
+                    let alternate = if forward { alternate } else { complement(alternate) };
+                    break Variant { contig, position, reference: vec![base], alternate: vec![alternate], genotype: vec![true] };
+                }
+            };
+            variants.push((variant, signature));
+        }
+        variants.sort_by_key(|(variant, _)| (variant.contig, variant.position));
+        Ok(variants)
+    }
+
+    /// Writes the exposure of every signature and the number of SNVs it caused as
+    /// `signature<TAB>exposure<TAB>mutations` lines. `mutations` holds the count
+    /// of each signature in turn.
+    pub fn write_exposures(&self, out: &mut impl Write, mutations: &[u64]) -> io::Result<()> {
+        writeln!(out, "signature\texposure\tmutations")?;
+        for ((signature, exposure), mutations) in self.signatures.iter().zip(&self.exposures).zip(mutations) {
+            writeln!(out, "{}\t{:.6}\t{}", signature.name, exposure, mutations)?;
+        }
+        Ok(())
+    }
+}
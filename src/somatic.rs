@@ -0,0 +1,111 @@
+use rand::Rng;
+use std::io::{self, Write};
+
+use crate::error::FastqGenError;
+use crate::reference::Reference;
+use crate::variants::Variant;
+
+/// Number of germline haplotypes the tumor clones derive from.
+const GERMLINE_PLOIDY: usize = 2;
+
+/// The haplotypes of a tumor sample: the two germline haplotypes, contributed by
+/// normal cells and by the unmutated alleles of tumor cells, and one clone
+/// haplotype per variant allele fraction. Every somatic variant is placed on one
+/// clone, a copy of one germline haplotype, that is sampled with a weight equal to
+/// the variant's allele fraction in the tumor sample.
+#[derive(Debug, Clone)]
+pub struct TumorModel {
+    /// Allele fraction of each clone in a pure tumor.
+    vafs: Vec<f64>,
+    /// The germline haplotype each clone is a copy of.
+    parents: Vec<usize>,
+    purity: f64,
+}
+
+impl TumorModel {
+    /// Places each allele fraction on the germline haplotype with the most room
+    /// left, largest first; the fractions carried by each germline haplotype may
+    /// add up to at most 0.5.
+    pub fn new(vafs: Vec<f64>, purity: f64) -> Result<Self, FastqGenError> {
+        if vafs.is_empty() || vafs.iter().any(|vaf| !(*vaf > 0.0 && *vaf <= 0.5)) {
+            return Err(FastqGenError::invalid("Somatic allele fractions must be above 0 and at most 0.5."));
+        }
+        if !(0.0..=1.0).contains(&purity) {
+            return Err(FastqGenError::invalid("Tumor purity must be between 0 and 1."));
+        }
+        let mut order: Vec<usize> = (0..vafs.len()).collect();
+        order.sort_by(|&a, &b| vafs[b].total_cmp(&vafs[a]));
+        let mut room = [0.5; GERMLINE_PLOIDY];
+        let mut parents = vec![0; vafs.len()];
+        for clone in order {
+            let parent = if room[0] >= room[1] { 0 } else { 1 };
+            if vafs[clone] > room[parent] + 1e-9 {
+                return Err(FastqGenError::invalid("Somatic allele fractions must fit on two germline haplotypes: each haplotype's fractions may add up to at most 0.5."));
+            }
+            room[parent] -= vafs[clone];
+            parents[clone] = parent;
+        }
+        Ok(TumorModel { vafs, parents, purity })
+    }
+
+    /// Number of germline and clone haplotypes.
+    pub fn ploidy(&self) -> usize {
+        GERMLINE_PLOIDY + self.vafs.len()
+    }
+
+    /// Allele fraction of the variants of `clone` in the tumor sample.
+    pub fn observed_vaf(&self, clone: usize) -> f64 {
+        self.vafs[clone] * self.purity
+    }
+
+    /// Extends the diploid genotype of a germline variant to the clones, which
+    /// carry their parent haplotype's allele.
+    pub fn expand_germline(&self, genotype: &mut Vec<bool>) {
+        let clones: Vec<bool> = self.parents.iter().map(|&parent| genotype[parent]).collect();
+        genotype.extend(clones);
+    }
+
+    /// Places a somatic variant on a clone chosen uniformly at random, and returns
+    /// the clone.
+    pub fn place_somatic(&self, rng: &mut impl Rng, variant: &mut Variant) -> usize {
+        let clone = rng.random_range(0..self.vafs.len());
+        variant.genotype = (0..self.ploidy()).map(|haplotype| haplotype == GERMLINE_PLOIDY + clone).collect();
+        clone
+    }
+
+    /// Sampling weights of the haplotypes in the tumor sample.
+    pub fn tumor_weights(&self) -> Vec<f64> {
+        let mut weights = vec![0.5; GERMLINE_PLOIDY];
+        for clone in 0..self.vafs.len() {
+            weights[self.parents[clone]] -= self.observed_vaf(clone);
+        }
+        weights.extend((0..self.vafs.len()).map(|clone| self.observed_vaf(clone)));
+        weights
+    }
+}
+
+/// Writes somatic variants as a sites-only VCF with `SOMATIC` and their allele
+/// fraction in the tumor sample as `AF`. `vafs` holds the fraction of each variant.
+pub fn write_somatic_vcf(out: &mut impl Write, reference: &Reference, variants: &[Variant], vafs: &[f64]) -> io::Result<()> {
+    writeln!(out, "##fileformat=VCFv4.2")?;
+    writeln!(out, "##source=fastqgen-{}", env!("CARGO_PKG_VERSION"))?;
+    for (name, length) in reference.contigs() {
+        writeln!(out, "##contig=<ID={},length={}>", name, length)?;
+    }
+    writeln!(out, "##INFO=<ID=SOMATIC,Number=0,Type=Flag,Description=\"Somatic variant\">")?;
+    writeln!(out, "##INFO=<ID=AF,Number=A,Type=Float,Description=\"Allele fraction in the tumor sample\">")?;
+    writeln!(out, "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO")?;
+
+    for (variant, vaf) in variants.iter().zip(vafs) {
+        writeln!(
+            out,
+            "{}\t{}\t.\t{}\t{}\t.\tPASS\tSOMATIC;AF={:.4}",
+            reference.contig_name(variant.contig),
+            variant.position + 1,
+            String::from_utf8_lossy(&variant.reference),
+            String::from_utf8_lossy(&variant.alternate),
+            vaf
+        )?;
+    }
+    Ok(())
+}
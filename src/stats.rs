@@ -0,0 +1,181 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+use crate::input::FastqRead;
+
+/// Highest Phred score tracked; higher scores are counted as this value.
+pub(crate) const MAX_PHRED: usize = 93;
+
+/// Summary statistics accumulated over the records of a FASTQ file.
+#[derive(Debug, Clone)]
+pub struct FastqStats {
+    quality_offset: u8,
+    reads: u64,
+    bases: u64,
+    gc: u64,
+    n: u64,
+    lengths: BTreeMap<usize, u64>,
+    /// Histogram of Phred scores at each read position.
+    position_quality: Vec<[u64; MAX_PHRED + 1]>,
+}
+
+/// The statistics of one file, as reported by `fastqgen stats`.
+#[derive(Debug, Clone, Serialize)]
+pub struct StatsReport {
+    pub file: String,
+    pub reads: u64,
+    pub bases: u64,
+    pub min_length: usize,
+    pub max_length: usize,
+    pub mean_length: f64,
+    /// Fraction of called (non-N) bases that are G or C.
+    pub gc_content: f64,
+    pub n_fraction: f64,
+    pub length_distribution: BTreeMap<usize, u64>,
+    /// Mean and median Phred score at each position, 1-based from the 5' end.
+    pub mean_quality: Vec<f64>,
+    pub median_quality: Vec<u8>,
+}
+
+impl FastqStats {
+    /// Statistics for quality strings encoded with `quality_offset` (33 for Phred+33).
+    pub fn new(quality_offset: u8) -> Self {
+        FastqStats {
+            quality_offset,
+            reads: 0,
+            bases: 0,
+            gc: 0,
+            n: 0,
+            lengths: BTreeMap::new(),
+            position_quality: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, record: &FastqRead) {
+        self.reads += 1;
+        self.bases += record.seq.len() as u64;
+        *self.lengths.entry(record.seq.len()).or_default() += 1;
+        for base in &record.seq {
+            match base.to_ascii_uppercase() {
+                b'G' | b'C' => self.gc += 1,
+                b'N' => self.n += 1,
+                _ => {}
+            }
+        }
+
+        if self.position_quality.len() < record.quality.len() {
+            self.position_quality.resize(record.quality.len(), [0; MAX_PHRED + 1]);
+        }
+        for (histogram, &score) in self.position_quality.iter_mut().zip(&record.quality) {
+            let phred = (score.saturating_sub(self.quality_offset) as usize).min(MAX_PHRED);
+            histogram[phred] += 1;
+        }
+    }
+
+    pub fn report(&self, file: &str) -> StatsReport {
+        let called = self.bases - self.n;
+        let fraction = |count: u64, total: u64| if total == 0 { 0.0 } else { count as f64 / total as f64 };
+        StatsReport {
+            file: file.to_string(),
+            reads: self.reads,
+            bases: self.bases,
+            min_length: self.lengths.keys().next().copied().unwrap_or(0),
+            max_length: self.lengths.keys().next_back().copied().unwrap_or(0),
+            mean_length: fraction(self.bases, self.reads),
+            gc_content: fraction(self.gc, called),
+            n_fraction: fraction(self.n, self.bases),
+            length_distribution: self.lengths.clone(),
+            mean_quality: self.position_quality.iter()
+                .map(|histogram| {
+                    let count: u64 = histogram.iter().sum();
+                    let total: u64 = histogram.iter().enumerate().map(|(phred, &n)| phred as u64 * n).sum();
+                    fraction(total, count)
+                })
+                .collect(),
+            median_quality: self.position_quality.iter().map(median).collect(),
+        }
+    }
+}
+
+fn median(histogram: &[u64; MAX_PHRED + 1]) -> u8 {
+    let count: u64 = histogram.iter().sum();
+    let mut seen = 0;
+    for (phred, &n) in histogram.iter().enumerate() {
+        seen += n;
+        if seen * 2 >= count && n > 0 {
+            return phred as u8;
+        }
+    }
+    0
+}
+
+impl StatsReport {
+    /// Writes the report as tab-separated sections: a summary, the length
+    /// distribution and per-position qualities.
+    pub fn write_table(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "# {}", self.file)?;
+        writeln!(out, "reads\t{}", self.reads)?;
+        writeln!(out, "bases\t{}", self.bases)?;
+        writeln!(out, "length\tmin {}\tmean {:.1}\tmax {}", self.min_length, self.mean_length, self.max_length)?;
+        writeln!(out, "gc_content\t{:.4}", self.gc_content)?;
+        writeln!(out, "n_fraction\t{:.6}", self.n_fraction)?;
+        writeln!(out)?;
+        writeln!(out, "length\tcount")?;
+        for (length, count) in &self.length_distribution {
+            writeln!(out, "{}\t{}", length, count)?;
+        }
+        writeln!(out)?;
+        writeln!(out, "position\tmean_quality\tmedian_quality")?;
+        for (position, (mean, median)) in self.mean_quality.iter().zip(&self.median_quality).enumerate() {
+            writeln!(out, "{}\t{:.2}\t{}", position + 1, mean, median)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(seq: &[u8], quality: &[u8]) -> FastqRead {
+        FastqRead { header: "read".to_string(), seq: seq.to_vec(), quality: quality.to_vec() }
+    }
+
+    #[test]
+    fn reports_lengths_and_base_content() {
+        let mut stats = FastqStats::new(33);
+        stats.add(&read(b"GGCCNN", b"IIIIII"));
+        stats.add(&read(b"atgc", b"IIII"));
+        let report = stats.report("test.fq");
+        assert_eq!((report.reads, report.bases, report.min_length, report.max_length), (2, 10, 4, 6));
+        assert_eq!(report.mean_length, 5.0);
+        // GC is a fraction of the called bases, leaving out N.
+        assert_eq!(report.gc_content, 6.0 / 8.0);
+        assert_eq!(report.n_fraction, 0.2);
+        assert_eq!(report.length_distribution, BTreeMap::from([(4, 1), (6, 1)]));
+    }
+
+    #[test]
+    fn summarizes_qualities_by_position() {
+        let mut stats = FastqStats::new(33);
+        for quality in [b"+5", b"5?", b"?I", b"I5"] {
+            stats.add(&read(b"AC", quality));
+        }
+        let report = stats.report("test.fq");
+        assert_eq!(report.mean_quality, vec![25.0, 27.5]);
+        // Of an even number of scores, the median is the lower middle one.
+        assert_eq!(report.median_quality, vec![20, 20]);
+    }
+
+    #[test]
+    fn reports_an_empty_file() {
+        let report = FastqStats::new(33).report("empty.fq");
+        assert_eq!((report.reads, report.bases, report.min_length, report.max_length), (0, 0, 0, 0));
+        assert_eq!((report.mean_length, report.gc_content, report.n_fraction), (0.0, 0.0, 0.0));
+        assert!(report.mean_quality.is_empty() && report.median_quality.is_empty());
+        let mut table = Vec::new();
+        report.write_table(&mut table).unwrap();
+        assert!(String::from_utf8(table).unwrap().starts_with("# empty.fq\nreads\t0\n"));
+    }
+}
@@ -0,0 +1,245 @@
+use rand::Rng;
+use rand::distr::Distribution;
+use rand::distr::weighted::WeightedIndex;
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+
+use crate::error::FastqGenError;
+use crate::reference::Reference;
+use crate::variants::{Variant, sample_genotype};
+
+/// Attempts at placing each structural variant clear of the others before giving up.
+const PLACEMENT_ATTEMPTS: usize = 1000;
+
+/// The kind of rearrangement a [`StructuralVariant`] makes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SvKind {
+    /// The region is removed.
+    Deletion,
+    /// The region is followed by a second copy of itself.
+    Duplication,
+    /// The region is replaced by its reverse complement.
+    Inversion,
+    /// The contig after the breakpoint is exchanged with that of the partner
+    /// contig after its breakpoint, as in a reciprocal translocation.
+    Translocation,
+}
+
+impl SvKind {
+    /// The VCF `SVTYPE` of the kind.
+    pub fn name(self) -> &'static str {
+        match self {
+            SvKind::Deletion => "DEL",
+            SvKind::Duplication => "DUP",
+            SvKind::Inversion => "INV",
+            SvKind::Translocation => "BND",
+        }
+    }
+}
+
+/// A structural variant injected into the simulated haplotypes.
+#[derive(Debug, Clone)]
+pub struct StructuralVariant {
+    pub kind: SvKind,
+    pub contig: usize,
+    /// 0-based, half-open region of the reference affected; a translocation's
+    /// breakpoint lies before `start`, and `end` equals `start`.
+    pub start: usize,
+    pub end: usize,
+    /// A translocation's partner contig and breakpoint.
+    pub partner: Option<(usize, usize)>,
+    /// Whether each haplotype carries the variant.
+    pub genotype: Vec<bool>,
+}
+
+impl StructuralVariant {
+    /// The regions of the reference the variant spans, including the base before
+    /// each breakpoint, which a VCF record is anchored on.
+    fn footprint(&self) -> impl Iterator<Item = (usize, usize, usize)> {
+        let partner = self.partner.map(|(contig, breakpoint)| (contig, breakpoint - 1, breakpoint + 1));
+        std::iter::once((self.contig, self.start - 1, self.end.max(self.start + 1))).chain(partner)
+    }
+}
+
+/// How many structural variants of each kind to inject.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SvCounts {
+    pub deletions: usize,
+    pub duplications: usize,
+    pub inversions: usize,
+    pub translocations: usize,
+}
+
+/// Places structural variants across the reference, with lengths drawn
+/// log-uniformly from `lengths` and genotypes drawn as in
+/// [`crate::simulate_snps`]. Variants do not overlap or abut one another, and
+/// each contig takes part in at most one translocation, whose breakpoints fall
+/// on two different contigs.
+pub fn simulate_structural_variants(reference: &Reference, rng: &mut impl Rng, counts: SvCounts, lengths: RangeInclusive<usize>, hom_fraction: f64, ploidy: usize) -> Result<Vec<StructuralVariant>, FastqGenError> {
+    let mut variants: Vec<StructuralVariant> = Vec::new();
+    let kinds = [
+        (SvKind::Deletion, counts.deletions),
+        (SvKind::Duplication, counts.duplications),
+        (SvKind::Inversion, counts.inversions),
+        (SvKind::Translocation, counts.translocations),
+    ];
+    if kinds.iter().all(|&(_, count)| count == 0) {
+        return Ok(variants);
+    }
+    if *lengths.start() == 0 || lengths.start() > lengths.end() {
+        return Err(FastqGenError::invalid("Structural variant lengths must be positive, with the minimum at most the maximum."));
+    }
+    if counts.translocations > 0 && reference.contigs().count() < 2 * counts.translocations {
+        return Err(FastqGenError::invalid("Each translocation needs two contigs of its own."));
+    }
+    let contig_dist = WeightedIndex::new(reference.contigs().map(|(_, length)| length))
+        .map_err(|e| FastqGenError::invalid(format!("Invalid reference for structural variants: {}", e)))?;
+    let (min, max) = (*lengths.start() as f64, *lengths.end() as f64);
+
+    for (kind, count) in kinds {
+        for _ in 0..count {
+            let placed = (0..PLACEMENT_ATTEMPTS).find_map(|_| {
+                let contig = contig_dist.sample(rng);
+                let contig_length = reference.contig_seq(contig).len();
+                let variant = if kind == SvKind::Translocation {
+                    let partner = contig_dist.sample(rng);
+                    let partner_length = reference.contig_seq(partner).len();
+                    if partner == contig || contig_length < 2 || partner_length < 2 {
+                        return None;
+                    }
+                    let start = rng.random_range(1..contig_length);
+                    let partner = (partner, rng.random_range(1..partner_length));
+                    StructuralVariant { kind, contig, start, end: start, partner: Some(partner), genotype: Vec::new() }
+                } else {
+                    let length = (min * (max / min).powf(rng.random::<f64>())).round() as usize;
+                    // Keep a reference base on either side of the variant.
+                    if length + 2 > contig_length {
+                        return None;
+                    }
+                    let start = rng.random_range(1..contig_length - length);
+                    StructuralVariant { kind, contig, start, end: start + length, partner: None, genotype: Vec::new() }
+                };
+                let clear = variants.iter().all(|other| {
+                    if kind == SvKind::Translocation
+                        && other.kind == SvKind::Translocation
+                        && other.footprint().any(|(contig, _, _)| variant.footprint().any(|(own, _, _)| own == contig))
+                    {
+                        return false;
+                    }
+                    variant.footprint().all(|(contig, start, end)| {
+                        other.footprint().all(|(other_contig, other_start, other_end)| {
+                            contig != other_contig || end < other_start || other_end < start
+                        })
+                    })
+                });
+                clear.then_some(variant)
+            });
+            let mut variant = placed.ok_or_else(|| FastqGenError::invalid(format!("Could not place {} {} structural variants apart from each other; ask for fewer or shorter ones.", count, kind.name())))?;
+            variant.genotype = sample_genotype(rng, hom_fraction, ploidy);
+            variants.push(variant);
+        }
+    }
+    variants.sort_by_key(|variant| (variant.contig, variant.start));
+    Ok(variants)
+}
+
+/// Drops every small variant that overlaps a structural variant or the base
+/// before one of its breakpoints.
+pub fn remove_within_structural_variants(variants: &mut Vec<Variant>, structural: &[StructuralVariant]) {
+    variants.retain(|variant| {
+        let end = variant.position + variant.reference.len();
+        structural.iter().flat_map(StructuralVariant::footprint).all(|(contig, start, sv_end)| {
+            contig != variant.contig || end <= start || sv_end <= variant.position
+        })
+    });
+}
+
+/// Writes structural variants as a single-sample VCF with phased genotypes:
+/// symbolic `<DEL>`, `<DUP:TANDEM>` and `<INV>` alleles with `SVTYPE`, `END` and
+/// `SVLEN`, and each translocation as the four breakend records of its two
+/// junctions.
+pub fn write_sv_vcf(out: &mut impl Write, reference: &Reference, variants: &[StructuralVariant]) -> io::Result<()> {
+    writeln!(out, "##fileformat=VCFv4.2")?;
+    writeln!(out, "##source=fastqgen-{}", env!("CARGO_PKG_VERSION"))?;
+    for (name, length) in reference.contigs() {
+        writeln!(out, "##contig=<ID={},length={}>", name, length)?;
+    }
+    writeln!(out, "##ALT=<ID=DEL,Description=\"Deletion\">")?;
+    writeln!(out, "##ALT=<ID=DUP:TANDEM,Description=\"Tandem duplication\">")?;
+    writeln!(out, "##ALT=<ID=INV,Description=\"Inversion\">")?;
+    writeln!(out, "##INFO=<ID=SVTYPE,Number=1,Type=String,Description=\"Type of structural variant\">")?;
+    writeln!(out, "##INFO=<ID=END,Number=1,Type=Integer,Description=\"End position of the variant\">")?;
+    writeln!(out, "##INFO=<ID=SVLEN,Number=1,Type=Integer,Description=\"Difference in length between the alleles\">")?;
+    writeln!(out, "##INFO=<ID=MATEID,Number=1,Type=String,Description=\"ID of the mate breakend\">")?;
+    writeln!(out, "##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">")?;
+    writeln!(out, "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tSIMULATED")?;
+
+    let mut records = Vec::new();
+    for (index, variant) in variants.iter().enumerate() {
+        let genotype: Vec<&str> = variant.genotype.iter().map(|&alt| if alt { "1" } else { "0" }).collect();
+        let genotype = genotype.join("|");
+        let name = reference.contig_name(variant.contig);
+        let base = |contig: usize, position: usize| reference.contig_seq(contig)[position] as char;
+        let id = format!("sv{}", index + 1);
+        let length = (variant.end - variant.start) as isize;
+        match (variant.kind, variant.partner) {
+            (SvKind::Translocation, Some((partner, breakpoint))) => {
+                let partner_name = reference.contig_name(partner);
+                let (a, b) = (variant.start, breakpoint);
+                // Junction 1 joins the contig before its breakpoint to the partner
+                // after its own; junction 2 joins the partner before to the contig after.
+                let breakends = [
+                    (variant.contig, a, format!("{}[{}:{}[", base(variant.contig, a - 1), partner_name, b + 1), format!("{}_1", id), format!("{}_2", id)),
+                    (partner, b + 1, format!("]{}:{}]{}", name, a, base(partner, b)), format!("{}_2", id), format!("{}_1", id)),
+                    (partner, b, format!("{}[{}:{}[", base(partner, b - 1), name, a + 1), format!("{}_3", id), format!("{}_4", id)),
+                    (variant.contig, a + 1, format!("]{}:{}]{}", partner_name, b, base(variant.contig, a)), format!("{}_4", id), format!("{}_3", id)),
+                ];
+                for (contig, position, alt, id, mate) in breakends {
+                    let info = format!("SVTYPE=BND;MATEID={}", mate);
+                    records.push((contig, position, id, base(contig, position - 1), alt, info, genotype.clone()));
+                }
+            }
+            (kind, _) => {
+                let (alt, svlen) = match kind {
+                    SvKind::Deletion => ("<DEL>", -length),
+                    SvKind::Duplication => ("<DUP:TANDEM>", length),
+                    _ => ("<INV>", 0),
+                };
+                let info = format!("SVTYPE={};END={};SVLEN={}", kind.name(), variant.end, svlen);
+                records.push((variant.contig, variant.start, id, base(variant.contig, variant.start - 1), alt.to_string(), info, genotype));
+            }
+        }
+    }
+    records.sort_by_key(|record| (record.0, record.1));
+    for (contig, position, id, ref_base, alt, info, genotype) in records {
+        writeln!(out, "{}\t{}\t{}\t{}\t{}\t.\tPASS\t{}\tGT\t{}", reference.contig_name(contig), position, id, ref_base, alt, info, genotype)?;
+    }
+    Ok(())
+}
+
+/// Writes structural variants as BEDPE: the 0-based, half-open single-base
+/// intervals either side of each variant's junction, its name, a `.` score, the
+/// strands of the junction, then its `SVTYPE` and phased genotype.
+pub fn write_sv_bedpe(out: &mut impl Write, reference: &Reference, variants: &[StructuralVariant]) -> io::Result<()> {
+    writeln!(out, "#chrom1\tstart1\tend1\tchrom2\tstart2\tend2\tname\tscore\tstrand1\tstrand2\tsvtype\tgenotype")?;
+    for (index, variant) in variants.iter().enumerate() {
+        let genotype: Vec<&str> = variant.genotype.iter().map(|&alt| if alt { "1" } else { "0" }).collect();
+        let name = reference.contig_name(variant.contig);
+        let (first, last) = (variant.start, variant.end - 1);
+        let (name2, start2, strands) = match (variant.kind, variant.partner) {
+            (SvKind::Translocation, Some((partner, breakpoint))) => (reference.contig_name(partner), breakpoint, ("+", "-")),
+            (SvKind::Deletion, _) => (name, variant.end, ("+", "-")),
+            (SvKind::Duplication, _) => (name, last, ("-", "+")),
+            _ => (name, last, ("+", "+")),
+        };
+        // A deletion joins the bases either side of it; the other kinds start at
+        // their first base.
+        let start1 = if variant.kind == SvKind::Deletion || variant.kind == SvKind::Translocation { first - 1 } else { first };
+        writeln!(
+            out,
+            "{}\t{}\t{}\t{}\t{}\t{}\tsv{}\t.\t{}\t{}\t{}\t{}",
+            name, start1, start1 + 1, name2, start2, start2 + 1, index + 1, strands.0, strands.1, variant.kind.name(), genotype.join("|")
+        )?;
+    }
+    Ok(())
+}
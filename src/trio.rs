@@ -0,0 +1,216 @@
+use rand::Rng;
+use rand::distr::Distribution;
+use rand::distr::weighted::WeightedIndex;
+use rand_distr::Poisson;
+use std::collections::HashSet;
+use std::io::{self, Write};
+
+use crate::error::FastqGenError;
+use crate::error_model::substitute;
+use crate::reference::Reference;
+use crate::variants::Variant;
+
+/// Number of haplotypes of the two parents: the mother's two, then the father's.
+pub const PARENTAL_HAPLOTYPES: usize = 4;
+
+/// Most positions drawn for each de novo mutation before giving up on finding a
+/// free one.
+const MAX_DE_NOVO_DRAWS: usize = 1000;
+
+/// A sample of a mother/father/child trio.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrioMember {
+    Mother,
+    Father,
+    Child,
+}
+
+impl TrioMember {
+    pub const ALL: [TrioMember; 3] = [TrioMember::Mother, TrioMember::Father, TrioMember::Child];
+
+    /// Name of the sample in file names and the pedigree VCF.
+    pub fn name(self) -> &'static str {
+        match self {
+            TrioMember::Mother => "mother",
+            TrioMember::Father => "father",
+            TrioMember::Child => "child",
+        }
+    }
+}
+
+/// How the parents' haplotypes pass to their child. Each parent transmits one
+/// haplotype per contig, a mosaic of its two haplotypes that switches at
+/// crossovers placed uniformly at a per-base recombination rate. The child's
+/// first haplotype comes from the mother and its second from the father.
+#[derive(Debug, Clone)]
+pub struct Pedigree {
+    /// For the mother and the father, and each contig, the haplotype transmitted
+    /// at the start of the contig and the sorted positions of the crossovers.
+    transmission: [Vec<(usize, Vec<usize>)>; 2],
+}
+
+impl Pedigree {
+    /// Draws the crossovers of both meioses at `recombination_rate` per base.
+    pub fn simulate(reference: &Reference, rng: &mut impl Rng, recombination_rate: f64) -> Result<Self, FastqGenError> {
+        if !(0.0..=1.0).contains(&recombination_rate) {
+            return Err(FastqGenError::invalid("The recombination rate must be between 0 and 1."));
+        }
+        let mut meiosis = || -> Result<Vec<(usize, Vec<usize>)>, FastqGenError> {
+            reference.contigs()
+                .map(|(_, length)| {
+                    let mean = recombination_rate * length as f64;
+                    let count = if mean > 0.0 {
+                        Poisson::new(mean).map_err(|e| FastqGenError::invalid(format!("Invalid recombination rate: {}", e)))?.sample(rng) as usize
+                    } else {
+                        0
+                    };
+                    let mut crossovers: Vec<usize> = (0..count).map(|_| rng.random_range(0..length)).collect();
+                    crossovers.sort_unstable();
+                    Ok((rng.random_range(0..2), crossovers))
+                })
+                .collect()
+        };
+        Ok(Pedigree { transmission: [meiosis()?, meiosis()?] })
+    }
+
+    /// Which of `parent`'s haplotypes (0 for the mother, 1 for the father) the
+    /// child inherits at `position` of `contig`.
+    fn transmitted(&self, parent: usize, contig: usize, position: usize) -> usize {
+        let (first, crossovers) = &self.transmission[parent][contig];
+        (first + crossovers.partition_point(|&crossover| crossover <= position)) % 2
+    }
+
+    /// The diploid genotype of `member` at `position` of `contig`, from the
+    /// genotype of the four parental haplotypes.
+    pub fn genotype(&self, member: TrioMember, contig: usize, position: usize, parental: &[bool]) -> Vec<bool> {
+        match member {
+            TrioMember::Mother => parental[..2].to_vec(),
+            TrioMember::Father => parental[2..PARENTAL_HAPLOTYPES].to_vec(),
+            TrioMember::Child => vec![parental[self.transmitted(0, contig, position)], parental[2 + self.transmitted(1, contig, position)]],
+        }
+    }
+
+    /// Draws `count` de novo SNVs of the child at uniform positions of
+    /// `reference`, clear of the parents' `variants`, each on the haplotype from
+    /// the mother or the father with equal probability. Returns them sorted, with
+    /// diploid genotypes.
+    pub fn simulate_de_novo(reference: &Reference, rng: &mut impl Rng, count: usize, variants: &[Variant]) -> Result<Vec<Variant>, FastqGenError> {
+        let mut used: HashSet<(usize, usize)> = variants.iter()
+            .flat_map(|variant| (variant.position..variant.position + variant.reference.len()).map(move |position| (variant.contig, position)))
+            .collect();
+        let contigs = WeightedIndex::new(reference.contigs().map(|(_, length)| length))
+            .map_err(|e| FastqGenError::invalid(format!("Invalid reference: {}", e)))?;
+        let mut de_novo = Vec::with_capacity(count);
+        for _ in 0..count {
+            let mut draws = 0;
+            let variant = loop {
+                draws += 1;
+                if draws > MAX_DE_NOVO_DRAWS {
+                    return Err(FastqGenError::invalid(format!("Found room for only {} de novo mutations in the reference.", de_novo.len())));
+                }
+                let contig = contigs.sample(rng);
+                let seq = reference.contig_seq(contig);
+                let position = rng.random_range(0..seq.len());
+                let base = seq[position];
+                if matches!(base, b'A' | b'C' | b'G' | b'T') && used.insert((contig, position)) {
+                    let paternal = rng.random_bool(0.5);
+                    break Variant { contig, position, reference: vec![base], alternate: vec![substitute(rng, base, b"ACGT")], genotype: vec![!paternal, paternal] };
+                }
+            };
+            de_novo.push(variant);
+        }
+        de_novo.sort_by_key(|variant| (variant.contig, variant.position));
+        Ok(de_novo)
+    }
+
+    /// Writes the parents' `variants` and the child's `de_novo` mutations as a VCF
+    /// with the phased genotypes of the mother, father and child, the child's
+    /// maternal allele first. De novo mutations are flagged `DENOVO`.
+    pub fn write_vcf(&self, out: &mut impl Write, reference: &Reference, variants: &[Variant], de_novo: &[Variant]) -> io::Result<()> {
+        writeln!(out, "##fileformat=VCFv4.2")?;
+        writeln!(out, "##source=fastqgen-{}", env!("CARGO_PKG_VERSION"))?;
+        for (name, length) in reference.contigs() {
+            writeln!(out, "##contig=<ID={},length={}>", name, length)?;
+        }
+        writeln!(out, "##PEDIGREE=<ID=child,Mother=mother,Father=father>")?;
+        writeln!(out, "##INFO=<ID=DENOVO,Number=0,Type=Flag,Description=\"De novo mutation of the child\">")?;
+        writeln!(out, "##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">")?;
+        writeln!(out, "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tmother\tfather\tchild")?;
+
+        let mut records: Vec<(&Variant, bool)> = variants.iter().map(|variant| (variant, false)).chain(de_novo.iter().map(|variant| (variant, true))).collect();
+        records.sort_by_key(|(variant, _)| (variant.contig, variant.position));
+        let phased = |genotype: &[bool]| genotype.iter().map(|&alt| if alt { "1" } else { "0" }).collect::<Vec<_>>().join("|");
+        for (variant, de_novo) in records {
+            let genotypes: Vec<String> = if de_novo {
+                vec!["0|0".to_string(), "0|0".to_string(), phased(&variant.genotype)]
+            } else {
+                TrioMember::ALL.iter().map(|&member| phased(&self.genotype(member, variant.contig, variant.position, &variant.genotype))).collect()
+            };
+            writeln!(
+                out,
+                "{}\t{}\t.\t{}\t{}\t.\tPASS\t{}\tGT\t{}",
+                reference.contig_name(variant.contig),
+                variant.position + 1,
+                String::from_utf8_lossy(&variant.reference),
+                String::from_utf8_lossy(&variant.alternate),
+                if de_novo { "DENOVO" } else { "." },
+                genotypes.join("\t")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    fn reference() -> Reference {
+        Reference::from_sequences(vec![("chr1".to_string(), b"ACGT".repeat(250)), ("chr2".to_string(), b"GGCA".repeat(100))], 1).unwrap()
+    }
+
+    #[test]
+    fn the_child_inherits_one_haplotype_from_each_parent() {
+        let pedigree = Pedigree::simulate(&reference(), &mut StdRng::seed_from_u64(1), 0.01).unwrap();
+        // The mother carries the allele on her first haplotype, the father on his second.
+        let parental = [true, false, false, true];
+        assert_eq!(pedigree.genotype(TrioMember::Mother, 0, 0, &parental), vec![true, false]);
+        assert_eq!(pedigree.genotype(TrioMember::Father, 0, 0, &parental), vec![false, true]);
+        for (contig, length) in [(0, 1000), (1, 400)] {
+            for position in 0..length {
+                let child = pedigree.genotype(TrioMember::Child, contig, position, &parental);
+                assert_eq!(child[0], pedigree.transmitted(0, contig, position) == 0);
+                assert_eq!(child[1], pedigree.transmitted(1, contig, position) == 1);
+            }
+        }
+    }
+
+    #[test]
+    fn crossovers_switch_the_transmitted_haplotype() {
+        let pedigree = Pedigree { transmission: [vec![(0, vec![10, 20])], vec![(1, Vec::new())]] };
+        let transmitted: Vec<usize> = [0, 9, 10, 19, 20, 30].iter().map(|&position| pedigree.transmitted(0, 0, position)).collect();
+        assert_eq!(transmitted, vec![0, 0, 1, 1, 0, 0]);
+        assert_eq!(pedigree.transmitted(1, 0, 15), 1);
+
+        let unlinked = Pedigree::simulate(&reference(), &mut StdRng::seed_from_u64(1), 0.0).unwrap();
+        assert!(unlinked.transmission.iter().flatten().all(|(_, crossovers)| crossovers.is_empty()));
+        assert!(Pedigree::simulate(&reference(), &mut StdRng::seed_from_u64(1), 1.5).is_err());
+    }
+
+    #[test]
+    fn de_novo_mutations_avoid_inherited_variants() {
+        let reference = reference();
+        let inherited = vec![Variant { contig: 0, position: 4, reference: b"ACGT".to_vec(), alternate: b"A".to_vec(), genotype: vec![true; 4] }];
+        let de_novo = Pedigree::simulate_de_novo(&reference, &mut StdRng::seed_from_u64(3), 200, &inherited).unwrap();
+        assert_eq!(de_novo.len(), 200);
+        assert!(de_novo.windows(2).all(|pair| (pair[0].contig, pair[0].position) < (pair[1].contig, pair[1].position)));
+        for variant in &de_novo {
+            assert!(variant.contig != 0 || !(4..8).contains(&variant.position));
+            assert_eq!(variant.reference, &reference.contig_seq(variant.contig)[variant.position..variant.position + 1]);
+            assert_ne!(variant.reference, variant.alternate);
+            assert_eq!(variant.genotype.iter().filter(|&&alt| alt).count(), 1);
+        }
+    }
+}
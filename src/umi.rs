@@ -0,0 +1,95 @@
+use rand::Rng;
+use rand::distr::Distribution;
+use rand_distr::Poisson;
+use std::io::{self, Write};
+
+use crate::error::FastqGenError;
+use crate::generator::Molecule;
+
+/// Where the UMI of each molecule is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UmiPlacement {
+    /// Prepended to read 1, as sequenced by inline-UMI library preps.
+    Read,
+    /// Appended to the read name after a colon, as `bcl2fastq` does.
+    #[default]
+    Name,
+    /// Kept out of the reads, to be written to a separate index file.
+    Index,
+}
+
+/// The unique molecular identifier of a read's molecule, with the qualities it
+/// was sequenced with. Duplicates of a molecule share its UMI sequence. Duplex
+/// UMIs join the UMIs of the two ends of the molecule with `-`, and their
+/// qualities with a space, as in the RX and QX tags of SAM.
+#[derive(Debug, Clone)]
+pub struct Umi {
+    pub seq: Vec<u8>,
+    pub quality: Vec<u8>,
+}
+
+/// Families of reads sequenced from PCR copies of one original molecule, for
+/// UMI-aware consensus callers such as fgbio. A family is the original and a
+/// Poisson number of further copies, each with PCR substitutions of its own. In
+/// a duplex library, every copy comes from either strand of the original: the
+/// A strand, read as the original, or the B strand, whose read 1 starts at the
+/// other end and whose UMIs are swapped.
+#[derive(Debug, Clone)]
+pub struct UmiFamilies {
+    copies: Poisson<f64>,
+    pcr_error_rate: f64,
+    duplex: bool,
+}
+
+impl UmiFamilies {
+    /// Single-strand families of `mean_size` reads on average, whose copies carry
+    /// substitutions at `pcr_error_rate` per base.
+    pub fn new(mean_size: f64, pcr_error_rate: f64) -> Result<Self, FastqGenError> {
+        if !(0.0..=1.0).contains(&pcr_error_rate) {
+            return Err(FastqGenError::invalid("The PCR error rate must be between 0 and 1."));
+        }
+        let copies = Poisson::new(mean_size - 1.0)
+            .map_err(|_| FastqGenError::invalid("The mean family size must be greater than 1."))?;
+        Ok(UmiFamilies { copies, pcr_error_rate, duplex: false })
+    }
+
+    /// Draws copies from both strands of every original.
+    pub fn with_duplex(mut self) -> Self {
+        self.duplex = true;
+        self
+    }
+
+    pub fn is_duplex(&self) -> bool {
+        self.duplex
+    }
+
+    pub fn pcr_error_rate(&self) -> f64 {
+        self.pcr_error_rate
+    }
+
+    /// Draws the number of reads of a family.
+    pub fn size(&self, rng: &mut impl Rng) -> u64 {
+        1 + self.copies.sample(rng) as u64
+    }
+
+    /// Draws whether a copy comes from the B strand of its original, never true
+    /// outside duplex libraries.
+    pub fn sample_strand(&self, rng: &mut impl Rng) -> bool {
+        self.duplex && rng.random_bool(0.5)
+    }
+
+    /// Appends a `read<TAB>family<TAB>strand<TAB>umi<TAB>molecule` truth line: the
+    /// family, the strand (`A` or `B`) the read was copied from, and the UMI and
+    /// template of the `original` molecule, without PCR or sequencing errors, as
+    /// read 1 of the A strand reads them.
+    pub fn write_truth(&self, out: &mut impl Write, id: &str, family: u64, bottom: bool, original: &Molecule) -> io::Result<()> {
+        let umi = original.umi().unwrap_or_default();
+        let umi = if self.duplex {
+            let (first, second) = umi.split_at(umi.len() / 2);
+            format!("{}-{}", String::from_utf8_lossy(first), String::from_utf8_lossy(second))
+        } else {
+            String::from_utf8_lossy(umi).into_owned()
+        };
+        writeln!(out, "{}\t{}\t{}\t{}\t{}", id, family, if bottom { 'B' } else { 'A' }, umi, String::from_utf8_lossy(original.template()))
+    }
+}
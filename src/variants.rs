@@ -0,0 +1,409 @@
+use rand::Rng;
+use rand::distr::Distribution;
+use rand::prelude::{IndexedRandom, SliceRandom};
+use rand_distr::Geometric;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use crate::error::FastqGenError;
+use crate::error_model::substitute;
+use crate::reference::{Reference, open_text};
+
+/// A variant injected into the simulated haplotypes.
+#[derive(Debug, Clone)]
+pub struct Variant {
+    pub contig: usize,
+    /// 0-based position of the first reference base.
+    pub position: usize,
+    pub reference: Vec<u8>,
+    pub alternate: Vec<u8>,
+    /// Whether each haplotype carries the alternate allele.
+    pub genotype: Vec<bool>,
+}
+
+impl Variant {
+    fn end(&self) -> usize {
+        self.position + self.reference.len()
+    }
+}
+
+/// Places SNPs at a per-base `rate` across every contig. A `hom_fraction` of them
+/// are homozygous; the rest are carried by one of the `ploidy` haplotypes.
+/// Positions with non-ACGT reference bases are skipped.
+pub fn simulate_snps(reference: &Reference, rng: &mut impl Rng, rate: f64, hom_fraction: f64, ploidy: usize) -> Result<Vec<Variant>, FastqGenError> {
+    if !(0.0..=1.0).contains(&rate) || !(0.0..=1.0).contains(&hom_fraction) {
+        return Err(FastqGenError::invalid("SNP rate and homozygous fraction must be between 0 and 1."));
+    }
+    let mut variants = Vec::new();
+    if rate == 0.0 {
+        return Ok(variants);
+    }
+    let gap = Geometric::new(rate).map_err(|e| FastqGenError::invalid(format!("Invalid SNP rate: {}", e)))?;
+
+    for index in 0..reference.contigs().count() {
+        let seq = reference.contig_seq(index);
+        let mut position = gap.sample(rng) as usize;
+        while position < seq.len() {
+            let base = seq[position];
+            if matches!(base, b'A' | b'C' | b'G' | b'T') {
+                variants.push(Variant {
+                    contig: index,
+                    position,
+                    reference: vec![base],
+                    alternate: vec![substitute(rng, base, b"ACGT")],
+                    genotype: sample_genotype(rng, hom_fraction, ploidy),
+                });
+            }
+            position += 1 + gap.sample(rng) as usize;
+        }
+    }
+
+    Ok(variants)
+}
+
+/// Length distribution of simulated indels: geometric with the given mean,
+/// truncated to `1..=max_length`.
+#[derive(Debug, Clone, Copy)]
+pub struct IndelLengths {
+    pub mean: f64,
+    pub max: usize,
+}
+
+impl IndelLengths {
+    fn sample(&self, rng: &mut impl Rng, extension: &Geometric) -> usize {
+        loop {
+            let length = 1 + extension.sample(rng) as usize;
+            if length <= self.max {
+                return length;
+            }
+        }
+    }
+}
+
+/// Places insertions and deletions (in equal proportion) at a per-base `rate`,
+/// with genotypes drawn as in [`simulate_snps`]. Alleles are left-anchored on the
+/// preceding reference base, as in VCF.
+pub fn simulate_indels(reference: &Reference, rng: &mut impl Rng, rate: f64, lengths: IndelLengths, hom_fraction: f64, ploidy: usize) -> Result<Vec<Variant>, FastqGenError> {
+    if !(0.0..=1.0).contains(&rate) || !(0.0..=1.0).contains(&hom_fraction) {
+        return Err(FastqGenError::invalid("Indel rate and homozygous fraction must be between 0 and 1."));
+    }
+    if lengths.mean < 1.0 || lengths.max == 0 {
+        return Err(FastqGenError::invalid("Indel mean length must be at least 1 and the maximum length positive."));
+    }
+    let mut variants = Vec::new();
+    if rate == 0.0 {
+        return Ok(variants);
+    }
+    let gap = Geometric::new(rate).map_err(|e| FastqGenError::invalid(format!("Invalid indel rate: {}", e)))?;
+    let extension = Geometric::new(1.0 / lengths.mean).map_err(|e| FastqGenError::invalid(format!("Invalid indel mean length: {}", e)))?;
+    let is_base = |base: &u8| matches!(base, b'A' | b'C' | b'G' | b'T');
+
+    for index in 0..reference.contigs().count() {
+        let seq = reference.contig_seq(index);
+        let mut position = gap.sample(rng) as usize;
+        while position < seq.len() {
+            let length = lengths.sample(rng, &extension);
+            let anchor = &seq[position..position + 1];
+            let (ref_allele, alt_allele) = if rng.random_bool(0.5) {
+                let inserted = (0..length).map(|_| *b"ACGT".choose(rng).unwrap());
+                (anchor.to_vec(), anchor.iter().copied().chain(inserted).collect())
+            } else {
+                let end = (position + 1 + length).min(seq.len());
+                (seq[position..end].to_vec(), anchor.to_vec())
+            };
+
+            if ref_allele.len() != alt_allele.len() && ref_allele.iter().all(is_base) {
+                variants.push(Variant {
+                    contig: index,
+                    position,
+                    reference: ref_allele,
+                    alternate: alt_allele,
+                    genotype: sample_genotype(rng, hom_fraction, ploidy),
+                });
+            }
+            position += 1 + gap.sample(rng) as usize;
+        }
+    }
+
+    Ok(variants)
+}
+
+/// Sorts variants by contig and position and drops any that overlap (or share an
+/// anchor base with) an earlier one, so they can be applied together.
+pub fn resolve_overlaps(variants: &mut Vec<Variant>) {
+    variants.sort_by_key(|variant| (variant.contig, variant.position));
+    let mut end: Option<(usize, usize)> = None;
+    variants.retain(|variant| {
+        if let Some((contig, end)) = end
+            && contig == variant.contig
+            && variant.position < end
+        {
+            return false;
+        }
+        end = Some((variant.contig, variant.end()));
+        true
+    });
+}
+
+/// Drops every variant that overlaps one in `fixed`, which must be sorted and
+/// free of overlaps (as returned by [`read_vcf`]).
+pub fn remove_overlapping(variants: &mut Vec<Variant>, fixed: &[Variant]) {
+    variants.retain(|variant| {
+        let next = fixed.partition_point(|other| (other.contig, other.end()) <= (variant.contig, variant.position));
+        fixed.get(next).is_none_or(|other| other.contig != variant.contig || other.position >= variant.end())
+    });
+}
+
+/// Reads the variants to spike into the haplotypes from a VCF file (optionally
+/// gzip-compressed). Each record's genotype comes from the `GT` of its first
+/// sample; without one, an `AF` INFO value sets how many of the `ploidy`
+/// haplotypes carry the allele (rounded, but at least one), and otherwise the
+/// variant is heterozygous. Non-carried records are skipped. Returns the
+/// variants sorted by position.
+pub fn read_vcf(path: &Path, reference: &Reference, rng: &mut impl Rng, ploidy: usize) -> Result<Vec<Variant>, FastqGenError> {
+    let reader = open_text(path)
+        .map_err(|e| FastqGenError::io(format!("Failed to open VCF {}", path.display()), e))?;
+    let mut variants = Vec::new();
+
+    for (line_number, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        let location = format!("{}:{}", path.display(), line_number + 1);
+        let fields: Vec<&str> = line.trim_end().split('\t').collect();
+        if fields.len() < 8 {
+            return Err(FastqGenError::parse(format!("{}: expected at least 8 tab-separated columns.", location)));
+        }
+
+        let contig = reference.contig_index(fields[0])
+            .ok_or_else(|| FastqGenError::parse(format!("{}: contig {} is not in the reference.", location, fields[0])))?;
+        let position = fields[1].parse::<usize>().ok().filter(|&pos| pos > 0)
+            .ok_or_else(|| FastqGenError::parse(format!("{}: invalid position {}.", location, fields[1])))? - 1;
+        let ref_allele = fields[3].to_ascii_uppercase().into_bytes();
+        let alt_allele = fields[4].to_ascii_uppercase().into_bytes();
+
+        if alt_allele == b"." {
+            continue;
+        }
+        if alt_allele.contains(&b',') {
+            return Err(FastqGenError::parse(format!("{}: multi-allelic records are not supported; split them first (e.g. bcftools norm -m-).", location)));
+        }
+        if !ref_allele.iter().chain(&alt_allele).all(|base| b"ACGTN".contains(base)) {
+            return Err(FastqGenError::parse(format!("{}: only sequence alleles are supported, found {} -> {}.", location, fields[3], fields[4])));
+        }
+        let seq = reference.contig_seq(contig);
+        if seq.get(position..position + ref_allele.len()) != Some(&ref_allele[..]) {
+            return Err(FastqGenError::parse(format!("{}: REF allele {} does not match the reference at {}:{}.", location, fields[3], fields[0], fields[1])));
+        }
+
+        let genotype = match vcf_genotype(&fields, ploidy).map_err(|e| FastqGenError::parse(format!("{}: {}", location, e)))? {
+            Some(genotype) => genotype,
+            None => match info_allele_fraction(fields[7]).map_err(|e| FastqGenError::parse(format!("{}: {}", location, e)))? {
+                Some(fraction) => {
+                    let carriers = ((fraction * ploidy as f64).round() as usize).clamp(1, ploidy);
+                    let mut genotype: Vec<bool> = (0..ploidy).map(|haplotype| haplotype < carriers).collect();
+                    genotype.shuffle(rng);
+                    genotype
+                }
+                None => sample_genotype(rng, 0.0, ploidy),
+            },
+        };
+        if genotype.contains(&true) {
+            variants.push(Variant { contig, position, reference: ref_allele, alternate: alt_allele, genotype });
+        }
+    }
+
+    variants.sort_by_key(|variant| (variant.contig, variant.position));
+    if let Some(pair) = variants.windows(2).find(|pair| pair[0].contig == pair[1].contig && pair[1].position < pair[0].end()) {
+        return Err(FastqGenError::parse(format!(
+            "VCF {} has overlapping variants at {}:{} and {}:{}.",
+            path.display(),
+            reference.contig_name(pair[0].contig),
+            pair[0].position + 1,
+            reference.contig_name(pair[1].contig),
+            pair[1].position + 1
+        )));
+    }
+    Ok(variants)
+}
+
+/// Parses the `GT` of the first sample, if there is one. A haploid call is
+/// applied to every haplotype.
+fn vcf_genotype(fields: &[&str], ploidy: usize) -> Result<Option<Vec<bool>>, String> {
+    let (Some(format), Some(sample)) = (fields.get(8), fields.get(9)) else {
+        return Ok(None);
+    };
+    let Some(gt_index) = format.split(':').position(|key| key == "GT") else {
+        return Ok(None);
+    };
+    let Some(gt) = sample.split(':').nth(gt_index) else {
+        return Ok(None);
+    };
+
+    let alleles = gt.split(['|', '/'])
+        .map(|allele| match allele {
+            "0" | "." => Ok(false),
+            "1" => Ok(true),
+            _ => Err(format!("unsupported genotype {}.", gt)),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    match alleles.len() {
+        1 => Ok(Some(vec![alleles[0]; ploidy])),
+        n if n == ploidy => Ok(Some(alleles)),
+        _ => Err(format!("genotype {} does not have ploidy {}.", gt, ploidy)),
+    }
+}
+
+fn info_allele_fraction(info: &str) -> Result<Option<f64>, String> {
+    let Some(value) = info.split(';').find_map(|entry| entry.strip_prefix("AF=")) else {
+        return Ok(None);
+    };
+    match value.parse::<f64>() {
+        Ok(fraction) if (0.0..=1.0).contains(&fraction) => Ok(Some(fraction)),
+        _ => Err(format!("invalid allele fraction AF={}.", value)),
+    }
+}
+
+pub(crate) fn sample_genotype(rng: &mut impl Rng, hom_fraction: f64, ploidy: usize) -> Vec<bool> {
+    if rng.random_bool(hom_fraction) {
+        vec![true; ploidy]
+    } else {
+        let carrier = rng.random_range(0..ploidy);
+        (0..ploidy).map(|haplotype| haplotype == carrier).collect()
+    }
+}
+
+/// Writes `variants` as a single-sample VCF with phased genotypes.
+pub fn write_vcf(out: &mut impl Write, reference: &Reference, variants: &[Variant]) -> io::Result<()> {
+    writeln!(out, "##fileformat=VCFv4.2")?;
+    writeln!(out, "##source=fastqgen-{}", env!("CARGO_PKG_VERSION"))?;
+    for (name, length) in reference.contigs() {
+        writeln!(out, "##contig=<ID={},length={}>", name, length)?;
+    }
+    writeln!(out, "##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">")?;
+    writeln!(out, "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tSIMULATED")?;
+
+    for variant in variants {
+        let genotype: Vec<&str> = variant.genotype.iter().map(|&alt| if alt { "1" } else { "0" }).collect();
+        writeln!(
+            out,
+            "{}\t{}\t.\t{}\t{}\t.\tPASS\t.\tGT\t{}",
+            reference.contig_name(variant.contig),
+            variant.position + 1,
+            String::from_utf8_lossy(&variant.reference),
+            String::from_utf8_lossy(&variant.alternate),
+            genotype.join("|")
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use std::fs;
+
+    const SEQ: &[u8] = b"ACGGTCATTGCAAGCTTCGA";
+
+    fn reference() -> Reference {
+        Reference::from_sequences(vec![("chr".to_string(), SEQ.to_vec())], 1).unwrap()
+    }
+
+    fn variant(position: usize, reference: &[u8], alternate: &[u8]) -> Variant {
+        Variant { contig: 0, position, reference: reference.to_vec(), alternate: alternate.to_vec(), genotype: vec![true, false] }
+    }
+
+    /// Writes `records` below a VCF header and reads them back with ploidy 2.
+    fn parse(name: &str, records: &[&str]) -> Result<Vec<Variant>, FastqGenError> {
+        let path = std::env::temp_dir().join(format!("fastqgen-{}-{}.vcf", std::process::id(), name));
+        let mut vcf = String::from("##fileformat=VCFv4.2\n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tSAMPLE\n");
+        for record in records {
+            vcf.push_str(record);
+            vcf.push('\n');
+        }
+        fs::write(&path, vcf).unwrap();
+        let variants = read_vcf(&path, &reference(), &mut StdRng::seed_from_u64(1), 2);
+        fs::remove_file(&path).unwrap();
+        variants
+    }
+
+    #[test]
+    fn reads_genotypes_and_allele_fractions() {
+        let variants = parse("genotypes", &[
+            "chr\t10\t.\tG\tGTT\t.\tPASS\t.\tGT\t0|1",
+            "chr\t2\t.\tc\tt\t.\tPASS\t.\tGT\t1",
+            "chr\t5\t.\tTCA\tT\t.\tPASS\tAF=1.0",
+            "chr\t15\t.\tC\tA\t.\tPASS\t.\tGT:DP\t0/0:12",
+            "chr\t17\t.\tT\t.\t.\tPASS\t.",
+        ]).unwrap();
+
+        let expected = [
+            Variant { genotype: vec![true, true], ..variant(1, b"C", b"T") },
+            Variant { genotype: vec![true, true], ..variant(4, b"TCA", b"T") },
+            Variant { genotype: vec![false, true], ..variant(9, b"G", b"GTT") },
+        ];
+        assert_eq!(format!("{:?}", variants), format!("{:?}", expected));
+    }
+
+    #[test]
+    fn rejects_invalid_records() {
+        for (name, record) in [
+            ("contig", "chrX\t2\t.\tC\tT\t.\tPASS\t."),
+            ("mismatch", "chr\t2\t.\tG\tT\t.\tPASS\t."),
+            ("multiallelic", "chr\t2\t.\tC\tT,G\t.\tPASS\t."),
+            ("symbolic", "chr\t2\t.\tC\t<DEL>\t.\tPASS\t."),
+            ("ploidy", "chr\t2\t.\tC\tT\t.\tPASS\t.\tGT\t0/1/1"),
+            ("fraction", "chr\t2\t.\tC\tT\t.\tPASS\tAF=2"),
+        ] {
+            assert!(parse(name, &[record]).is_err(), "{} was accepted", name);
+        }
+        assert!(parse("overlap", &["chr\t5\t.\tTCA\tT\t.\tPASS\t.", "chr\t6\t.\tC\tG\t.\tPASS\t."]).is_err());
+    }
+
+    #[test]
+    fn written_variants_read_back() {
+        let variants = vec![variant(1, b"C", b"T"), variant(4, b"TCA", b"T"), variant(9, b"G", b"GTT")];
+        let mut vcf = Vec::new();
+        write_vcf(&mut vcf, &reference(), &variants).unwrap();
+        let records: Vec<&str> = std::str::from_utf8(&vcf).unwrap().lines().filter(|line| !line.starts_with('#')).collect();
+        let read = parse("roundtrip", &records).unwrap();
+        assert_eq!(format!("{:?}", read), format!("{:?}", variants));
+    }
+
+    #[test]
+    fn resolve_overlaps_keeps_the_first_variant() {
+        let mut variants = vec![variant(9, b"G", b"GTT"), variant(4, b"TCA", b"T"), variant(5, b"C", b"G"), variant(7, b"T", b"A")];
+        resolve_overlaps(&mut variants);
+        assert_eq!(variants.iter().map(|variant| variant.position).collect::<Vec<_>>(), vec![4, 7, 9]);
+
+        let mut simulated = vec![variant(2, b"G", b"A"), variant(6, b"A", b"C"), variant(8, b"T", b"A")];
+        remove_overlapping(&mut simulated, &variants);
+        assert_eq!(simulated.iter().map(|variant| variant.position).collect::<Vec<_>>(), vec![2, 8]);
+    }
+
+    #[test]
+    fn simulated_variants_match_the_reference() {
+        let reference = reference();
+        let mut rng = StdRng::seed_from_u64(7);
+        let snps = simulate_snps(&reference, &mut rng, 0.5, 0.0, 2).unwrap();
+        assert!(!snps.is_empty());
+        for snp in &snps {
+            assert_eq!(snp.reference, &SEQ[snp.position..snp.position + 1]);
+            assert_ne!(snp.reference, snp.alternate);
+            assert_eq!(snp.genotype.iter().filter(|&&alt| alt).count(), 1);
+        }
+
+        let lengths = IndelLengths { mean: 2.0, max: 3 };
+        let indels = simulate_indels(&reference, &mut rng, 0.5, lengths, 1.0, 2).unwrap();
+        assert!(!indels.is_empty());
+        for indel in &indels {
+            assert_eq!(indel.reference, &SEQ[indel.position..indel.position + indel.reference.len()]);
+            assert_eq!(indel.reference[0], indel.alternate[0]);
+            assert!(indel.reference.len().abs_diff(indel.alternate.len()) <= 3);
+            assert_eq!(indel.genotype, vec![true, true]);
+        }
+    }
+}
@@ -0,0 +1,286 @@
+use rand::Rng;
+use rand::distr::Distribution;
+use rand::distr::weighted::WeightedIndex;
+use rand_distr::Geometric;
+use std::io::{self, BufRead, Write};
+use std::ops::Range;
+use std::path::Path;
+
+use crate::error::FastqGenError;
+use crate::expression::sample_abundances;
+use crate::reference::open_text;
+
+/// Attempts at recombining a productive clonotype before giving up.
+const MAX_RECOMBINATION_ATTEMPTS: usize = 1000;
+
+/// Amino acids of the codons in TCAG order (TTT, TTC, TTA, TTG, TCT, ...).
+const CODON_TABLE: &[u8; 64] = b"FFLLSSSSYY**CC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG";
+
+/// A V, D or J gene segment of an immunoglobulin or T-cell receptor locus.
+#[derive(Debug, Clone)]
+pub struct GeneSegment {
+    /// Allele name, e.g. `IGHV1-18*01`.
+    pub name: String,
+    pub seq: Vec<u8>,
+}
+
+/// Reads gene segments from a FASTA file (optionally gzipped), such as those of
+/// IMGT/GENE-DB. The name is the second `|`-separated field of IMGT headers, or
+/// else the first word of the header. Sequences are upper-cased, with IMGT gap
+/// dots removed.
+pub fn read_gene_segments(path: &Path) -> Result<Vec<GeneSegment>, FastqGenError> {
+    let reader = open_text(path)
+        .map_err(|e| FastqGenError::io(format!("Failed to open gene segments {}", path.display()), e))?;
+    let mut segments: Vec<GeneSegment> = Vec::new();
+
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim_end();
+        if let Some(header) = line.strip_prefix('>') {
+            let name = match header.split('|').nth(1) {
+                Some(name) => name.trim(),
+                None => header.split_whitespace().next().unwrap_or_default(),
+            };
+            segments.push(GeneSegment { name: name.to_string(), seq: Vec::new() });
+        } else if !line.is_empty() {
+            let segment = segments.last_mut()
+                .ok_or_else(|| FastqGenError::parse(format!("{} is not a FASTA file: sequence found before the first header.", path.display())))?;
+            segment.seq.extend(line.bytes().filter(|&base| base != b'.').map(|base| base.to_ascii_uppercase()));
+        }
+    }
+    Ok(segments)
+}
+
+/// A recombined receptor: its V, D (for heavy and beta chains) and J segments
+/// and the sequence they were joined into, from the start of the V segment to
+/// the end of the J segment.
+#[derive(Debug, Clone)]
+pub struct Clonotype {
+    pub v: usize,
+    pub d: Option<usize>,
+    pub j: usize,
+    pub seq: Vec<u8>,
+    /// The junction in `seq`, from the conserved cysteine of the V segment to the
+    /// conserved tryptophan or phenylalanine of the J segment, both included (the
+    /// CDR3 of MiXCR); `None` when a segment lacks its anchor.
+    pub junction: Option<Range<usize>>,
+}
+
+impl Clonotype {
+    /// Whether the junction keeps the reading frame of the V segment into the J
+    /// segment, with no stop codon before the end of the junction.
+    pub fn is_productive(&self) -> bool {
+        match &self.junction {
+            Some(junction) => junction.len() % 3 == 0 && !translate(&self.seq[..junction.end]).contains(&b'*'),
+            None => false,
+        }
+    }
+}
+
+/// The V, D and J segments of a locus and how they recombine: the segments are
+/// drawn uniformly, bases are deleted from the ends being joined and
+/// non-templated (N) bases inserted between them, both in geometrically
+/// distributed numbers. Loci without D segments (light and alpha chains) join V
+/// to J directly.
+#[derive(Debug, Clone)]
+pub struct VdjSegments {
+    v: Vec<GeneSegment>,
+    d: Vec<GeneSegment>,
+    j: Vec<GeneSegment>,
+    /// Start of the conserved cysteine codon of every V segment.
+    v_anchors: Vec<Option<usize>>,
+    /// End of the conserved tryptophan or phenylalanine codon of every J segment.
+    j_anchors: Vec<Option<usize>>,
+    deletions: Geometric,
+    insertions: Geometric,
+}
+
+impl VdjSegments {
+    /// Recombines with `deletion_mean` bases deleted from every joined end and
+    /// `insertion_mean` N bases inserted at every junction, on average.
+    pub fn new(v: Vec<GeneSegment>, d: Vec<GeneSegment>, j: Vec<GeneSegment>, deletion_mean: f64, insertion_mean: f64) -> Result<Self, FastqGenError> {
+        if v.is_empty() || j.is_empty() {
+            return Err(FastqGenError::invalid("Recombination needs at least one V and one J segment."));
+        }
+        if let Some(segment) = v.iter().chain(&d).chain(&j).find(|segment| segment.seq.is_empty()) {
+            return Err(FastqGenError::invalid(format!("Gene segment {} has no bases.", segment.name)));
+        }
+        let geometric = |mean: f64, what: &str| {
+            if !mean.is_finite() || mean < 0.0 {
+                return Err(FastqGenError::invalid(format!("The mean number of {} must not be negative.", what)));
+            }
+            Geometric::new(1.0 / (mean + 1.0)).map_err(|e| FastqGenError::invalid(format!("Invalid {}: {}", what, e)))
+        };
+        let deletions = geometric(deletion_mean, "deleted bases")?;
+        let insertions = geometric(insertion_mean, "inserted bases")?;
+        let v_anchors = v.iter().map(|segment| v_anchor(&segment.seq)).collect();
+        let j_anchors = j.iter().map(|segment| j_anchor(&segment.seq)).collect();
+        Ok(VdjSegments { v, d, j, v_anchors, j_anchors, deletions, insertions })
+    }
+
+    pub fn v(&self) -> &[GeneSegment] {
+        &self.v
+    }
+
+    pub fn d(&self) -> &[GeneSegment] {
+        &self.d
+    }
+
+    pub fn j(&self) -> &[GeneSegment] {
+        &self.j
+    }
+
+    /// Recombines a clonotype. Deletions stop short of the junction's anchors
+    /// and never remove a whole segment.
+    pub fn recombine(&self, rng: &mut impl Rng) -> Clonotype {
+        let v = rng.random_range(0..self.v.len());
+        let d = (!self.d.is_empty()).then(|| rng.random_range(0..self.d.len()));
+        let j = rng.random_range(0..self.j.len());
+
+        let v_seq = &self.v[v].seq;
+        let v_keep = self.v_anchors[v].map_or(1, |anchor| anchor + 3);
+        let v_end = v_seq.len() - self.deleted(rng, v_seq.len() - v_keep);
+        let mut seq = v_seq[..v_end].to_vec();
+        if let Some(d) = d {
+            self.insert(rng, &mut seq);
+            let d_seq = &self.d[d].seq;
+            let start = self.deleted(rng, d_seq.len().saturating_sub(1));
+            let end = d_seq.len() - self.deleted(rng, d_seq.len() - start - 1);
+            seq.extend_from_slice(&d_seq[start..end]);
+        }
+        self.insert(rng, &mut seq);
+        let j_seq = &self.j[j].seq;
+        let j_keep = self.j_anchors[j].map_or(j_seq.len() - 1, |anchor| anchor - 3);
+        let j_start = self.deleted(rng, j_keep);
+        let junction = self.v_anchors[v].zip(self.j_anchors[j]).map(|(v_anchor, j_anchor)| v_anchor..seq.len() + j_anchor - j_start);
+        seq.extend_from_slice(&j_seq[j_start..]);
+        Clonotype { v, d, j, seq, junction }
+    }
+
+    /// Draws a number of deleted bases, at most `max`.
+    fn deleted(&self, rng: &mut impl Rng, max: usize) -> usize {
+        (self.deletions.sample(rng) as usize).min(max)
+    }
+
+    /// Appends N bases to `seq`.
+    fn insert(&self, rng: &mut impl Rng, seq: &mut Vec<u8>) {
+        let count = self.insertions.sample(rng);
+        seq.extend((0..count).map(|_| b"ACGT"[rng.random_range(0..4)]));
+    }
+
+    /// Recombines `count` clonotypes, only productive ones unless
+    /// `unproductive` is set.
+    pub fn sample_clonotypes(&self, rng: &mut impl Rng, count: usize, unproductive: bool) -> Result<Vec<Clonotype>, FastqGenError> {
+        (0..count)
+            .map(|_| {
+                (0..MAX_RECOMBINATION_ATTEMPTS)
+                    .map(|_| self.recombine(rng))
+                    .find(|clonotype| unproductive || clonotype.is_productive())
+                    .ok_or_else(|| FastqGenError::invalid("The segments recombine into no productive clonotype; check that V segments have their conserved cysteine and J segments their W/F-G-X-G motif."))
+            })
+            .collect()
+    }
+}
+
+/// Start of the last in-frame cysteine codon of a V segment, the conserved
+/// Cys104.
+fn v_anchor(seq: &[u8]) -> Option<usize> {
+    (0..seq.len() / 3).rev().map(|codon| 3 * codon).find(|&start| matches!(&seq[start..start + 3], b"TGT" | b"TGC"))
+}
+
+/// End of the first tryptophan or phenylalanine codon of a J segment followed by
+/// the G-X-G of the conserved W/F-G-X-G motif.
+fn j_anchor(seq: &[u8]) -> Option<usize> {
+    (0..seq.len().saturating_sub(10))
+        .find(|&start| matches!(&seq[start..start + 3], b"TGG" | b"TTT" | b"TTC") && seq[start + 3..].starts_with(b"GG") && seq[start + 9..].starts_with(b"GG"))
+        .map(|start| start + 3)
+}
+
+/// Translates `seq` in frame from its first base, with `X` for codons that are
+/// not all A, C, G and T.
+fn translate(seq: &[u8]) -> Vec<u8> {
+    let index = |base: u8| match base {
+        b'T' => Some(0),
+        b'C' => Some(1),
+        b'A' => Some(2),
+        b'G' => Some(3),
+        _ => None,
+    };
+    seq.chunks_exact(3)
+        .map(|codon| match (index(codon[0]), index(codon[1]), index(codon[2])) {
+            (Some(first), Some(second), Some(third)) => CODON_TABLE[16 * first + 4 * second + third],
+            _ => b'X',
+        })
+        .collect()
+}
+
+/// Draws the relative abundance of every clone from a log-normal with log-scale
+/// standard deviation `sd`, scaled to sum to one.
+pub fn sample_clone_abundances(count: usize, rng: &mut impl Rng, sd: f64) -> Result<Vec<f64>, FastqGenError> {
+    let tpm = sample_abundances(count, rng, sd)?;
+    Ok(tpm.iter().map(|tpm| tpm / 1e6).collect())
+}
+
+/// The clones of an immune repertoire, drawn by abundance.
+#[derive(Debug, Clone)]
+pub struct Repertoire {
+    segments: VdjSegments,
+    clones: Vec<Clonotype>,
+    abundances: Vec<f64>,
+    weights: WeightedIndex<f64>,
+}
+
+impl Repertoire {
+    pub fn new(segments: VdjSegments, clones: Vec<Clonotype>, abundances: Vec<f64>) -> Result<Self, FastqGenError> {
+        if clones.is_empty() || abundances.len() != clones.len() {
+            return Err(FastqGenError::invalid("A repertoire needs one abundance per clone, and at least one clone."));
+        }
+        let weights = WeightedIndex::new(&abundances)
+            .map_err(|_| FastqGenError::invalid("At least one clone must be abundant."))?;
+        Ok(Repertoire { segments, clones, abundances, weights })
+    }
+
+    pub fn clones(&self) -> &[Clonotype] {
+        &self.clones
+    }
+
+    /// Draws the index of a clone.
+    pub fn sample(&self, rng: &mut impl Rng) -> usize {
+        self.weights.sample(rng)
+    }
+
+    /// Writes the clonotype table: clone ID, V, D and J alleles, junction bases
+    /// and amino acids (`.` without one), productivity, relative abundance, then
+    /// one true read count column per name in `columns`. `counts` holds the
+    /// counts of each clone in turn, one per column.
+    pub fn write_clonotypes(&self, out: &mut impl Write, columns: &[&str], counts: &[u64]) -> io::Result<()> {
+        writeln!(out, "clone\tv\td\tj\tjunction\tjunction_aa\tproductive\tabundance\t{}", columns.join("\t"))?;
+        let segments = &self.segments;
+        for (index, ((clone, abundance), counts)) in self.clones.iter().zip(&self.abundances).zip(counts.chunks(columns.len())).enumerate() {
+            let d = clone.d.map_or(".", |d| segments.d[d].name.as_str());
+            let (junction, junction_aa) = match &clone.junction {
+                Some(junction) => {
+                    let bases = &clone.seq[junction.clone()];
+                    let amino_acids = if bases.len() % 3 == 0 { translate(bases) } else { b".".to_vec() };
+                    (String::from_utf8_lossy(bases).into_owned(), String::from_utf8_lossy(&amino_acids).into_owned())
+                }
+                None => (".".to_string(), ".".to_string()),
+            };
+            let counts: Vec<String> = counts.iter().map(u64::to_string).collect();
+            writeln!(
+                out,
+                "clone{}\t{}\t{}\t{}\t{}\t{}\t{}\t{:.6}\t{}",
+                index + 1,
+                segments.v[clone.v].name,
+                d,
+                segments.j[clone.j].name,
+                junction,
+                junction_aa,
+                clone.is_productive(),
+                abundance,
+                counts.join("\t")
+            )?;
+        }
+        Ok(())
+    }
+}